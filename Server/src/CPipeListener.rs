@@ -8,6 +8,7 @@ use std::{
 };
 use windows::Win32::{
     Foundation::{LPARAM, LRESULT, WPARAM},
+    Storage::FileSystem::FlushFileBuffers,
     UI::{
         Shell::ICredentialProviderEvents,
         WindowsAndMessaging::{
@@ -17,9 +18,11 @@ use windows::Win32::{
     },
 };
 use windows_core::HSTRING;
+use zeroize::Zeroize;
 
 use crate::{
-    Pipe::{read, Client, Server},
+    Pipe::{read_frame, write_frame, Client, Server},
+    protocol::{decode_with_legacy, encode, Message},
     SharedCredentials
 };
 
@@ -157,31 +160,52 @@ impl CPipeListener {
                         if !running_clone.load(Ordering::SeqCst) {
                             break;
                         }
-                        match read(server.handle) {
-                            Ok(content) => {
-                                let parts: Vec<&str> = content.split("::FaceWinUnlock::").collect();
-
-                                if parts.len() == 2 {
-                                    let user_name = parts[0];
-                                    let password = parts[1];
-
-                                    let mut creds = shared_creds_clone.lock().unwrap();
-                                    creds.username = user_name.to_string();
-                                    creds.password = password.to_string();
-                                    creds.is_ready = true;
-
-                                    info!("成功解析用户信息: {}", user_name);
-
-                                    // 触发登录逻辑
-                                    is_unlocked_clone.store(true, Ordering::SeqCst);
-                                    let _ = events_wrapper.0.CredentialsChanged(advise_context);
-                                } else {
-                                    warn!("收到未知格式的数据: {}", content);
-                                }
+                        // 收到凭据后回复软件，读取失败（软件已断开）时不回复
+                        // 没有帧头的数据按旧的 NUL 结尾格式解析，兼容打开了 legacyPipeFormat 的软件
+                        // 解析后立即清除帧中的密码
+                        let received = read_frame(server.handle).map(|mut frame| {
+                            let message = decode_with_legacy(&frame);
+                            frame.zeroize();
+                            message
+                        });
+                        let accepted = match received {
+                            Ok(Ok(Message::Credentials { user_name, password })) => {
+                                let mut creds = shared_creds_clone.lock().unwrap();
+                                creds.username = user_name.clone();
+                                creds.password.zeroize();
+                                creds.password = password;
+                                creds.is_ready = true;
+
+                                info!("成功解析用户信息: {}", user_name);
+
+                                // 触发登录逻辑
+                                is_unlocked_clone.store(true, Ordering::SeqCst);
+                                let _ = events_wrapper.0.CredentialsChanged(advise_context);
+                                Some(true)
+                            }
+                            Ok(Ok(message)) => {
+                                warn!("收到不应由软件发送的消息: {:?}", message);
+                                Some(false)
+                            }
+                            Ok(Err(e)) => {
+                                warn!("收到无法解析的管道消息: {}", e);
+                                Some(false)
                             }
                             Err(_e) => {
                                 // 先不记了
                                 // error!("读取管道数据失败：{:?}", e);
+                                None
+                            }
+                        };
+                        if let Some(accepted) = accepted {
+                            match encode(&Message::Ack { accepted }) {
+                                Ok(frame) => {
+                                    // 等待软件读取回复后再断开，断开会丢弃管道中未读取的数据
+                                    if write_frame(server.handle, &frame).is_ok() {
+                                        let _ = FlushFileBuffers(server.handle);
+                                    }
+                                }
+                                Err(e) => error!("编码 ack 消息失败: {}", e),
                             }
                         }
                         let _ = server.disconnect();
@@ -211,7 +235,14 @@ impl CPipeListener {
                 while running_client.load(Ordering::SeqCst) {
                     if IS_SEND_RUN.load(Ordering::SeqCst) {
                         IS_SEND_RUN.store(false, Ordering::SeqCst);
-                        if let Err(e) = crate::Pipe::write(client.handle, String::from("run")) {
+                        let frame = match encode(&Message::Run) {
+                            Ok(frame) => frame,
+                            Err(e) => {
+                                error!("编码 run 消息失败: {}", e);
+                                continue;
+                            }
+                        };
+                        if let Err(e) = write_frame(client.handle, &frame) {
                             println!("向客户端写入数据失败: {:?}", e);
                         }
                     }
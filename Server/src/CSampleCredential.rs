@@ -6,6 +6,7 @@ use windows::Win32::{
     }
 };
 use windows_core::{implement, BOOL, PCWSTR, PWSTR};
+use zeroize::Zeroize;
 use crate::{dll_add_ref, dll_release, CLSID_SampleProvider, SharedCredentials};
 
 /// 凭据实现类，代表登录界面上的一个磁贴
@@ -204,7 +205,7 @@ impl ICredentialProviderCredential_Impl for SampleCredential_Impl {
 
             // 获取管道收到的用户名和密码
             let v_username = to_wide_vec(&full_username);
-            let v_password = to_wide_vec(&creds.password);
+            let mut v_password = to_wide_vec(&creds.password);
 
             // 转换成 PCWSTR (指向 u16 数组开头的指针)
             let pwz_username = PCWSTR(v_username.as_ptr());
@@ -228,13 +229,16 @@ impl ICredentialProviderCredential_Impl for SampleCredential_Impl {
             let out_buf = CoTaskMemAlloc(auth_buffer_size as usize) as *mut u8;
 
             // 第二次调用真正打包
-            CredPackAuthenticationBufferW(
+            let packed = CredPackAuthenticationBufferW(
                 CRED_PACK_FLAGS(0),
                 pwz_username,
                 pwz_password,
                 Some(out_buf), // 传入分配好的指针
                 &mut auth_buffer_size
-            )?;
+            );
+            // 打包成功或失败都清除密码的 UTF-16 副本
+            v_password.zeroize();
+            packed?;
 
             // 填充返回给 Windows 的结构体
             *pcpgsr = CPGSR_RETURN_CREDENTIAL_FINISHED;
@@ -264,7 +268,7 @@ impl ICredentialProviderCredential_Impl for SampleCredential_Impl {
                 let mut creds = self.shared_creds.lock().unwrap();
                 // 清空错误凭据
                 creds.username.clear();
-                creds.password.clear();
+                creds.password.zeroize();
                 creds.is_ready = false;
 
                 // 设置错误提示文本
@@ -1,4 +1,3 @@
-use std::{ffi::OsStr, os::windows::ffi::OsStrExt};
 use windows::Win32::{
     Foundation::{CloseHandle, GetLastError, E_UNEXPECTED, GENERIC_WRITE, HANDLE}, 
     Storage::FileSystem::{CreateFileW, ReadFile, WriteFile, FILE_FLAGS_AND_ATTRIBUTES, FILE_SHARE_MODE, OPEN_EXISTING, PIPE_ACCESS_DUPLEX}, 
@@ -7,36 +6,33 @@ use windows::Win32::{
 };
 use windows_core::{Error, Result, HSTRING};
 
-pub fn read(handle: HANDLE) -> Result<String> {
-    let mut buf = [0u16; 256];
+use crate::protocol::MAX_FRAME_LEN;
+
+// 读取一帧原始数据，解析交给 protocol 模块
+// 缓冲区比最大帧多 1 字节，超长的帧会读取失败或被 decode 拒绝，不会被截断后当作合法帧
+pub fn read_frame(handle: HANDLE) -> Result<Vec<u8>> {
+    let mut buf = vec![0u8; MAX_FRAME_LEN + 1];
     let mut read = 0;
 
-    let byte_slice = unsafe { std::slice::from_raw_parts_mut(buf.as_mut_ptr() as *mut u8, buf.len() * 2) };
-    
-    unsafe { ReadFile(handle, Some(byte_slice), Some(&mut read), None) }?;
+    unsafe { ReadFile(handle, Some(&mut buf), Some(&mut read), None) }?;
 
-    let content = String::from_utf16_lossy(&buf[.. (read as usize / 2)]);
-    
-    Ok(content.trim_matches('\0').to_string())
+    buf.truncate(read as usize);
+    Ok(buf)
 }
 
-pub fn write(handle: HANDLE, content: String) -> Result<()> { 
-    // 转 UTF-16 含 \0
-    let wide_chars: Vec<u16> = OsStr::new(&content).encode_wide().chain(std::iter::once(0)).collect();
-    // 转 &[u8] 切片
-    let write_buf = unsafe { std::slice::from_raw_parts(
-        wide_chars.as_ptr() as *const u8,
-        wide_chars.len() * 2,
-    ) };
-    // 准备字节数（可变引用，匹配 Option<&mut u32>）
-    let mut total_bytes = write_buf.len() as u32;
-
-    unsafe { WriteFile(
-        handle,
-        Some(write_buf),
-        Some(&mut total_bytes),
-        None
-    ) }
+// 写入一帧由 protocol 模块编码的数据，写入不完整时继续写入剩余部分
+// 消息模式的管道一次写入整条消息，只有字节模式的管道可能出现部分写入
+pub fn write_frame(handle: HANDLE, frame: &[u8]) -> Result<()> {
+    let mut sent = 0;
+    while sent < frame.len() {
+        let mut written = 0u32;
+        unsafe { WriteFile(handle, Some(&frame[sent..]), Some(&mut written), None) }?;
+        if written == 0 {
+            return Err(Error::new(E_UNEXPECTED, "管道写入失败：没有写入任何数据"));
+        }
+        sent += written as usize;
+    }
+    Ok(())
 }
 
 pub struct Server {
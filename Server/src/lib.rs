@@ -23,6 +23,9 @@ pub mod CSampleProvider;
 pub mod CSampleCredential;
 pub mod CPipeListener;
 pub mod Pipe;
+// 管道协议与软件端共用同一份代码，保证两端编解码一致
+#[path = "../../UI/src-tauri/src/utils/protocol.rs"]
+pub mod protocol;
 
 use CSampleProvider::SampleProvider;
 
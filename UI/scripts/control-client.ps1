@@ -0,0 +1,83 @@
+# FaceWinUnlock-Tauri 本地控制管道客户端
+# 需要先在 设置 -> 通用行为 中开启“本地控制管道”，并重启软件
+#
+# 用法：
+#   .\control-client.ps1 get-status
+#   .\control-client.ps1 set-armed -Armed $false
+#   .\control-client.ps1 lock-now
+#   .\control-client.ps1 run-self-test
+#   .\control-client.ps1 -Test        依次调用每个命令并检查返回结果（lock-now 会真的锁屏，需要加 -IncludeLock）
+param(
+    [ValidateSet("get-status", "set-armed", "lock-now", "run-self-test")]
+    [string]$Command = "get-status",
+    [bool]$Armed = $true,
+    [switch]$Test,
+    [switch]$IncludeLock
+)
+
+$PipeName = "FaceWinUnlockControl"
+
+function Invoke-Control([hashtable]$Request) {
+    $pipe = New-Object System.IO.Pipes.NamedPipeClientStream(".", $PipeName, [System.IO.Pipes.PipeDirection]::InOut)
+    try {
+        $pipe.Connect(5000)
+        $pipe.ReadMode = [System.IO.Pipes.PipeTransmissionMode]::Message
+
+        $bytes = [System.Text.Encoding]::UTF8.GetBytes(($Request | ConvertTo-Json -Compress))
+        $pipe.Write($bytes, 0, $bytes.Length)
+        $pipe.Flush()
+
+        # 读取一条完整的消息
+        $buffer = New-Object byte[] 4096
+        $stream = New-Object System.IO.MemoryStream
+        do {
+            $read = $pipe.Read($buffer, 0, $buffer.Length)
+            $stream.Write($buffer, 0, $read)
+        } while (-not $pipe.IsMessageComplete)
+
+        return [System.Text.Encoding]::UTF8.GetString($stream.ToArray()) | ConvertFrom-Json
+    } finally {
+        $pipe.Dispose()
+    }
+}
+
+function Assert-Success([string]$Name, $Result) {
+    if ($Result.code -ne 200) {
+        throw "$Name 失败：$($Result.msg)"
+    }
+    Write-Host "[通过] $Name"
+}
+
+if (-not $Test) {
+    $request = @{ command = $Command }
+    if ($Command -eq "set-armed") {
+        $request.armed = $Armed
+    }
+    Invoke-Control $request | ConvertTo-Json -Depth 10
+    exit 0
+}
+
+# 依次测试每个命令
+$status = Invoke-Control @{ command = "get-status" }
+Assert-Success "get-status" $status
+$originalArmed = $status.data.armed
+
+Assert-Success "set-armed false" (Invoke-Control @{ command = "set-armed"; armed = $false })
+if ((Invoke-Control @{ command = "get-status" }).data.armed -ne $false) {
+    throw "set-armed false 后状态未改变"
+}
+Assert-Success "set-armed 恢复" (Invoke-Control @{ command = "set-armed"; armed = $originalArmed })
+
+Assert-Success "run-self-test" (Invoke-Control @{ command = "run-self-test" })
+
+$invalid = Invoke-Control @{ command = "unknown-verb" }
+if ($invalid.code -eq 200) {
+    throw "未知命令应返回错误"
+}
+Write-Host "[通过] 未知命令被拒绝"
+
+if ($IncludeLock) {
+    Assert-Success "lock-now" (Invoke-Control @{ command = "lock-now" })
+} else {
+    Write-Host "[跳过] lock-now（加 -IncludeLock 执行）"
+}
@@ -0,0 +1,17 @@
+// 对管道协议的 decode 做模糊测试：任意输入都不能 panic
+// 运行：在 UI/src-tauri/fuzz 下执行 cargo +nightly fuzz run decode
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+// protocol.rs 只依赖标准库，直接引用，不需要编译整个 Tauri 程序
+#[path = "../../src/utils/protocol.rs"]
+mod protocol;
+
+fuzz_target!(|data: &[u8]| {
+    // 能解码的帧重新编码后应与输入完全一致
+    if let Ok(message) = protocol::decode(data) {
+        let frame = protocol::encode(&message).expect("解码成功的消息应能重新编码");
+        assert_eq!(frame, data);
+    }
+});
@@ -6,23 +6,69 @@ use std::{
     },
 };
 // Learn more about Tauri commands at https://tauri.app/develop/calling-rust/
-use tauri::{tray::TrayIcon, Manager, Wry};
+use serde::Serialize;
+use tauri::{tray::TrayIcon, AppHandle, Manager, Wry};
 use windows::Win32::{
-    Foundation::HWND,
-    System::RemoteDesktop::{WTSRegisterSessionNotification, NOTIFY_FOR_THIS_SESSION},
-    UI::Shell::SetWindowSubclass,
+    Foundation::{HANDLE, HWND},
+    System::{
+        Power::RegisterPowerSettingNotification,
+        RemoteDesktop::{WTSRegisterSessionNotification, NOTIFY_FOR_THIS_SESSION},
+        SystemServices::GUID_CONSOLE_DISPLAY_STATE,
+    },
+    UI::{Shell::SetWindowSubclass, WindowsAndMessaging::DEVICE_NOTIFY_WINDOW_HANDLE},
 };
 
 pub mod modules;
 pub mod proc;
 pub mod utils;
 use modules::faces::{
-    check_face_from_camera, check_face_from_img, save_face_registration, verify_face,
+    add_registration_sample, check_face_from_bytes, check_face_from_camera, check_face_from_img,
+    start_preview, stop_preview, get_faces_dir, get_match_config, set_match_config,
+    get_detector_params, set_detector_params, get_preview_quality, set_preview_quality,
+    get_preview_max_dim, set_preview_max_dim, get_match_threshold, set_match_threshold,
+    get_duplicate_threshold, set_duplicate_threshold, save_face_registration_averaged,
+    check_face_quality, identify_face, verify_face_against_registered, list_registered_faces,
+    delete_face_registration, rename_face_registration, set_face_username,
+    update_face_registration, reload_face_cache, migrate_face_files,
+    materialize_face_files, prune_registration_samples, save_face_registration,
+    validate_face_store, verify_face, BlackFrameConfig, MatchConfig,
 };
 use modules::init::{
     check_admin_privileges, check_camera_status, deploy_core_components, uninstall_init,
 };
-use modules::options::write_to_registry;
+use modules::auto_unlock::{set_auto_unlock_timeout, start_auto_unlock, stop_auto_unlock};
+use modules::backup::{export_face_registrations, import_face_registrations};
+use modules::calibration::{
+    auto_detect_orientation, get_camera_calibration, set_camera_calibration, ActiveCalibration,
+};
+use modules::capabilities::{check_opencv_capabilities, run_capability_check};
+use modules::conference::{get_pause_status, spawn_conference_monitor};
+use modules::continuous_verify::{start_continuous_verify, stop_continuous_verify};
+use modules::control::{get_unlock_status, set_unlock_armed, spawn_control_server};
+use modules::credential::{
+    clear_credentials, spawn_credential_monitor, store_credentials, validate_stored_credential,
+};
+use modules::face_search::search_registrations;
+use modules::face_store;
+use modules::face_watch::spawn_faces_watcher;
+use modules::engine::get_unlock_engine_trace;
+use modules::liveness::{finish_flash_challenge, start_flash_challenge, verify_liveness};
+use modules::drift::{get_reenrollment_status, snooze_reenrollment_reminder};
+use modules::metrics::get_unlock_latency_breakdown;
+use modules::migrations::{get_migration_status, run_pending_migrations, MigrationContext};
+use modules::presets::{apply_policy_preset, get_settings, sync_policy_preset};
+use modules::options::{
+    list_config_snapshots, mark_configuration_known_good, revert_to_known_good, write_to_registry,
+};
+use modules::retention::{
+    reactivate_registration, run_retention_maintenance, spawn_retention_scheduler,
+};
+use modules::statistics::{get_stored_data_summary, get_unlock_statistics, set_score_precision};
+use modules::replay::{
+    get_debug_capture, replay_unlock_attempt, run_cli as run_replay_cli, set_debug_capture,
+};
+use modules::supervisor::{install_panic_hook, run_cli as run_supervisor_cli, EXIT_FATAL_INIT};
+use modules::support::{create_support_bundle, run_cli as run_support_cli};
 use opencv::{
     core::Ptr,
     objdetect::{FaceDetectorYN, FaceRecognizerSF},
@@ -32,11 +78,16 @@ use proc::wnd_proc_subclass;
 use tauri_plugin_log::{Target, TargetKind};
 use utils::api::{
     check_global_autostart, disable_global_autostart, enable_global_autostart, get_camera,
-    get_now_username, init_model, open_camera, open_directory, stop_camera, test_win_logon,
-    close_app
+    get_now_username, init_model, list_cameras, open_camera, open_directory, stop_camera,
+    test_win_logon, unload_models,
+    close_app, restart_app, get_app_phase, get_pipeline_priority, lock_now, not_ready,
+    set_app_phase, set_pipe_name, DEFAULT_PIPE_NAME,
 };
+use utils::custom_result::CustomResult;
 mod tray;
 use tray::create_system_tray;
+mod window_placement;
+use window_placement::{hide_main_window, restore_placement, show_main_window};
 
 use r2d2::Pool;
 use r2d2_sqlite::SqliteConnectionManager;
@@ -46,11 +97,39 @@ pub struct OpenCVResource<T> {
 }
 unsafe impl<T> Send for OpenCVResource<T> {}
 unsafe impl<T> Sync for OpenCVResource<T> {}
+// 软件运行阶段，前端可能在 setup 完成前/退出过程中调用命令
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum AppPhase {
+    /// setup 尚未完成
+    Starting,
+    /// 可以正常使用
+    Ready,
+    /// 正在退出
+    ShuttingDown,
+}
+
 // 持久存储模型
 pub struct AppState {
+    pub phase: AppPhase,
     pub detector: Option<OpenCVResource<Ptr<FaceDetectorYN>>>,
     pub recognizer: Option<OpenCVResource<Ptr<FaceRecognizerSF>>>,
     pub camera: Option<OpenCVResource<VideoCapture>>,
+    // 检测和匹配阈值
+    pub match_config: MatchConfig,
+    // 发送解锁凭据的管道名称
+    pub pipe_name: String,
+    // 实时预览的停止标记，没有在预览时为 None
+    pub preview_cancel: Option<Arc<AtomicBool>>,
+}
+
+impl AppState {
+    // 检查是否处于可用阶段，未就绪时返回可重试的错误
+    pub fn ensure_ready(&self) -> Result<(), CustomResult> {
+        match self.phase {
+            AppPhase::Ready => Ok(()),
+            phase => Err(not_ready(phase)),
+        }
+    }
 }
 
 // 是否退出线程
@@ -66,12 +145,22 @@ lazy_static::lazy_static! {
     static ref GLOBAL_TRAY: Mutex<Option<Arc<TrayIcon<Wry>>>> = Mutex::new(None);
     static ref TRAY_IS_READY: Mutex<bool> = Mutex::new(false);
     static ref DB_POOL: Mutex<Option<Pool<SqliteConnectionManager>>> = Mutex::new(None);
+    // 全局 AppHandle，用于在 proc 等没有 app 的地方向前端发送事件
+    static ref APP_HANDLE: Mutex<Option<AppHandle>> = Mutex::new(None);
     // 不在使用状态管理，因为proc获取不到
     static ref APP_STATE: Mutex<AppState> = Mutex::new(AppState {
+        phase: AppPhase::Starting,
         detector: None,
         recognizer: None,
         camera: None,
+        match_config: MatchConfig::default(),
+        pipe_name: String::from(DEFAULT_PIPE_NAME),
+        preview_cancel: None,
     });
+    // 黑帧检测阈值（隐私挡板、红外补光关闭等情况）
+    static ref BLACK_FRAME_CONFIG: Mutex<BlackFrameConfig> = Mutex::new(BlackFrameConfig::default());
+    // 当前摄像头的校准（旋转、镜像、裁剪）
+    static ref CAMERA_CALIBRATION: Mutex<ActiveCalibration> = Mutex::new(ActiveCalibration::default());
 
     // 全局只读软件根目录
     pub static ref ROOT_DIR: &'static Path = {
@@ -94,14 +183,36 @@ lazy_static::lazy_static! {
 // 计时器，确定何时调用面容识别代码
 static IS_LOCKED: AtomicBool = AtomicBool::new(false);
 const TIMER_ID_LOCK_CHECK: usize = 1001;
+// 预热超时计时器，屏保/熄屏后一段时间内没有锁屏则释放摄像头
+const TIMER_ID_PREWARM: usize = 1002;
+// 会话是否处于锁屏状态
+static IS_SESSION_LOCKED: AtomicBool = AtomicBool::new(false);
+// 是否已经根据屏保/熄屏提前打开了摄像头
+static IS_PRE_WARMED: AtomicBool = AtomicBool::new(false);
 
 // 全局摄像头索引
 static CAMERA_INDEX: AtomicI32 = AtomicI32::new(0);
 // 面容不匹配时，当前的尝试次数
 static MATCH_FAIL_COUNT: AtomicI32 = AtomicI32::new(0);
+// 连续读取到黑帧的次数
+static BLACK_FRAME_COUNT: AtomicI32 = AtomicI32::new(0);
+// 本次锁屏期间摄像头是否被遮挡，被遮挡后不再尝试，解锁/重新锁屏时重置
+static IS_CAMERA_OBSTRUCTED: AtomicBool = AtomicBool::new(false);
+// 是否因检测到会议/录屏暂停了预览和预热
+static IS_CONFERENCE_PAUSED: AtomicBool = AtomicBool::new(false);
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
+    // 命令行生成支持包、回放调试记录、守护模式时不启动界面
+    let args: Vec<String> = env::args().collect();
+    if let Some(code) = run_support_cli(&args)
+        .or_else(|| run_replay_cli(&args))
+        .or_else(|| run_supervisor_cli(&args))
+    {
+        std::process::exit(code);
+    }
+    install_panic_hook();
+
     // 获取软件安装目录，用于将日志放到软件安装目录下
     let log_path = ROOT_DIR.join("logs");
     let mut builder = tauri::Builder::default();
@@ -110,13 +221,11 @@ pub fn run() {
     {
         builder = builder
             .plugin(tauri_plugin_single_instance::init(|app, _args, _cwd| {
-                let main = app
-                    .get_webview_window("main")
-                    .expect("no main window");
-                if !main.is_visible().unwrap() {
-                    main.show().unwrap();
-                }
-                main.set_focus().unwrap();
+                // setup 未完成时窗口可能还不存在
+                let Some(main) = app.get_webview_window("main") else {
+                    return;
+                };
+                show_main_window(&main);
             }))
             .plugin(tauri_plugin_fs::init())
             // 对话框
@@ -129,7 +238,11 @@ pub fn run() {
             // })
             // 文件系统插件
             .plugin(tauri_plugin_opener::init())
-            .plugin(tauri_plugin_sql::Builder::default().build())
+            .plugin(
+                tauri_plugin_sql::Builder::default()
+                    .add_migrations(face_store::FACE_STORE_URL, face_store::plugin_migrations())
+                    .build(),
+            )
             // 注册日志插件
             .plugin(
                 tauri_plugin_log::Builder::new()
@@ -145,8 +258,13 @@ pub fn run() {
                     .build(),
             )
             .setup(|app| {
+                if let Ok(mut guard) = APP_HANDLE.lock() {
+                    *guard = Some(app.app_handle().clone());
+                }
                 let _ = create_system_tray(app.app_handle());
-                let window = app.get_webview_window("main").unwrap();
+                let window = app
+                    .get_webview_window("main")
+                    .ok_or("找不到主窗口，软件无法完成启动")?;
                 #[cfg(debug_assertions)] // 仅在调试(debug)版本中包含此代码
                 {
                     window.open_devtools();
@@ -155,13 +273,19 @@ pub fn run() {
 
                 #[cfg(windows)]
                 {
-                    let window = app.get_webview_window("main").unwrap();
-                    let hwnd = window.hwnd().unwrap();
+                    let hwnd = window.hwnd()?;
                     unsafe {
                         // 注册 WTS 通知
                         let _ =
                             WTSRegisterSessionNotification(HWND(hwnd.0), NOTIFY_FOR_THIS_SESSION);
 
+                        // 注册熄屏通知，作为即将锁屏的提示，用于预热摄像头
+                        let _ = RegisterPowerSettingNotification(
+                            HANDLE(hwnd.0),
+                            &GUID_CONSOLE_DISPLAY_STATE,
+                            DEVICE_NOTIFY_WINDOW_HANDLE,
+                        );
+
                         // 注入子类化回调来捕获 WM_WTSSESSION_CHANGE
                         // on_window_event 收不到这个消息
                         let _ = SetWindowSubclass(HWND(hwnd.0), Some(wnd_proc_subclass), 0, 0);
@@ -171,12 +295,42 @@ pub fn run() {
                 let args: Vec<String> = env::args().collect();
                 let is_silent = args.iter().any(|arg| arg == "-s" || arg == "--silent" || arg == "--s");
                 if !is_silent {
-                    // 只有不是静默启动时才显示
-                    window.show().unwrap();
+                    // 只有不是静默启动时才显示，按上次保存的位置显示
+                    #[cfg(windows)]
+                    if let Ok(hwnd) = window.hwnd() {
+                        restore_placement(HWND(hwnd.0));
+                    }
+                    let _ = window.show();
                 }
 
+                // 后台检测 OpenCV 能力，出错时给出更明确的提示
+                std::thread::spawn(|| {
+                    run_capability_check();
+                });
+
+                // 后台按数据保留策略定时清理
+                spawn_retention_scheduler();
+
+                // 后台检测会议/录屏，期间自动暂停预览和预热
+                spawn_conference_monitor();
+
+                // 设置中启用时，启动供脚本使用的控制管道
+                spawn_control_server();
+
+                // 启用期间每天验证一次保存的密码是否仍然有效
+                spawn_credential_monitor();
+
                 // 添加一个线程，用于创建管道
 
+                // 执行未完成的数据迁移，失败的迁移下次启动重试，不阻止启动
+                run_pending_migrations(&MigrationContext::current());
+
+                // 监视面容目录，软件外添加/删除面容文件时更新缓存并通知前端
+                spawn_faces_watcher();
+
+                // setup 完成，允许前端调用依赖状态的命令
+                set_app_phase(AppPhase::Ready);
+
                 Ok(())
             })
             .on_window_event(|window, event| {
@@ -184,7 +338,7 @@ pub fn run() {
                     match event {
                         tauri::WindowEvent::CloseRequested { api, .. } => {
                             api.prevent_close();
-                            let _ = window.hide();
+                            hide_main_window(window);
                         }
                         _ => {}
                     }
@@ -194,30 +348,113 @@ pub fn run() {
                 // init 初始化模块
                 check_admin_privileges,
                 check_camera_status,
+                check_opencv_capabilities,
                 deploy_core_components,
                 uninstall_init,
                 // 面容模块
                 check_face_from_img,
+                check_face_from_bytes,
                 check_face_from_camera,
+                start_preview,
+                stop_preview,
                 verify_face,
+                get_match_config,
+                set_match_config,
+                get_detector_params,
+                set_detector_params,
+                get_preview_quality,
+                set_preview_quality,
+                get_preview_max_dim,
+                set_preview_max_dim,
+                get_match_threshold,
+                set_match_threshold,
+                get_duplicate_threshold,
+                set_duplicate_threshold,
+                identify_face,
+                reload_face_cache,
+                migrate_face_files,
+                verify_face_against_registered,
+                save_face_registration_averaged,
+                check_face_quality,
+                list_registered_faces,
+                delete_face_registration,
+                rename_face_registration,
+                set_face_username,
+                update_face_registration,
                 save_face_registration,
+                add_registration_sample,
+                prune_registration_samples,
+                get_faces_dir,
+                validate_face_store,
+                materialize_face_files,
+                get_reenrollment_status,
+                snooze_reenrollment_reminder,
+                reactivate_registration,
                 // 配置模块
                 write_to_registry,
+                mark_configuration_known_good,
+                list_config_snapshots,
+                revert_to_known_good,
+                run_retention_maintenance,
+                // 统计模块
+                get_unlock_latency_breakdown,
+                get_unlock_statistics,
+                get_stored_data_summary,
+                set_score_precision,
+                create_support_bundle,
                 // 通用api
                 get_now_username,
                 test_win_logon,
                 init_model,
+                list_cameras,
                 open_camera,
                 stop_camera,
+                unload_models,
                 get_camera,
+                get_camera_calibration,
+                set_camera_calibration,
+                auto_detect_orientation,
                 open_directory,
                 enable_global_autostart,
                 disable_global_autostart,
                 check_global_autostart,
-                close_app
+                get_pipeline_priority,
+                get_app_phase,
+                get_pause_status,
+                get_migration_status,
+                search_registrations,
+                export_face_registrations,
+                import_face_registrations,
+                replay_unlock_attempt,
+                set_debug_capture,
+                get_debug_capture,
+                apply_policy_preset,
+                get_settings,
+                sync_policy_preset,
+                get_unlock_status,
+                get_unlock_engine_trace,
+                start_auto_unlock,
+                stop_auto_unlock,
+                set_auto_unlock_timeout,
+                start_continuous_verify,
+                stop_continuous_verify,
+                start_flash_challenge,
+                finish_flash_challenge,
+                verify_liveness,
+                set_unlock_armed,
+                validate_stored_credential,
+                store_credentials,
+                clear_credentials,
+                lock_now,
+                set_pipe_name,
+                close_app,
+                restart_app
             ]);
     }
-    builder
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+    // 启动失败时重启也无法恢复，使用单独的退出码让守护进程停止
+    if let Err(e) = builder.run(tauri::generate_context!()) {
+        tauri_plugin_log::log::error!("软件启动失败：{}", e);
+        eprintln!("error while running tauri application: {}", e);
+        std::process::exit(EXIT_FATAL_INIT);
+    }
 }
@@ -0,0 +1,174 @@
+// 连续识别：锁屏后不等待锁屏界面的运行请求，持续识别直到解锁成功、超时或调用 stop_auto_unlock
+// 每一轮识别与锁屏界面触发的相同（连续几帧匹配后发送该面容保存的凭据），失败后按重试间隔继续，由解锁引擎决定是否还能重试
+use std::{
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+    thread::sleep,
+    time::Duration,
+};
+
+use serde_json::json;
+use tauri_plugin_log::log::info;
+
+use crate::{
+    modules::{
+        control::is_armed,
+        engine::{self, EngineState},
+        options::{read_option, save_option},
+    },
+    proc::run_before,
+    utils::{
+        api::{emit_event, ensure_ready},
+        custom_result::CustomResult,
+        db_writer,
+    },
+    IS_RUN, IS_SESSION_LOCKED,
+};
+
+// 识别被停止或超时时 run 返回的错误前缀，不计入失败次数
+pub const AUTO_UNLOCK_STOPPED: &str = "AutoUnlockStopped";
+const TIMEOUT_OPTION: &str = "autoUnlockTimeout";
+pub const DEFAULT_TIMEOUT_SECS: u64 = 30;
+const TIMEOUT_RANGE: std::ops::RangeInclusive<u64> = 5..=600;
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+pub const PROGRESS_EVENT: &str = "auto-unlock-progress";
+pub const FINISHED_EVENT: &str = "auto-unlock-finished";
+
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+static CANCEL: AtomicBool = AtomicBool::new(false);
+// 超时时间（Unix 毫秒），0 表示还没有锁屏、没有开始计时
+static DEADLINE: AtomicU64 = AtomicU64::new(0);
+
+// 识别过程中每一帧检查，连续识别被停止或超时时结束本轮识别
+pub fn should_stop() -> bool {
+    if !ACTIVE.load(Ordering::SeqCst) {
+        return false;
+    }
+    let deadline = DEADLINE.load(Ordering::SeqCst);
+    CANCEL.load(Ordering::SeqCst) || (deadline > 0 && engine::now_millis() >= deadline)
+}
+
+fn default_timeout_secs() -> u64 {
+    read_option(TIMEOUT_OPTION)
+        .and_then(|v| v.parse::<u64>().ok())
+        .filter(|v| TIMEOUT_RANGE.contains(v))
+        .unwrap_or(DEFAULT_TIMEOUT_SECS)
+}
+
+fn validate_timeout(secs: u64) -> Result<u64, CustomResult> {
+    if !TIMEOUT_RANGE.contains(&secs) {
+        return Err(CustomResult::error(
+            Some(format!(
+                "超时时间必须在 {} 到 {} 秒之间，当前为 {}",
+                TIMEOUT_RANGE.start(),
+                TIMEOUT_RANGE.end(),
+                secs
+            )),
+            None,
+        ));
+    }
+    Ok(secs)
+}
+
+fn remaining_ms() -> Option<u64> {
+    match DEADLINE.load(Ordering::SeqCst) {
+        0 => None,
+        deadline => Some(deadline.saturating_sub(engine::now_millis())),
+    }
+}
+
+fn drive(timeout: Duration) -> &'static str {
+    // 还没有锁屏时等待锁屏，超时从锁屏开始计算
+    while !IS_SESSION_LOCKED.load(Ordering::SeqCst) {
+        if CANCEL.load(Ordering::SeqCst) {
+            return "stopped";
+        }
+        sleep(POLL_INTERVAL);
+    }
+    DEADLINE.store(engine::now_millis() + timeout.as_millis() as u64, Ordering::SeqCst);
+
+    let mut attempt = 0;
+    let mut last_state = None;
+    loop {
+        if CANCEL.load(Ordering::SeqCst) {
+            return "stopped";
+        }
+        if should_stop() {
+            return "timeout";
+        }
+        if !IS_SESSION_LOCKED.load(Ordering::SeqCst) {
+            return "session_unlocked";
+        }
+        let state = engine::current_state();
+        match state {
+            EngineState::Succeeded => return "succeeded",
+            EngineState::Aborted { .. } | EngineState::Disarmed => return "aborted",
+            _ => {}
+        }
+        if last_state != Some(state) {
+            emit_event(PROGRESS_EVENT, json!({"stage": "waiting", "engine": state, "attempt": attempt, "remaining_ms": remaining_ms()}));
+            last_state = Some(state);
+        }
+        // 冷却期间 run_before 不会识别，由引擎控制重试间隔
+        if !IS_RUN.load(Ordering::SeqCst) && !matches!(state, EngineState::CoolingDown { until } if until > engine::now_millis()) {
+            attempt += 1;
+            emit_event(PROGRESS_EVENT, json!({"stage": "looking", "attempt": attempt, "remaining_ms": remaining_ms()}));
+            run_before();
+        }
+        sleep(POLL_INTERVAL);
+    }
+}
+
+// 开始连续识别，timeout_secs 未指定时使用设置中的值；没有锁屏时等到锁屏后开始
+// 过程中发送 auto-unlock-progress 事件，结束时发送 auto-unlock-finished 事件，reason 为
+// succeeded、timeout、stopped、session_unlocked、aborted（失败次数用完、摄像头被遮挡、已停用）
+#[tauri::command]
+pub fn start_auto_unlock(timeout_secs: Option<u64>) -> Result<CustomResult, CustomResult> {
+    ensure_ready()?;
+    if !is_armed() {
+        return Err(CustomResult::error(Some(String::from("面容解锁已停用")), None));
+    }
+    let secs = match timeout_secs {
+        Some(secs) => validate_timeout(secs)?,
+        None => default_timeout_secs(),
+    };
+    if ACTIVE.swap(true, Ordering::SeqCst) {
+        return Err(CustomResult::error(Some(String::from("连续识别已经在运行")), None));
+    }
+    CANCEL.store(false, Ordering::SeqCst);
+    DEADLINE.store(0, Ordering::SeqCst);
+
+    std::thread::spawn(move || {
+        let reason = drive(Duration::from_secs(secs));
+        ACTIVE.store(false, Ordering::SeqCst);
+        CANCEL.store(false, Ordering::SeqCst);
+        DEADLINE.store(0, Ordering::SeqCst);
+        info!("连续识别结束：{}", reason);
+        emit_event(FINISHED_EVENT, json!({"reason": reason}));
+    });
+
+    info!("开始连续识别，超时 {} 秒", secs);
+    Ok(CustomResult::success(
+        None,
+        Some(json!({"timeout_secs": secs, "session_locked": IS_SESSION_LOCKED.load(Ordering::SeqCst)})),
+    ))
+}
+
+// 停止连续识别，正在进行的识别在下一帧结束
+#[tauri::command]
+pub fn stop_auto_unlock() -> Result<CustomResult, CustomResult> {
+    let running = ACTIVE.load(Ordering::SeqCst);
+    if running {
+        CANCEL.store(true, Ordering::SeqCst);
+    }
+    Ok(CustomResult::success(None, Some(json!({"stopped": running}))))
+}
+
+// 保存连续识别的默认超时时间（秒）
+#[tauri::command]
+pub fn set_auto_unlock_timeout(timeout_secs: u64) -> Result<CustomResult, CustomResult> {
+    ensure_ready()?;
+    let secs = validate_timeout(timeout_secs)?;
+    db_writer::write(move |tx| save_option(tx, TIMEOUT_OPTION, &secs.to_string()))
+        .map_err(|e| CustomResult::error(Some(e), None))?;
+    Ok(CustomResult::success(None, Some(json!({"timeout_secs": secs}))))
+}
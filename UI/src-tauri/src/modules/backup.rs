@@ -0,0 +1,385 @@
+// 面容备份：把所有面容导出为一个文件，重装系统后再导入
+// 备份中保存原始特征（不做模板保护，也不做 DPAPI 加密），否则换电脑或重装系统后无法使用，请妥善保管备份文件
+// 不包含数据库中的账户和密码，导入后需要重新关联账户
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use opencv::{
+    core::{Mat, Vector},
+    imgcodecs,
+    prelude::*,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tauri_plugin_log::log::{info, warn};
+
+use crate::{
+    modules::{
+        face_search::FEATURE_DIM,
+        face_watch::cached_faces,
+        faces::{remove_face_files, store_registration, FaceDescriptor, MAX_SAMPLES},
+        template::raw_samples,
+    },
+    utils::{
+        api::ensure_ready,
+        custom_result::CustomResult,
+        storage::{faces_dir, name_key, safe_file_stem, with_retry},
+    },
+};
+
+const BACKUP_MAGIC: [u8; 4] = *b"FWFB";
+pub const BACKUP_VERSION: u8 = 1;
+
+#[derive(Serialize, Deserialize)]
+struct BackupEntry {
+    name: String,
+    // 原始特征
+    samples: Vec<Vec<f32>>,
+    thumbnail: Option<Vec<u8>>,
+    // 录入时保存的图片（JPEG）
+    image: Vec<u8>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct FaceBackup {
+    created: u64,
+    app_version: String,
+    entries: Vec<BackupEntry>,
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+fn encode_backup(backup: &FaceBackup) -> Result<Vec<u8>, String> {
+    let mut encoded = Vec::new();
+    encoded.extend_from_slice(&BACKUP_MAGIC);
+    encoded.push(BACKUP_VERSION);
+    encoded.extend_from_slice(&bincode::serialize(backup).map_err(|e| format!("编码备份文件失败：{}", e))?);
+    Ok(encoded)
+}
+
+fn decode_backup(buffer: &[u8]) -> Result<FaceBackup, String> {
+    if buffer.len() <= BACKUP_MAGIC.len() || buffer[..BACKUP_MAGIC.len()] != BACKUP_MAGIC {
+        return Err(String::from("不是面容备份文件"));
+    }
+    match buffer[BACKUP_MAGIC.len()] {
+        BACKUP_VERSION => bincode::deserialize(&buffer[BACKUP_MAGIC.len() + 1..])
+            .map_err(|e| format!("备份文件已损坏：{}", e)),
+        version => Err(format!("不支持的备份文件版本 {}", version)),
+    }
+}
+
+// 检查一个面容能否导入，返回解码后的图片
+fn validate_entry(entry: &BackupEntry) -> Result<Mat, String> {
+    if entry.name.trim().is_empty() {
+        return Err(String::from("名称为空"));
+    }
+    if entry.samples.is_empty() || entry.samples.len() > MAX_SAMPLES {
+        return Err(format!("样本数量 {} 无效", entry.samples.len()));
+    }
+    // 特征长度与当前识别模型不一致时无法比较
+    if let Some(sample) = entry.samples.iter().find(|s| s.len() != FEATURE_DIM) {
+        return Err(format!("特征长度为 {}，当前模型为 {}", sample.len(), FEATURE_DIM));
+    }
+    if entry.samples.iter().flatten().any(|v| !v.is_finite()) {
+        return Err(String::from("特征包含无效数值"));
+    }
+    let image = imgcodecs::imdecode(&Vector::<u8>::from_slice(&entry.image), imgcodecs::IMREAD_COLOR)
+        .map_err(|e| format!("图片解码失败：{}", e))?;
+    if image.empty() {
+        return Err(String::from("图片解码失败"));
+    }
+    Ok(image)
+}
+
+// 名称（忽略大小写）和特征都与已有面容相同
+fn is_duplicate(existing: &[(FaceDescriptor, Vec<Vec<f32>>)], entry: &BackupEntry) -> bool {
+    existing
+        .iter()
+        .any(|(descriptor, samples)| descriptor.name_matches(&entry.name) && *samples == entry.samples)
+}
+
+// 名称已存在时追加序号：张三 (2)、张三 (3)……
+fn unique_name(name: &str, used: &HashSet<String>) -> String {
+    if !used.contains(&name_key(name)) {
+        return name.to_string();
+    }
+    (2..)
+        .map(|i| format!("{} ({})", name, i))
+        .find(|candidate| !used.contains(&name_key(candidate)))
+        .unwrap_or_else(|| name.to_string())
+}
+
+// 导出图片的文件名，由面容名称生成，文件名不区分大小写，重复时追加序号：zhang-3f2a1b0c、zhang-3f2a1b0c-2……
+fn unique_stem(stem: String, used: &mut HashSet<String>) -> String {
+    let stem = if used.contains(&name_key(&stem)) {
+        (2..)
+            .map(|i| safe_file_stem(&format!("{}-{}", stem, i)))
+            .find(|candidate| !used.contains(&name_key(candidate)))
+            .unwrap_or(stem)
+    } else {
+        stem
+    };
+    used.insert(name_key(&stem));
+    stem
+}
+
+// 把导出的面容图片按名称保存到目录中，方便查看备份包含哪些面容，导入时不使用
+fn export_images(dir: &Path, entries: &[(String, &[u8])]) -> Result<(), String> {
+    fs::create_dir_all(dir).map_err(|e| format!("创建图片目录失败：{}", e))?;
+    for (stem, image) in entries {
+        let path = dir.join(format!("{}.jpg", stem));
+        with_retry(|| fs::write(&path, image)).map_err(|e| format!("写入图片 {:?} 失败：{}", path, e))?;
+    }
+    Ok(())
+}
+
+// 导出所有面容，先写临时文件再替换，导出中断不会留下不完整的备份
+// images_dir 不为空时同时把每个面容的录入图片保存到该目录，文件名由面容名称生成
+pub fn export_registrations(dest: &Path, images_dir: Option<&Path>) -> Result<(usize, Vec<serde_json::Value>), String> {
+    let (faces, invalid) = cached_faces();
+    let mut failed: Vec<_> = invalid
+        .into_iter()
+        .map(|i| json!({"file_name": i.file_name, "reason": i.error}))
+        .collect();
+
+    let mut entries = Vec::new();
+    let mut stems = Vec::new();
+    let mut used_stems = HashSet::new();
+    for (file_stem, descriptor) in faces {
+        // 受保护的面容需要还原为原始特征，密钥不可用时无法导出
+        let Some(samples) = raw_samples(&descriptor) else {
+            failed.push(json!({"file_name": file_stem, "reason": "模板密钥不可用"}));
+            continue;
+        };
+        let image = match fs::read(faces_dir().join(format!("{}.faceimg", file_stem))) {
+            Ok(image) => image,
+            // 录入时选择了不保存图片
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                failed.push(json!({"file_name": file_stem, "reason": "录入时没有保存图片"}));
+                continue;
+            }
+            Err(e) => {
+                failed.push(json!({"file_name": file_stem, "reason": format!("读取图片失败：{}", e)}));
+                continue;
+            }
+        };
+        stems.push(unique_stem(descriptor.file_stem(), &mut used_stems));
+        entries.push(BackupEntry { name: descriptor.name, samples, thumbnail: descriptor.thumbnail, image });
+    }
+
+    if let Some(dir) = images_dir {
+        let images: Vec<_> = stems.into_iter().zip(entries.iter().map(|e| e.image.as_slice())).collect();
+        export_images(dir, &images)?;
+    }
+    let count = entries.len();
+    let encoded = encode_backup(&FaceBackup {
+        created: now_secs(),
+        app_version: env!("CARGO_PKG_VERSION").to_string(),
+        entries,
+    })?;
+    let temp_path = dest.with_extension("tmp");
+    with_retry(|| fs::write(&temp_path, &encoded)).map_err(|e| format!("写入备份文件失败：{}", e))?;
+    with_retry(|| fs::rename(&temp_path, dest)).map_err(|e| {
+        let _ = fs::remove_file(&temp_path);
+        format!("写入备份文件失败：{}", e)
+    })?;
+    warn!("已导出 {} 个面容到 {:?}，备份文件包含未加密的面容特征", count, dest);
+    Ok((count, failed))
+}
+
+// 导出所有面容到一个备份文件，images_dir 不为空时同时导出录入图片
+#[tauri::command]
+pub fn export_face_registrations(dest_path: String, images_dir: Option<String>) -> Result<CustomResult, CustomResult> {
+    ensure_ready()?;
+    let dest = PathBuf::from(&dest_path);
+    let images_dir = images_dir.filter(|dir| !dir.trim().is_empty()).map(PathBuf::from);
+    let (exported, failed) =
+        export_registrations(&dest, images_dir.as_deref()).map_err(|e| CustomResult::error(Some(e), None))?;
+    Ok(CustomResult::success(
+        None,
+        Some(json!({
+            "path": dest.to_string_lossy(),
+            "version": BACKUP_VERSION,
+            "exported": exported,
+            "failed": failed,
+        })),
+    ))
+}
+
+// 从备份文件导入面容，不覆盖已有的面容
+// 与已有面容名称和特征都相同的跳过（重复导入），只有名称相同的追加序号
+// 备份文件无法解析时不导入任何面容；保存过程中出错时删除本次已导入的面容
+#[tauri::command]
+pub fn import_face_registrations(src_path: String) -> Result<CustomResult, CustomResult> {
+    ensure_ready()?;
+    let buffer = fs::read(&src_path)
+        .map_err(|e| CustomResult::error(Some(format!("读取备份文件失败：{}", e)), None))?;
+    let backup = decode_backup(&buffer).map_err(|e| CustomResult::error(Some(e), None))?;
+
+    let (faces, _) = cached_faces();
+    let existing: Vec<(FaceDescriptor, Vec<Vec<f32>>)> = faces
+        .into_iter()
+        .filter_map(|(_, descriptor)| raw_samples(&descriptor).map(|samples| (descriptor, samples)))
+        .collect();
+    let mut used: HashSet<String> = existing.iter().map(|(descriptor, _)| name_key(&descriptor.name)).collect();
+
+    // 先检查所有面容，保存前确定要导入的内容
+    let mut pending = Vec::new();
+    let mut skipped = Vec::new();
+    let mut failed = Vec::new();
+    for entry in backup.entries {
+        let image = match validate_entry(&entry) {
+            Ok(image) => image,
+            Err(reason) => {
+                failed.push(json!({"name": entry.name, "reason": reason}));
+                continue;
+            }
+        };
+        if is_duplicate(&existing, &entry) {
+            skipped.push(json!({"name": entry.name, "reason": "面容已存在"}));
+            continue;
+        }
+        let name = unique_name(&entry.name, &used);
+        used.insert(name_key(&name));
+        let descriptor = FaceDescriptor {
+            name,
+            samples: entry.samples,
+            key_fingerprint: None,
+            thumbnail: entry.thumbnail,
+            model_version: None,
+            created_at: None,
+        };
+        pending.push((descriptor, image));
+    }
+
+    let mut added = Vec::new();
+    for (descriptor, image) in pending {
+        let name = descriptor.name.clone();
+        match store_registration(descriptor, Some(&image)) {
+            Ok(base_name) => added.push(json!({"file_name": base_name.to_string(), "name": name})),
+            Err(e) => {
+                for item in &added {
+                    if let Err(err) = remove_face_files(item["file_name"].as_str().unwrap_or_default()) {
+                        warn!("撤销导入的面容 {} 失败：{}", item["file_name"], err);
+                    }
+                }
+                return Err(CustomResult::error(
+                    Some(format!("导入面容 {} 失败，已撤销本次导入：{}", name, e.msg)),
+                    None,
+                ));
+            }
+        }
+    }
+
+    info!(
+        "从 {} 导入面容：新增 {}，跳过 {}，失败 {}",
+        src_path,
+        added.len(),
+        skipped.len(),
+        failed.len()
+    );
+    Ok(CustomResult::success(
+        None,
+        Some(json!({
+            "added": added.len(),
+            "skipped": skipped.len(),
+            "failed": failed.len(),
+            "faces": added,
+            "skipped_faces": skipped,
+            "failed_faces": failed,
+            "created": backup.created,
+            "app_version": backup.app_version,
+        })),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 中文、表情、混合文字和设备保留名
+    const NAMES: [&str; 7] = ["张三", "😀 Smile", "Иван Petrov", "CON", "Straße", "Zoë", "山田 Taro"];
+
+    fn entry(name: &str, value: f32) -> BackupEntry {
+        BackupEntry {
+            name: name.to_string(),
+            samples: vec![vec![value; 4]],
+            thumbnail: None,
+            image: name.as_bytes().to_vec(),
+        }
+    }
+
+    #[test]
+    fn names_survive_export_and_import() {
+        let entries: Vec<_> = NAMES.iter().enumerate().map(|(i, n)| entry(n, i as f32)).collect();
+        let encoded = encode_backup(&FaceBackup { created: 1, app_version: String::from("test"), entries }).unwrap();
+        let decoded = decode_backup(&encoded).unwrap();
+        let names: Vec<_> = decoded.entries.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, NAMES);
+
+        // 已有的面容只有大小写不同，导入时视为同名
+        let existing = vec![(
+            FaceDescriptor {
+                name: String::from("STRASSE"),
+                samples: vec![vec![4.0; 4]],
+                key_fingerprint: None,
+                thumbnail: None,
+                model_version: None,
+                created_at: None,
+            },
+            vec![vec![4.0; 4]],
+        )];
+        let mut used: HashSet<String> = existing.iter().map(|(d, _)| name_key(&d.name)).collect();
+        let mut imported = Vec::new();
+        for entry in &decoded.entries {
+            if is_duplicate(&existing, entry) {
+                continue;
+            }
+            let name = unique_name(&entry.name, &used);
+            used.insert(name_key(&name));
+            imported.push(name);
+        }
+        // Straße 与已有的 STRASSE 名称和特征都相同，跳过
+        assert_eq!(imported, ["张三", "😀 Smile", "Иван Petrov", "CON", "Zoë", "山田 Taro"]);
+
+        // 特征不同时追加序号
+        let renamed = entry("straße", 9.0);
+        assert!(!is_duplicate(&existing, &renamed));
+        assert_eq!(unique_name(&renamed.name, &used), "straße (2)");
+    }
+
+    #[test]
+    fn exported_images_use_safe_unique_names() {
+        let mut used = HashSet::new();
+        let mut names = NAMES.to_vec();
+        // 只有大小写不同的名称在 Windows 上是同一个文件
+        names.extend(["con", "张三"]);
+        let stems: Vec<_> = names.iter().map(|n| unique_stem(safe_file_stem(n), &mut used)).collect();
+        assert_eq!(stems[3], "_CON");
+        assert_eq!(stems[7], "con-2");
+        assert_eq!(stems[8], format!("{}-2", stems[0]));
+
+        let dir = std::env::temp_dir().join(format!("facewinunlock-export-{}", uuid::Uuid::new_v4()));
+        let images: Vec<_> = stems.iter().cloned().zip(names.iter().map(|n| n.as_bytes())).collect();
+        export_images(&dir, &images).unwrap();
+        let mut files: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        files.sort();
+        let mut expected: Vec<_> = stems.iter().map(|s| format!("{}.jpg", s)).collect();
+        expected.sort();
+        assert_eq!(files, expected);
+        assert_eq!(fs::read(dir.join(format!("{}.jpg", stems[1]))).unwrap(), "😀 Smile".as_bytes());
+        fs::remove_dir_all(dir).ok();
+    }
+}
@@ -0,0 +1,258 @@
+// 摄像头校准：按设备保存旋转、镜像和裁剪区域
+// 在读取摄像头画面后立即应用（read_mat_from_camera），录入、一致性验证和锁屏解锁看到的都是校准后的画面
+use std::collections::BTreeMap;
+
+use opencv::{
+    core::{self, Mat, Rect},
+    prelude::*,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tauri_plugin_log::log::{info, warn};
+
+use crate::{
+    modules::{
+        face_policy::detect_faces,
+        faces::{camera_error, grab_frame, resize_mat},
+        options::{read_option, save_option},
+    },
+    utils::{
+        api::{camera_device_id, ensure_models_loaded, ensure_ready},
+        custom_result::CustomResult,
+        db_writer,
+    },
+    APP_STATE, CAMERA_CALIBRATION, CAMERA_INDEX,
+};
+
+// 设置项：设备路径 -> 校准参数（JSON）
+const CALIBRATION_OPTION: &str = "cameraCalibration";
+// 没有设备路径（部分虚拟摄像头）时按序号保存
+const INDEX_KEY_PREFIX: &str = "index:";
+// 自动检测方向时缩小画面，只需要判断能否检测到人脸
+const DETECT_MAX_DIM: f32 = 640.0;
+
+// 裁剪区域，使用摄像头原始画面的坐标
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CropRect {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+// 处理顺序：裁剪 -> 顺时针旋转 -> 水平镜像
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CameraCalibration {
+    // 顺时针旋转角度：0、90、180、270
+    pub rotation: u16,
+    pub mirror: bool,
+    pub crop: Option<CropRect>,
+}
+
+// 当前打开的摄像头及其校准
+#[derive(Debug, Clone, Default)]
+pub struct ActiveCalibration {
+    pub device_key: Option<String>,
+    pub calibration: CameraCalibration,
+}
+
+fn rotate_code(rotation: u16) -> Option<i32> {
+    match rotation {
+        90 => Some(core::ROTATE_90_CLOCKWISE),
+        180 => Some(core::ROTATE_180),
+        270 => Some(core::ROTATE_90_COUNTERCLOCKWISE),
+        _ => None,
+    }
+}
+
+fn rotate(frame: &Mat, rotation: u16) -> Result<Mat, String> {
+    let Some(code) = rotate_code(rotation) else {
+        return Ok(frame.clone());
+    };
+    let mut rotated = Mat::default();
+    core::rotate(frame, &mut rotated, code).map_err(|e| format!("旋转画面失败: {}", e))?;
+    Ok(rotated)
+}
+
+impl CameraCalibration {
+    pub fn is_identity(&self) -> bool {
+        *self == CameraCalibration::default()
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if rotate_code(self.rotation).is_none() && self.rotation != 0 {
+            return Err(format!("旋转角度只能是 0、90、180、270，当前为 {}", self.rotation));
+        }
+        if let Some(crop) = self.crop {
+            if crop.x < 0 || crop.y < 0 || crop.width <= 0 || crop.height <= 0 {
+                return Err(format!("裁剪区域无效：{:?}", crop));
+            }
+        }
+        Ok(())
+    }
+
+    // 只做裁剪，裁剪区域超出画面时按画面大小截断
+    fn crop_frame(&self, frame: Mat) -> Result<Mat, String> {
+        let Some(crop) = self.crop else {
+            return Ok(frame);
+        };
+        let bounds = Rect::new(0, 0, frame.cols(), frame.rows());
+        let rect = Rect::new(crop.x, crop.y, crop.width, crop.height) & bounds;
+        if rect.width <= 0 || rect.height <= 0 {
+            return Err(format!("裁剪区域 {:?} 不在画面（{}x{}）内", crop, frame.cols(), frame.rows()));
+        }
+        Mat::roi(&frame, rect)
+            .and_then(|roi| roi.try_clone())
+            .map_err(|e| format!("裁剪画面失败: {}", e))
+    }
+
+    // 对摄像头原始画面应用校准
+    pub fn apply(&self, frame: Mat) -> Result<Mat, String> {
+        if self.is_identity() {
+            return Ok(frame);
+        }
+        let frame = rotate(&self.crop_frame(frame)?, self.rotation)?;
+        if !self.mirror {
+            return Ok(frame);
+        }
+        let mut mirrored = Mat::default();
+        core::flip(&frame, &mut mirrored, 1).map_err(|e| format!("镜像画面失败: {}", e))?;
+        Ok(mirrored)
+    }
+}
+
+// 保存校准使用的键：设备路径，没有时使用序号
+fn device_key(index: i32) -> String {
+    camera_device_id(index).unwrap_or_else(|| format!("{}{}", INDEX_KEY_PREFIX, index))
+}
+
+fn load_all() -> BTreeMap<String, CameraCalibration> {
+    read_option(CALIBRATION_OPTION)
+        .and_then(|v| match serde_json::from_str(&v) {
+            Ok(map) => Some(map),
+            Err(e) => {
+                warn!("解析摄像头校准失败，按未校准处理：{}", e);
+                None
+            }
+        })
+        .unwrap_or_default()
+}
+
+// 打开摄像头前调用，之后读取的画面按该设备的校准处理
+pub fn activate_calibration(index: i32) {
+    let key = device_key(index);
+    let calibration = load_all().get(&key).copied().unwrap_or_default();
+    if !calibration.is_identity() {
+        info!("摄像头 {} 使用校准 {:?}", key, calibration);
+    }
+    if let Ok(mut guard) = CAMERA_CALIBRATION.lock() {
+        *guard = ActiveCalibration { device_key: Some(key), calibration };
+    }
+}
+
+// 当前摄像头的校准
+pub fn active_calibration() -> CameraCalibration {
+    CAMERA_CALIBRATION
+        .lock()
+        .map(|guard| guard.calibration)
+        .unwrap_or_default()
+}
+
+// 获取摄像头的校准，未指定设备时为当前设置的摄像头
+#[tauri::command]
+pub fn get_camera_calibration(device_id: Option<String>) -> Result<CustomResult, CustomResult> {
+    ensure_ready()?;
+    let key = device_id.unwrap_or_else(|| device_key(CAMERA_INDEX.load(std::sync::atomic::Ordering::SeqCst)));
+    let calibration = load_all().get(&key).copied().unwrap_or_default();
+    Ok(CustomResult::success(
+        None,
+        Some(json!({"device_id": key, "calibration": calibration})),
+    ))
+}
+
+// 保存摄像头的校准，未指定设备时为当前设置的摄像头
+#[tauri::command]
+pub fn set_camera_calibration(
+    device_id: Option<String>,
+    calibration: CameraCalibration,
+) -> Result<CustomResult, CustomResult> {
+    ensure_ready()?;
+    calibration
+        .validate()
+        .map_err(|e| CustomResult::error(Some(e), None))?;
+    let key = device_id.unwrap_or_else(|| device_key(CAMERA_INDEX.load(std::sync::atomic::Ordering::SeqCst)));
+
+    let mut all = load_all();
+    if calibration.is_identity() {
+        all.remove(&key);
+    } else {
+        all.insert(key.clone(), calibration);
+    }
+    let value = serde_json::to_string(&all)
+        .map_err(|e| CustomResult::error(Some(format!("保存摄像头校准失败：{}", e)), None))?;
+    db_writer::write(move |tx| save_option(tx, CALIBRATION_OPTION, &value))
+        .map_err(|e| CustomResult::error(Some(e), None))?;
+
+    // 正在使用的摄像头立即生效
+    if let Ok(mut guard) = CAMERA_CALIBRATION.lock() {
+        if guard.device_key.as_deref() == Some(key.as_str()) {
+            guard.calibration = calibration;
+        }
+    }
+    info!("已保存摄像头 {} 的校准 {:?}", key, calibration);
+    Ok(CustomResult::success(
+        None,
+        Some(json!({"device_id": key, "calibration": calibration})),
+    ))
+}
+
+// 辅助检测摄像头方向：读取一帧，依次尝试四个旋转角度，返回各角度检测到的人脸
+// 裁剪和镜像按当前校准处理，结果只用于提示，不会自动保存
+#[tauri::command]
+pub fn auto_detect_orientation(face_detection_threshold: f32) -> Result<CustomResult, CustomResult> {
+    ensure_ready()?;
+    let current = active_calibration();
+    let frame = grab_frame().map_err(camera_error)?;
+    let frame = CameraCalibration { rotation: 0, ..current }
+        .apply(frame)
+        .and_then(|f| resize_mat(&f, DETECT_MAX_DIM))
+        .map_err(|e| CustomResult::error(Some(e), None))?;
+
+    let mut app_state = APP_STATE
+        .lock()
+        .map_err(|e| CustomResult::error(Some(format!("获取app状态失败 {}", e)), None))?;
+    ensure_models_loaded(&mut app_state).map_err(|e| CustomResult::error(Some(e), None))?;
+    let Some(detector) = app_state.detector.as_mut() else {
+        return Err(CustomResult::error(Some(String::from("人脸检测模型未初始化")), None));
+    };
+
+    let mut results = Vec::new();
+    let mut best: Option<(u16, f32)> = None;
+    for rotation in [0u16, 90, 180, 270] {
+        let rotated = rotate(&frame, rotation).map_err(|e| CustomResult::error(Some(e), None))?;
+        let (faces, score) = match detect_faces(&mut detector.inner, &rotated, face_detection_threshold) {
+            Ok(faces) => {
+                // 第 15 列是检测置信度
+                let score = (0..faces.rows())
+                    .filter_map(|row| faces.at_2d::<f32>(row, 14).ok().copied())
+                    .fold(0.0f32, f32::max);
+                (faces.rows(), score)
+            }
+            Err(_) => (0, 0.0),
+        };
+        if faces > 0 && best.map_or(true, |(_, s)| score > s) {
+            best = Some((rotation, score));
+        }
+        results.push(json!({"rotation": rotation, "faces": faces, "score": score}));
+    }
+
+    Ok(CustomResult::success(
+        None,
+        Some(json!({
+            "current": current.rotation,
+            "recommended": best.map(|(rotation, _)| rotation),
+            "results": results,
+        })),
+    ))
+}
@@ -0,0 +1,197 @@
+use std::sync::Mutex;
+
+use opencv::{
+    core::{self, Mat, Scalar, Size, Vector, CV_8UC3},
+    imgcodecs,
+    objdetect::{FaceDetectorYN, FaceRecognizerSF},
+    prelude::*,
+    videoio,
+};
+use serde::Serialize;
+use serde_json::json;
+use tauri_plugin_log::log::{info, warn};
+
+use crate::{utils::custom_result::CustomResult, ROOT_DIR};
+
+// 能力项名称
+pub const CAP_BUILD_INFO: &str = "build_info";
+pub const CAP_VIDEOIO_MSMF: &str = "videoio_msmf";
+pub const CAP_VIDEOIO_DSHOW: &str = "videoio_dshow";
+pub const CAP_FACE_DETECTOR: &str = "face_detector_yn";
+pub const CAP_FACE_RECOGNIZER: &str = "face_recognizer_sf";
+pub const CAP_IMENCODE_JPG: &str = "imencode_jpg";
+pub const CAP_IMENCODE_PNG: &str = "imencode_png";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Capability {
+    /// 能力项名称
+    pub name: &'static str,
+    /// 是否可用
+    pub ok: bool,
+    /// 检测详情或错误信息
+    pub detail: String,
+    /// 不可用时的修复建议
+    pub hint: Option<&'static str>,
+}
+
+lazy_static::lazy_static! {
+    // 启动时的检测结果，用于在其他错误信息中给出提示
+    static ref CAPABILITIES: Mutex<Vec<Capability>> = Mutex::new(Vec::new());
+}
+
+// 检测 OpenCV 的编译/运行能力，并缓存结果
+pub fn run_capability_check() -> Vec<Capability> {
+    let capabilities = vec![
+        check_build_info(),
+        check_videoio_backend(
+            CAP_VIDEOIO_MSMF,
+            videoio::CAP_MSMF,
+            "请使用 -DWITH_MSMF=ON 重新编译 OpenCV",
+        ),
+        check_videoio_backend(
+            CAP_VIDEOIO_DSHOW,
+            videoio::CAP_DSHOW,
+            "请使用 -DWITH_DSHOW=ON 重新编译 OpenCV",
+        ),
+        check_face_detector(),
+        check_face_recognizer(),
+        check_imencode(CAP_IMENCODE_JPG, ".jpg", "请使用 -DWITH_JPEG=ON 重新编译 OpenCV"),
+        check_imencode(CAP_IMENCODE_PNG, ".png", "请使用 -DWITH_PNG=ON 重新编译 OpenCV"),
+    ];
+
+    for cap in capabilities.iter().filter(|c| !c.ok) {
+        warn!("OpenCV 能力检测未通过 {}: {}", cap.name, cap.detail);
+    }
+    info!("OpenCV 能力检测完成");
+
+    if let Ok(mut guard) = CAPABILITIES.lock() {
+        *guard = capabilities.clone();
+    }
+    capabilities
+}
+
+// 如果某项能力检测未通过，在错误信息后追加修复建议
+pub fn with_capability_hint(name: &str, msg: String) -> String {
+    let Ok(guard) = CAPABILITIES.lock() else {
+        return msg;
+    };
+    match guard.iter().find(|c| c.name == name && !c.ok) {
+        Some(cap) => format!(
+            "{}（OpenCV 缺少 {} 能力：{}）",
+            msg,
+            cap.name,
+            cap.hint.unwrap_or("请检查 OpenCV 安装")
+        ),
+        None => msg,
+    }
+}
+
+// 检测 OpenCV 能力
+#[tauri::command]
+pub fn check_opencv_capabilities() -> Result<CustomResult, CustomResult> {
+    let capabilities = run_capability_check();
+    let all_ok = capabilities.iter().all(|c| c.ok);
+    Ok(CustomResult::success(
+        None,
+        Some(json!({"ok": all_ok, "capabilities": capabilities})),
+    ))
+}
+
+fn pass(name: &'static str, detail: String) -> Capability {
+    Capability {
+        name,
+        ok: true,
+        detail,
+        hint: None,
+    }
+}
+
+fn fail(name: &'static str, detail: String, hint: &'static str) -> Capability {
+    Capability {
+        name,
+        ok: false,
+        detail,
+        hint: Some(hint),
+    }
+}
+
+fn check_build_info() -> Capability {
+    match core::get_build_information() {
+        Ok(info) => pass(
+            CAP_BUILD_INFO,
+            format!(
+                "OpenCV {}，编译信息 {} 字节",
+                core::get_version_string().unwrap_or_default(),
+                info.len()
+            ),
+        ),
+        Err(e) => fail(
+            CAP_BUILD_INFO,
+            e.to_string(),
+            "OpenCV 动态库可能损坏，请重新安装 OpenCV",
+        ),
+    }
+}
+
+fn check_videoio_backend(name: &'static str, api: i32, hint: &'static str) -> Capability {
+    let backends = match videoio::get_camera_backends() {
+        Ok(backends) => backends,
+        Err(e) => return fail(name, e.to_string(), hint),
+    };
+    if backends.iter().any(|b| b as i32 == api) {
+        pass(name, String::from("已编译"))
+    } else {
+        fail(name, String::from("未编译该摄像头后端"), hint)
+    }
+}
+
+fn check_face_detector() -> Capability {
+    let hint = "请确认 OpenCV 编译了 objdetect 和 dnn 模块，并且 resources 下存在 YuNet 模型";
+    let model_path = ROOT_DIR
+        .join("resources")
+        .join("face_detection_yunet_2023mar.onnx");
+    let result = FaceDetectorYN::create(
+        model_path.to_str().unwrap_or(""),
+        "",
+        Size::new(32, 32),
+        0.9,
+        0.3,
+        5000,
+        0,
+        0,
+    )
+    .and_then(|mut detector| {
+        // 用一张很小的黑图跑一次检测，确认推理可用
+        let img = Mat::new_size_with_default(Size::new(32, 32), CV_8UC3, Scalar::all(0.0))?;
+        let mut faces = Mat::default();
+        detector.detect(&img, &mut faces)
+    });
+    match result {
+        Ok(_) => pass(CAP_FACE_DETECTOR, String::from("可以正常创建并推理")),
+        Err(e) => fail(CAP_FACE_DETECTOR, e.to_string(), hint),
+    }
+}
+
+fn check_face_recognizer() -> Capability {
+    let hint = "请确认 OpenCV 编译了 objdetect 和 dnn 模块，并且 resources 下存在 SFace 模型";
+    let model_path = ROOT_DIR
+        .join("resources")
+        .join("face_recognition_sface_2021dec.onnx");
+    match FaceRecognizerSF::create(model_path.to_str().unwrap_or(""), "", 0, 0) {
+        Ok(_) => pass(CAP_FACE_RECOGNIZER, String::from("可以正常创建")),
+        Err(e) => fail(CAP_FACE_RECOGNIZER, e.to_string(), hint),
+    }
+}
+
+fn check_imencode(name: &'static str, ext: &str, hint: &'static str) -> Capability {
+    let result = Mat::new_size_with_default(Size::new(8, 8), CV_8UC3, Scalar::all(0.0))
+        .and_then(|img| {
+            let mut buf = Vector::<u8>::new();
+            imgcodecs::imencode(ext, &img, &mut buf, &Vector::new())
+        });
+    match result {
+        Ok(true) => pass(name, String::from("编码正常")),
+        Ok(false) => fail(name, String::from("编码失败"), hint),
+        Err(e) => fail(name, e.to_string(), hint),
+    }
+}
@@ -0,0 +1,152 @@
+use std::{
+    env,
+    sync::{atomic::Ordering, Mutex},
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+use serde_json::{json, Value};
+use tauri_plugin_log::log::info;
+use winreg::{enums::HKEY_CURRENT_USER, RegKey};
+
+use crate::{
+    modules::options::read_option,
+    tray::set_tray_tooltip,
+    utils::{api::emit_event, custom_result::CustomResult},
+    IS_CONFERENCE_PAUSED,
+};
+
+// 暂停原因，会返回给前端
+pub const CONFERENCE_DETECTED: &str = "conference_detected";
+// 检测间隔
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+// 会议结束后等待多久再恢复，避免两场会议之间短暂释放摄像头导致反复切换
+const RESUME_DEBOUNCE: Duration = Duration::from_secs(60);
+// Windows 记录各程序使用摄像头/屏幕捕获的位置
+const CONSENT_STORE: &str =
+    "Software\\Microsoft\\Windows\\CurrentVersion\\CapabilityAccessManager\\ConsentStore";
+// 需要检查的能力：摄像头、程序化屏幕捕获（Windows 11 的共享屏幕）
+const CAPABILITIES: [&str; 2] = ["webcam", "graphicsCaptureProgrammatic"];
+
+lazy_static::lazy_static! {
+    // 最近一次检测到会议的时间和来源
+    static ref LAST_DETECTED: Mutex<Option<(Instant, String)>> = Mutex::new(None);
+}
+
+// 是否开启了检测到会议时自动暂停
+fn is_auto_pause_enabled() -> bool {
+    read_option("autoPauseConference").as_deref() != Some("false")
+}
+
+// 检查是否有其他程序正在使用摄像头或屏幕捕获，返回能力和程序名
+fn detect_conference() -> Option<String> {
+    // NonPackaged 下的子项名称是把 \ 替换为 # 的程序路径，排除本程序自己
+    let own_key = env::current_exe()
+        .ok()
+        .map(|p| p.to_string_lossy().replace('\\', "#").to_lowercase());
+    let hkcu = RegKey::predef(HKEY_CURRENT_USER);
+
+    for capability in CAPABILITIES {
+        let Ok(store) = hkcu.open_subkey(format!("{}\\{}", CONSENT_STORE, capability)) else {
+            continue;
+        };
+        for name in store.enum_keys().flatten() {
+            let Ok(key) = store.open_subkey(&name) else {
+                continue;
+            };
+            if name == "NonPackaged" {
+                for app in key.enum_keys().flatten() {
+                    if Some(app.to_lowercase()) == own_key {
+                        continue;
+                    }
+                    if key.open_subkey(&app).map(|k| is_in_use(&k)).unwrap_or(false) {
+                        return Some(format!("{}: {}", capability, app.replace('#', "\\")));
+                    }
+                }
+            } else if is_in_use(&key) {
+                return Some(format!("{}: {}", capability, name));
+            }
+        }
+    }
+    None
+}
+
+// 开始使用后还没有结束时间，说明正在使用
+fn is_in_use(key: &RegKey) -> bool {
+    let start: u64 = key.get_value("LastUsedTimeStart").unwrap_or(0);
+    let stop: u64 = key.get_value("LastUsedTimeStop").unwrap_or(1);
+    start != 0 && stop == 0
+}
+
+// 当前暂停状态
+pub fn pause_status() -> Value {
+    let paused = IS_CONFERENCE_PAUSED.load(Ordering::SeqCst);
+    let source = LAST_DETECTED
+        .lock()
+        .ok()
+        .and_then(|guard| guard.as_ref().map(|(_, source)| source.clone()));
+    json!({
+        "paused": paused,
+        "paused_reason": if paused { Some(CONFERENCE_DETECTED) } else { None },
+        "source": if paused { source } else { None },
+        "auto_pause_enabled": is_auto_pause_enabled(),
+    })
+}
+
+// 预览等后台工作在暂停期间直接返回，data 中带上暂停原因
+pub fn ensure_not_paused() -> Result<(), CustomResult> {
+    if !IS_CONFERENCE_PAUSED.load(Ordering::SeqCst) {
+        return Ok(());
+    }
+    Err(CustomResult::error(
+        Some(String::from("检测到会议或屏幕共享，已暂停摄像头预览")),
+        Some(json!({"condition": "Paused", "paused_reason": CONFERENCE_DETECTED, "retryable": true})),
+    ))
+}
+
+fn set_paused(paused: bool) {
+    if IS_CONFERENCE_PAUSED.swap(paused, Ordering::SeqCst) == paused {
+        return;
+    }
+    info!("{}", if paused { "检测到会议，暂停预览和预热" } else { "会议结束，恢复预览和预热" });
+    set_tray_tooltip(if paused { Some("检测到会议，已暂停") } else { None });
+    emit_event("pause-status-changed", pause_status());
+}
+
+// 后台检测会议/录屏，检测到时立即暂停，条件消失一段时间后再恢复
+pub fn spawn_conference_monitor() {
+    std::thread::spawn(|| loop {
+        let detected = if is_auto_pause_enabled() {
+            detect_conference()
+        } else {
+            None
+        };
+
+        match detected {
+            Some(source) => {
+                if let Ok(mut guard) = LAST_DETECTED.lock() {
+                    *guard = Some((Instant::now(), source));
+                }
+                set_paused(true);
+            }
+            None if IS_CONFERENCE_PAUSED.load(Ordering::SeqCst) => {
+                let elapsed = LAST_DETECTED
+                    .lock()
+                    .ok()
+                    .and_then(|guard| guard.as_ref().map(|(time, _)| time.elapsed()));
+                // 关闭了自动暂停时立即恢复
+                if !is_auto_pause_enabled() || elapsed.map_or(true, |e| e >= RESUME_DEBOUNCE) {
+                    set_paused(false);
+                }
+            }
+            None => {}
+        }
+        sleep(POLL_INTERVAL);
+    });
+}
+
+// 获取暂停状态
+#[tauri::command]
+pub fn get_pause_status() -> Result<CustomResult, CustomResult> {
+    Ok(CustomResult::success(None, Some(pause_status())))
+}
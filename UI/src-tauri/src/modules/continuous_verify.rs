@@ -0,0 +1,228 @@
+// 连续验证：后台读取摄像头画面，与参考图片或已录入的面容比较，通过 face-verify-progress 事件推送分数
+// 界面不需要循环调用 verify_face 传整张图片；摄像头和模型的锁只在每一帧读取、匹配期间持有，
+// stop_camera 等命令不会被阻塞，摄像头关闭后自动停止
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+use opencv::core::Mat;
+use serde_json::json;
+use tauri_plugin_log::log::{info, warn};
+
+use crate::{
+    modules::{
+        conference::ensure_not_paused,
+        face_policy::{match_frame, MultiFacePolicy},
+        faces::{
+            best_registered_match, decode_reference, get_feature, match_threshold, read_mat_from_camera,
+            NO_REGISTERED_FACES,
+        },
+        options::read_option,
+        retention::active_cached_faces,
+    },
+    utils::{
+        api::{emit_event, ensure_ready},
+        custom_result::CustomResult,
+        priority::{PriorityGuard, WorkMode},
+    },
+    APP_STATE,
+};
+
+// 每秒比较的次数，每次都要检测和提取特征，不宜过高
+pub const DEFAULT_VERIFY_RATE: u32 = 5;
+const VERIFY_RATE_RANGE: std::ops::RangeInclusive<u32> = 1..=15;
+// 连续失败多少次后停止，未检测到人脸不算失败
+const MAX_VERIFY_FAILURES: u32 = 30;
+pub const VERIFY_PROGRESS_EVENT: &str = "face-verify-progress";
+pub const VERIFY_STOPPED_EVENT: &str = "face-verify-stopped";
+
+// 当前连续验证的停止标记，再次开始时先停止之前的验证
+static CANCEL: Mutex<Option<Arc<AtomicBool>>> = Mutex::new(None);
+
+// 比较的对象：参考图片的特征，或所有已录入的面容
+enum Target {
+    Reference(Mat),
+    Registered,
+}
+
+fn camera_closed() -> bool {
+    APP_STATE.lock().map(|state| state.camera.is_none()).unwrap_or(false)
+}
+
+// 读取一帧并比较，返回推送给界面的结果（不含 fps）
+fn verify_once(target: &Target, face_detection_threshold: f32, threshold: f64) -> Result<serde_json::Value, String> {
+    let frame = read_mat_from_camera()?;
+    match target {
+        Target::Reference(reference) => {
+            // 与锁屏解锁使用同一个多人脸策略
+            let policy = MultiFacePolicy::from_option(read_option("multiFacePolicy"));
+            let matched = match_frame(
+                &frame,
+                std::slice::from_ref(reference),
+                None,
+                face_detection_threshold,
+                policy,
+                false,
+            )?;
+            if !matched.score.is_finite() {
+                return Err(format!("匹配分数无效（{}）", matched.score));
+            }
+            Ok(json!({
+                "score": matched.score,
+                "threshold": threshold,
+                "passed": matched.score >= threshold,
+            }))
+        }
+        Target::Registered => {
+            let feature = get_feature(&frame, face_detection_threshold)?;
+            let (faces, _) = active_cached_faces()?;
+            let mut warnings = Vec::new();
+            let best = best_registered_match(&feature, faces, &mut warnings).map_err(|e| e.msg)?;
+            let Some(best) = best else {
+                return Err(String::from("所有面容文件都无法用于验证"));
+            };
+            Ok(json!({
+                "score": best.score,
+                "threshold": threshold,
+                "passed": best.score >= threshold,
+                "name": best.name,
+                "file_name": best.file_name,
+                "sample_index": best.sample_index,
+            }))
+        }
+    }
+}
+
+// 开始连续验证，reference_base64 为参考图片，未指定时与所有已录入的面容比较
+// 每次比较后发送 face-verify-progress 事件 {score, passed, fps, threshold}，失败时为 {error, fps}；
+// 结束时发送 face-verify-stopped 事件，reason 为 stopped、camera_closed、camera_error；再次调用会先停止之前的验证
+#[tauri::command]
+pub fn start_continuous_verify(
+    face_detection_threshold: f32,
+    reference_base64: Option<String>,
+    rate: Option<u32>,
+) -> Result<CustomResult, CustomResult> {
+    ensure_ready()?;
+    ensure_not_paused()?;
+    let rate = rate.unwrap_or(DEFAULT_VERIFY_RATE);
+    if !VERIFY_RATE_RANGE.contains(&rate) {
+        return Err(CustomResult::error(
+            Some(format!(
+                "每秒验证次数必须在 {} 到 {} 之间，当前为 {}",
+                VERIFY_RATE_RANGE.start(),
+                VERIFY_RATE_RANGE.end(),
+                rate
+            )),
+            None,
+        ));
+    }
+    if camera_closed() {
+        return Err(CustomResult::error(Some(String::from("请先打开摄像头")), None));
+    }
+    // 参考图片的特征只提取一次
+    let target = match reference_base64 {
+        Some(reference_base64) => {
+            let ref_img = decode_reference(reference_base64)?;
+            let feature = get_feature(&ref_img, face_detection_threshold)
+                .map_err(|e| CustomResult::error(Some(format!("特征提取失败: {}", e)), None))?;
+            Target::Reference(feature)
+        }
+        None => {
+            let (faces, _) = active_cached_faces().map_err(|e| CustomResult::error(Some(e), None))?;
+            if faces.is_empty() {
+                return Err(CustomResult::error(
+                    Some(String::from("没有可用于验证的面容，请先录入面容")),
+                    Some(json!({"condition": NO_REGISTERED_FACES})),
+                ));
+            }
+            Target::Registered
+        }
+    };
+    let threshold = match_threshold();
+
+    let cancel = Arc::new(AtomicBool::new(false));
+    {
+        let mut current = CANCEL
+            .lock()
+            .map_err(|e| CustomResult::error(Some(format!("获取连续验证状态失败 {}", e)), None))?;
+        if let Some(previous) = current.replace(cancel.clone()) {
+            previous.store(true, Ordering::SeqCst);
+        }
+    }
+
+    let interval = Duration::from_millis(1000 / rate as u64);
+    std::thread::spawn(move || {
+        let _priority = PriorityGuard::new(WorkMode::Background);
+        let mut failures = 0;
+        let mut last: Option<Instant> = None;
+        let reason = loop {
+            if cancel.load(Ordering::SeqCst) {
+                break "stopped";
+            }
+            if camera_closed() {
+                break "camera_closed";
+            }
+            let started = Instant::now();
+            // 实际达到的每秒比较次数，包括等待时间
+            let fps = last.map_or(0.0, |last| 1.0 / started.duration_since(last).as_secs_f64().max(0.001));
+            last = Some(started);
+            // 会议期间不读取摄像头，等待恢复
+            if let Err(e) = ensure_not_paused() {
+                emit_event(VERIFY_PROGRESS_EVENT, json!({"error": e.msg, "condition": e.data["condition"], "fps": fps}));
+                sleep(interval);
+                continue;
+            }
+            match verify_once(&target, face_detection_threshold, threshold) {
+                Ok(mut result) => {
+                    failures = 0;
+                    result["fps"] = json!(fps);
+                    emit_event(VERIFY_PROGRESS_EVENT, result);
+                }
+                Err(e) => {
+                    if !e.contains("未检测到人脸") {
+                        failures += 1;
+                    }
+                    emit_event(VERIFY_PROGRESS_EVENT, json!({"error": e, "passed": false, "fps": fps}));
+                    if failures >= MAX_VERIFY_FAILURES {
+                        warn!("连续验证连续 {} 次失败，已停止：{}", failures, e);
+                        break "camera_error";
+                    }
+                }
+            }
+            if let Some(rest) = interval.checked_sub(started.elapsed()) {
+                sleep(rest);
+            }
+        };
+
+        // 只清除自己的标记，不影响之后开始的验证
+        if let Ok(mut current) = CANCEL.lock() {
+            if current.as_ref().is_some_and(|c| Arc::ptr_eq(c, &cancel)) {
+                *current = None;
+            }
+        }
+        emit_event(VERIFY_STOPPED_EVENT, json!({"reason": reason}));
+        info!("连续验证已结束：{}", reason);
+    });
+
+    info!("开始连续验证，每秒 {} 次", rate);
+    Ok(CustomResult::success(None, Some(json!({"rate": rate, "threshold": threshold}))))
+}
+
+// 停止连续验证，返回之前是否在验证
+#[tauri::command]
+pub fn stop_continuous_verify() -> Result<CustomResult, CustomResult> {
+    let previous = CANCEL
+        .lock()
+        .map_err(|e| CustomResult::error(Some(format!("获取连续验证状态失败 {}", e)), None))?
+        .take();
+    let running = previous.is_some();
+    if let Some(cancel) = previous {
+        cancel.store(true, Ordering::SeqCst);
+    }
+    Ok(CustomResult::success(None, Some(json!({"stopped": running}))))
+}
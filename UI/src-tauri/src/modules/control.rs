@@ -0,0 +1,256 @@
+// 本地控制管道，供脚本（AutoHotkey、计划任务等）查询状态和控制面容解锁
+// 与 DLL 使用的凭据管道相互独立，默认关闭，只允许当前用户连接
+use std::{sync::atomic::Ordering, time::Duration};
+
+use r2d2_sqlite::rusqlite;
+use serde::Deserialize;
+use serde_json::json;
+use tauri_plugin_log::log::{error, info, warn};
+use windows::{core::HSTRING, Win32::Foundation::HANDLE};
+
+use crate::{
+    modules::{
+        capabilities::check_opencv_capabilities,
+        conference::pause_status,
+        engine::{current_state, sync_armed},
+        options::{read_option, save_option},
+    },
+    utils::{
+        api::{emit_event, ensure_ready, lock_now},
+        custom_result::CustomResult,
+        db_writer,
+        pipe::{client_process, read_message_timeout, write_frame, Server},
+    },
+    APP_STATE, IS_RUN, IS_SESSION_LOCKED,
+};
+
+// 控制管道名称
+pub const CONTROL_PIPE_NAME: &str = r"\\.\pipe\FaceWinUnlockControl";
+// 单条请求的最大长度
+const MAX_REQUEST_LEN: usize = 4096;
+// 客户端超过该时间没有发送请求时断开，管道只有一个实例，空闲的客户端会阻塞其他调用方
+const CLIENT_IDLE_TIMEOUT: Duration = Duration::from_secs(5);
+
+// 控制命令，JSON 格式：{"command": "set-armed", "armed": false}
+#[derive(Deserialize)]
+#[serde(tag = "command", rename_all = "kebab-case")]
+enum ControlRequest {
+    GetStatus,
+    SetArmed { armed: bool },
+    LockNow,
+    RunSelfTest,
+}
+
+impl ControlRequest {
+    fn verb(&self) -> &'static str {
+        match self {
+            ControlRequest::GetStatus => "get-status",
+            ControlRequest::SetArmed { .. } => "set-armed",
+            ControlRequest::LockNow => "lock-now",
+            ControlRequest::RunSelfTest => "run-self-test",
+        }
+    }
+}
+
+// 面容解锁是否启用，未设置时默认启用
+pub fn is_armed() -> bool {
+    read_option("unlockArmed").as_deref() != Some("false")
+}
+
+// 获取面容解锁的状态
+#[tauri::command]
+pub fn get_unlock_status() -> Result<CustomResult, CustomResult> {
+    ensure_ready()?;
+    let (phase, camera_open) = match APP_STATE.try_lock() {
+        Ok(app_state) => (Some(app_state.phase), app_state.camera.is_some()),
+        // 识别中会长时间持有锁，此时摄像头一定是打开的
+        Err(_) => (None, true),
+    };
+    Ok(CustomResult::success(
+        None,
+        Some(json!({
+            "phase": phase,
+            "armed": is_armed(),
+            "session_locked": IS_SESSION_LOCKED.load(Ordering::SeqCst),
+            "recognizing": IS_RUN.load(Ordering::SeqCst),
+            "engine": current_state(),
+            "camera_open": camera_open,
+            "pause": pause_status(),
+            "control_pipe_enabled": read_option("controlPipe").as_deref() == Some("true"),
+        })),
+    ))
+}
+
+// 启用/停用面容解锁，停用后锁屏时不会打开摄像头
+#[tauri::command]
+pub fn set_unlock_armed(armed: bool) -> Result<CustomResult, CustomResult> {
+    ensure_ready()?;
+    db_writer::write(move |tx| save_option(tx, "unlockArmed", if armed { "true" } else { "false" }))
+        .map_err(|e| CustomResult::error(Some(e), None))?;
+    sync_armed(armed);
+
+    info!("面容解锁已{}", if armed { "启用" } else { "停用" });
+    emit_event("unlock-armed-changed", json!({"armed": armed}));
+    Ok(CustomResult::success(None, Some(json!({"armed": armed}))))
+}
+
+// 执行一条控制命令，与界面调用的是同一组命令函数
+fn dispatch(request: &ControlRequest) -> Result<CustomResult, CustomResult> {
+    match request {
+        ControlRequest::GetStatus => get_unlock_status(),
+        ControlRequest::SetArmed { armed } => set_unlock_armed(*armed),
+        ControlRequest::LockNow => lock_now(),
+        ControlRequest::RunSelfTest => check_opencv_capabilities(),
+    }
+}
+
+// 记录每一次调用，包括调用方的进程和结果
+fn write_control_log(verb: &str, pid: u32, image: Option<&str>, result: &CustomResult) {
+    let (verb, image, code, msg) = (verb.to_string(), image.map(String::from), result.code, result.msg.clone());
+    db_writer::write_detached("写入控制日志失败", move |tx| {
+        tx.execute(
+            "INSERT INTO control_log (verb, pid, image, code, msg) VALUES (?1, ?2, ?3, ?4, ?5)",
+            rusqlite::params![verb, pid, image, code, msg],
+        )
+        .map(|_| ())
+        .map_err(|e| format!("{:?}", e))
+    });
+}
+
+// 处理一个已连接的客户端，每条请求返回一条 CustomResult 的 JSON
+// 客户端断开或空闲超时后返回，由调用方断开管道等待下一个客户端
+fn handle_client(handle: HANDLE, dispatch: fn(&ControlRequest) -> Result<CustomResult, CustomResult>) {
+    let (pid, image) = client_process(handle);
+    loop {
+        let request = match read_message_timeout(handle, MAX_REQUEST_LEN, CLIENT_IDLE_TIMEOUT) {
+            Ok(Some(request)) => request,
+            Ok(None) => {
+                info!("控制管道客户端（PID {}）空闲超时，已断开", pid);
+                break;
+            }
+            Err(_) => break,
+        };
+        let (verb, result) = match serde_json::from_slice::<ControlRequest>(&request) {
+            Ok(request) => (
+                request.verb(),
+                dispatch(&request).unwrap_or_else(|e| e),
+            ),
+            Err(e) => (
+                "invalid",
+                CustomResult::error(Some(format!("无法解析的控制命令：{}", e)), None),
+            ),
+        };
+        info!(
+            "控制管道命令 {}（PID {}，{}）：{}",
+            verb,
+            pid,
+            image.as_deref().unwrap_or("未知程序"),
+            result.code
+        );
+        write_control_log(verb, pid, image.as_deref(), &result);
+
+        if let Err(e) = write_frame(handle, result.to_string().as_bytes()) {
+            warn!("控制管道回复失败：{:?}", e);
+            break;
+        }
+    }
+}
+
+// 设置中启用后，在后台启动控制管道，修改设置后需要重启软件
+pub fn spawn_control_server() {
+    if read_option("controlPipe").as_deref() != Some("true") {
+        return;
+    }
+
+    std::thread::spawn(|| {
+        let mut server = match Server::new_for_current_user(HSTRING::from(CONTROL_PIPE_NAME)) {
+            Ok(server) => server,
+            Err(e) => {
+                error!("创建控制管道失败：{:?}", e);
+                return;
+            }
+        };
+        info!("控制管道已启动：{}", CONTROL_PIPE_NAME);
+
+        loop {
+            if let Err(e) = server.connect() {
+                warn!("等待控制管道连接失败：{:?}", e);
+                std::thread::sleep(std::time::Duration::from_secs(1));
+                continue;
+            }
+            handle_client(server.handle, dispatch);
+            let _ = server.disconnect();
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::mpsc, thread};
+
+    use serde_json::Value;
+
+    use super::*;
+    use crate::utils::pipe::{read_message, Client};
+
+    // 不执行真正的命令（lock-now 会锁定电脑），只返回收到的命令
+    fn echo(request: &ControlRequest) -> Result<CustomResult, CustomResult> {
+        let armed = match request {
+            ControlRequest::SetArmed { armed } => Some(*armed),
+            _ => None,
+        };
+        Ok(CustomResult::success(None, Some(json!({"verb": request.verb(), "armed": armed}))))
+    }
+
+    fn request(client: &Client, body: &str) -> Value {
+        write_frame(client.handle, body.as_bytes()).unwrap();
+        serde_json::from_slice(&read_message(client.handle, MAX_REQUEST_LEN).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn parses_every_verb() {
+        let cases = [
+            (r#"{"command": "get-status"}"#, "get-status"),
+            (r#"{"command": "set-armed", "armed": false}"#, "set-armed"),
+            (r#"{"command": "lock-now"}"#, "lock-now"),
+            (r#"{"command": "run-self-test"}"#, "run-self-test"),
+        ];
+        for (body, verb) in cases {
+            assert_eq!(serde_json::from_str::<ControlRequest>(body).unwrap().verb(), verb);
+        }
+        assert!(serde_json::from_str::<ControlRequest>(r#"{"command": "set-armed"}"#).is_err());
+        assert!(serde_json::from_str::<ControlRequest>(r#"{"command": "format-disk"}"#).is_err());
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn round_trips_each_verb_and_drops_idle_client() {
+        let pipe_name = format!(r"\\.\pipe\FaceWinUnlockControlTest-{}", uuid::Uuid::new_v4());
+        let (ready_tx, ready_rx) = mpsc::channel();
+        let (done_tx, done_rx) = mpsc::channel();
+        let name = pipe_name.clone();
+        thread::spawn(move || {
+            let mut server = Server::new_for_current_user(HSTRING::from(name)).unwrap();
+            ready_tx.send(()).unwrap();
+            server.connect().unwrap();
+            handle_client(server.handle, echo);
+            done_tx.send(()).unwrap();
+        });
+        ready_rx.recv().unwrap();
+
+        let client = Client::new_duplex(HSTRING::from(pipe_name)).unwrap();
+        for verb in ["get-status", "lock-now", "run-self-test"] {
+            let reply = request(&client, &format!(r#"{{"command": "{}"}}"#, verb));
+            assert_eq!(reply["code"], 200);
+            assert_eq!(reply["data"]["verb"], verb);
+        }
+        let reply = request(&client, r#"{"command": "set-armed", "armed": false}"#);
+        assert_eq!(reply["data"], json!({"verb": "set-armed", "armed": false}));
+        let reply = request(&client, "not json");
+        assert_eq!(reply["code"], 500);
+
+        // 客户端不再发送请求，服务端在空闲超时后断开，不会一直阻塞其他调用方
+        assert!(done_rx.recv_timeout(CLIENT_IDLE_TIMEOUT + Duration::from_secs(5)).is_ok());
+        drop(client);
+    }
+}
@@ -0,0 +1,431 @@
+// 预检已保存的账户密码：Windows 密码修改后面容仍能匹配，但发送的旧密码会登录失败，用户到锁屏时才发现
+// 使用 LogonUserW 的网络登录验证，不创建交互会话，也不需要锁屏；任何情况下都不记录密码
+// 自动验证在睡眠唤醒后和启用期间每天执行一次，并限制频率，避免触发账户锁定策略
+// 密码保存在 Windows 凭据管理器中，解锁和验证时在后端读取，数据库中不保存密码
+use std::{
+    sync::{atomic::Ordering, Mutex},
+    thread::sleep,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use lazy_static::lazy_static;
+use r2d2_sqlite::rusqlite::Connection;
+use serde::Serialize;
+use serde_json::json;
+use tauri_plugin_log::log::{info, warn};
+use zeroize::Zeroize;
+use windows::{
+    core::{PCWSTR, PWSTR},
+    Win32::{
+        Foundation::{
+            CloseHandle, ERROR_ACCOUNT_DISABLED, ERROR_ACCOUNT_EXPIRED, ERROR_ACCOUNT_LOCKED_OUT,
+            ERROR_LOGON_FAILURE, ERROR_LOGON_TYPE_NOT_GRANTED, ERROR_NOT_FOUND,
+            ERROR_PASSWORD_EXPIRED, ERROR_PASSWORD_MUST_CHANGE, HANDLE, WIN32_ERROR,
+        },
+        Security::{
+            Credentials::{
+                CredDeleteW, CredFree, CredReadW, CredWriteW, CREDENTIALW, CRED_PERSIST_LOCAL_MACHINE,
+                CRED_TYPE_GENERIC,
+            },
+            LogonUserW, LOGON32_LOGON_NETWORK, LOGON32_PROVIDER_DEFAULT,
+        },
+    },
+};
+
+use crate::{
+    modules::{
+        control::{is_armed, set_unlock_armed},
+        options::{get_conn, query_option, save_option},
+    },
+    proc::FaceExtraData,
+    utils::{
+        api::{emit_event, ensure_ready},
+        custom_result::CustomResult,
+        db_writer,
+    },
+    IS_RUN,
+};
+
+// 凭据管理器中的目标名称前缀，后面接用户名
+const VAULT_TARGET_PREFIX: &str = "FaceWinUnlock:";
+// 上次自动验证的时间（Unix 秒）
+const LAST_CHECK_OPTION: &str = "credentialCheckTime";
+// 启用期间每天验证一次
+const DAILY_INTERVAL: u64 = 24 * 60 * 60;
+// 两次自动验证的最短间隔，频繁睡眠唤醒时不会重复验证
+const MIN_AUTO_INTERVAL: u64 = 60 * 60;
+// 唤醒后等待网络恢复再验证，域账户需要连接域控制器
+const RESUME_DELAY: Duration = Duration::from_secs(30);
+const FIRST_CHECK_DELAY: Duration = Duration::from_secs(5 * 60);
+const CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+lazy_static! {
+    // 自动验证不并发执行
+    static ref AUTO_CHECK_LOCK: Mutex<()> = Mutex::new(());
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CredentialStatus {
+    Valid,
+    BadPassword,
+    Expired,
+    LockedOut,
+    Disabled,
+    // 策略不允许网络登录、无法连接域控制器等，无法判断密码是否正确
+    Unverifiable,
+    // 凭据管理器中没有保存密码
+    Missing,
+}
+
+impl CredentialStatus {
+    // 确定无法解锁，需要用户重新输入密码
+    fn is_invalid(&self) -> bool {
+        matches!(self, CredentialStatus::BadPassword | CredentialStatus::Expired | CredentialStatus::LockedOut | CredentialStatus::Disabled | CredentialStatus::Missing)
+    }
+}
+
+// 一个已保存的账户，可能对应多个面容
+struct StoredAccount {
+    face_ids: Vec<i64>,
+    user_name: String,
+    account_type: String,
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+fn to_wide(value: &str) -> Vec<u16> {
+    value.encode_utf16().chain(std::iter::once(0)).collect()
+}
+
+// 本地账户解锁时用户名带 .\ 前缀，保存和读取时都去掉前缀，保证是同一条凭据
+fn vault_target(user_name: &str) -> Vec<u16> {
+    let user_name = user_name.strip_prefix(".\\").unwrap_or(user_name);
+    to_wide(&format!("{}{}", VAULT_TARGET_PREFIX, user_name))
+}
+
+// 保存到当前 Windows 用户的凭据管理器，只有本机的当前用户能读取
+fn write_vault(user_name: &str, password: &str) -> windows::core::Result<()> {
+    let mut target = vault_target(user_name);
+    let mut user = to_wide(user_name);
+    // 凭据内容为不含结尾 0 的 UTF-16
+    let mut blob: Vec<u8> = password.encode_utf16().flat_map(|c| c.to_le_bytes()).collect();
+    let credential = CREDENTIALW {
+        Type: CRED_TYPE_GENERIC,
+        TargetName: PWSTR(target.as_mut_ptr()),
+        UserName: PWSTR(user.as_mut_ptr()),
+        CredentialBlobSize: blob.len() as u32,
+        CredentialBlob: blob.as_mut_ptr(),
+        Persist: CRED_PERSIST_LOCAL_MACHINE,
+        ..Default::default()
+    };
+    let result = unsafe { CredWriteW(&credential, 0) };
+    blob.zeroize();
+    result
+}
+
+// 读取凭据管理器中保存的密码，没有保存时返回 None
+pub fn read_vault_password(user_name: &str) -> windows::core::Result<Option<String>> {
+    let target = vault_target(user_name);
+    let mut credential: *mut CREDENTIALW = std::ptr::null_mut();
+    if let Err(e) = unsafe { CredReadW(PCWSTR(target.as_ptr()), CRED_TYPE_GENERIC, None, &mut credential) } {
+        if e.code() == ERROR_NOT_FOUND.to_hresult() {
+            return Ok(None);
+        }
+        return Err(e);
+    }
+    let password = unsafe {
+        // 空密码保存后 CredentialBlob 为空指针，不能构造切片
+        if (*credential).CredentialBlob.is_null() || (*credential).CredentialBlobSize == 0 {
+            CredFree(credential as *const _);
+            return Ok(Some(String::new()));
+        }
+        let blob = std::slice::from_raw_parts_mut((*credential).CredentialBlob, (*credential).CredentialBlobSize as usize);
+        let mut wide: Vec<u16> = blob.chunks_exact(2).map(|c| u16::from_le_bytes([c[0], c[1]])).collect();
+        let password = String::from_utf16_lossy(&wide);
+        wide.zeroize();
+        blob.zeroize();
+        CredFree(credential as *const _);
+        password
+    };
+    Ok(Some(password))
+}
+
+// 删除凭据管理器中保存的密码，返回是否存在
+fn delete_vault(user_name: &str) -> windows::core::Result<bool> {
+    let target = vault_target(user_name);
+    match unsafe { CredDeleteW(PCWSTR(target.as_ptr()), CRED_TYPE_GENERIC, None) } {
+        Ok(()) => Ok(true),
+        Err(e) if e.code() == ERROR_NOT_FOUND.to_hresult() => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+// 用户名和域：本地账户使用 "."，DOMAIN\user 拆开，UPN（user@domain、微软账户邮箱）不需要域
+fn split_account(user_name: &str, account_type: &str) -> (String, Option<String>) {
+    if account_type == "local" {
+        return (user_name.to_string(), Some(String::from(".")));
+    }
+    match user_name.split_once('\\') {
+        Some((domain, user)) => (user.to_string(), Some(domain.to_string())),
+        None => (user_name.to_string(), None),
+    }
+}
+
+fn classify(code: WIN32_ERROR) -> CredentialStatus {
+    match code {
+        ERROR_LOGON_FAILURE => CredentialStatus::BadPassword,
+        ERROR_PASSWORD_EXPIRED | ERROR_PASSWORD_MUST_CHANGE => CredentialStatus::Expired,
+        ERROR_ACCOUNT_LOCKED_OUT => CredentialStatus::LockedOut,
+        ERROR_ACCOUNT_DISABLED | ERROR_ACCOUNT_EXPIRED => CredentialStatus::Disabled,
+        // 密码验证通过后才会检查登录类型
+        ERROR_LOGON_TYPE_NOT_GRANTED => CredentialStatus::Valid,
+        // 空密码账户禁止网络登录（ERROR_ACCOUNT_RESTRICTION）、无法连接域控制器等
+        _ => CredentialStatus::Unverifiable,
+    }
+}
+
+// 验证一个账户，返回状态和系统错误码
+fn check_logon(user_name: &str, account_type: &str, password: &str) -> (CredentialStatus, Option<u32>) {
+    let (user, domain) = split_account(user_name, account_type);
+    let user = to_wide(&user);
+    let domain = domain.map(|d| to_wide(&d));
+    let mut password = to_wide(password);
+    let mut token = HANDLE::default();
+    let result = unsafe {
+        LogonUserW(
+            PCWSTR(user.as_ptr()),
+            domain.as_ref().map_or(PCWSTR::null(), |d| PCWSTR(d.as_ptr())),
+            PCWSTR(password.as_ptr()),
+            LOGON32_LOGON_NETWORK,
+            LOGON32_PROVIDER_DEFAULT,
+            &mut token,
+        )
+    };
+    // 尽快清除内存中的密码副本
+    password.zeroize();
+
+    match result {
+        Ok(()) => {
+            let _ = unsafe { CloseHandle(token) };
+            (CredentialStatus::Valid, None)
+        }
+        Err(e) => {
+            let code = WIN32_ERROR::from_error(&e).unwrap_or_default();
+            (classify(code), Some(code.0))
+        }
+    }
+}
+
+// 读取已保存的账户，相同账户只验证一次；锁定和已停用的面容不参与解锁，不需要验证
+fn load_accounts(face_id: Option<i64>) -> Result<Vec<StoredAccount>, String> {
+    let conn = get_conn()?;
+    let mut stmt = conn
+        .prepare("SELECT id, user_name, account_type, json_data FROM faces;")
+        .map_err(|e| format!("准备查询面容数据失败：{:?}", e))?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get::<&str, i64>("id")?,
+                row.get::<&str, String>("user_name")?,
+                row.get::<&str, String>("account_type")?,
+                row.get::<&str, String>("json_data")?,
+            ))
+        })
+        .map_err(|e| format!("查询面容数据失败：{:?}", e))?;
+
+    let mut accounts: Vec<StoredAccount> = Vec::new();
+    for (id, user_name, account_type, json_data) in rows.flatten() {
+        if face_id.is_some_and(|f| f != id) {
+            continue;
+        }
+        let active = serde_json::from_str::<FaceExtraData>(&json_data)
+            .map(|extra| !extra.lock && !extra.expired)
+            .unwrap_or(false);
+        if face_id.is_none() && !active {
+            continue;
+        }
+        match accounts.iter_mut().find(|a| a.user_name == user_name && a.account_type == account_type) {
+            Some(account) => account.face_ids.push(id),
+            None => accounts.push(StoredAccount { face_ids: vec![id], user_name, account_type }),
+        }
+    }
+    Ok(accounts)
+}
+
+fn validate_accounts(accounts: &[StoredAccount]) -> Vec<(CredentialStatus, serde_json::Value)> {
+    accounts
+        .iter()
+        .map(|account| {
+            let (status, code) = match read_vault_password(&account.user_name) {
+                Ok(Some(mut password)) => {
+                    let result = check_logon(&account.user_name, &account.account_type, &password);
+                    password.zeroize();
+                    result
+                }
+                Ok(None) => (CredentialStatus::Missing, None),
+                Err(e) => (CredentialStatus::Unverifiable, WIN32_ERROR::from_error(&e).map(|c| c.0)),
+            };
+            if status != CredentialStatus::Valid {
+                warn!("账户 {} 的密码验证结果：{:?}（错误码 {:?}）", account.user_name, status, code);
+            }
+            (
+                status,
+                json!({
+                    "face_ids": account.face_ids,
+                    "user_name": account.user_name,
+                    "account_type": account.account_type,
+                    "status": status,
+                    "error_code": code,
+                }),
+            )
+        })
+        .collect()
+}
+
+// 验证已保存的密码，未指定面容时验证所有参与解锁的账户
+#[tauri::command]
+pub fn validate_stored_credential(face_id: Option<i64>) -> Result<CustomResult, CustomResult> {
+    ensure_ready()?;
+    let accounts = load_accounts(face_id).map_err(|e| CustomResult::error(Some(e), None))?;
+    if face_id.is_some() && accounts.is_empty() {
+        return Err(CustomResult::error(Some(format!("找不到面容 {}", face_id.unwrap_or_default())), None));
+    }
+    let results: Vec<_> = validate_accounts(&accounts).into_iter().map(|(_, item)| item).collect();
+    Ok(CustomResult::success(None, Some(json!({"results": results}))))
+}
+
+// 把账户密码保存到 Windows 凭据管理器，之后解锁时不传密码即可从中读取
+#[tauri::command]
+pub fn store_credentials(user_name: String, mut password: String) -> Result<CustomResult, CustomResult> {
+    ensure_ready()?;
+    if user_name.trim().is_empty() {
+        password.zeroize();
+        return Err(CustomResult::error(Some(String::from("用户名不能为空")), None));
+    }
+    if password.is_empty() {
+        return Err(CustomResult::error(Some(String::from("密码不能为空")), None));
+    }
+    let result = write_vault(&user_name, &password);
+    password.zeroize();
+    result.map_err(|e| CustomResult::error(Some(format!("保存凭据失败：{:?}", e)), None))?;
+    info!("已将账户 {} 的凭据保存到凭据管理器", user_name);
+    Ok(CustomResult::success(None, None))
+}
+
+// 删除凭据管理器中保存的账户密码
+#[tauri::command]
+pub fn clear_credentials(user_name: String) -> Result<CustomResult, CustomResult> {
+    ensure_ready()?;
+    let removed = delete_vault(&user_name)
+        .map_err(|e| CustomResult::error(Some(format!("删除凭据失败：{:?}", e)), None))?;
+    if removed {
+        info!("已删除账户 {} 在凭据管理器中的凭据", user_name);
+    }
+    Ok(CustomResult::success(None, Some(json!({"removed": removed}))))
+}
+
+// 把数据库中明文保存的密码移到凭据管理器，移动成功后清空数据库中的密码，返回移动的数量
+// 单个账户失败时继续处理其他账户，最后整体报告失败，下次启动重试
+pub fn move_passwords_to_vault(conn: &Connection) -> Result<usize, String> {
+    let mut stmt = conn
+        .prepare("SELECT id, user_name, user_pwd FROM faces WHERE user_pwd != '';")
+        .map_err(|e| format!("准备查询面容数据失败：{:?}", e))?;
+    let rows: Vec<(i64, String, String)> = stmt
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+        .map_err(|e| format!("查询面容数据失败：{:?}", e))?
+        .flatten()
+        .collect();
+    drop(stmt);
+
+    let mut moved = 0;
+    let mut errors = Vec::new();
+    for (id, user_name, mut password) in rows {
+        let result = write_vault(&user_name, &password);
+        password.zeroize();
+        if let Err(e) = result {
+            errors.push(format!("账户 {}：{:?}", user_name, e));
+            continue;
+        }
+        conn.execute("UPDATE faces SET user_pwd = '' WHERE id = ?1", [id])
+            .map_err(|e| format!("清除面容 {} 的密码失败：{:?}", id, e))?;
+        moved += 1;
+    }
+    if !errors.is_empty() {
+        return Err(format!("已移动 {} 个，{} 个账户保存失败：{}", moved, errors.len(), errors.join("；")));
+    }
+    Ok(moved)
+}
+
+// 自动验证：只在启用期间执行，受最短间隔限制；发现密码失效时停用面容解锁并通知用户
+fn auto_validate(reason: &str, min_interval: u64) {
+    let Ok(_guard) = AUTO_CHECK_LOCK.try_lock() else {
+        return;
+    };
+    if IS_RUN.load(Ordering::SeqCst) || !is_armed() {
+        return;
+    }
+    let Ok(conn) = get_conn() else {
+        return;
+    };
+    let last = query_option(&conn, LAST_CHECK_OPTION)
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(0);
+    drop(conn);
+    let now = now_secs();
+    if now.saturating_sub(last) < min_interval {
+        return;
+    }
+    // 先记录时间，验证过程中出错也不会马上重试
+    if let Err(e) = db_writer::write(move |tx| save_option(tx, LAST_CHECK_OPTION, &now.to_string())) {
+        warn!("记录密码验证时间失败：{}", e);
+        return;
+    }
+
+    let accounts = match load_accounts(None) {
+        Ok(accounts) => accounts,
+        Err(e) => {
+            warn!("读取已保存的账户失败：{}", e);
+            return;
+        }
+    };
+    let invalid: Vec<_> = validate_accounts(&accounts)
+        .into_iter()
+        .filter(|(status, _)| status.is_invalid())
+        .map(|(_, item)| item)
+        .collect();
+    info!("自动验证已保存的密码（{}）：{} 个账户，{} 个失效", reason, accounts.len(), invalid.len());
+    if invalid.is_empty() {
+        return;
+    }
+
+    // 继续使用旧密码解锁只会失败，还可能导致账户被锁定
+    if let Err(e) = set_unlock_armed(false) {
+        warn!("停用面容解锁失败：{:?}", e.msg);
+    }
+    emit_event("credential-invalid", json!({"reason": reason, "accounts": invalid}));
+}
+
+// 睡眠唤醒后验证一次
+pub fn validate_after_resume() {
+    std::thread::spawn(|| {
+        sleep(RESUME_DELAY);
+        auto_validate("resume", MIN_AUTO_INTERVAL);
+    });
+}
+
+// 启用期间每天验证一次
+pub fn spawn_credential_monitor() {
+    std::thread::spawn(|| {
+        sleep(FIRST_CHECK_DELAY);
+        loop {
+            auto_validate("daily", DAILY_INTERVAL);
+            sleep(CHECK_INTERVAL);
+        }
+    });
+}
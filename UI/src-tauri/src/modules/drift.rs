@@ -0,0 +1,200 @@
+use r2d2_sqlite::rusqlite::{self, Connection};
+use serde_json::{json, Value};
+use tauri_plugin_log::log::info;
+
+use crate::{
+    modules::options::{get_conn, query_option},
+    utils::{
+        api::{emit_event, ensure_ready},
+        custom_result::CustomResult,
+        db_writer,
+    },
+};
+
+// 近期/长期滑动平均的权重
+const RECENT_ALPHA: f64 = 0.3;
+const LONG_ALPHA: f64 = 0.02;
+// 成功匹配次数少于该值时不做判断，避免样本太少误报
+const MIN_SAMPLES: i64 = 10;
+// 近期平均比长期平均低多少时提示重新录入
+const DEFAULT_DRIFT: f64 = 0.08;
+// 近期平均高出阈值不足多少时提示重新录入
+const DEFAULT_MARGIN: f64 = 0.05;
+
+// 单个面容的分数统计
+struct ScoreStats {
+    recent_avg: f64,
+    long_avg: f64,
+    sample_count: i64,
+    is_snoozed: bool,
+}
+
+// 记录一次真实解锁成功的匹配分数，并在分数明显下降时提示重新录入
+// 只在锁屏自动解锁成功后调用，失败和界面上的测试验证不计入
+pub fn record_match_score(
+    conn: &Connection,
+    face_token: &str,
+    score: f64,
+    threshold: f64,
+) -> Result<(), String> {
+    let stats = match load_stats(conn, face_token)? {
+        Some(old) => ScoreStats {
+            recent_avg: old.recent_avg + RECENT_ALPHA * (score - old.recent_avg),
+            long_avg: old.long_avg + LONG_ALPHA * (score - old.long_avg),
+            sample_count: old.sample_count + 1,
+            is_snoozed: old.is_snoozed,
+        },
+        None => ScoreStats {
+            recent_avg: score,
+            long_avg: score,
+            sample_count: 1,
+            is_snoozed: false,
+        },
+    };
+
+    conn.execute(
+        "INSERT INTO face_score_stats (face_token, recent_avg, long_avg, sample_count) VALUES (?1, ?2, ?3, ?4) \
+         ON CONFLICT(face_token) DO UPDATE SET recent_avg = excluded.recent_avg, long_avg = excluded.long_avg, \
+         sample_count = excluded.sample_count, lastTime = datetime('now', 'localtime')",
+        rusqlite::params![face_token, stats.recent_avg, stats.long_avg, stats.sample_count],
+    )
+    .map_err(|e| format!("保存匹配分数统计失败：{:?}", e))?;
+
+    if stats.is_snoozed {
+        return Ok(());
+    }
+    if let Some(reason) = evaluate(conn, &stats, threshold) {
+        info!("面容 {} 建议重新录入: {}", face_token, reason);
+        emit_event(
+            "reenrollment-recommended",
+            json!({"file_name": face_token, "reason": reason}),
+        );
+    }
+    Ok(())
+}
+
+// 判断是否需要重新录入，返回原因
+fn evaluate(conn: &Connection, stats: &ScoreStats, threshold: f64) -> Option<String> {
+    if stats.sample_count < MIN_SAMPLES {
+        return None;
+    }
+    let drift: f64 = query_option(conn, "reenrollDrift")
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_DRIFT);
+    let margin: f64 = query_option(conn, "reenrollMargin")
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_MARGIN);
+
+    if stats.long_avg - stats.recent_avg >= drift {
+        Some(format!(
+            "近期平均分 {:.3} 比长期平均分 {:.3} 下降明显",
+            stats.recent_avg, stats.long_avg
+        ))
+    } else if stats.recent_avg < threshold + margin {
+        Some(format!(
+            "近期平均分 {:.3} 已接近阈值 {:.3}",
+            stats.recent_avg, threshold
+        ))
+    } else {
+        None
+    }
+}
+
+fn load_stats(conn: &Connection, face_token: &str) -> Result<Option<ScoreStats>, String> {
+    let result = conn.query_row(
+        "SELECT recent_avg, long_avg, sample_count, \
+         (snooze_until IS NOT NULL AND snooze_until > datetime('now', 'localtime')) AS is_snoozed \
+         FROM face_score_stats WHERE face_token = ?1;",
+        [face_token],
+        |row| {
+            Ok(ScoreStats {
+                recent_avg: row.get::<&str, f64>("recent_avg")?,
+                long_avg: row.get::<&str, f64>("long_avg")?,
+                sample_count: row.get::<&str, i64>("sample_count")?,
+                is_snoozed: row.get::<&str, bool>("is_snoozed")?,
+            })
+        },
+    );
+    match result {
+        Ok(stats) => Ok(Some(stats)),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(format!("查询匹配分数统计失败：{:?}", e)),
+    }
+}
+
+// 所有面容的重新录入建议
+pub fn reenrollment_status(conn: &Connection) -> Result<Vec<Value>, String> {
+    let mut stmt = conn
+        .prepare("SELECT face_token, json_data FROM faces;")
+        .map_err(|e| format!("准备查询面容数据失败：{:?}", e))?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get::<&str, String>("face_token")?,
+                row.get::<&str, String>("json_data")?,
+            ))
+        })
+        .map_err(|e| format!("查询面容数据失败：{:?}", e))?;
+
+    let mut list = Vec::new();
+    for (face_token, json_data) in rows.flatten() {
+        let threshold = serde_json::from_str::<Value>(&json_data)
+            .ok()
+            .and_then(|v| v["threshold"].as_f64())
+            .unwrap_or(0.0)
+            / 100.0;
+        let Some(stats) = load_stats(conn, &face_token)? else {
+            continue;
+        };
+        let reason = if stats.is_snoozed {
+            None
+        } else {
+            evaluate(conn, &stats, threshold)
+        };
+        list.push(json!({
+            "file_name": face_token,
+            "recent_avg": stats.recent_avg,
+            "long_avg": stats.long_avg,
+            "sample_count": stats.sample_count,
+            "snoozed": stats.is_snoozed,
+            "recommended": reason.is_some(),
+            "reason": reason,
+        }));
+    }
+    Ok(list)
+}
+
+// 获取重新录入建议
+#[tauri::command]
+pub fn get_reenrollment_status() -> Result<CustomResult, CustomResult> {
+    ensure_ready()?;
+    let conn = get_conn().map_err(|e| CustomResult::error(Some(e), None))?;
+    let list = reenrollment_status(&conn).map_err(|e| CustomResult::error(Some(e), None))?;
+    Ok(CustomResult::success(None, Some(json!(list))))
+}
+
+// 暂停某个面容的重新录入提醒
+#[tauri::command]
+pub fn snooze_reenrollment_reminder(
+    file_name: String,
+    days: u32,
+) -> Result<CustomResult, CustomResult> {
+    ensure_ready()?;
+    let token = file_name.clone();
+    let changed = db_writer::write(move |tx| {
+        tx.execute(
+            "UPDATE face_score_stats SET snooze_until = datetime('now', 'localtime', ?1) WHERE face_token = ?2",
+            rusqlite::params![format!("+{} days", days), token],
+        )
+        .map_err(|e| format!("暂停提醒失败：{:?}", e))
+    })
+    .map_err(|e| CustomResult::error(Some(e), None))?;
+
+    if changed == 0 {
+        return Err(CustomResult::error(
+            Some(format!("面容 {} 还没有匹配记录", file_name)),
+            None,
+        ));
+    }
+    Ok(CustomResult::success(None, None))
+}
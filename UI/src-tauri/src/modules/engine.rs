@@ -0,0 +1,427 @@
+// 自动解锁引擎的状态机
+// 锁屏解锁流程中的各个环节（预热、识别、冷却）都通过 fire 提交事件，由 transition 统一决定下一个状态
+// 约定：
+//   - 摄像头只在 PreWarming、Attempting 状态下由引擎持有
+//   - 只有 Attempting 状态下才会发送凭据
+//   - 每次锁屏都以一个终止状态（Succeeded 或 Aborted）结束，解锁后回到空闲状态
+use std::{
+    collections::VecDeque,
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use lazy_static::lazy_static;
+use serde::Serialize;
+use serde_json::json;
+use tauri_plugin_log::log::info;
+
+use crate::utils::custom_result::CustomResult;
+
+// 内存中保留的状态转换记录条数
+const TRACE_CAPACITY: usize = 200;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AbortReason {
+    // 用户用其他方式解锁了
+    SessionUnlocked,
+    // 匹配失败次数达到上限
+    RetriesExhausted,
+    // 摄像头被遮挡
+    CameraObstructed,
+    // 锁屏期间停用了面容解锁
+    Disarmed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum EngineState {
+    // 面容解锁已停用
+    Disarmed,
+    // 已启用，没有进行中的识别
+    Armed,
+    // 熄屏/屏保时提前打开了摄像头
+    PreWarming,
+    // 正在识别，attempt_no 为本次锁屏的第几次识别
+    Attempting { attempt_no: u32 },
+    // 识别失败，until（Unix 毫秒）之前不允许重试
+    CoolingDown { until: u64 },
+    // 本次锁屏已发送凭据
+    Succeeded,
+    // 本次锁屏不再识别
+    Aborted { reason: AbortReason },
+}
+
+impl EngineState {
+    // 终止状态，一次锁屏只会进入一次
+    pub fn is_terminal(&self) -> bool {
+        matches!(self, EngineState::Succeeded | EngineState::Aborted { .. })
+    }
+
+    // 引擎是否可以持有摄像头
+    pub fn holds_camera(&self) -> bool {
+        matches!(self, EngineState::PreWarming | EngineState::Attempting { .. })
+    }
+
+    fn idle(armed: bool) -> Self {
+        if armed {
+            EngineState::Armed
+        } else {
+            EngineState::Disarmed
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum EngineEvent {
+    SessionLocked,
+    SessionUnlocked,
+    // 启用/停用面容解锁
+    ArmedChanged { armed: bool },
+    PreWarmStarted,
+    // 预热超时或预热失败
+    PreWarmReleased,
+    AttemptStarted,
+    MatchSucceeded,
+    // 匹配失败，failures 为本次锁屏累计的失败次数
+    MatchFailed { failures: u32, until: u64 },
+    // 识别过程出错（打开摄像头失败等），不计入失败次数
+    AttemptErrored { until: u64 },
+    CameraObstructed,
+    // 系统即将睡眠
+    Suspended,
+}
+
+// 状态以外、决定转换结果的信息
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct EngineContext {
+    pub armed: bool,
+    pub session_locked: bool,
+    // 本次锁屏已经开始的识别次数
+    pub attempts: u32,
+    // 允许的最大失败次数
+    pub max_failures: u32,
+}
+
+// 状态转换，返回 None 表示当前状态下忽略该事件
+pub fn transition(state: EngineState, event: EngineEvent, ctx: &EngineContext) -> Option<EngineState> {
+    use EngineEvent as E;
+    use EngineState as S;
+    match (state, event) {
+        // 锁屏开始新的一轮；已预热的摄像头继续使用
+        (S::PreWarming, E::SessionLocked) => Some(S::PreWarming),
+        (S::Disarmed, E::SessionLocked) => Some(S::Disarmed),
+        (_, E::SessionLocked) => Some(S::idle(ctx.armed)),
+
+        // 解锁：已经终止的回到空闲，否则先以 SessionUnlocked 终止本次锁屏
+        (s, E::SessionUnlocked) if s.is_terminal() => Some(S::idle(ctx.armed)),
+        (_, E::SessionUnlocked) if ctx.session_locked => Some(S::Aborted { reason: AbortReason::SessionUnlocked }),
+        (_, E::SessionUnlocked) => Some(S::idle(ctx.armed)),
+
+        (S::Disarmed, E::ArmedChanged { armed: true }) => Some(S::Armed),
+        (s, E::ArmedChanged { armed: false }) if !s.is_terminal() && s != S::Disarmed => {
+            if ctx.session_locked {
+                Some(S::Aborted { reason: AbortReason::Disarmed })
+            } else {
+                Some(S::Disarmed)
+            }
+        }
+
+        // 预热只在未锁屏时开始
+        (S::Armed, E::PreWarmStarted) if !ctx.session_locked => Some(S::PreWarming),
+        (S::PreWarming, E::PreWarmReleased) => Some(S::Armed),
+
+        (S::Armed | S::PreWarming | S::CoolingDown { .. }, E::AttemptStarted) if ctx.session_locked => {
+            Some(S::Attempting { attempt_no: ctx.attempts + 1 })
+        }
+        (S::Attempting { .. }, E::MatchSucceeded) => Some(S::Succeeded),
+        (S::Attempting { .. }, E::MatchFailed { failures, .. }) if failures >= ctx.max_failures => {
+            Some(S::Aborted { reason: AbortReason::RetriesExhausted })
+        }
+        (S::Attempting { .. }, E::MatchFailed { until, .. } | E::AttemptErrored { until }) => {
+            Some(S::CoolingDown { until })
+        }
+        (S::Attempting { .. }, E::CameraObstructed) => Some(S::Aborted { reason: AbortReason::CameraObstructed }),
+
+        // 睡眠时释放摄像头，唤醒后仍处于锁屏时可以重新识别
+        (S::PreWarming | S::Attempting { .. } | S::CoolingDown { .. }, E::Suspended) => Some(S::Armed),
+
+        _ => None,
+    }
+}
+
+// 一条状态转换记录
+#[derive(Debug, Clone, Serialize)]
+pub struct TraceEntry {
+    // Unix 毫秒
+    pub timestamp: u64,
+    pub trigger: EngineEvent,
+    pub from: EngineState,
+    pub to: EngineState,
+    // false 表示当前状态下忽略了该事件
+    pub accepted: bool,
+}
+
+struct Engine {
+    state: EngineState,
+    ctx: EngineContext,
+    trace: VecDeque<TraceEntry>,
+}
+
+lazy_static! {
+    static ref ENGINE: Mutex<Engine> = Mutex::new(Engine::new(crate::proc::MAX_RETRY as u32));
+}
+
+pub fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+impl Engine {
+    fn new(max_failures: u32) -> Self {
+        Engine {
+            state: EngineState::Armed,
+            ctx: EngineContext {
+                armed: true,
+                session_locked: false,
+                attempts: 0,
+                max_failures,
+            },
+            trace: VecDeque::with_capacity(TRACE_CAPACITY),
+        }
+    }
+
+    fn fire(&mut self, event: EngineEvent) -> Option<EngineState> {
+        let next = self.apply(event);
+        if event == EngineEvent::SessionUnlocked {
+            // 以 SessionUnlocked 终止本次锁屏后回到空闲
+            if next.is_some_and(|s| s.is_terminal()) {
+                self.ctx.session_locked = false;
+                return self.apply(EngineEvent::SessionUnlocked);
+            }
+            self.ctx.session_locked = false;
+        }
+        next
+    }
+
+    fn apply(&mut self, event: EngineEvent) -> Option<EngineState> {
+        let from = self.state;
+        let next = transition(from, event, &self.ctx);
+
+        // 更新上下文
+        match event {
+            EngineEvent::SessionLocked => {
+                self.ctx.session_locked = true;
+                self.ctx.attempts = 0;
+            }
+            EngineEvent::ArmedChanged { armed } => self.ctx.armed = armed,
+            EngineEvent::AttemptStarted if next.is_some() => self.ctx.attempts += 1,
+            _ => {}
+        }
+
+        if self.trace.len() >= TRACE_CAPACITY {
+            self.trace.pop_front();
+        }
+        self.trace.push_back(TraceEntry {
+            timestamp: now_millis(),
+            trigger: event,
+            from,
+            to: next.unwrap_or(from),
+            accepted: next.is_some(),
+        });
+        match next {
+            Some(to) => {
+                if to != from {
+                    info!("解锁引擎 {:?} -> {:?}（{:?}）", from, to, event);
+                }
+                self.state = to;
+            }
+            None => info!("解锁引擎在 {:?} 状态下忽略了事件 {:?}", from, event),
+        }
+        next
+    }
+}
+
+// 提交一个事件，返回转换后的状态；事件被忽略时返回 None
+pub fn fire(event: EngineEvent) -> Option<EngineState> {
+    ENGINE.lock().ok()?.fire(event)
+}
+
+// 当前状态
+pub fn current_state() -> EngineState {
+    ENGINE.lock().map(|e| e.state).unwrap_or(EngineState::Disarmed)
+}
+
+// 与设置中的启用状态同步，状态不同时才提交事件
+pub fn sync_armed(armed: bool) {
+    let differs = ENGINE.lock().map(|e| e.ctx.armed != armed).unwrap_or(false);
+    if differs {
+        fire(EngineEvent::ArmedChanged { armed });
+    }
+}
+
+// 发送凭据前检查，只有正在识别时才允许
+pub fn ensure_attempting() -> Result<(), String> {
+    match current_state() {
+        EngineState::Attempting { .. } => Ok(()),
+        state => Err(format!("解锁引擎处于 {:?} 状态，拒绝发送凭据", state)),
+    }
+}
+
+// 获取解锁引擎的当前状态和最近的状态转换记录
+#[tauri::command]
+pub fn get_unlock_engine_trace() -> Result<CustomResult, CustomResult> {
+    let engine = ENGINE
+        .lock()
+        .map_err(|e| CustomResult::error(Some(format!("获取解锁引擎状态失败 {}", e)), None))?;
+    Ok(CustomResult::success(
+        None,
+        Some(json!({
+            "state": engine.state,
+            "context": engine.ctx,
+            "trace": engine.trace,
+        })),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 固定种子的伪随机数（xorshift），失败时可以用同一种子复现
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, n: u64) -> u64 {
+            self.next() % n
+        }
+    }
+
+    fn random_event(rng: &mut Rng) -> EngineEvent {
+        let until = rng.below(1_000_000);
+        match rng.below(11) {
+            0 => EngineEvent::SessionLocked,
+            1 => EngineEvent::SessionUnlocked,
+            2 => EngineEvent::ArmedChanged { armed: rng.below(2) == 0 },
+            3 => EngineEvent::PreWarmStarted,
+            4 => EngineEvent::PreWarmReleased,
+            5 => EngineEvent::AttemptStarted,
+            6 => EngineEvent::MatchSucceeded,
+            7 => EngineEvent::MatchFailed { failures: rng.below(6) as u32, until },
+            8 => EngineEvent::AttemptErrored { until },
+            9 => EngineEvent::CameraObstructed,
+            _ => EngineEvent::Suspended,
+        }
+    }
+
+    // 对 fire 中每一次状态转换检查约定
+    fn check_step(engine: &Engine, entry: &TraceEntry, terminals: &mut u32, seed: u64) {
+        let (from, to, event) = (entry.from, entry.to, entry.trigger);
+        if !entry.accepted {
+            return;
+        }
+        // 摄像头只在 PreWarming、Attempting 状态下持有，只有预热和开始识别才会打开摄像头
+        if to.holds_camera() && !from.holds_camera() {
+            assert!(
+                matches!(event, EngineEvent::PreWarmStarted | EngineEvent::AttemptStarted),
+                "seed {}: {:?} -> {:?} 由 {:?} 打开了摄像头",
+                seed, from, to, event
+            );
+        }
+        if matches!(to, EngineState::Attempting { .. }) {
+            assert!(engine.ctx.session_locked, "seed {}: 未锁屏时进入 {:?}", seed, to);
+        }
+        // 只有 Attempting 状态下才会发送凭据
+        if to == EngineState::Succeeded && from != EngineState::Succeeded {
+            assert!(
+                matches!(from, EngineState::Attempting { .. }) && event == EngineEvent::MatchSucceeded,
+                "seed {}: {:?} -> Succeeded 由 {:?} 触发",
+                seed, from, event
+            );
+        }
+        if to.is_terminal() && !from.is_terminal() {
+            *terminals += 1;
+            assert_eq!(*terminals, 1, "seed {}: 一次锁屏进入了多个终止状态，最后为 {:?}", seed, to);
+        }
+    }
+
+    #[test]
+    fn random_event_sequences_keep_invariants() {
+        for seed in 1..=500u64 {
+            let mut rng = Rng(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15));
+            let mut engine = Engine::new(3);
+            let mut terminals = 0;
+            // 每个事件最多产生两条记录，保持在记录容量以内
+            for _ in 0..TRACE_CAPACITY / 2 {
+                let event = random_event(&mut rng);
+                let was_locked = engine.ctx.session_locked;
+                if event == EngineEvent::SessionLocked {
+                    terminals = 0;
+                }
+                let start = engine.trace.len();
+                engine.fire(event);
+                for entry in engine.trace.range(start..) {
+                    check_step(&engine, entry, &mut terminals, seed);
+                }
+
+                if event == EngineEvent::SessionUnlocked {
+                    // 每次锁屏都以一个终止状态结束，解锁后回到空闲并释放摄像头
+                    if was_locked {
+                        assert_eq!(terminals, 1, "seed {}: 本次锁屏进入了 {} 个终止状态", seed, terminals);
+                    }
+                    assert!(
+                        matches!(engine.state, EngineState::Armed | EngineState::Disarmed),
+                        "seed {}: 解锁后停留在 {:?}",
+                        seed, engine.state
+                    );
+                    terminals = 0;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn credentials_are_only_allowed_while_attempting() {
+        let ctx = EngineContext { armed: true, session_locked: true, attempts: 0, max_failures: 3 };
+        let states = [
+            EngineState::Disarmed,
+            EngineState::Armed,
+            EngineState::PreWarming,
+            EngineState::Attempting { attempt_no: 1 },
+            EngineState::CoolingDown { until: 0 },
+            EngineState::Succeeded,
+            EngineState::Aborted { reason: AbortReason::RetriesExhausted },
+        ];
+        for state in states {
+            let next = transition(state, EngineEvent::MatchSucceeded, &ctx);
+            assert_eq!(next.is_some(), matches!(state, EngineState::Attempting { .. }), "{:?}", state);
+        }
+    }
+
+    #[test]
+    fn lock_cycle_ends_in_one_terminal_state() {
+        let mut engine = Engine::new(2);
+        engine.fire(EngineEvent::SessionLocked);
+        engine.fire(EngineEvent::AttemptStarted);
+        engine.fire(EngineEvent::MatchFailed { failures: 1, until: 10 });
+        engine.fire(EngineEvent::AttemptStarted);
+        assert_eq!(engine.state, EngineState::Attempting { attempt_no: 2 });
+        engine.fire(EngineEvent::MatchFailed { failures: 2, until: 20 });
+        assert_eq!(engine.state, EngineState::Aborted { reason: AbortReason::RetriesExhausted });
+        // 终止后不再开始识别
+        assert_eq!(engine.fire(EngineEvent::AttemptStarted), None);
+        engine.fire(EngineEvent::SessionUnlocked);
+        assert_eq!(engine.state, EngineState::Armed);
+        assert!(!engine.ctx.session_locked);
+    }
+}
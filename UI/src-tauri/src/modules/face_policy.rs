@@ -0,0 +1,360 @@
+// 画面中有多张人脸时的匹配策略
+// verify_face、重新启用验证和锁屏自动解锁都通过 match_frame 匹配，保证行为一致
+use opencv::{
+    core::{Mat, Size},
+    objdetect::{FaceDetectorYN, FaceRecognizerSF, FaceRecognizerSF_DisType},
+    prelude::*,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{modules::template::TemplateKey, utils::api::ensure_models_loaded, APP_STATE};
+
+// 检测到旁观者时的错误前缀，调用方据此区分
+pub const BYSTANDER_DETECTED: &str = "BystanderDetected";
+// 其他人脸面积达到主人脸的这个比例时，reject_if_multiple 模式拒绝本次验证
+// 远处路过的小人脸不计入
+const BYSTANDER_MIN_FRACTION: f32 = 0.25;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MultiFacePolicy {
+    // 所有人脸都与参考面容比较，取最高分，最方便但最不安全
+    BestMatch,
+    // 只比较最大（面积相同时最靠近中心）的人脸
+    PrimaryFaceOnly,
+    // 存在其他足够大的人脸时直接拒绝
+    RejectIfMultiple,
+}
+
+impl MultiFacePolicy {
+    // 从设置项 multiFacePolicy 解析，未设置或无法识别时使用 primary_face_only
+    pub fn from_option(value: Option<String>) -> Self {
+        match value.as_deref() {
+            Some("best_match") => MultiFacePolicy::BestMatch,
+            Some("reject_if_multiple") => MultiFacePolicy::RejectIfMultiple,
+            _ => MultiFacePolicy::PrimaryFaceOnly,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MultiFacePolicy::BestMatch => "best_match",
+            MultiFacePolicy::PrimaryFaceOnly => "primary_face_only",
+            MultiFacePolicy::RejectIfMultiple => "reject_if_multiple",
+        }
+    }
+}
+
+// 一致性验证使用的距离：余弦相似度越大越相似，L2 距离越小越相似
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchMetric {
+    #[default]
+    Cosine,
+    L2,
+    // 两个条件都满足才算通过
+    Both,
+}
+
+impl MatchMetric {
+    // 按所选距离判断是否通过，余弦分数不低于阈值、L2 距离不超过阈值分别算通过
+    pub fn passed(&self, cosine_passed: bool, l2_passed: bool) -> bool {
+        match self {
+            MatchMetric::Cosine => cosine_passed,
+            MatchMetric::L2 => l2_passed,
+            MatchMetric::Both => cosine_passed && l2_passed,
+        }
+    }
+}
+
+// FaceRecognizerSF 计算两种距离前都会先把特征归一化，此时 L2 = sqrt(2 - 2 * cos)
+// 由余弦分数换算，不需要重新提取特征，分数最高的样本也是 L2 距离最小的样本
+pub fn l2_from_cosine(cosine: f64) -> f64 {
+    (2.0 - 2.0 * cosine).max(0.0).sqrt()
+}
+
+// 说明模式下最多返回的样本分数个数
+const EXPLAIN_TOP_K: usize = 5;
+
+// 单个样本的分数
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct SampleScore {
+    pub index: usize,
+    pub score: f64,
+}
+
+// 一次匹配的结果
+#[derive(Debug, Clone, Serialize)]
+pub struct FaceMatch {
+    pub score: f64,
+    // 使用的人脸在检测结果中的序号
+    pub face_index: usize,
+    pub face_count: usize,
+    pub policy: MultiFacePolicy,
+    // 分数最高的参考样本序号
+    pub sample_index: usize,
+    // 说明模式下各样本的分数（从高到低，最多 EXPLAIN_TOP_K 个），平时为 None
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sample_scores: Option<Vec<SampleScore>>,
+}
+
+// 检测图片中的所有人脸，每行一张人脸：x, y, w, h, 5 个关键点, 分数
+pub fn detect_faces(
+    detector: &mut opencv::core::Ptr<FaceDetectorYN>,
+    img: &Mat,
+    face_detection_threshold: f32,
+) -> Result<Mat, String> {
+    let mut faces = Mat::default();
+    detector
+        .set_input_size(img.size().map_err(|e| format!("获取Mat尺寸失败: {}", e))?)
+        .map_err(|e| format!("设置输入尺寸失败: {}", e))?;
+    detector
+        .set_score_threshold(face_detection_threshold)
+        .map_err(|e| format!("设置分数阈值失败: {}", e))?;
+    detector
+        .detect(img, &mut faces)
+        .map_err(|e| format!("OpenCV 检测失败: {}", e))?;
+    if faces.rows() < 1 {
+        return Err("未检测到人脸".into());
+    }
+    Ok(faces)
+}
+
+// 对齐裁剪并提取一张人脸的特征
+pub fn extract_feature(
+    recognizer: &mut opencv::core::Ptr<FaceRecognizerSF>,
+    img: &Mat,
+    faces: &Mat,
+    index: usize,
+) -> Result<Mat, String> {
+    extract_feature_with_crop(recognizer, img, faces, index).map(|(feature, _)| feature)
+}
+
+// 提取特征，同时返回对齐裁剪后的人脸图片
+pub fn extract_feature_with_crop(
+    recognizer: &mut opencv::core::Ptr<FaceRecognizerSF>,
+    img: &Mat,
+    faces: &Mat,
+    index: usize,
+) -> Result<(Mat, Mat), String> {
+    let face = faces
+        .row(index as i32)
+        .map_err(|e| format!("读取人脸位置失败: {}", e))?;
+    let mut aligned = Mat::default();
+    let mut feature = Mat::default();
+    // 人脸对齐与裁剪
+    recognizer
+        .align_crop(img, &face, &mut aligned)
+        .map_err(|e| format!("人脸对齐失败: {}", e))?;
+    // 提取特征
+    recognizer
+        .feature(&aligned, &mut feature)
+        .map_err(|e| format!("特征提取失败: {}", e))?;
+    Ok((feature.clone(), aligned))
+}
+
+// 人脸面积
+fn face_area(faces: &Mat, index: usize) -> f32 {
+    let w = faces.at_2d::<f32>(index as i32, 2).copied().unwrap_or(0.0);
+    let h = faces.at_2d::<f32>(index as i32, 3).copied().unwrap_or(0.0);
+    w.max(0.0) * h.max(0.0)
+}
+
+// 人脸中心到图片中心的距离（平方）
+fn distance_to_center(faces: &Mat, index: usize, size: Size) -> f32 {
+    let value = |col| faces.at_2d::<f32>(index as i32, col).copied().unwrap_or(0.0);
+    let cx = value(0) + value(2) / 2.0 - size.width as f32 / 2.0;
+    let cy = value(1) + value(3) / 2.0 - size.height as f32 / 2.0;
+    cx * cx + cy * cy
+}
+
+// 主人脸：面积最大，面积相同时最靠近中心
+pub fn primary_face(faces: &Mat, size: Size) -> usize {
+    (0..faces.rows().max(0) as usize)
+        .max_by(|&a, &b| {
+            face_area(faces, a)
+                .total_cmp(&face_area(faces, b))
+                .then_with(|| distance_to_center(faces, b, size).total_cmp(&distance_to_center(faces, a, size)))
+        })
+        .unwrap_or(0)
+}
+
+// 按策略确定需要比较的人脸
+fn candidate_faces(faces: &Mat, size: Size, policy: MultiFacePolicy) -> Result<Vec<usize>, String> {
+    let count = faces.rows().max(0) as usize;
+    let primary = primary_face(faces, size);
+    match policy {
+        MultiFacePolicy::BestMatch => Ok((0..count).collect()),
+        MultiFacePolicy::PrimaryFaceOnly => Ok(vec![primary]),
+        MultiFacePolicy::RejectIfMultiple => {
+            let min_area = face_area(faces, primary) * BYSTANDER_MIN_FRACTION;
+            let bystanders = (0..count)
+                .filter(|&i| i != primary && face_area(faces, i) >= min_area)
+                .count();
+            if bystanders > 0 {
+                return Err(format!(
+                    "{}：画面中还有 {} 张其他人脸，已拒绝本次验证",
+                    BYSTANDER_DETECTED, bystanders
+                ));
+            }
+            Ok(vec![primary])
+        }
+    }
+}
+
+// 按策略把画面中的人脸与参考面容的所有样本匹配，取分数最高的样本
+// explain 为 false 时只记录最高分的样本，不保存每个样本的分数，避免拖慢解锁
+// 分数为 NaN 时立即返回，由调用方按失败处理
+// 参考面容启用了模板保护时，probe_key 为对应的密钥，摄像头特征做同样的变换后再比较
+pub fn match_frame(
+    img: &Mat,
+    references: &[Mat],
+    probe_key: Option<&TemplateKey>,
+    face_detection_threshold: f32,
+    policy: MultiFacePolicy,
+    explain: bool,
+) -> Result<FaceMatch, String> {
+    let mut app_state = APP_STATE
+        .lock()
+        .map_err(|e| format!("获取app状态失败 {}", e))?;
+    let state = &mut *app_state;
+    ensure_models_loaded(state)?;
+    let Some(detector) = state.detector.as_mut() else {
+        return Err(String::from("人脸检测模型未初始化"));
+    };
+    let Some(recognizer) = state.recognizer.as_mut() else {
+        return Err(String::from("人脸识别模型未初始化"));
+    };
+    match_frame_with(&mut detector.inner, &mut recognizer.inner, img, references, probe_key, face_detection_threshold, policy, explain)
+}
+
+// 使用指定的模型匹配，调试回放使用单独加载的模型，不占用 APP_STATE
+pub fn match_frame_with(
+    detector: &mut opencv::core::Ptr<FaceDetectorYN>,
+    recognizer: &mut opencv::core::Ptr<FaceRecognizerSF>,
+    img: &Mat,
+    references: &[Mat],
+    probe_key: Option<&TemplateKey>,
+    face_detection_threshold: f32,
+    policy: MultiFacePolicy,
+    explain: bool,
+) -> Result<FaceMatch, String> {
+    let faces = detect_faces(detector, img, face_detection_threshold)?;
+    let size = img.size().map_err(|e| format!("获取Mat尺寸失败: {}", e))?;
+    let face_count = faces.rows().max(0) as usize;
+
+    let mut best: Option<FaceMatch> = None;
+    for face_index in candidate_faces(&faces, size, policy)? {
+        let feature = extract_feature(recognizer, img, &faces, face_index)?;
+        let feature = match probe_key {
+            Some(key) => key.apply_mat(&feature)?,
+            None => feature,
+        };
+        let mut current = FaceMatch {
+            score: f64::NEG_INFINITY,
+            face_index,
+            face_count,
+            policy,
+            sample_index: 0,
+            sample_scores: explain.then(Vec::new),
+        };
+        for (sample_index, reference) in references.iter().enumerate() {
+            let score = recognizer
+                .match_(reference, &feature, FaceRecognizerSF_DisType::FR_COSINE.into())
+                .map_err(|e| format!("特征匹配失败: {}", e))?;
+            if !score.is_finite() {
+                current.score = score;
+                current.sample_index = sample_index;
+                return Ok(current);
+            }
+            if let Some(scores) = current.sample_scores.as_mut() {
+                scores.push(SampleScore { index: sample_index, score });
+            }
+            if score > current.score {
+                current.score = score;
+                current.sample_index = sample_index;
+            }
+        }
+        if let Some(scores) = current.sample_scores.as_mut() {
+            scores.sort_by(|a, b| b.score.total_cmp(&a.score));
+            scores.truncate(EXPLAIN_TOP_K);
+        }
+        if best.as_ref().map_or(true, |b| current.score > b.score) {
+            best = Some(current);
+        }
+    }
+    // 没有样本时分数仍是初始值
+    best.filter(|b| b.score.is_finite())
+        .ok_or_else(|| String::from("参考面容中没有样本"))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    // 检测结果：每行 x, y, w, h, 5 个关键点, 置信度
+    pub(crate) fn detections(boxes: &[[f32; 4]]) -> Mat {
+        let rows: Vec<[f32; 15]> = boxes
+            .iter()
+            .map(|&[x, y, w, h]| {
+                let mut row = [0.0; 15];
+                row[..4].copy_from_slice(&[x, y, w, h]);
+                row[14] = 0.9;
+                row
+            })
+            .collect();
+        Mat::from_slice_2d(&rows).unwrap()
+    }
+
+    #[test]
+    fn primary_face_is_largest() {
+        let faces = detections(&[[300.0, 200.0, 40.0, 40.0], [0.0, 0.0, 120.0, 120.0]]);
+        assert_eq!(primary_face(&faces, Size::new(640, 480)), 1);
+    }
+
+    #[test]
+    fn primary_face_prefers_center_when_areas_match() {
+        // 面积相同，第二张人脸位于画面中心
+        let faces = detections(&[[0.0, 0.0, 80.0, 80.0], [280.0, 200.0, 80.0, 80.0]]);
+        assert_eq!(primary_face(&faces, Size::new(640, 480)), 1);
+        let faces = detections(&[[280.0, 200.0, 80.0, 80.0], [0.0, 0.0, 80.0, 80.0]]);
+        assert_eq!(primary_face(&faces, Size::new(640, 480)), 0);
+    }
+
+    #[test]
+    fn primary_face_defaults_to_first_without_detections() {
+        assert_eq!(primary_face(&Mat::default(), Size::new(640, 480)), 0);
+    }
+
+    // 主人脸在中间，左侧有一张足够大的旁观者，右上角有一张远处的小人脸
+    fn crowded_frame() -> Mat {
+        detections(&[[20.0, 40.0, 90.0, 90.0], [260.0, 160.0, 120.0, 120.0], [560.0, 10.0, 30.0, 30.0]])
+    }
+
+    #[test]
+    fn best_match_compares_every_face() {
+        let candidates = candidate_faces(&crowded_frame(), Size::new(640, 480), MultiFacePolicy::BestMatch).unwrap();
+        assert_eq!(candidates, vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn primary_face_only_ignores_bystanders() {
+        let policy = MultiFacePolicy::PrimaryFaceOnly;
+        assert_eq!(candidate_faces(&crowded_frame(), Size::new(640, 480), policy).unwrap(), vec![1]);
+        let single = detections(&[[100.0, 100.0, 80.0, 80.0]]);
+        assert_eq!(candidate_faces(&single, Size::new(640, 480), policy).unwrap(), vec![0]);
+    }
+
+    #[test]
+    fn reject_if_multiple_rejects_large_bystanders_only() {
+        let policy = MultiFacePolicy::RejectIfMultiple;
+        let err = candidate_faces(&crowded_frame(), Size::new(640, 480), policy).unwrap_err();
+        assert!(err.starts_with(BYSTANDER_DETECTED));
+        // 只有一张旁观者计入，远处的小人脸不计入
+        assert!(err.contains("1 张"));
+
+        // 只有远处的小人脸时按主人脸验证
+        let faces = detections(&[[260.0, 160.0, 120.0, 120.0], [560.0, 10.0, 30.0, 30.0]]);
+        assert_eq!(candidate_faces(&faces, Size::new(640, 480), policy).unwrap(), vec![0]);
+    }
+}
@@ -0,0 +1,278 @@
+// 面容搜索：按用户、名称、日期、重新录入建议、模型是否匹配筛选，支持排序和分页
+// 条目结构与 faces 表的行一致（不含密码），前端可以沿用列表的渲染
+// 前端数据库不可用时退回到面容数据库中的面容，在内存中筛选
+use std::{cmp::Ordering, collections::HashMap};
+
+use r2d2_sqlite::rusqlite::{self, Connection, OpenFlags};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::{
+    modules::{
+        drift::reenrollment_status,
+        face_watch::{cached_face_data, cached_faces},
+        faces::is_current_model,
+        options::get_conn,
+        template::probe_key,
+    },
+    utils::{api::ensure_ready, custom_result::CustomResult, storage::name_key},
+    ROOT_DIR,
+};
+
+// 当前识别模型输出的特征长度，样本长度不同说明是其他模型录入的
+pub const FEATURE_DIM: usize = 128;
+
+// 搜索使用的索引，由迁移创建
+pub const REGISTRATION_INDEXES: &str = "\
+    CREATE INDEX IF NOT EXISTS idx_faces_user_name ON faces(user_name COLLATE NOCASE);\
+    CREATE INDEX IF NOT EXISTS idx_faces_create_time ON faces(createTime);\
+    CREATE INDEX IF NOT EXISTS idx_unlock_log_face_verified ON unlock_log(face_id, is_unlock, lastTime);";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortKey {
+    // 别名，没有别名时使用用户名
+    Name,
+    Owner,
+    #[default]
+    Created,
+    LastVerified,
+}
+
+// 搜索条件，未设置的条件不筛选
+// 日期格式与数据库一致（2024-01-31 08:00:00），可以只写前缀，例如 2024-01-31，结束日期包含当天
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct RegistrationQuery {
+    // Windows 用户名，忽略大小写
+    pub owner: Option<String>,
+    // 账户类型：local / online
+    pub account_type: Option<String>,
+    // 别名或用户名包含的文字，按 Unicode 规则忽略大小写
+    pub name: Option<String>,
+    pub created_from: Option<String>,
+    pub created_to: Option<String>,
+    // 最后一次自动解锁成功的时间
+    pub verified_from: Option<String>,
+    pub verified_to: Option<String>,
+    pub needs_reenrollment: Option<bool>,
+    pub model_mismatch: Option<bool>,
+    pub sort: SortKey,
+    pub descending: bool,
+    pub limit: Option<usize>,
+    pub offset: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RegistrationEntry {
+    // 只有面容文件、没有数据库记录时为 None
+    pub id: Option<i64>,
+    pub user_name: String,
+    pub account_type: String,
+    pub face_token: String,
+    pub json_data: String,
+    #[serde(rename = "createTime")]
+    pub create_time: Option<String>,
+    pub last_verified: Option<String>,
+    pub needs_reenrollment: bool,
+    pub model_mismatch: bool,
+}
+
+impl RegistrationEntry {
+    fn alias(&self) -> Option<String> {
+        serde_json::from_str::<Value>(&self.json_data)
+            .ok()
+            .and_then(|v| v["alias"].as_str().map(String::from))
+            .filter(|a| !a.is_empty())
+    }
+
+    fn display_name(&self) -> String {
+        self.alias().unwrap_or_else(|| self.user_name.clone())
+    }
+}
+
+// 日期在范围内，to 按前缀比较，包含结束日期当天
+fn in_range(value: Option<&str>, from: Option<&str>, to: Option<&str>) -> bool {
+    if from.is_none() && to.is_none() {
+        return true;
+    }
+    let Some(value) = value else {
+        return false;
+    };
+    from.map_or(true, |from| value >= from)
+        && to.map_or(true, |to| value.get(..to.len()).unwrap_or(value) <= to)
+}
+
+// 数据库已经处理的条件之外的筛选
+fn matches(entry: &RegistrationEntry, query: &RegistrationQuery) -> bool {
+    if let Some(owner) = &query.owner {
+        if name_key(&entry.user_name) != name_key(owner) {
+            return false;
+        }
+    }
+    if let Some(account_type) = &query.account_type {
+        if &entry.account_type != account_type {
+            return false;
+        }
+    }
+    if let Some(name) = query.name.as_deref().map(name_key).filter(|n| !n.is_empty()) {
+        let alias_hit = entry.alias().is_some_and(|a| name_key(&a).contains(&name));
+        if !alias_hit && !name_key(&entry.user_name).contains(&name) {
+            return false;
+        }
+    }
+    in_range(entry.create_time.as_deref(), query.created_from.as_deref(), query.created_to.as_deref())
+        && in_range(entry.last_verified.as_deref(), query.verified_from.as_deref(), query.verified_to.as_deref())
+        && query.needs_reenrollment.map_or(true, |v| entry.needs_reenrollment == v)
+        && query.model_mismatch.map_or(true, |v| entry.model_mismatch == v)
+}
+
+// 排序，值相同（或都没有值）时按 face_token 保证分页稳定
+fn compare(a: &RegistrationEntry, b: &RegistrationEntry, key: SortKey) -> Ordering {
+    let ordering = match key {
+        SortKey::Name => name_key(&a.display_name()).cmp(&name_key(&b.display_name())),
+        SortKey::Owner => name_key(&a.user_name).cmp(&name_key(&b.user_name)),
+        SortKey::Created => a.create_time.cmp(&b.create_time),
+        SortKey::LastVerified => a.last_verified.cmp(&b.last_verified),
+    };
+    ordering.then_with(|| a.face_token.cmp(&b.face_token))
+}
+
+// 筛选、排序、分页，返回总数和当前页
+pub fn filter_registrations(
+    entries: Vec<RegistrationEntry>,
+    query: &RegistrationQuery,
+) -> (usize, Vec<RegistrationEntry>) {
+    let mut list: Vec<_> = entries.into_iter().filter(|e| matches(e, query)).collect();
+    list.sort_by(|a, b| {
+        let ordering = compare(a, b, query.sort);
+        if query.descending {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    });
+    let total = list.len();
+    let page = list
+        .into_iter()
+        .skip(query.offset)
+        .take(query.limit.unwrap_or(usize::MAX))
+        .collect();
+    (total, page)
+}
+
+// 特征无法读取，由其他识别模型生成（模型标识或样本长度不同），或模板密钥已更换
+fn is_model_mismatch(face_token: &str) -> bool {
+    match cached_face_data(face_token) {
+        Ok(descriptor) => {
+            !is_current_model(descriptor.model_version.as_deref())
+                || descriptor.samples.iter().any(|s| s.len() != FEATURE_DIM)
+                || probe_key(&descriptor).is_err()
+        }
+        Err(_) => true,
+    }
+}
+
+// 从数据库读取，用户和创建时间条件在 SQL 中筛选（使用索引），其余条件在内存中处理
+fn load_from_db(conn: &Connection, query: &RegistrationQuery) -> Result<Vec<RegistrationEntry>, String> {
+    let mut sql = String::from(
+        "SELECT id, user_name, account_type, face_token, json_data, createTime, \
+         (SELECT MAX(lastTime) FROM unlock_log WHERE face_id = faces.id AND is_unlock = 1) AS last_verified \
+         FROM faces WHERE 1 = 1",
+    );
+    let mut params: Vec<String> = Vec::new();
+    if let Some(owner) = &query.owner {
+        params.push(owner.trim().to_string());
+        sql.push_str(&format!(" AND user_name = ?{} COLLATE NOCASE", params.len()));
+    }
+    if let Some(from) = &query.created_from {
+        params.push(from.clone());
+        sql.push_str(&format!(" AND createTime >= ?{}", params.len()));
+    }
+
+    let reenrollment: HashMap<String, bool> = reenrollment_status(conn)?
+        .into_iter()
+        .filter_map(|item| Some((item["file_name"].as_str()?.to_string(), item["recommended"].as_bool()?)))
+        .collect();
+
+    let mut stmt = conn
+        .prepare(&sql)
+        .map_err(|e| format!("准备查询面容数据失败：{:?}", e))?;
+    let rows = stmt
+        .query_map(rusqlite::params_from_iter(params.iter()), |row| {
+            let face_token = row.get::<&str, String>("face_token")?;
+            Ok(RegistrationEntry {
+                id: Some(row.get::<&str, i64>("id")?),
+                user_name: row.get::<&str, String>("user_name")?,
+                account_type: row.get::<&str, String>("account_type")?,
+                json_data: row.get::<&str, String>("json_data")?,
+                create_time: row.get::<&str, Option<String>>("createTime")?,
+                last_verified: row.get::<&str, Option<String>>("last_verified")?,
+                needs_reenrollment: reenrollment.get(&face_token).copied().unwrap_or(false),
+                model_mismatch: is_model_mismatch(&face_token),
+                face_token,
+            })
+        })
+        .map_err(|e| format!("查询面容数据失败：{:?}", e))?;
+    rows.collect::<rusqlite::Result<Vec<_>>>()
+        .map_err(|e| format!("读取面容数据失败：{:?}", e))
+}
+
+// 前端数据库不可用时，列出面容数据库中的面容，用特征中保存的名称作为别名
+fn load_from_store() -> Result<Vec<RegistrationEntry>, String> {
+    let (faces, _) = cached_faces();
+    Ok(faces
+        .into_iter()
+        .map(|(face_token, descriptor)| RegistrationEntry {
+            id: None,
+            user_name: String::new(),
+            account_type: String::new(),
+            json_data: json!({"alias": descriptor.name}).to_string(),
+            create_time: None,
+            last_verified: None,
+            needs_reenrollment: false,
+            model_mismatch: is_model_mismatch(&face_token),
+            face_token,
+        })
+        .collect())
+}
+
+fn has_faces_table(conn: &Connection) -> bool {
+    conn.query_row(
+        "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = 'faces';",
+        [],
+        |row| row.get::<usize, i64>(0),
+    )
+    .is_ok_and(|count| count > 0)
+}
+
+// 搜索已录入的面容
+#[tauri::command]
+pub fn search_registrations(query: RegistrationQuery) -> Result<CustomResult, CustomResult> {
+    ensure_ready()?;
+    // 连接池在 init_model 之后才存在，之前直接只读打开数据库
+    let loaded = match get_conn() {
+        Ok(conn) => has_faces_table(&conn).then(|| load_from_db(&conn, &query)),
+        Err(_) => Connection::open_with_flags(ROOT_DIR.join("database.db"), OpenFlags::SQLITE_OPEN_READ_ONLY)
+            .ok()
+            .filter(has_faces_table)
+            .map(|conn| load_from_db(&conn, &query)),
+    };
+    let (source, entries) = match loaded {
+        Some(entries) => ("database", entries),
+        None => ("face_store", load_from_store()),
+    };
+    let entries = entries.map_err(|e| CustomResult::error(Some(e), None))?;
+
+    let (total, items) = filter_registrations(entries, &query);
+    Ok(CustomResult::success(
+        None,
+        Some(json!({
+            "source": source,
+            "total": total,
+            "offset": query.offset,
+            "limit": query.limit,
+            "items": items,
+        })),
+    ))
+}
@@ -0,0 +1,213 @@
+// 面容特征数据库：每个面容一行，feature 为加密并带校验和的特征（与原 .face 文件内容相同）
+// 数据库由 tauri_plugin_sql 注册（FACE_STORE_URL），表结构由插件的迁移创建，位于 AppData 中的应用配置目录，
+// 软件安装在 Program Files 时也可以写入；后端打开同一个文件，读写都通过这一个连接
+// 录入图片（.faceimg）仍保存在面容目录中
+use std::{path::Path, sync::Mutex, time::Duration};
+
+use lazy_static::lazy_static;
+use r2d2_sqlite::rusqlite::{self, params, Connection, OptionalExtension};
+use tauri_plugin_sql::{Migration, MigrationKind};
+
+use crate::utils::{db_writer::CONNECTION_PRAGMAS, storage::face_store_path};
+
+// tauri_plugin_sql 中的数据库地址，相对于应用配置目录
+pub const FACE_STORE_URL: &str = "sqlite:faces.db";
+pub const FACE_STORE_FILE: &str = "faces.db";
+
+// face_token 与前端 faces 表的 face_token 相同（录入时生成的 UUID），也是录入图片的文件名
+// username 为对应的 Windows 用户名，与前端 faces 表的 user_name 同步
+pub const FACES_TABLE: &str = "CREATE TABLE IF NOT EXISTS faces (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    face_token TEXT NOT NULL UNIQUE,
+    name TEXT NOT NULL,
+    username TEXT NOT NULL DEFAULT '',
+    feature BLOB NOT NULL,
+    created_at INTEGER NOT NULL
+);";
+
+// 注册到 tauri_plugin_sql 的迁移，只能在末尾追加
+pub fn plugin_migrations() -> Vec<Migration> {
+    vec![Migration {
+        version: 1,
+        description: "create_faces_table",
+        sql: FACES_TABLE,
+        kind: MigrationKind::Up,
+    }]
+}
+
+lazy_static! {
+    // 首次使用时打开，之后一直使用同一个连接，读写都在锁内完成
+    static ref STORE: Mutex<Option<Connection>> = Mutex::new(None);
+}
+
+// 一条面容记录
+pub struct StoredFace {
+    pub face_token: String,
+    pub name: String,
+    // Windows 用户名，录入后由前端保存账户时写入
+    pub username: String,
+    // 加密后的特征
+    pub feature: Vec<u8>,
+    // 录入时间（Unix 毫秒）
+    pub created_at: u64,
+}
+
+// 打开面容数据库并创建表，不存在时创建数据库文件
+// 后端可能比插件先打开数据库，建表语句与插件的迁移相同，谁先执行都可以
+pub fn open(path: &Path) -> Result<Connection, String> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| format!("创建面容数据库目录失败：{}", e))?;
+    }
+    let conn = Connection::open(path).map_err(|e| format!("打开面容数据库失败：{:?}", e))?;
+    conn.busy_timeout(Duration::from_secs(5))
+        .and_then(|_| conn.execute_batch(CONNECTION_PRAGMAS))
+        .and_then(|_| conn.execute_batch(FACES_TABLE))
+        .map_err(|e| format!("初始化面容数据库失败：{:?}", e))?;
+    Ok(conn)
+}
+
+// 使用全局连接执行，首次调用时打开数据库
+pub fn with_store<T, F>(f: F) -> Result<T, String>
+where
+    F: FnOnce(&Connection) -> rusqlite::Result<T>,
+{
+    let mut store = STORE
+        .lock()
+        .map_err(|e| format!("获取面容数据库锁失败 {}", e))?;
+    if store.is_none() {
+        *store = Some(open(&face_store_path())?);
+    }
+    let conn = store.as_ref().ok_or_else(|| String::from("面容数据库未打开"))?;
+    f(conn).map_err(|e| format!("读写面容数据库失败：{:?}", e))
+}
+
+// 保存面容，face_token 已存在时替换特征和名称，保留原来的录入时间和用户名
+pub fn save(conn: &Connection, face: &StoredFace) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT INTO faces (face_token, name, username, feature, created_at) VALUES (?1, ?2, ?3, ?4, ?5)
+         ON CONFLICT(face_token) DO UPDATE SET name = excluded.name, feature = excluded.feature;",
+        params![face.face_token, face.name, face.username, face.feature, face.created_at as i64],
+    )
+    .map(|_| ())
+}
+
+// 更新面容对应的 Windows 用户名，返回面容是否存在
+pub fn set_username(conn: &Connection, face_token: &str, username: &str) -> rusqlite::Result<bool> {
+    conn.execute(
+        "UPDATE faces SET username = ?2 WHERE face_token = ?1;",
+        params![face_token, username],
+    )
+    .map(|count| count > 0)
+}
+
+fn from_row(row: &rusqlite::Row) -> rusqlite::Result<StoredFace> {
+    Ok(StoredFace {
+        face_token: row.get("face_token")?,
+        name: row.get("name")?,
+        username: row.get("username")?,
+        feature: row.get("feature")?,
+        created_at: row.get::<&str, i64>("created_at")?.max(0) as u64,
+    })
+}
+
+// 读取一个面容，不存在时返回 None
+pub fn load(conn: &Connection, face_token: &str) -> rusqlite::Result<Option<StoredFace>> {
+    conn.query_row(
+        "SELECT face_token, name, username, feature, created_at FROM faces WHERE face_token = ?1;",
+        [face_token],
+        from_row,
+    )
+    .optional()
+}
+
+// 读取所有面容，按 face_token 排序
+pub fn load_all(conn: &Connection) -> rusqlite::Result<Vec<StoredFace>> {
+    let mut stmt = conn.prepare("SELECT face_token, name, username, feature, created_at FROM faces ORDER BY face_token;")?;
+    let rows = stmt.query_map([], from_row)?;
+    rows.collect()
+}
+
+// 删除面容，返回是否存在
+pub fn remove(conn: &Connection, face_token: &str) -> rusqlite::Result<bool> {
+    conn.execute("DELETE FROM faces WHERE face_token = ?1;", [face_token])
+        .map(|count| count > 0)
+}
+
+pub fn contains(conn: &Connection, face_token: &str) -> rusqlite::Result<bool> {
+    conn.query_row("SELECT COUNT(*) FROM faces WHERE face_token = ?1;", [face_token], |row| {
+        row.get::<usize, i64>(0)
+    })
+    .map(|count| count > 0)
+}
+
+pub fn count(conn: &Connection) -> rusqlite::Result<usize> {
+    conn.query_row("SELECT COUNT(*) FROM faces;", [], |row| row.get::<usize, i64>(0))
+        .map(|count| count as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(FACES_TABLE).unwrap();
+        conn
+    }
+
+    fn face(token: &str, name: &str, feature: &[u8]) -> StoredFace {
+        StoredFace {
+            face_token: token.to_string(),
+            name: name.to_string(),
+            username: String::new(),
+            feature: feature.to_vec(),
+            created_at: 1_700_000_000_000,
+        }
+    }
+
+    #[test]
+    fn save_and_load_round_trip() {
+        let conn = store();
+        save(&conn, &face("b", "second", &[2])).unwrap();
+        save(&conn, &face("a", "first", &[1, 1])).unwrap();
+
+        let loaded = load(&conn, "a").unwrap().unwrap();
+        assert_eq!(loaded.name, "first");
+        assert_eq!(loaded.feature, vec![1, 1]);
+        assert_eq!(loaded.created_at, 1_700_000_000_000);
+        assert!(load(&conn, "missing").unwrap().is_none());
+
+        let tokens: Vec<_> = load_all(&conn).unwrap().into_iter().map(|f| f.face_token).collect();
+        assert_eq!(tokens, ["a", "b"]);
+        assert_eq!(count(&conn).unwrap(), 2);
+    }
+
+    #[test]
+    fn resave_keeps_username_and_created_at() {
+        let conn = store();
+        save(&conn, &face("a", "old", &[1])).unwrap();
+        assert!(set_username(&conn, "a", "alice").unwrap());
+        assert!(!set_username(&conn, "missing", "bob").unwrap());
+
+        let mut updated = face("a", "new", &[9]);
+        updated.created_at = 1;
+        save(&conn, &updated).unwrap();
+
+        let loaded = load(&conn, "a").unwrap().unwrap();
+        assert_eq!(loaded.name, "new");
+        assert_eq!(loaded.feature, vec![9]);
+        assert_eq!(loaded.username, "alice");
+        assert_eq!(loaded.created_at, 1_700_000_000_000);
+        assert_eq!(count(&conn).unwrap(), 1);
+    }
+
+    #[test]
+    fn remove_reports_existence() {
+        let conn = store();
+        save(&conn, &face("a", "first", &[1])).unwrap();
+        assert!(contains(&conn, "a").unwrap());
+        assert!(remove(&conn, "a").unwrap());
+        assert!(!remove(&conn, "a").unwrap());
+        assert!(!contains(&conn, "a").unwrap());
+    }
+}
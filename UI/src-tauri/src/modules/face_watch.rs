@@ -0,0 +1,343 @@
+// 面容特征缓存，以及面容目录监视
+// 面容保存在面容数据库中，缓存由保存/删除原地更新；用户或同步工具在软件外向面容目录放入 .face 文件时，
+// 自动导入数据库、更新缓存并通知前端。目录监视失效（目录被删除、句柄失效）时重新创建，期间按固定间隔重新扫描
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant, SystemTime},
+};
+
+use lazy_static::lazy_static;
+use serde::Serialize;
+use tauri_plugin_log::log::{info, warn};
+use windows::{
+    core::HSTRING,
+    Win32::{
+        Foundation::{HANDLE, WAIT_OBJECT_0, WAIT_TIMEOUT},
+        Storage::FileSystem::{
+            FindCloseChangeNotification, FindFirstChangeNotificationW, FindNextChangeNotification,
+            FILE_NOTIFY_CHANGE_FILE_NAME, FILE_NOTIFY_CHANGE_LAST_WRITE, FILE_NOTIFY_CHANGE_SIZE,
+        },
+        System::Threading::WaitForSingleObject,
+    },
+};
+
+use crate::{
+    modules::{
+        face_store,
+        faces::{decode_stored_face, import_face_file, load_face_data, FaceDescriptor},
+    },
+    utils::{api::emit_event, storage::faces_dir},
+};
+
+// 导入了面容目录中的文件时发送的事件
+pub const FACE_STORE_CHANGED: &str = "face-store-changed";
+// 收到通知后等待目录安静下来再扫描，同步工具通常连续写入多个文件
+const DEBOUNCE: Duration = Duration::from_millis(500);
+// 监视失效时的重新扫描间隔，也是正常监视时的兜底扫描间隔
+const RESCAN_INTERVAL: Duration = Duration::from_secs(30);
+// 软件自己写入后的这段时间内，该文件的变化不视为外部修改
+const OWN_WRITE_WINDOW: Duration = Duration::from_secs(5);
+const FACE_EXT: &str = "face";
+
+// 文件的长度和修改时间，用于判断文件是否变化
+type FileStamp = (u64, Option<SystemTime>);
+
+lazy_static! {
+    // 已加载的面容特征，按文件名（不含扩展名）缓存
+    static ref REGISTRATION_CACHE: Mutex<HashMap<String, FaceDescriptor>> = Mutex::new(HashMap::new());
+    // 上次扫描时目录中的 .face 文件（导入失败、留在目录中的文件）
+    static ref SNAPSHOT: Mutex<HashMap<String, FileStamp>> = Mutex::new(HashMap::new());
+    // 软件自己正在写入/删除的文件
+    static ref OWN_WRITES: Mutex<HashMap<String, Instant>> = Mutex::new(HashMap::new());
+    // 数据库中无法解析的面容及原因，与缓存一起维护
+    static ref INVALID_FACES: Mutex<HashMap<String, String>> = Mutex::new(HashMap::new());
+}
+
+// 缓存已包含数据库中的所有面容，之后由保存/删除和目录导入原地更新，不再重新读取数据库
+static CACHE_COMPLETE: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Default, Serialize)]
+pub struct FaceStoreDelta {
+    // 导入的新面容
+    pub added: Vec<String>,
+    // 导入时替换了数据库中已有的面容
+    pub modified: Vec<String>,
+    // 文件存在但无法导入，留在面容目录中
+    pub invalid: Vec<InvalidFace>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct InvalidFace {
+    pub file_name: String,
+    pub error: String,
+}
+
+impl FaceStoreDelta {
+    fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.modified.is_empty() && self.invalid.is_empty()
+    }
+}
+
+fn face_stem(path: &Path) -> Option<String> {
+    if path.extension().and_then(|e| e.to_str()) != Some(FACE_EXT) {
+        return None;
+    }
+    path.file_stem().and_then(|s| s.to_str()).map(String::from)
+}
+
+// 软件自己写入或删除面容目录中的 .face 文件前调用，避免被当作外部修改
+pub fn mark_own_write(path: &Path) {
+    let Some(stem) = face_stem(path) else {
+        return;
+    };
+    if let Ok(mut own) = OWN_WRITES.lock() {
+        own.insert(stem, Instant::now());
+    }
+}
+
+// 面容保存成功后更新缓存，解锁时直接使用新的特征
+// 缓存只在写入成功后更新，写入失败时数据库和缓存都不变
+pub fn cache_saved_face(file_stem: &str, descriptor: &FaceDescriptor) {
+    if let Ok(mut invalid) = INVALID_FACES.lock() {
+        invalid.remove(file_stem);
+    }
+    if let Ok(mut cache) = REGISTRATION_CACHE.lock() {
+        cache.insert(file_stem.to_string(), descriptor.clone());
+    }
+}
+
+// 面容删除成功后从缓存中移除
+pub fn cache_removed_face(file_stem: &str) {
+    if let Ok(mut invalid) = INVALID_FACES.lock() {
+        invalid.remove(file_stem);
+    }
+    if let Ok(mut cache) = REGISTRATION_CACHE.lock() {
+        cache.remove(file_stem);
+    }
+}
+
+// 重新读取数据库中的所有面容并替换缓存，返回已加载的数量和无法解析的面容
+// 数据库无法打开时保留原来的缓存
+pub fn reload_cached_faces() -> (usize, Vec<InvalidFace>) {
+    let stored = match face_store::with_store(face_store::load_all) {
+        Ok(stored) => stored,
+        Err(e) => {
+            warn!("读取面容数据库失败：{}", e);
+            return (0, Vec::new());
+        }
+    };
+    let mut loaded = HashMap::new();
+    let mut invalid = HashMap::new();
+    for face in stored {
+        match decode_stored_face(&face) {
+            Ok(descriptor) => {
+                loaded.insert(face.face_token, descriptor);
+            }
+            Err(e) => {
+                warn!("面容 {} 无法解析：{}", face.face_token, e);
+                invalid.insert(face.face_token, e.to_string());
+            }
+        }
+    }
+    let count = loaded.len();
+    let errors = invalid_list(&invalid);
+    if let (Ok(mut cache), Ok(mut invalid_faces)) = (REGISTRATION_CACHE.lock(), INVALID_FACES.lock()) {
+        *cache = loaded;
+        *invalid_faces = invalid;
+        CACHE_COMPLETE.store(true, Ordering::SeqCst);
+    }
+    info!("已加载 {} 个面容到缓存，{} 个无法解析", count, errors.len());
+    (count, errors)
+}
+
+fn invalid_list(invalid: &HashMap<String, String>) -> Vec<InvalidFace> {
+    let mut list: Vec<InvalidFace> = invalid
+        .iter()
+        .map(|(stem, error)| InvalidFace { file_name: stem.clone(), error: error.clone() })
+        .collect();
+    list.sort_by(|a, b| a.file_name.cmp(&b.file_name));
+    list
+}
+
+// 缓存中的所有面容（按文件名排序）和无法解析的面容，首次调用时读取整个数据库
+pub fn cached_faces() -> (Vec<(String, FaceDescriptor)>, Vec<InvalidFace>) {
+    if !CACHE_COMPLETE.load(Ordering::SeqCst) {
+        reload_cached_faces();
+    }
+    let mut faces: Vec<(String, FaceDescriptor)> = REGISTRATION_CACHE
+        .lock()
+        .map(|cache| cache.iter().map(|(stem, d)| (stem.clone(), d.clone())).collect())
+        .unwrap_or_default();
+    faces.sort_by(|a, b| a.0.cmp(&b.0));
+    let invalid = INVALID_FACES.lock().map(|i| invalid_list(&i)).unwrap_or_default();
+    (faces, invalid)
+}
+
+// 读取面容特征，优先使用缓存
+pub fn cached_face_data(file_stem: &str) -> Result<FaceDescriptor, Box<dyn std::error::Error>> {
+    if let Some(descriptor) = REGISTRATION_CACHE.lock().ok().and_then(|c| c.get(file_stem).cloned()) {
+        return Ok(descriptor);
+    }
+    let descriptor = load_face_data(file_stem)?;
+    if let Ok(mut cache) = REGISTRATION_CACHE.lock() {
+        cache.insert(file_stem.to_string(), descriptor.clone());
+    }
+    Ok(descriptor)
+}
+
+// 读取目录中所有 .face 文件的状态，目录不存在时返回空
+fn scan_dir(dir: &Path) -> HashMap<String, FileStamp> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return HashMap::new();
+    };
+    entries
+        .flatten()
+        .filter_map(|entry| {
+            let stem = face_stem(&entry.path())?;
+            let meta = entry.metadata().ok()?;
+            Some((stem, (meta.len(), meta.modified().ok())))
+        })
+        .collect()
+}
+
+// 重新扫描目录，导入新出现或有变化的 .face 文件，返回导入结果
+// 导入成功的文件会被删除，导入失败的文件留在目录中，内容不变时不再重复处理
+fn reconcile(dir: &Path) -> FaceStoreDelta {
+    let current = scan_dir(dir);
+    let mut delta = FaceStoreDelta::default();
+    let Ok(mut snapshot) = SNAPSHOT.lock() else {
+        return delta;
+    };
+    let own: HashMap<String, Instant> = match OWN_WRITES.lock() {
+        Ok(mut own) => {
+            own.retain(|_, at| at.elapsed() < OWN_WRITE_WINDOW);
+            own.clone()
+        }
+        Err(_) => HashMap::new(),
+    };
+
+    let changed: Vec<String> = current
+        .iter()
+        .filter(|(stem, stamp)| snapshot.get(*stem) != Some(*stamp) && !own.contains_key(*stem))
+        .map(|(stem, _)| stem.clone())
+        .collect();
+    *snapshot = current;
+
+    for stem in changed {
+        let path = dir.join(format!("{}.{}", stem, FACE_EXT));
+        let result = face_store::with_store(|conn| Ok(import_face_file(conn, &path, &stem).map_err(|e| e.to_string())))
+            .and_then(|result| result);
+        match result {
+            Ok((descriptor, existed)) => {
+                // 文件已删除，之后再出现同名文件时重新导入
+                snapshot.remove(&stem);
+                cache_saved_face(&stem, &descriptor);
+                if existed {
+                    delta.modified.push(stem);
+                } else {
+                    delta.added.push(stem);
+                }
+            }
+            Err(error) => delta.invalid.push(InvalidFace { file_name: stem, error }),
+        }
+    }
+    delta
+}
+
+fn rescan_and_notify(dir: &Path) {
+    let delta = reconcile(dir);
+    if delta.is_empty() {
+        return;
+    }
+    info!(
+        "已导入面容目录中的文件：新增 {}，替换 {}，无法导入 {}",
+        delta.added.len(),
+        delta.modified.len(),
+        delta.invalid.len()
+    );
+    for invalid in &delta.invalid {
+        warn!("面容文件 {} 无法导入：{}", invalid.file_name, invalid.error);
+    }
+    emit_event(FACE_STORE_CHANGED, &delta);
+}
+
+// 目录变化通知句柄，离开作用域时关闭
+struct ChangeNotification(HANDLE);
+
+impl ChangeNotification {
+    fn new(dir: &Path) -> Option<Self> {
+        let handle = unsafe {
+            FindFirstChangeNotificationW(
+                &HSTRING::from(dir.as_os_str()),
+                false,
+                FILE_NOTIFY_CHANGE_FILE_NAME | FILE_NOTIFY_CHANGE_LAST_WRITE | FILE_NOTIFY_CHANGE_SIZE,
+            )
+        };
+        handle.ok().map(ChangeNotification)
+    }
+}
+
+impl Drop for ChangeNotification {
+    fn drop(&mut self) {
+        let _ = unsafe { FindCloseChangeNotification(self.0) };
+    }
+}
+
+// 监视目录直到句柄失效，返回后由调用方重新创建
+fn watch(dir: &Path, notification: &ChangeNotification) {
+    loop {
+        match unsafe { WaitForSingleObject(notification.0, RESCAN_INTERVAL.as_millis() as u32) } {
+            WAIT_OBJECT_0 => {
+                // 等待连续的变化结束
+                loop {
+                    if unsafe { FindNextChangeNotification(notification.0) }.is_err() {
+                        rescan_and_notify(dir);
+                        return;
+                    }
+                    if unsafe { WaitForSingleObject(notification.0, DEBOUNCE.as_millis() as u32) } != WAIT_OBJECT_0 {
+                        break;
+                    }
+                }
+                rescan_and_notify(dir);
+                // 目录本身被删除后通知句柄不会再触发
+                if !dir.exists() {
+                    return;
+                }
+            }
+            WAIT_TIMEOUT => rescan_and_notify(dir),
+            _ => {
+                rescan_and_notify(dir);
+                return;
+            }
+        }
+    }
+}
+
+// 启动面容目录监视线程，先导入软件未运行期间放入目录的文件
+pub fn spawn_faces_watcher() {
+    let dir: PathBuf = faces_dir().to_path_buf();
+    std::thread::spawn(move || {
+        rescan_and_notify(&dir);
+        loop {
+            match ChangeNotification::new(&dir) {
+                Some(notification) => {
+                    info!("开始监视面容目录 {:?}", dir);
+                    watch(&dir, &notification);
+                    warn!("面容目录监视失效，重新创建");
+                    std::thread::sleep(DEBOUNCE);
+                }
+                None => {
+                    // 目录不存在（尚未录入面容或被删除），定时扫描直到可以重新监视
+                    std::thread::sleep(RESCAN_INTERVAL);
+                    rescan_and_notify(&dir);
+                }
+            }
+        }
+    });
+}
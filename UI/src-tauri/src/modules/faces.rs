@@ -1,45 +1,204 @@
 use std::{
-    fs, io::{Read, Write}, path::PathBuf, thread::sleep, time::Duration
+    fs, io::{Read, Write}, path::{Path, PathBuf}, sync::{atomic::{AtomicBool, AtomicI32, Ordering}, Arc, RwLock}, thread::sleep, time::{Duration, Instant}
 };
 
-use crate::{utils::custom_result::CustomResult, APP_STATE, ROOT_DIR};
+use crate::{
+    modules::{
+        calibration::active_calibration,
+        conference::ensure_not_paused,
+        face_store::{self, StoredFace},
+        face_watch::{cache_removed_face, cache_saved_face, cached_faces, mark_own_write, reload_cached_faces},
+        liveness::{recent_challenge_score, wait_for_blink, BlinkLiveness, BLINK_TIMEOUT},
+        face_search::FEATURE_DIM,
+        face_policy::{detect_faces, extract_feature, extract_feature_with_crop, l2_from_cosine, match_frame, primary_face, FaceMatch, MatchMetric, MultiFacePolicy, BYSTANDER_DETECTED},
+        engine,
+        model_check::MODEL_SANITY_CHECK_FAILED,
+        options::{read_option, save_option},
+        retention::active_cached_faces,
+        template::{
+            probe_key, protect_descriptor, protect_file_data, unprotect_file_data, FACE_FILE_UNREADABLE,
+            TEMPLATE_KEY_MISMATCH, TEMPLATE_PROTECTION_OPTION,
+        },
+    },
+    utils::{
+        api::{emit_event, ensure_models_loaded, ensure_ready},
+        custom_result::CustomResult,
+        db_writer,
+        frame_cache::{encode_jpeg_cached, next_frame_id},
+        priority::{PriorityGuard, WorkMode},
+        storage::{
+            face_store_path, faces_dir, is_cloud_placeholder, is_cloud_synced,
+            is_controlled_folder_access_enabled, name_key, safe_file_stem, with_retry,
+        },
+    },
+    APP_STATE, BLACK_FRAME_CONFIG, BLACK_FRAME_COUNT,
+};
 use base64::{engine::general_purpose, Engine};
+use lazy_static::lazy_static;
+use r2d2_sqlite::rusqlite::Connection;
 use opencv::{
-    core::{Mat, Point, Rect, Scalar, Size, Vector},
+    core::{self, Mat, Point, Rect, Scalar, Size, Vector},
     imgcodecs, imgproc,
     objdetect::FaceRecognizerSF_DisType,
     prelude::*,
 };
 use serde::{Deserialize, Serialize};
 use serde_json::json;
+use tauri::ipc::InvokeBody;
+use tauri_plugin_log::log::{info, warn};
 use uuid::Uuid;
 
-#[derive(Serialize, Deserialize, Debug)]
+// 一个面容可以保存多个样本，匹配时取分数最高的样本
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct FaceDescriptor {
     pub name: String,
-    pub feature: Vec<f32>,
+    pub samples: Vec<Vec<f32>>,
+    // 启用模板保护时为密钥指纹，样本是变换后的特征；None 表示原始特征
+    pub key_fingerprint: Option<String>,
+    // 录入时截取的人脸小图（JPEG），用于面容列表显示头像
+    #[serde(default)]
+    pub thumbnail: Option<Vec<u8>>,
+    // 生成特征的识别模型，None 表示版本 6 之前保存、模型未知
+    #[serde(default)]
+    pub model_version: Option<String>,
+    // 录入时间（Unix 毫秒），None 表示版本 6 之前保存
+    #[serde(default)]
+    pub created_at: Option<u64>,
+}
+
+// 旧版本保存的固定模型标识，只对应安装目录中的默认识别模型
+const LEGACY_RECOGNIZER_MODEL_VERSION: &str = "sface_2021dec";
+const LEGACY_RECOGNIZER_MODEL_FILE: &str = "face_recognition_sface_2021dec.onnx";
+
+lazy_static! {
+    // 当前识别模型的标识（文件名 + 内容校验和），加载识别模型时设置，保存在特征中，更换模型后可据此识别需要重新录入的面容
+    static ref RECOGNIZER_MODEL_VERSION: RwLock<Option<String>> = RwLock::new(None);
+}
+
+// 当前识别模型的标识，模型尚未加载时为 None
+pub fn recognizer_model_version() -> Option<String> {
+    RECOGNIZER_MODEL_VERSION.read().ok().and_then(|version| version.clone())
+}
+
+pub fn set_recognizer_model_version(version: String) {
+    if let Ok(mut current) = RECOGNIZER_MODEL_VERSION.write() {
+        *current = Some(version);
+    }
+}
+
+// 特征是否由当前识别模型生成
+// 没有模型标识（版本 6 之前保存）或模型尚未加载时无法判断，按一致处理，由样本长度等其他条件判断
+pub fn is_current_model(model_version: Option<&str>) -> bool {
+    let (Some(stored), Some(current)) = (model_version, recognizer_model_version()) else {
+        return true;
+    };
+    if stored == LEGACY_RECOGNIZER_MODEL_VERSION {
+        return current.starts_with(&format!("{}#", LEGACY_RECOGNIZER_MODEL_FILE));
+    }
+    stored == current
+}
+
+// 版本 5 的特征文件没有模型标识和录入时间
+#[derive(Deserialize)]
+struct ThumbnailDescriptor {
+    name: String,
+    samples: Vec<Vec<f32>>,
+    key_fingerprint: Option<String>,
+    thumbnail: Option<Vec<u8>>,
+}
+
+// 版本 4 的特征文件没有头像
+#[derive(Deserialize)]
+struct ProtectedDescriptor {
+    name: String,
+    samples: Vec<Vec<f32>>,
+    key_fingerprint: Option<String>,
+}
+
+// 版本 3 的特征文件没有模板保护
+#[derive(Deserialize)]
+struct MultiSampleDescriptor {
+    name: String,
+    samples: Vec<Vec<f32>>,
+}
+
+// 版本 1、2 的特征文件只有一个样本
+#[derive(Deserialize)]
+struct SingleSampleDescriptor {
+    name: String,
+    feature: Vec<f32>,
+}
+
+// 每个面容最多保存的样本数
+pub const MAX_SAMPLES: usize = 10;
+
+// 将 OpenCV 的 Mat 转换为 Vec
+fn mat_to_vec(feature_mat: &Mat) -> Result<Vec<f32>, Box<dyn std::error::Error>> {
+    // 确保 Mat 是连续的，然后转换为 Vec
+    let mut feature_vec: Vec<f32> = vec![0.0f32; feature_mat.total()];
+    let data = feature_mat.data_typed::<f32>()?;
+    feature_vec.copy_from_slice(data);
+    Ok(feature_vec)
 }
 
 impl FaceDescriptor {
     // 将 OpenCV 的 Mat 转换为可序列化的结构
     pub fn from_mat(name: &str, feature_mat: &Mat) -> Result<Self, Box<dyn std::error::Error>> {
-        // 确保 Mat 是连续的，然后转换为 Vec
-        let mut feature_vec: Vec<f32> = vec![0.0f32; feature_mat.total()];
-        let data = feature_mat.data_typed::<f32>()?;
-        feature_vec.copy_from_slice(data);
-
         Ok(FaceDescriptor {
             name: name.to_string(),
-            feature: feature_vec,
+            samples: vec![mat_to_vec(feature_mat)?],
+            key_fingerprint: None,
+            thumbnail: None,
+            model_version: recognizer_model_version(),
+            created_at: Some(engine::now_millis()),
         })
     }
 
+    // 追加一个样本
+    pub fn push_sample(&mut self, feature_mat: &Mat) -> Result<(), Box<dyn std::error::Error>> {
+        if self.samples.len() >= MAX_SAMPLES {
+            return Err(format!("每个面容最多保存 {} 个样本", MAX_SAMPLES).into());
+        }
+        self.samples.push(mat_to_vec(feature_mat)?);
+        Ok(())
+    }
+
+    // 由名称生成的安全文件名，导出等需要用名称命名文件时使用
+    pub fn file_stem(&self) -> String {
+        safe_file_stem(&self.name)
+    }
+
+    // 按 Unicode 规则忽略大小写比较名称
+    pub fn name_matches(&self, name: &str) -> bool {
+        name_key(&self.name) == name_key(name)
+    }
+
+    // 将所有样本还原回 OpenCV Mat，顺序与样本序号一致
+    pub fn to_mats(&self) -> Result<Vec<Mat>, Box<dyn std::error::Error>> {
+        if self.samples.is_empty() {
+            return Err("面容数据中没有样本".into());
+        }
+        self.samples.iter().map(|sample| Self::sample_to_mat(sample)).collect()
+    }
+
     // 将特征向量还原回 OpenCV Mat
-    pub fn to_mat(&self) -> Result<Mat, Box<dyn std::error::Error>> {
+    // 长度与当前识别模型的特征维度不一致时返回错误，更换模型后旧样本不会以错误的形状参与匹配
+    fn sample_to_mat(sample: &[f32]) -> Result<Mat, Box<dyn std::error::Error>> {
+        if sample.is_empty() {
+            return Err("面容样本为空".into());
+        }
+        if sample.len() != FEATURE_DIM {
+            return Err(format!(
+                "面容样本的特征长度为 {}，当前识别模型为 {}，请重新录入",
+                sample.len(),
+                FEATURE_DIM
+            )
+            .into());
+        }
         // 从切片创建原始 Mat (默认为 N 行 1 列)
-        let m = Mat::from_slice(&self.feature)?;
+        let m = Mat::from_slice(sample)?;
 
-        // 变换形状为 1 行 128 列
+        // 变换形状为 1 行 N 列（reshape 的参数为通道数和行数，列数由长度决定）
         // reshape 返回的是 Result<BoxedRef<Mat>, ...>
         let m_reshaped = m.reshape(1, 1)?;
 
@@ -50,415 +209,3701 @@ impl FaceDescriptor {
     }
 }
 
-struct CaptureResponse {
-    display_base64: String, // 带框的
-    raw_base64: String,     // 不带框的（仅缩放）
+// 默认 JPEG 质量，与 OpenCV 默认值一致，用于录入使用的原图
+const DEFAULT_JPEG_QUALITY: i32 = 95;
+// 预览画面的 JPEG 质量，只影响显示；每帧都要传给界面，质量越低数据越少
+pub const DEFAULT_PREVIEW_QUALITY: i32 = 80;
+const PREVIEW_QUALITY_OPTION: &str = "previewJpegQuality";
+const PREVIEW_QUALITY_RANGE: std::ops::RangeInclusive<i32> = 30..=100;
+static PREVIEW_QUALITY: AtomicI32 = AtomicI32::new(DEFAULT_PREVIEW_QUALITY);
+// 检测和预览图片的最大边长：越小检测越快、传给界面的数据越少，越大预览越清晰
+pub const DEFAULT_PREVIEW_MAX_DIM: i32 = 800;
+const PREVIEW_MAX_DIM_OPTION: &str = "previewMaxDim";
+const PREVIEW_MAX_DIM_RANGE: std::ops::RangeInclusive<i32> = 240..=4096;
+static PREVIEW_MAX_DIM: AtomicI32 = AtomicI32::new(DEFAULT_PREVIEW_MAX_DIM);
+
+// 检测预览的图片格式，PNG 无损，画框和五官点没有压缩痕迹，也方便核对检测坐标
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    #[default]
+    Jpeg,
+    Png,
 }
 
-// 从图片中检测人脸
-#[tauri::command]
-pub fn check_face_from_img(
-    img_path: String,
-    face_detection_threshold: f32,
-) -> Result<CustomResult, CustomResult> {
-    // 从fs读取图片
-    // opencv不支持中文，搞了半个小时 ...
-    let bytes = std::fs::read(&img_path)
-        .map_err(|e| CustomResult::error(Some(format!("图片读取失败: {}", e)), None))?;
-    let v = Vector::<u8>::from_iter(bytes);
-    let src = imgcodecs::imdecode(&v, imgcodecs::IMREAD_COLOR)
-        .map_err(|e| CustomResult::error(Some(format!("OpenCV 解码失败: {}", e)), None))?;
+impl OutputFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Jpeg => ".jpg",
+            OutputFormat::Png => ".png",
+        }
+    }
 
-    if src.empty() {
+    fn mime(&self) -> &'static str {
+        match self {
+            OutputFormat::Jpeg => "image/jpeg",
+            OutputFormat::Png => "image/png",
+        }
+    }
+}
+
+// 当前的预览 JPEG 质量
+pub fn preview_quality() -> i32 {
+    PREVIEW_QUALITY.load(Ordering::SeqCst)
+}
+
+fn validate_preview_quality(quality: i32) -> Result<i32, CustomResult> {
+    if !PREVIEW_QUALITY_RANGE.contains(&quality) {
         return Err(CustomResult::error(
-            Some(String::from("图片读取失败")),
+            Some(format!(
+                "预览画面质量必须在 {} 到 {} 之间，当前为 {}",
+                PREVIEW_QUALITY_RANGE.start(),
+                PREVIEW_QUALITY_RANGE.end(),
+                quality
+            )),
             None,
         ));
     }
+    Ok(quality)
+}
 
-    let result = detect_and_format(src, face_detection_threshold)
-        .map_err(|e| CustomResult::error(Some(format!("OpenCV 检测失败: {}", e)), None))?;
+// 当前的检测和预览最大边长
+pub fn preview_max_dim() -> i32 {
+    PREVIEW_MAX_DIM.load(Ordering::SeqCst)
+}
+
+fn validate_preview_max_dim(max_dim: i32) -> Result<i32, CustomResult> {
+    if !PREVIEW_MAX_DIM_RANGE.contains(&max_dim) {
+        return Err(CustomResult::error(
+            Some(format!(
+                "最大边长必须在 {} 到 {} 之间，当前为 {}",
+                PREVIEW_MAX_DIM_RANGE.start(),
+                PREVIEW_MAX_DIM_RANGE.end(),
+                max_dim
+            )),
+            None,
+        ));
+    }
+    Ok(max_dim)
+}
+
+// 命令参数中的最大边长，未指定时使用设置中的值
+fn resolve_max_dim(max_dim: Option<i32>) -> Result<f32, CustomResult> {
+    let max_dim = match max_dim {
+        Some(max_dim) => validate_preview_max_dim(max_dim)?,
+        None => preview_max_dim(),
+    };
+    Ok(max_dim as f32)
+}
+
+// 连接池就绪后读取预览画面质量和最大边长
+pub fn load_preview_settings() {
+    let quality = read_option(PREVIEW_QUALITY_OPTION)
+        .and_then(|v| v.parse::<i32>().ok())
+        .filter(|v| PREVIEW_QUALITY_RANGE.contains(v))
+        .unwrap_or(DEFAULT_PREVIEW_QUALITY);
+    PREVIEW_QUALITY.store(quality, Ordering::SeqCst);
+    let max_dim = read_option(PREVIEW_MAX_DIM_OPTION)
+        .and_then(|v| v.parse::<i32>().ok())
+        .filter(|v| PREVIEW_MAX_DIM_RANGE.contains(v))
+        .unwrap_or(DEFAULT_PREVIEW_MAX_DIM);
+    PREVIEW_MAX_DIM.store(max_dim, Ordering::SeqCst);
+}
 
+// 获取预览画面的 JPEG 质量
+#[tauri::command]
+pub fn get_preview_quality() -> Result<CustomResult, CustomResult> {
+    ensure_ready()?;
     Ok(CustomResult::success(
         None,
-        Some(json!({
-            "display_base64": result.display_base64,
-            "raw_base64": result.raw_base64
-        })),
+        Some(json!({"quality": preview_quality(), "default": DEFAULT_PREVIEW_QUALITY})),
     ))
 }
 
-// 从摄像头中检测人脸
+// 修改预览画面的 JPEG 质量，立即生效
 #[tauri::command]
-pub fn check_face_from_camera(face_detection_threshold: f32) -> Result<CustomResult, CustomResult> {
-    let frame = read_mat_from_camera()
-        .map_err(|e| CustomResult::error(Some(format!("摄像头读取失败: {}", e)), None))?;
-
-    let result = detect_and_format(frame, face_detection_threshold)
-        .map_err(|e| CustomResult::error(Some(format!("OpenCV 检测失败: {}", e)), None))?;
+pub fn set_preview_quality(quality: i32) -> Result<CustomResult, CustomResult> {
+    ensure_ready()?;
+    let quality = validate_preview_quality(quality)?;
+    db_writer::write(move |tx| save_option(tx, PREVIEW_QUALITY_OPTION, &quality.to_string()))
+        .map_err(|e| CustomResult::error(Some(e), None))?;
+    PREVIEW_QUALITY.store(quality, Ordering::SeqCst);
+    Ok(CustomResult::success(None, Some(json!({"quality": quality}))))
+}
 
+// 获取检测和预览图片的最大边长
+#[tauri::command]
+pub fn get_preview_max_dim() -> Result<CustomResult, CustomResult> {
+    ensure_ready()?;
     Ok(CustomResult::success(
         None,
         Some(json!({
-            "display_base64": result.display_base64,
-            "raw_base64": result.raw_base64
+            "max_dim": preview_max_dim(),
+            "default": DEFAULT_PREVIEW_MAX_DIM,
+            "min": PREVIEW_MAX_DIM_RANGE.start(),
+            "max": PREVIEW_MAX_DIM_RANGE.end(),
         })),
     ))
 }
 
-// 一致性验证
+// 修改检测和预览图片的最大边长，立即生效
 #[tauri::command]
-pub async fn verify_face(
-    reference_base64: String,
-    face_detection_threshold: f32,
-) -> Result<CustomResult, CustomResult> {
-    let frame = read_mat_from_camera()
-        .map_err(|e| CustomResult::error(Some(format!("摄像头读取失败: {}", e)), None))?;
-    // 解码图片
-    let ref_bytes = general_purpose::STANDARD
-        .decode(reference_base64)
-        .map_err(|e| CustomResult::error(Some(format!("图片解码失败: {}", e)), None))?;
-    let v = Vector::<u8>::from_iter(ref_bytes);
-    let ref_img = imgcodecs::imdecode(&v, opencv::imgcodecs::IMREAD_COLOR)
-        .map_err(|e| CustomResult::error(Some(format!("从bse64读取图片失败: {}", e)), None))?;
-
-    let ref_feature = get_feature(&ref_img, face_detection_threshold)
-        .map_err(|e| CustomResult::error(Some(format!("特征提取失败: {}", e)), None))?;
-    let cur_feature = get_feature(&frame, face_detection_threshold)
-        .map_err(|e| CustomResult::error(Some(format!("特征提取失败: {}", e)), None))?;
-
-    let app_state = APP_STATE
-        .lock()
-        .map_err(|e| CustomResult::error(Some(format!("获取app状态失败 {}", e)), None))?;
+pub fn set_preview_max_dim(max_dim: i32) -> Result<CustomResult, CustomResult> {
+    ensure_ready()?;
+    let max_dim = validate_preview_max_dim(max_dim)?;
+    db_writer::write(move |tx| save_option(tx, PREVIEW_MAX_DIM_OPTION, &max_dim.to_string()))
+        .map_err(|e| CustomResult::error(Some(e), None))?;
+    PREVIEW_MAX_DIM.store(max_dim, Ordering::SeqCst);
+    Ok(CustomResult::success(None, Some(json!({"max_dim": max_dim}))))
+}
 
-    let Some(recognizer) = app_state.recognizer.as_ref() else {
-        return Err(CustomResult::error(
-            Some(String::from("人脸识别模型未初始化")),
-            None,
-        ));
-    };
+// 摄像头被遮挡时错误信息的前缀，调用方用 contains 判断
+pub const CAMERA_OBSTRUCTED: &str = "CameraObstructed";
 
-    let score = recognizer
-        .inner
-        .match_(
-            &ref_feature,
-            &cur_feature,
-            FaceRecognizerSF_DisType::FR_COSINE.into(),
-        )
-        .map_err(|e| CustomResult::error(Some(format!("特征匹配失败: {}", e)), None))?;
+// 黑帧检测配置
+// 画面整体很暗（均值低）并且几乎没有细节（标准差低）时判定为黑帧
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BlackFrameConfig {
+    /// 灰度均值阈值，低于该值视为过暗
+    pub mean_threshold: f64,
+    /// 灰度标准差阈值，低于该值视为画面无内容
+    pub stddev_threshold: f64,
+    /// 连续多少帧黑帧后判定为摄像头被遮挡
+    pub limit: i32,
+}
 
-    let mut result_mat = frame.clone();
-    if let Ok(resize_mat) = resize_mat(&frame, 800.0) {
-        result_mat = resize_mat;
+impl Default for BlackFrameConfig {
+    fn default() -> Self {
+        Self {
+            mean_threshold: 12.0,
+            stddev_threshold: 6.0,
+            limit: 15,
+        }
     }
-    Ok(CustomResult::success(
-        None,
-        Some(json!(
-            {
-                "score": score,
-                "display_base64": mat_to_base64(&result_mat)
-            }
-        )),
-    ))
 }
 
-// 保存特征到文件
-#[tauri::command]
-pub fn save_face_registration(
-    name: String,
-    reference_base64: String,
-    face_detection_threshold: f32,
-) -> Result<CustomResult, CustomResult> {
-    // 获取软件数据目录并创建 faces 文件夹
-    let path = ROOT_DIR.join("faces");
+// 从数据库加载黑帧检测配置，环境本身较暗的用户可以调低阈值
+pub fn load_black_frame_config() {
+    let default = BlackFrameConfig::default();
+    let config = BlackFrameConfig {
+        mean_threshold: read_option("blackFrameMean")
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(default.mean_threshold),
+        stddev_threshold: read_option("blackFrameStddev")
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(default.stddev_threshold),
+        limit: read_option("blackFrameLimit")
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(default.limit),
+    };
 
-    if !path.exists() {
-        std::fs::create_dir_all(&path).map_err(|e| {
-            CustomResult::error(Some(format!("创建 faces 文件夹失败: {}", e)), None)
-        })?;
+    if let Ok(mut guard) = BLACK_FRAME_CONFIG.lock() {
+        *guard = config;
     }
+}
 
-    // 解码图片
-    let ref_bytes = general_purpose::STANDARD
-        .decode(reference_base64)
-        .map_err(|e| CustomResult::error(Some(format!("图片解码失败: {}", e)), None))?;
-    let v = Vector::<u8>::from_iter(ref_bytes);
-    let ref_img = imgcodecs::imdecode(&v, opencv::imgcodecs::IMREAD_COLOR)
-        .map_err(|e| CustomResult::error(Some(format!("从bse64读取图片失败: {}", e)), None))?;
-
-    let feature_mat = get_feature(&ref_img, face_detection_threshold)
-        .map_err(|e| CustomResult::error(Some(format!("特征提取失败: {}", e)), None))?;
-
-    let descriptor = FaceDescriptor::from_mat(&name, &feature_mat)
-        .map_err(|e| CustomResult::error(Some(format!("特征描述失败: {}", e)), None))?;
+// 摄像头读取失败时的返回值，被遮挡时在 data 中带上 condition 方便前端区分
+pub fn camera_error(e: String) -> CustomResult {
+    if e.contains(CAMERA_OBSTRUCTED) {
+        CustomResult::error(
+            Some(format!("摄像头读取失败: {}", e)),
+            Some(json!({"condition": CAMERA_OBSTRUCTED})),
+        )
+    } else {
+        CustomResult::error(Some(format!("摄像头读取失败: {}", e)), None)
+    }
+}
 
-    let base_name = Uuid::new_v4();
+struct CaptureResponse {
+    display_base64: String, // 带框的
+    raw_base64: String,     // 不带框的（仅缩放）
+    faces: Vec<FaceBox>,    // 检测到的所有人脸，坐标为缩放后图片的坐标
+    width: i32,             // 缩放后图片的尺寸，坐标以此为准
+    height: i32,
+}
 
-    // 保存特征
-    let feature_name = format!("{}.face", base_name);
-    let mut feature_path = path.clone();
-    feature_path.push(feature_name);
-    save_face_data(&feature_path, &descriptor)
-        .map_err(|e| CustomResult::error(Some(format!("保存特征数据失败: {}", e)), None))?;
+// 检测到的一张人脸
+#[derive(Debug, Clone, Copy, Serialize)]
+struct FaceBox {
+    x: f32,
+    y: f32,
+    width: f32,
+    height: f32,
+    // 检测置信度，与顶层的 confidence 含义相同
+    #[serde(rename = "confidence")]
+    score: f32,
+    // 是否为主人脸（录入和匹配使用的人脸）
+    primary: bool,
+    // 右眼、左眼、鼻尖、右嘴角、左嘴角
+    landmarks: [Landmark; 5],
+}
 
-    // 保存图片
-    let file_name = format!("{}.faceimg", base_name);
-    let mut file_path = path.clone();
-    file_path.push(file_name);
-    let resize_mat: Mat = resize_mat(&ref_img, 800.0)
-        .map_err(|e| CustomResult::error(Some(format!("图片缩放失败: {}", e)), None))?;
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+struct Landmark {
+    x: f32,
+    y: f32,
+}
 
-    let mut buf = Vector::<u8>::new();
-    imgcodecs::imencode(".jpg", &resize_mat, &mut buf, &Vector::new()).unwrap();
-    fs::write(file_path, buf).map_err(|e| {
-        // 图片保存失败删除面容特征
-        if let Err(err) = fs::remove_file(feature_path.clone()) {
-            CustomResult::error(
-                Some(format!(
-                    "特征文件删除失败: {} 文件地址：{:?}",
-                    err, feature_path
-                )),
-                None,
-            )
-        } else {
-            CustomResult::error(Some(format!("图片保存失败: {}", e)), None)
+impl CaptureResponse {
+    // 主人脸的框和五官坐标，前端用于自己绘制或提示对齐
+    fn primary_json(&self) -> serde_json::Value {
+        match self.faces.iter().find(|f| f.primary) {
+            Some(face) => json!({
+                "box": {"x": face.x, "y": face.y, "w": face.width, "h": face.height},
+                "landmarks": face.landmarks,
+                "confidence": face.score,
+            }),
+            None => json!({"box": null, "landmarks": [], "confidence": null}),
         }
-    })?;
+    }
 
-    Ok(CustomResult::success(
-        None,
-        Some(json!({"file_name": base_name})),
-    ))
+    // 检测命令返回给界面的内容
+    fn to_json(&self) -> serde_json::Value {
+        let primary = self.primary_json();
+        // 主人脸置信度低于录入下限时界面提示靠近或正对摄像头，这样的画面录入会被拒绝
+        let low_confidence = primary["confidence"]
+            .as_f64()
+            .is_some_and(|c| (c as f32) < min_enroll_confidence());
+        json!({
+            "display_base64": self.display_base64,
+            "raw_base64": self.raw_base64,
+            "width": self.width,
+            "height": self.height,
+            "box": primary["box"],
+            "landmarks": primary["landmarks"],
+            // 主人脸的检测置信度
+            "confidence": primary["confidence"],
+            "low_confidence": low_confidence,
+            // 检测到的所有人脸，预览中每张都画框
+            "face_count": self.faces.len(),
+            "faces": self.faces
+        })
+    }
 }
 
-// 提取特征点
-pub fn get_feature(img: &Mat, face_detection_threshold: f32) -> Result<Mat, String> {
-    let mut app_state = APP_STATE
-        .lock()
-        .map_err(|e| format!("获取app状态失败 {}", e))?;
+// 图片数据的大小上限，误选了超大文件时直接拒绝
+const MAX_IMAGE_BYTES: usize = 32 * 1024 * 1024;
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
 
-    if app_state.detector.is_none() {
-        return Err(String::from("人脸检测模型未初始化"));
-    }
-    if app_state.recognizer.is_none() {
-        return Err(String::from("人脸识别模型未初始化"));
+// 解码 base64 或 data URL，忽略其中的空白和换行
+fn decode_base64_image(text: &str) -> Result<Vec<u8>, String> {
+    let text = text.trim();
+    let payload = match text.strip_prefix("data:") {
+        Some(rest) => {
+            let (header, data) = rest
+                .split_once(',')
+                .ok_or_else(|| String::from("data URL 格式错误"))?;
+            if !header.ends_with(";base64") {
+                return Err(String::from("只支持 base64 编码的 data URL"));
+            }
+            data
+        }
+        None => text,
+    };
+    let cleaned: String = payload.chars().filter(|c| !c.is_whitespace()).collect();
+    if cleaned.len() / 4 * 3 > MAX_IMAGE_BYTES {
+        return Err(format!("图片过大，最大支持 {} MB", MAX_IMAGE_BYTES / 1024 / 1024));
     }
+    general_purpose::STANDARD
+        .decode(cleaned)
+        .map_err(|e| format!("base64 数据无效: {}", e))
+}
 
-    let faces = {
-        let detector = app_state.detector.as_mut().unwrap();
-        let mut faces = Mat::default();
-        detector
-            .inner
-            .set_input_size(img.size().map_err(|e| format!("获取Mat尺寸失败: {}", e))?)
-            .map_err(|e| format!("设置输入尺寸失败: {}", e))?;
-        detector
-            .inner
-            .set_score_threshold(face_detection_threshold)
-            .map_err(|e| format!("设置分数阈值失败: {}", e))?;
-        detector
-            .inner
-            .detect(img, &mut faces)
-            .map_err(|e| format!("OpenCV 检测失败: {}", e))?;
-        faces
-    };
-
-    if faces.rows() > 0 {
-        let mut aligned = Mat::default();
-        let mut feature = Mat::default();
-
-        let recognizer = app_state.recognizer.as_mut().unwrap();
-        // 人脸对齐与裁剪
-        recognizer
-            .inner
-            .align_crop(img, &faces.row(0).unwrap(), &mut aligned)
-            .map_err(|e| format!("人脸对齐失败: {}", e))?;
-        // 提取特征
-        recognizer
-            .inner
-            .feature(&aligned, &mut feature)
-            .map_err(|e| format!("特征提取失败: {}", e))?;
-
-        Ok(feature.clone())
+// PNG 的颜色类型为灰度 + 透明或 RGBA
+fn is_png_with_alpha(bytes: &[u8]) -> bool {
+    bytes.starts_with(PNG_SIGNATURE) && bytes.get(25).is_some_and(|t| *t == 4 || *t == 6)
+}
+
+// EXIF 中的方向标签
+const EXIF_ORIENTATION_TAG: u16 = 0x0112;
+
+// 读取 JPEG（APP1）或 PNG（eXIf）中的 EXIF 方向（1-8），没有或无法解析时返回 None
+fn exif_orientation(bytes: &[u8]) -> Option<u16> {
+    let tiff = if bytes.starts_with(&[0xFF, 0xD8]) {
+        jpeg_exif(bytes)?
+    } else if bytes.starts_with(PNG_SIGNATURE) {
+        png_exif(bytes)?
     } else {
-        Err("未检测到人脸".into())
+        return None;
+    };
+    let little = match tiff.get(..2)? {
+        b"II" => true,
+        b"MM" => false,
+        _ => return None,
+    };
+    let u16_at = |at: usize| {
+        let b = tiff.get(at..at + 2)?;
+        Some(if little { u16::from_le_bytes([b[0], b[1]]) } else { u16::from_be_bytes([b[0], b[1]]) })
+    };
+    let u32_at = |at: usize| {
+        let b = tiff.get(at..at + 4)?;
+        Some(if little { u32::from_le_bytes([b[0], b[1], b[2], b[3]]) } else { u32::from_be_bytes([b[0], b[1], b[2], b[3]]) })
+    };
+    // IFD0 中每个条目 12 字节：标签、类型、数量、值
+    let ifd = u32_at(4)? as usize;
+    let count = u16_at(ifd)? as usize;
+    (0..count)
+        .map(|i| ifd + 2 + i * 12)
+        .find(|&entry| u16_at(entry) == Some(EXIF_ORIENTATION_TAG))
+        .and_then(|entry| u16_at(entry + 8))
+        .filter(|orientation| (1..=8).contains(orientation))
+}
+
+// JPEG 的 APP1 段中 "Exif\0\0" 之后的 TIFF 数据
+fn jpeg_exif(bytes: &[u8]) -> Option<&[u8]> {
+    let mut at = 2;
+    while at + 4 <= bytes.len() && bytes[at] == 0xFF {
+        let marker = bytes[at + 1];
+        // 图像数据开始后不会再有 EXIF
+        if marker == 0xDA {
+            return None;
+        }
+        let len = u16::from_be_bytes([bytes[at + 2], bytes[at + 3]]) as usize;
+        let segment = bytes.get(at + 4..at + 2 + len)?;
+        if marker == 0xE1 {
+            if let Some(tiff) = segment.strip_prefix(b"Exif\0\0") {
+                return Some(tiff);
+            }
+        }
+        at += 2 + len;
     }
+    None
 }
 
-// 从摄像头中读取视频帧
-pub fn read_mat_from_camera() -> Result<Mat, String> {
-    // 此处在 proc中，face_recog_type == "operation" 时，如果系统进入睡眠状态
-    // 这里会变成死锁，而Win + L锁屏就不会，并且按延迟时间的解锁，即便进入睡眠状态
-    // 也不会变成死锁，具体原因不明，真让人头大...
-    // 所以这里改成 try_lock，不堵塞主线程了
-    let mut app_state = None;
-    for _ in 0..3 {
-        if let Ok(inner) = APP_STATE.try_lock() {
-            app_state = Some(inner);
-            break;
+// PNG 的 eXIf 块中的 TIFF 数据
+fn png_exif(bytes: &[u8]) -> Option<&[u8]> {
+    let mut at = PNG_SIGNATURE.len();
+    while at + 8 <= bytes.len() {
+        let len = u32::from_be_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]]) as usize;
+        let kind = &bytes[at + 4..at + 8];
+        if kind == b"IDAT" {
+            return None;
         }
-        sleep(Duration::from_millis(100));
+        if kind == b"eXIf" {
+            return bytes.get(at + 8..at + 8 + len);
+        }
+        // 长度、类型、数据、CRC
+        at = at.checked_add(12 + len)?;
     }
-    
-    // 重试3次还拿不到，再返回错误
-    let mut app_state = app_state.ok_or_else(|| {
-        format!("获取app状态失败（锁被占用超过300ms）")
-    })?;
+    None
+}
 
-    // 如果摄像头没打开
-    if app_state.camera.is_none() {
-        return Err(String::from("请先打开摄像头"));
+// 按 EXIF 方向旋转、翻转画面，与 OpenCV 读取图片时的处理一致
+fn apply_exif_orientation(img: Mat, orientation: u16) -> Result<Mat, String> {
+    let flip = |src: &Mat, code: i32| {
+        let mut dst = Mat::default();
+        core::flip(src, &mut dst, code).map(|_| dst).map_err(|e| format!("翻转图片失败: {}", e))
+    };
+    let transpose = |src: &Mat| {
+        let mut dst = Mat::default();
+        core::transpose(src, &mut dst).map(|_| dst).map_err(|e| format!("旋转图片失败: {}", e))
+    };
+    match orientation {
+        2 => flip(&img, 1),
+        3 => flip(&img, -1),
+        4 => flip(&img, 0),
+        5 => transpose(&img),
+        6 => flip(&transpose(&img)?, 1),
+        7 => flip(&transpose(&img)?, -1),
+        8 => flip(&transpose(&img)?, 0),
+        _ => Ok(img),
     }
+}
 
-    let cam = app_state.camera.as_mut().unwrap();
-    let mut frame = Mat::default();
+// 解码图片数据，统一转换为 8 位 BGR，并按 EXIF 方向转正（手机竖拍的照片）
+// 返回的画面和预览、坐标都使用转正后的方向
+fn decode_image(bytes: &[u8]) -> Result<Mat, String> {
+    let img = decode_image_unrotated(bytes)?;
+    match exif_orientation(bytes) {
+        Some(orientation) if orientation != 1 => apply_exif_orientation(img, orientation),
+        _ => Ok(img),
+    }
+}
 
-    cam.inner
-        .read(&mut frame)
-        .map_err(|e| format!("摄像头读取失败: {}", e))?;
+// 解码时忽略 EXIF 方向，透明通道和颜色按同一方向合成后再统一转正
+fn decode_image_unrotated(bytes: &[u8]) -> Result<Mat, String> {
+    if bytes.is_empty() {
+        return Err(String::from("图片数据为空"));
+    }
+    if bytes.len() > MAX_IMAGE_BYTES {
+        return Err(format!("图片过大，最大支持 {} MB", MAX_IMAGE_BYTES / 1024 / 1024));
+    }
+    let v = Vector::<u8>::from_slice(bytes);
+    let color = imgcodecs::imdecode(&v, imgcodecs::IMREAD_COLOR | imgcodecs::IMREAD_IGNORE_ORIENTATION)
+        .map_err(|e| format!("OpenCV 解码失败: {}", e))?;
+    if color.empty() {
+        return Err(String::from("图片读取失败"));
+    }
+    if !is_png_with_alpha(bytes) {
+        return Ok(color);
+    }
 
-    if frame.empty() {
-        return Err(String::from("抓取到空帧"));
+    // IMREAD_COLOR 会丢掉透明通道，透明区域通常变成黑色，粘贴的截图需要铺到白底上再检测
+    let unchanged = imgcodecs::imdecode(&v, imgcodecs::IMREAD_UNCHANGED)
+        .map_err(|e| format!("OpenCV 解码失败: {}", e))?;
+    if unchanged.channels() != 4 {
+        return Ok(color);
+    }
+    let mut alpha = Mat::default();
+    core::extract_channel(&unchanged, &mut alpha, 3).map_err(|e| format!("读取透明通道失败: {}", e))?;
+    if alpha.depth() != core::CV_8U {
+        // 16 位 PNG
+        let mut alpha8 = Mat::default();
+        alpha
+            .convert_to(&mut alpha8, core::CV_8U, 1.0 / 257.0, 0.0)
+            .map_err(|e| format!("转换透明通道失败: {}", e))?;
+        alpha = alpha8;
     }
+    let mut white = Mat::new_rows_cols_with_default(color.rows(), color.cols(), core::CV_8UC3, Scalar::all(255.0))
+        .map_err(|e| format!("创建图片失败: {}", e))?;
+    // 半透明像素按不透明处理，人脸区域一般不透明
+    color
+        .copy_to_masked(&mut white, &alpha)
+        .map_err(|e| format!("合成图片失败: {}", e))?;
+    Ok(white)
+}
 
-    Ok(frame)
+// 检测图片中的人脸，返回带框和不带框的图片
+fn check_face_from_mat(
+    src: Mat,
+    face_detection_threshold: f32,
+    format: OutputFormat,
+    max_dim: f32,
+) -> Result<CustomResult, CustomResult> {
+    let result = detect_and_format(src, face_detection_threshold, None, format, preview_quality(), max_dim)
+        .map_err(|e| CustomResult::error(Some(format!("OpenCV 检测失败: {}", e)), None))?;
+    Ok(CustomResult::success(None, Some(result.to_json())))
 }
 
-// 等比例缩放Mat
-fn resize_mat(src: &Mat, max_dim: f32) -> Result<Mat, String> {
-    let size = src.size().map_err(|e| e.to_string())?;
-    let scale = (max_dim / (size.width.max(size.height) as f32)).min(1.0);
+// base64 无法解析和图片无法解码时的错误条件，界面据此给出不同提示
+pub const INVALID_BASE64: &str = "invalid_base64";
+pub const INVALID_IMAGE: &str = "invalid_image";
 
-    let mut resize_mat = Mat::default();
-    if scale < 1.0 {
-        let new_size = Size::new(
-            (size.width as f32 * scale) as i32,
-            (size.height as f32 * scale) as i32,
-        );
-        imgproc::resize(
-            &src,
-            &mut resize_mat,
-            new_size,
-            0.0,
-            0.0,
-            imgproc::INTER_AREA,
-        )
-        .ok();
-    } else {
-        resize_mat = src.clone();
-    }
+// 解析 base64（或 data URL）图片，两种失败使用不同的错误条件
+fn decode_base64_mat(text: &str) -> Result<Mat, CustomResult> {
+    let bytes = decode_base64_image(text)
+        .map_err(|e| CustomResult::error(Some(e), Some(json!({"condition": INVALID_BASE64}))))?;
+    decode_image(&bytes).map_err(|e| CustomResult::error(Some(e), Some(json!({"condition": INVALID_IMAGE}))))
+}
 
-    Ok(resize_mat)
+// 从图片中检测人脸，img_base64 不为空时直接使用内存中的图片，不读取 img_path
+// output_format 为 "jpeg"（默认）或 "png"，同时作用于带框和不带框的图片
+// max_dim 为检测和返回图片的最大边长，未指定时使用设置中的值；返回的人脸坐标对应缩放后的图片
+#[tauri::command]
+pub fn check_face_from_img(
+    img_path: Option<String>,
+    face_detection_threshold: f32,
+    img_base64: Option<String>,
+    output_format: Option<OutputFormat>,
+    max_dim: Option<i32>,
+) -> Result<CustomResult, CustomResult> {
+    ensure_ready()?;
+    let max_dim = resolve_max_dim(max_dim)?;
+    let src = match (img_base64, img_path) {
+        (Some(data), _) => decode_base64_mat(&data)?,
+        (None, Some(path)) => read_image_file(&path)?,
+        (None, None) => return Err(CustomResult::error(Some(String::from("缺少参数 imgPath 或 imgBase64")), None)),
+    };
+    check_face_from_mat(src, face_detection_threshold, output_format.unwrap_or_default(), max_dim)
 }
 
-// 处理人脸特征点
-fn detect_and_format(src: Mat, face_detection_threshold: f32) -> Result<CaptureResponse, String> {
-    let mut app_state = APP_STATE
-        .lock()
-        .map_err(|e| format!("获取app状态失败 {}", e))?;
+// 读取图片文件
+fn read_image_file(img_path: &str) -> Result<Mat, CustomResult> {
+    // 从fs读取图片
+    // opencv不支持中文，搞了半个小时 ...
+    let bytes = std::fs::read(img_path)
+        .map_err(|e| CustomResult::error(Some(format!("图片读取失败: {}", e)), None))?;
+    decode_image(&bytes).map_err(|e| CustomResult::error(Some(e), None))
+}
 
-    let Some(detector) = app_state.detector.as_mut() else {
-        return Err(String::from("人脸检测模型未初始化"));
+// 从内存中的图片检测人脸（拖放、粘贴），不需要先写入临时文件
+// 请求体可以是二进制图片数据，阈值放在 face-detection-threshold 请求头中；
+// 也可以是参数 image（base64 或 data URL）和 faceDetectionThreshold
+#[tauri::command]
+pub fn check_face_from_bytes(request: tauri::ipc::Request<'_>) -> Result<CustomResult, CustomResult> {
+    ensure_ready()?;
+    let missing = |name: &str| CustomResult::error(Some(format!("缺少参数 {}", name)), None);
+    let (src, face_detection_threshold) = match request.body() {
+        InvokeBody::Raw(bytes) => {
+            let threshold = request
+                .headers()
+                .get("face-detection-threshold")
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.parse::<f32>().ok())
+                .ok_or_else(|| missing("face-detection-threshold"))?;
+            let src = decode_image(bytes)
+                .map_err(|e| CustomResult::error(Some(e), Some(json!({"condition": INVALID_IMAGE}))))?;
+            (src, threshold)
+        }
+        InvokeBody::Json(args) => {
+            let image = args["image"].as_str().ok_or_else(|| missing("image"))?;
+            let threshold = args["faceDetectionThreshold"]
+                .as_f64()
+                .ok_or_else(|| missing("faceDetectionThreshold"))?;
+            (decode_base64_mat(image)?, threshold as f32)
+        }
     };
+    check_face_from_mat(src, face_detection_threshold, OutputFormat::default(), preview_max_dim() as f32)
+}
 
-    // 等比例缩放
-    let raw_mat = resize_mat(&src, 800.0)?;
+// 从摄像头中检测人脸，output_format、max_dim 同 check_face_from_img
+#[tauri::command]
+pub fn check_face_from_camera(
+    face_detection_threshold: f32,
+    output_format: Option<OutputFormat>,
+    max_dim: Option<i32>,
+) -> Result<CustomResult, CustomResult> {
+    ensure_ready()?;
+    let max_dim = resolve_max_dim(max_dim)?;
+    ensure_not_paused()?;
+    // 预览属于后台工作，降低优先级
+    let _priority = PriorityGuard::new(WorkMode::Background);
+    let data = capture_preview_frame(face_detection_threshold, output_format.unwrap_or_default(), max_dim)?;
+    Ok(CustomResult::success(None, Some(data)))
+}
 
-    // 检测
-    let mut display_mat = raw_mat.clone(); // 用于显示的副本
-    let mut faces = Mat::default();
-    detector
-        .inner
-        .set_input_size(
-            display_mat
-                .size()
-                .map_err(|e| format!("获取Mat尺寸失败: {}", e))?,
-        )
-        .map_err(|e| format!("设置输入尺寸失败: {}", e))?;
-    detector
-        .inner
-        .set_score_threshold(face_detection_threshold)
-        .map_err(|e| format!("设置分数阈值失败: {}", e))?;
-    detector
-        .inner
-        .detect(&display_mat, &mut faces)
-        .map_err(|e| format!("OpenCV 检测失败: {}", e))?;
+// 读取一帧并检测，摄像头和模型的锁只在读取和检测期间持有
+fn capture_preview_frame(
+    face_detection_threshold: f32,
+    format: OutputFormat,
+    max_dim: f32,
+) -> Result<serde_json::Value, CustomResult> {
+    let (frame_id, frame) = read_camera_frame().map_err(camera_error)?;
 
-    if faces.rows() > 0 {
-        let x = *faces
-            .at_2d::<f32>(0, 0)
-            .map_err(|e| format!("图片坐标获取失败: {}", e))?;
-        let y = *faces
-            .at_2d::<f32>(0, 1)
-            .map_err(|e| format!("图片坐标获取失败: {}", e))?;
-        let w = *faces
-            .at_2d::<f32>(0, 2)
-            .map_err(|e| format!("图片坐标获取失败: {}", e))?;
-        let h = *faces
-            .at_2d::<f32>(0, 3)
-            .map_err(|e| format!("图片坐标获取失败: {}", e))?;
-
-        let color = Scalar::new(255.0, 242.0, 0.0, 0.0);
-        imgproc::rectangle(
-            &mut display_mat,
-            Rect::new(x as i32, y as i32, w as i32, h as i32),
-            color,
-            2,
-            imgproc::LINE_8,
-            0,
-        )
-        .map_err(|e| format!("图片绘制失败: {}", e))?;
+    let result = detect_and_format(
+        frame,
+        face_detection_threshold,
+        Some(frame_id),
+        format,
+        preview_quality(),
+        max_dim,
+    )
+        .map_err(|e| CustomResult::error(Some(format!("OpenCV 检测失败: {}", e)), None))?;
+    let mut data = result.to_json();
+    data["frame_id"] = json!(frame_id);
+    Ok(data)
+}
 
-        // 绘制五官
-        for i in (4..14).step_by(2) {
-            // 五官不影响检测结果，所以绘制失败可以忽略
-            if let (Ok(px), Ok(py)) = (faces.at_2d::<f32>(0, i), faces.at_2d::<f32>(0, i + 1)) {
-                imgproc::circle(
-                    &mut display_mat,
-                    Point::new(*px as i32, *py as i32),
-                    4,
-                    Scalar::new(0.0, 255.0, 0.0, 0.0), // 绿色
-                    -1,
-                    imgproc::LINE_AA,
-                    0,
-                )
-                .ok();
+// 实时预览每秒推送的帧数
+pub const DEFAULT_PREVIEW_FPS: u32 = 15;
+const PREVIEW_FPS_RANGE: std::ops::RangeInclusive<u32> = 1..=30;
+// 连续失败多少次后停止预览，未检测到人脸不算失败
+const MAX_PREVIEW_FAILURES: u32 = 30;
+pub const CAMERA_FRAME_EVENT: &str = "camera-frame";
+pub const PREVIEW_STOPPED_EVENT: &str = "camera-preview-stopped";
+
+// 开始实时预览：后台读取摄像头画面并检测，通过 camera-frame 事件推送给界面，不需要界面轮询
+// 每一帧的内容与 check_face_from_camera 相同，失败时为 {"error", "condition"}；再次调用会先停止之前的预览
+#[tauri::command]
+pub fn start_preview(
+    face_detection_threshold: f32,
+    fps: Option<u32>,
+    output_format: Option<OutputFormat>,
+    max_dim: Option<i32>,
+) -> Result<CustomResult, CustomResult> {
+    ensure_ready()?;
+    ensure_not_paused()?;
+    let max_dim = resolve_max_dim(max_dim)?;
+    let fps = fps.unwrap_or(DEFAULT_PREVIEW_FPS);
+    if !PREVIEW_FPS_RANGE.contains(&fps) {
+        return Err(CustomResult::error(
+            Some(format!(
+                "预览帧率必须在 {} 到 {} 之间，当前为 {}",
+                PREVIEW_FPS_RANGE.start(),
+                PREVIEW_FPS_RANGE.end(),
+                fps
+            )),
+            None,
+        ));
+    }
+    let format = output_format.unwrap_or_default();
+
+    let cancel = Arc::new(AtomicBool::new(false));
+    {
+        let mut app_state = APP_STATE
+            .lock()
+            .map_err(|e| CustomResult::error(Some(format!("获取app状态失败 {}", e)), None))?;
+        if let Some(previous) = app_state.preview_cancel.replace(cancel.clone()) {
+            previous.store(true, Ordering::SeqCst);
+        }
+    }
+
+    let interval = Duration::from_millis(1000 / fps as u64);
+    std::thread::spawn(move || {
+        let _priority = PriorityGuard::new(WorkMode::Background);
+        let mut failures = 0;
+        let reason = loop {
+            if cancel.load(Ordering::SeqCst) {
+                break "stopped";
+            }
+            let started = Instant::now();
+            // 会议期间不读取摄像头，等待恢复
+            if let Err(e) = ensure_not_paused() {
+                emit_event(CAMERA_FRAME_EVENT, json!({"error": e.msg, "condition": e.data["condition"]}));
+                sleep(interval);
+                continue;
+            }
+            match capture_preview_frame(face_detection_threshold, format, max_dim) {
+                Ok(frame) => {
+                    failures = 0;
+                    emit_event(CAMERA_FRAME_EVENT, frame);
+                }
+                Err(e) => {
+                    if !e.msg.contains("未检测到人脸") {
+                        failures += 1;
+                    }
+                    emit_event(CAMERA_FRAME_EVENT, json!({"error": e.msg, "condition": e.data["condition"]}));
+                    if failures >= MAX_PREVIEW_FAILURES {
+                        warn!("实时预览连续 {} 次失败，已停止：{}", failures, e.msg);
+                        break "camera_error";
+                    }
+                }
+            }
+            if let Some(rest) = interval.checked_sub(started.elapsed()) {
+                sleep(rest);
+            }
+        };
+
+        // 只清除自己的标记，不影响之后开始的预览
+        if let Ok(mut app_state) = APP_STATE.lock() {
+            if app_state.preview_cancel.as_ref().is_some_and(|c| Arc::ptr_eq(c, &cancel)) {
+                app_state.preview_cancel = None;
             }
         }
+        emit_event(PREVIEW_STOPPED_EVENT, json!({"reason": reason}));
+        info!("实时预览已结束：{}", reason);
+    });
 
-        Ok(CaptureResponse {
-            display_base64: mat_to_base64(&display_mat),
-            raw_base64: mat_to_base64(&raw_mat),
-        })
+    info!("开始实时预览，{} 帧/秒", fps);
+    Ok(CustomResult::success(None, Some(json!({"fps": fps, "max_dim": max_dim}))))
+}
+
+// 停止实时预览，返回之前是否在预览
+#[tauri::command]
+pub fn stop_preview() -> Result<CustomResult, CustomResult> {
+    let previous = APP_STATE
+        .lock()
+        .map_err(|e| CustomResult::error(Some(format!("获取app状态失败 {}", e)), None))?
+        .preview_cancel
+        .take();
+    let running = previous.is_some();
+    if let Some(cancel) = previous {
+        cancel.store(true, Ordering::SeqCst);
+    }
+    Ok(CustomResult::success(None, Some(json!({"stopped": running}))))
+}
+
+// SFace 余弦相似度的推荐阈值（OpenCV 文档），没有设置时使用
+pub const DEFAULT_MATCH_THRESHOLD: f64 = 0.363;
+// SFace L2 距离的推荐阈值（OpenCV 文档），距离不超过该值视为同一人
+pub const DEFAULT_L2_THRESHOLD: f64 = 1.128;
+// 设置项：一致性验证和 1:N 识别使用的余弦阈值（0-1），新录入面容的解锁阈值也以此为默认值
+pub const MATCH_THRESHOLD_OPTION: &str = "matchThreshold";
+// 设置项：检测器的初始分数阈值和非极大值抑制阈值
+const SCORE_THRESHOLD_OPTION: &str = "detectorScoreThreshold";
+const NMS_THRESHOLD_OPTION: &str = "detectorNmsThreshold";
+// 设置项：检测器非极大值抑制前保留的候选框数量
+const TOP_K_OPTION: &str = "detectorTopK";
+pub const DEFAULT_TOP_K: i32 = 5000;
+const TOP_K_RANGE: std::ops::RangeInclusive<i32> = 1..=10000;
+
+// 检测和匹配阈值，保存在 AppState 中，修改后不需要重新加载模型
+// 检测时传入了 face_detection_threshold 的调用仍使用传入的分数阈值
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct MatchConfig {
+    pub score_threshold: f32,
+    pub nms_threshold: f32,
+    pub match_threshold: f32,
+    // 旧版本界面提交的设置中没有该字段
+    #[serde(default = "default_top_k")]
+    pub top_k: i32,
+}
+
+fn default_top_k() -> i32 {
+    DEFAULT_TOP_K
+}
+
+impl Default for MatchConfig {
+    fn default() -> Self {
+        Self {
+            score_threshold: 0.9,
+            nms_threshold: 0.3,
+            match_threshold: DEFAULT_MATCH_THRESHOLD as f32,
+            top_k: DEFAULT_TOP_K,
+        }
+    }
+}
+
+impl MatchConfig {
+    fn validate(&self) -> Result<(), CustomResult> {
+        for (name, value) in [
+            ("score_threshold", self.score_threshold),
+            ("nms_threshold", self.nms_threshold),
+        ] {
+            if !value.is_finite() || !(0.0..=1.0).contains(&value) {
+                return Err(CustomResult::error(
+                    Some(format!("{} 必须在 0 到 1 之间，当前为 {}", name, value)),
+                    None,
+                ));
+            }
+        }
+        if !TOP_K_RANGE.contains(&self.top_k) {
+            return Err(CustomResult::error(
+                Some(format!(
+                    "top_k 必须在 {} 到 {} 之间，当前为 {}",
+                    TOP_K_RANGE.start(),
+                    TOP_K_RANGE.end(),
+                    self.top_k
+                )),
+                None,
+            ));
+        }
+        validate_match_threshold(self.match_threshold as f64).map(|_| ())
+    }
+
+    // 应用到 AppState 和已加载的检测器
+    fn apply(self) -> Result<(), String> {
+        let mut app_state = APP_STATE
+            .lock()
+            .map_err(|e| format!("获取app状态失败 {}", e))?;
+        app_state.match_config = self;
+        if let Some(detector) = app_state.detector.as_mut() {
+            detector
+                .inner
+                .set_score_threshold(self.score_threshold)
+                .and_then(|_| detector.inner.set_nms_threshold(self.nms_threshold))
+                .and_then(|_| detector.inner.set_top_k(self.top_k))
+                .map_err(|e| format!("设置检测器阈值失败: {}", e))?;
+        }
+        Ok(())
+    }
+}
+
+// 余弦阈值必须在 0-1 之间
+fn validate_match_threshold(threshold: f64) -> Result<f64, CustomResult> {
+    if !threshold.is_finite() || !(0.0..=1.0).contains(&threshold) {
+        return Err(CustomResult::error(
+            Some(format!("匹配阈值必须在 0 到 1 之间，当前为 {}", threshold)),
+            None,
+        ));
+    }
+    Ok(threshold)
+}
+
+// 归一化特征的 L2 距离在 0-2 之间
+fn validate_l2_threshold(threshold: f64) -> Result<f64, CustomResult> {
+    if !threshold.is_finite() || !(0.0..=2.0).contains(&threshold) {
+        return Err(CustomResult::error(
+            Some(format!("L2 阈值必须在 0 到 2 之间，当前为 {}", threshold)),
+            None,
+        ));
+    }
+    Ok(threshold)
+}
+
+// 从数据库加载检测和匹配阈值，无效的值使用默认值
+pub fn load_match_config() {
+    let default = MatchConfig::default();
+    let read = |key: &str, default: f32| {
+        read_option(key)
+            .and_then(|v| v.parse::<f32>().ok())
+            .filter(|v| v.is_finite() && (0.0..=1.0).contains(v))
+            .unwrap_or(default)
+    };
+    let config = MatchConfig {
+        score_threshold: read(SCORE_THRESHOLD_OPTION, default.score_threshold),
+        nms_threshold: read(NMS_THRESHOLD_OPTION, default.nms_threshold),
+        match_threshold: read(MATCH_THRESHOLD_OPTION, default.match_threshold),
+        top_k: read_option(TOP_K_OPTION)
+            .and_then(|v| v.parse::<i32>().ok())
+            .filter(|v| TOP_K_RANGE.contains(v))
+            .unwrap_or(default.top_k),
+    };
+    if let Err(e) = config.apply() {
+        warn!("加载检测和匹配阈值失败：{}", e);
+    }
+}
+
+pub fn match_config() -> MatchConfig {
+    APP_STATE
+        .lock()
+        .map(|state| state.match_config)
+        .unwrap_or_default()
+}
+
+// 当前的匹配阈值
+pub fn match_threshold() -> f64 {
+    match_config().match_threshold as f64
+}
+
+// 保存并立即应用
+fn save_match_config(config: MatchConfig) -> Result<(), CustomResult> {
+    config.validate()?;
+    db_writer::write(move |tx| {
+        save_option(tx, SCORE_THRESHOLD_OPTION, &config.score_threshold.to_string())?;
+        save_option(tx, NMS_THRESHOLD_OPTION, &config.nms_threshold.to_string())?;
+        save_option(tx, TOP_K_OPTION, &config.top_k.to_string())?;
+        save_option(tx, MATCH_THRESHOLD_OPTION, &config.match_threshold.to_string())
+    })
+    .map_err(|e| CustomResult::error(Some(e), None))?;
+    config.apply().map_err(|e| CustomResult::error(Some(e), None))?;
+    info!("检测和匹配阈值已设置为 {:?}", config);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_match_config() -> Result<CustomResult, CustomResult> {
+    ensure_ready()?;
+    Ok(CustomResult::success(
+        None,
+        Some(json!({"config": match_config(), "default": MatchConfig::default()})),
+    ))
+}
+
+#[tauri::command]
+pub fn set_match_config(config: MatchConfig) -> Result<CustomResult, CustomResult> {
+    ensure_ready()?;
+    save_match_config(config)?;
+    Ok(CustomResult::success(None, Some(json!({"config": config}))))
+}
+
+// 检测器参数：分数阈值、非极大值抑制阈值和候选框数量，设置页面显示和修改
+#[tauri::command]
+pub fn get_detector_params() -> Result<CustomResult, CustomResult> {
+    ensure_ready()?;
+    let config = match_config();
+    let default = MatchConfig::default();
+    Ok(CustomResult::success(
+        None,
+        Some(json!({
+            "score_threshold": config.score_threshold,
+            "nms_threshold": config.nms_threshold,
+            "top_k": config.top_k,
+            "default": {
+                "score_threshold": default.score_threshold,
+                "nms_threshold": default.nms_threshold,
+                "top_k": default.top_k,
+            },
+        })),
+    ))
+}
+
+// 修改检测器参数，立即应用到已加载的检测器并保存，下次启动时加载；匹配阈值不变
+#[tauri::command]
+pub fn set_detector_params(score_threshold: f32, nms_threshold: f32, top_k: i32) -> Result<CustomResult, CustomResult> {
+    ensure_ready()?;
+    let config = MatchConfig { score_threshold, nms_threshold, top_k, ..match_config() };
+    save_match_config(config)?;
+    Ok(CustomResult::success(
+        None,
+        Some(json!({"score_threshold": score_threshold, "nms_threshold": nms_threshold, "top_k": top_k})),
+    ))
+}
+
+#[tauri::command]
+pub fn get_match_threshold() -> Result<CustomResult, CustomResult> {
+    ensure_ready()?;
+    Ok(CustomResult::success(
+        None,
+        Some(json!({"threshold": match_threshold(), "default": DEFAULT_MATCH_THRESHOLD})),
+    ))
+}
+
+#[tauri::command]
+pub fn set_match_threshold(threshold: f64) -> Result<CustomResult, CustomResult> {
+    ensure_ready()?;
+    let threshold = validate_match_threshold(threshold)?;
+    save_match_config(MatchConfig { match_threshold: threshold as f32, ..match_config() })?;
+    Ok(CustomResult::success(None, Some(json!({"threshold": threshold}))))
+}
+
+// 画面完全静止（照片、冻结的虚拟摄像头）时的错误条件
+pub const STATIC_SCENE: &str = "StaticScene";
+// 运动检测读取的帧数和帧间隔
+const MOTION_FRAMES: usize = 4;
+const MOTION_FRAME_INTERVAL: Duration = Duration::from_millis(60);
+// 人脸区域相邻帧灰度差的平均值下限，真实画面即使不动也有传感器噪声
+pub const DEFAULT_MIN_MOTION: f64 = 0.3;
+// 设置项：运动量下限，不同摄像头的噪声差异较大
+const MIN_MOTION_OPTION: &str = "minFaceMotion";
+
+// 已保存的运动量下限
+pub fn min_face_motion() -> f64 {
+    read_option(MIN_MOTION_OPTION)
+        .and_then(|v| v.parse::<f64>().ok())
+        .filter(|v| v.is_finite() && *v >= 0.0)
+        .unwrap_or(DEFAULT_MIN_MOTION)
+}
+
+// 相邻帧在人脸区域内灰度差的平均值，帧数少于 2 时无法计算
+pub fn face_motion(frames: &[Mat], face: Rect) -> Result<f64, String> {
+    if frames.len() < 2 {
+        return Err(String::from("运动检测至少需要两帧"));
+    }
+    let mut regions = Vec::with_capacity(frames.len());
+    for frame in frames {
+        let rect = face & Rect::new(0, 0, frame.cols(), frame.rows());
+        if rect.width <= 0 || rect.height <= 0 {
+            return Err(String::from("人脸区域不在画面内"));
+        }
+        let roi = Mat::roi(frame, rect)
+            .and_then(|roi| roi.try_clone())
+            .map_err(|e| format!("截取人脸区域失败: {}", e))?;
+        let gray = if roi.channels() == 1 {
+            roi
+        } else {
+            let mut gray = Mat::default();
+            imgproc::cvt_color_def(&roi, &mut gray, imgproc::COLOR_BGR2GRAY)
+                .map_err(|e| format!("灰度转换失败: {}", e))?;
+            gray
+        };
+        regions.push(gray);
+    }
+
+    let mut total = 0.0;
+    for pair in regions.windows(2) {
+        // 分辨率变化等情况下尺寸不同，无法比较
+        if pair[0].size().ok() != pair[1].size().ok() {
+            return Err(String::from("相邻帧的尺寸不一致"));
+        }
+        let mut diff = Mat::default();
+        core::absdiff(&pair[0], &pair[1], &mut diff).map_err(|e| format!("计算帧差失败: {}", e))?;
+        total += core::mean(&diff, &core::no_array())
+            .map_err(|e| format!("计算帧差失败: {}", e))?[0];
+    }
+    Ok(total / (regions.len() - 1) as f64)
+}
+
+// 连续读取几帧，计算主人脸区域的运动量，画面静止时拒绝；返回最后一帧和运动量
+// 最后一帧没有人脸时不判断运动（返回 None），由后续的匹配报告未检测到人脸
+pub fn capture_with_motion_check(
+    face_detection_threshold: f32,
+    min_motion: f64,
+) -> Result<(u64, Mat, Option<f64>), CustomResult> {
+    let mut frames = Vec::with_capacity(MOTION_FRAMES);
+    let mut last_id = 0;
+    for i in 0..MOTION_FRAMES {
+        if i > 0 {
+            sleep(MOTION_FRAME_INTERVAL);
+        }
+        let (frame_id, frame) = read_camera_frame().map_err(camera_error)?;
+        last_id = frame_id;
+        frames.push(frame);
+    }
+    let last = frames.last().cloned().unwrap_or_default();
+
+    let faces = {
+        let mut app_state = APP_STATE
+            .lock()
+            .map_err(|e| CustomResult::error(Some(format!("获取app状态失败 {}", e)), None))?;
+        ensure_models_loaded(&mut app_state).map_err(|e| CustomResult::error(Some(e), None))?;
+        let Some(detector) = app_state.detector.as_mut() else {
+            return Err(CustomResult::error(Some(String::from("人脸检测模型未初始化")), None));
+        };
+        match detect_faces(&mut detector.inner, &last, face_detection_threshold) {
+            Ok(faces) => faces,
+            Err(_) => return Ok((last_id, last, None)),
+        }
+    };
+    let size = last
+        .size()
+        .map_err(|e| CustomResult::error(Some(format!("获取Mat尺寸失败: {}", e)), None))?;
+    let row = primary_face(&faces, size) as i32;
+    let value = |col| faces.at_2d::<f32>(row, col).copied().unwrap_or(0.0) as i32;
+    let face = Rect::new(value(0), value(1), value(2), value(3));
+    let motion = check_face_motion(&frames, face, min_motion)?;
+    Ok((last_id, last, Some(motion)))
+}
+
+// 人脸区域的运动量低于下限时拒绝，返回运动量
+fn check_face_motion(frames: &[Mat], face: Rect, min_motion: f64) -> Result<f64, CustomResult> {
+    let motion = face_motion(frames, face).map_err(|e| CustomResult::error(Some(e), None))?;
+    if motion < min_motion {
+        warn!("人脸区域运动量 {:.3} 低于下限 {:.3}，画面可能是照片或冻结的虚拟摄像头", motion, min_motion);
+        return Err(CustomResult::error(
+            Some(format!("画面没有变化（运动量 {:.2}），可能是照片或虚拟摄像头", motion)),
+            Some(json!({"condition": STATIC_SCENE, "motion": motion, "min_motion": min_motion})),
+        ));
+    }
+    Ok(motion)
+}
+
+// verify_face 一次最多读取的帧数
+pub const MAX_VERIFY_FRAMES: u32 = 10;
+
+// 一致性验证，threshold 为余弦阈值（0-1），未指定时使用已保存的匹配阈值
+// metric 为 l2 或 both 时同时判断 L2 距离，l2_threshold 未指定时使用 OpenCV 推荐值
+// liveness 为 true 时先等待一次眨眼，超时未眨眼时不通过
+// motion_check 为 true 时连续读取几帧，人脸区域完全静止时拒绝，min_motion 未指定时使用设置中的值
+// probe_path 为图片路径时用该图片代替摄像头画面，便于用保存的照片调整阈值
+// max_dim 为返回画面的最大边长，未指定时使用设置中的值
+// frames 为读取的帧数（默认 1，最多 MAX_VERIFY_FRAMES），分数取有人脸的帧的平均值，同时返回最高分和有人脸的帧数
+#[tauri::command]
+pub async fn verify_face(
+    reference_base64: String,
+    face_detection_threshold: f32,
+    threshold: Option<f64>,
+    metric: Option<MatchMetric>,
+    l2_threshold: Option<f64>,
+    liveness: Option<bool>,
+    motion_check: Option<bool>,
+    min_motion: Option<f64>,
+    explain: Option<bool>,
+    probe_path: Option<String>,
+    max_dim: Option<i32>,
+    frames: Option<u32>,
+) -> Result<CustomResult, CustomResult> {
+    ensure_ready()?;
+    let max_dim = resolve_max_dim(max_dim)?;
+    // 指定了图片时用图片代替摄像头画面，眨眼和运动检测需要摄像头，此时跳过
+    let probe = match probe_path {
+        Some(path) => Some(read_image_file(&path)?),
+        None => {
+            ensure_not_paused()?;
+            None
+        }
+    };
+    let threshold = match threshold {
+        Some(threshold) => validate_match_threshold(threshold)?,
+        None => match_threshold(),
+    };
+    let metric = metric.unwrap_or_default();
+    let l2_threshold = validate_l2_threshold(l2_threshold.unwrap_or(DEFAULT_L2_THRESHOLD))?;
+    let _priority = PriorityGuard::new(WorkMode::Background);
+    // 眨眼后再读取用于匹配的画面
+    let liveness = if liveness.unwrap_or(false) && probe.is_none() {
+        wait_for_blink(BLINK_TIMEOUT).map_err(camera_error)?
+    } else {
+        BlinkLiveness::Skipped
+    };
+    // 图片代替摄像头画面时只有一帧
+    let frame_count = if probe.is_some() { 1 } else { frames.unwrap_or(1).clamp(1, MAX_VERIFY_FRAMES) };
+    let (first_frame, motion) = if let Some(frame) = probe {
+        // 图片不是摄像头画面，单独分配一个帧序号
+        ((next_frame_id(), frame), None)
+    } else if motion_check.unwrap_or(false) {
+        let (frame_id, frame, motion) =
+            capture_with_motion_check(face_detection_threshold, min_motion.unwrap_or_else(min_face_motion))?;
+        ((frame_id, frame), motion)
     } else {
-        Err(String::from("未检测到人脸"))
+        (read_camera_frame().map_err(camera_error)?, None)
+    };
+    let ref_img = decode_reference(reference_base64)?;
+
+    let ref_feature = get_feature(&ref_img, face_detection_threshold)
+        .map_err(|e| CustomResult::error(Some(format!("特征提取失败: {}", e)), None))?;
+    // 与锁屏解锁使用同一个多人脸策略
+    let policy = MultiFacePolicy::from_option(read_option("multiFacePolicy"));
+    let match_error = |e: String| {
+        if e.starts_with(BYSTANDER_DETECTED) {
+            CustomResult::error(
+                Some(e),
+                Some(json!({"condition": BYSTANDER_DETECTED, "policy": policy.as_str()})),
+            )
+        } else {
+            CustomResult::error(Some(format!("特征提取失败: {}", e)), None)
+        }
+    };
+
+    // 逐帧匹配，没有人脸的帧跳过；分数取有人脸的帧的平均值，显示和匹配详情使用分数最高的帧
+    let mut scores = Vec::with_capacity(frame_count as usize);
+    let mut best: Option<(u64, Mat, FaceMatch)> = None;
+    let mut no_face = None;
+    let mut next_frame = Some(first_frame);
+    for _ in 0..frame_count {
+        let (frame_id, frame) = match next_frame.take() {
+            Some(frame) => frame,
+            None => read_camera_frame().map_err(camera_error)?,
+        };
+        let matched = match match_frame(
+            &frame,
+            std::slice::from_ref(&ref_feature),
+            None,
+            face_detection_threshold,
+            policy,
+            explain.unwrap_or(false),
+        ) {
+            Ok(matched) => matched,
+            Err(e) if frame_count > 1 && e.contains("未检测到人脸") => {
+                no_face = Some(e);
+                continue;
+            }
+            Err(e) => return Err(match_error(e)),
+        };
+        // NaN 分数不能当作任何结果显示
+        if !matched.score.is_finite() {
+            return Err(CustomResult::error(
+                Some(format!("匹配分数无效（{}），模型可能已损坏，请重新安装软件或重新下载模型文件", matched.score)),
+                Some(json!({"condition": MODEL_SANITY_CHECK_FAILED, "stage": "runtime_match", "suggestion": "repair_models"})),
+            ));
+        }
+        scores.push(matched.score);
+        if best.as_ref().map_or(true, |(_, _, b)| matched.score > b.score) {
+            best = Some((frame_id, frame, matched));
+        }
     }
+    let Some((frame_id, frame, matched)) = best else {
+        return Err(match_error(no_face.unwrap_or_else(|| String::from("未检测到人脸"))));
+    };
+    let score = scores.iter().sum::<f64>() / scores.len() as f64;
+    let max_score = matched.score;
+
+    // 余弦越大越好，L2 越小越好
+    let l2_score = l2_from_cosine(score);
+    let cosine_passed = score >= threshold;
+    let l2_passed = l2_score <= l2_threshold;
+    let passed = liveness != BlinkLiveness::Timeout && metric.passed(cosine_passed, l2_passed);
+
+    // 只用于显示，使用预览画面质量；同一帧只编码一次
+    let display_base64 = encode_jpeg_cached(frame_id, &frame, max_dim, preview_quality())
+        .map(|bytes| jpeg_to_data_url(&bytes))
+        .map_err(|e| CustomResult::error(Some(e), None))?;
+    Ok(CustomResult::success(
+        None,
+        Some(json!(
+            {
+                // 多帧时为有人脸的帧的平均分数
+                "score": score,
+                "max_score": max_score,
+                "frames": frame_count,
+                "face_frames": scores.len(),
+                "threshold": threshold,
+                "l2_score": l2_score,
+                "l2_threshold": l2_threshold,
+                "metric": metric,
+                "cosine_passed": cosine_passed,
+                "l2_passed": l2_passed,
+                "passed": passed,
+                "matched": passed,
+                "liveness": liveness,
+                // 未进行运动检测时为 null
+                "motion": motion,
+                "policy": matched.policy,
+                "face_index": matched.face_index,
+                "face_count": matched.face_count,
+                "sample_index": matched.sample_index,
+                "sample_scores": matched.sample_scores,
+                // 最近一次颜色闪烁挑战的分数，没有进行挑战时为 null
+                "challenge_liveness_score": recent_challenge_score(),
+                "display_base64": display_base64
+            }
+        )),
+    ))
 }
 
-fn mat_to_base64(mat: &Mat) -> String {
-    let mut buf = Vector::<u8>::new();
-    imgcodecs::imencode(".jpg", mat, &mut buf, &Vector::new()).unwrap();
-    format!(
-        "data:image/jpeg;base64,{}",
-        general_purpose::STANDARD.encode(buf.as_slice())
-    )
+// 录入图片太暗、人脸太小或太模糊时的错误信息
+pub const IMAGE_TOO_DARK: &str = "图片过暗";
+pub const IMAGE_BLURRY: &str = "图片模糊";
+pub const FACE_TOO_SMALL_MSG: &str = "人脸太小";
+// 对应的错误条件，界面据此提示调整光线、靠近摄像头或保持静止
+pub const TOO_DARK: &str = "TooDark";
+pub const TOO_BLURRY: &str = "TooBlurry";
+pub const FACE_TOO_SMALL: &str = "FaceTooSmall";
+// 计算清晰度前把人脸区域缩放到固定宽度，不同分辨率的摄像头得到的分数可以比较
+const QUALITY_FACE_WIDTH: i32 = 128;
+
+// 录入图片的质量下限，不同摄像头的画面差异较大，可以按摄像头调整
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QualityLimits {
+    /// 人脸区域的灰度均值下限（0-255）
+    pub min_brightness: f64,
+    /// 人脸区域拉普拉斯方差下限，越大越清晰
+    pub min_sharpness: f64,
+    /// 人脸宽度占画面宽度的比例下限（0-1）
+    pub min_face_ratio: f64,
+}
+
+impl Default for QualityLimits {
+    fn default() -> Self {
+        Self {
+            min_brightness: 50.0,
+            min_sharpness: 60.0,
+            min_face_ratio: 0.15,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct FaceQuality {
+    pub brightness: f64,
+    pub sharpness: f64,
+    pub face_ratio: f64,
+}
+
+impl FaceQuality {
+    // 不满足的条件和错误信息，先判断亮度，过暗的画面通常也不清晰；人脸太小时放大后也不清晰，先于清晰度判断
+    fn problem(&self, limits: &QualityLimits) -> Option<(&'static str, &'static str)> {
+        if self.brightness < limits.min_brightness {
+            Some((TOO_DARK, IMAGE_TOO_DARK))
+        } else if self.face_ratio < limits.min_face_ratio {
+            Some((FACE_TOO_SMALL, FACE_TOO_SMALL_MSG))
+        } else if self.sharpness < limits.min_sharpness {
+            Some((TOO_BLURRY, IMAGE_BLURRY))
+        } else {
+            None
+        }
+    }
+}
+
+// 计算主人脸区域的亮度、清晰度和人脸占画面的比例
+fn face_quality(img: &Mat, face_detection_threshold: f32, face_index: Option<usize>) -> Result<FaceQuality, String> {
+    let faces = {
+        let mut app_state = APP_STATE
+            .lock()
+            .map_err(|e| format!("获取app状态失败 {}", e))?;
+        ensure_models_loaded(&mut app_state)?;
+        let Some(detector) = app_state.detector.as_mut() else {
+            return Err(String::from("人脸检测模型未初始化"));
+        };
+        detect_faces(&mut detector.inner, img, face_detection_threshold)?
+    };
+    let size = img.size().map_err(|e| format!("获取Mat尺寸失败: {}", e))?;
+    let row = select_face(&faces, size, face_index)? as i32;
+    let value = |col| faces.at_2d::<f32>(row, col).copied().unwrap_or(0.0) as i32;
+    let rect = Rect::new(value(0), value(1), value(2), value(3)) & Rect::new(0, 0, size.width, size.height);
+    if rect.width <= 0 || rect.height <= 0 {
+        return Err(String::from("人脸区域不在图片内"));
+    }
+
+    let roi = Mat::roi(img, rect)
+        .and_then(|roi| roi.try_clone())
+        .map_err(|e| format!("截取人脸区域失败: {}", e))?;
+    let gray = if roi.channels() == 1 {
+        roi
+    } else {
+        let mut gray = Mat::default();
+        imgproc::cvt_color_def(&roi, &mut gray, imgproc::COLOR_BGR2GRAY)
+            .map_err(|e| format!("灰度转换失败: {}", e))?;
+        gray
+    };
+    let height = (rect.height as f32 * QUALITY_FACE_WIDTH as f32 / rect.width as f32).round().max(1.0) as i32;
+    let mut resized = Mat::default();
+    imgproc::resize(&gray, &mut resized, Size::new(QUALITY_FACE_WIDTH, height), 0.0, 0.0, imgproc::INTER_AREA)
+        .map_err(|e| format!("缩放人脸区域失败: {}", e))?;
+
+    let brightness = core::mean(&resized, &core::no_array())
+        .map_err(|e| format!("计算亮度失败: {}", e))?[0];
+    let mut laplacian = Mat::default();
+    imgproc::laplacian(&resized, &mut laplacian, core::CV_64F, 1, 1.0, 0.0, core::BORDER_DEFAULT)
+        .map_err(|e| format!("计算清晰度失败: {}", e))?;
+    let mut mean = Mat::default();
+    let mut stddev = Mat::default();
+    core::mean_std_dev(&laplacian, &mut mean, &mut stddev, &core::no_array())
+        .map_err(|e| format!("计算清晰度失败: {}", e))?;
+    let stddev = *stddev
+        .at::<f64>(0)
+        .map_err(|e| format!("计算清晰度失败: {}", e))?;
+
+    Ok(FaceQuality {
+        brightness,
+        sharpness: stddev * stddev,
+        face_ratio: rect.width as f64 / size.width.max(1) as f64,
+    })
 }
 
-// 保存人脸数据到文件
-fn save_face_data(
-    path: &std::path::PathBuf,
-    data: &FaceDescriptor,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let encoded: Vec<u8> = bincode::serialize(data)?;
-    let mut file = std::fs::File::create(path)?;
-    file.write_all(&encoded)?;
+// 录入图片的质量不满足下限时返回错误，condition 为不满足的条件
+fn ensure_face_quality(
+    img: &Mat,
+    face_detection_threshold: f32,
+    face_index: Option<usize>,
+    limits: QualityLimits,
+) -> Result<(), CustomResult> {
+    let quality = face_quality(img, face_detection_threshold, face_index)
+        .map_err(|e| CustomResult::error(Some(format!("特征提取失败: {}", e)), None))?;
+    if let Some((condition, problem)) = quality.problem(&limits) {
+        return Err(CustomResult::error(
+            Some(format!(
+                "{}（亮度 {:.0}，清晰度 {:.0}，人脸占画面 {:.0}%），请调整光线、靠近摄像头或保持静止后重新拍摄",
+                problem, quality.brightness, quality.sharpness, quality.face_ratio * 100.0
+            )),
+            Some(json!({"condition": condition, "quality": quality, "limits": limits})),
+        ));
+    }
     Ok(())
 }
 
-// 从文件加载人脸数据
-pub fn load_face_data(path: &PathBuf) -> Result<FaceDescriptor, Box<dyn std::error::Error>> {
-    let mut file = std::fs::File::open(path)?;
-    let mut buffer = Vec::new();
-    file.read_to_end(&mut buffer)?;
-    let decoded: FaceDescriptor = bincode::deserialize(&buffer)?;
-    Ok(decoded)
+// 检查录入图片的质量，界面在采集时实时显示
+#[tauri::command]
+pub fn check_face_quality(
+    reference_base64: String,
+    face_detection_threshold: f32,
+    limits: Option<QualityLimits>,
+) -> Result<CustomResult, CustomResult> {
+    ensure_ready()?;
+    let limits = limits.unwrap_or_default();
+    let img = decode_reference(reference_base64)?;
+    let quality = face_quality(&img, face_detection_threshold, None)
+        .map_err(|e| CustomResult::error(Some(e), None))?;
+    let problem = quality.problem(&limits);
+    Ok(CustomResult::success(
+        problem.map(|(_, msg)| String::from(msg)),
+        Some(json!({
+            "brightness": quality.brightness,
+            "sharpness": quality.sharpness,
+            "face_ratio": quality.face_ratio,
+            "limits": limits,
+            "passed": problem.is_none(),
+            "condition": problem.map(|(condition, _)| condition),
+        })),
+    ))
+}
+
+// 新面容与已录入的面容疑似同一个人时的结果条件
+pub const SIMILAR_FACE_EXISTS: &str = "SimilarFaceExists";
+// 设置项：录入时判断重复录入的余弦阈值（0-1），没有设置时与一致性验证阈值相同
+const DUPLICATE_THRESHOLD_OPTION: &str = "duplicateFaceThreshold";
+
+// 判断重复录入的阈值，调高后只拦截几乎相同的面容
+pub fn duplicate_threshold() -> f64 {
+    read_option(DUPLICATE_THRESHOLD_OPTION)
+        .and_then(|v| v.parse::<f64>().ok())
+        .filter(|v| v.is_finite() && (0.0..=1.0).contains(v))
+        .unwrap_or_else(match_threshold)
+}
+
+#[tauri::command]
+pub fn get_duplicate_threshold() -> Result<CustomResult, CustomResult> {
+    ensure_ready()?;
+    Ok(CustomResult::success(
+        None,
+        Some(json!({"threshold": duplicate_threshold(), "match_threshold": match_threshold()})),
+    ))
+}
+
+// 保存重复录入的阈值，threshold 为 null 时恢复为与一致性验证阈值相同
+#[tauri::command]
+pub fn set_duplicate_threshold(threshold: Option<f64>) -> Result<CustomResult, CustomResult> {
+    ensure_ready()?;
+    let value = match threshold {
+        Some(threshold) => validate_match_threshold(threshold)?.to_string(),
+        None => String::new(),
+    };
+    db_writer::write(move |tx| save_option(tx, DUPLICATE_THRESHOLD_OPTION, &value))
+        .map_err(|e| CustomResult::error(Some(e), None))?;
+    Ok(CustomResult::success(None, Some(json!({"threshold": duplicate_threshold()}))))
+}
+
+// 名称相同（按 Unicode 大小写折叠比较）的已录入面容，录入后返回给界面提示
+fn same_name_registrations(name: &str) -> Vec<String> {
+    let (faces, _) = cached_faces();
+    faces
+        .into_iter()
+        .filter(|(_, descriptor)| descriptor.name_matches(name))
+        .map(|(file_stem, _)| file_stem)
+        .collect()
+}
+
+// 与已录入的面容比较，返回分数达到重复录入阈值的面容（疑似同一个人），分数从高到低
+// 使用与一致性验证相同的 match_，阈值见 duplicate_threshold
+fn find_similar_registrations(feature: &Mat) -> Result<Vec<serde_json::Value>, String> {
+    let threshold = duplicate_threshold();
+    let (faces, _) = cached_faces();
+    let mut app_state = APP_STATE
+        .lock()
+        .map_err(|e| format!("获取app状态失败 {}", e))?;
+    ensure_models_loaded(&mut app_state)?;
+    let Some(recognizer) = app_state.recognizer.as_mut() else {
+        return Err(String::from("人脸识别模型未初始化"));
+    };
+
+    let mut similar = Vec::new();
+    for (file_stem, descriptor) in faces {
+        // 受保护的面容需要把新特征做同样的变换，密钥不可用时无法比较
+        let probe = match probe_key(&descriptor) {
+            Ok(Some(key)) => key.apply_mat(feature)?,
+            Ok(None) => feature.clone(),
+            Err(_) => continue,
+        };
+        let Ok(references) = descriptor.to_mats() else {
+            continue;
+        };
+        let mut best = f64::NEG_INFINITY;
+        for reference in &references {
+            let score = recognizer
+                .inner
+                .match_(reference, &probe, FaceRecognizerSF_DisType::FR_COSINE.into())
+                .map_err(|e| format!("特征匹配失败: {}", e))?;
+            if score.is_finite() && score > best {
+                best = score;
+            }
+        }
+        if best >= threshold {
+            similar.push((best, json!({"file_name": file_stem, "name": descriptor.name, "score": best})));
+        }
+    }
+    similar.sort_by(|a, b| b.0.total_cmp(&a.0));
+    Ok(similar.into_iter().map(|(_, item)| item).collect())
+}
+
+// 保存特征到文件，图片过暗、人脸太小、模糊或检测置信度过低时拒绝录入，quality_limits 未指定时使用默认下限
+// 与已录入的面容疑似同一个人时不保存，返回 saved 为 false 和相似的面容，确认后传 force 为 true 再保存
+// keep_photo 为 false 时不保存录入图片（.faceimg），默认保存
+#[tauri::command]
+pub fn save_face_registration(
+    name: String,
+    reference_base64: String,
+    face_detection_threshold: f32,
+    quality_limits: Option<QualityLimits>,
+    force: Option<bool>,
+    face_index: Option<usize>,
+    keep_photo: Option<bool>,
+) -> Result<CustomResult, CustomResult> {
+    ensure_ready()?;
+    // 解码图片
+    let ref_img = decode_reference(reference_base64)?;
+
+    ensure_face_quality(&ref_img, face_detection_threshold, face_index, quality_limits.unwrap_or_default())?;
+
+    let FeatureCrop { feature: feature_mat, aligned, rect: face, confidence, face_index } =
+        get_feature_with_crop(&ref_img, face_detection_threshold, face_index)
+            .map_err(|e| CustomResult::error(Some(format!("特征提取失败: {}", e)), None))?;
+    ensure_enroll_confidence(confidence)?;
+
+    if !force.unwrap_or(false) {
+        let similar = find_similar_registrations(&feature_mat)
+            .map_err(|e| CustomResult::error(Some(format!("比较已录入的面容失败: {}", e)), None))?;
+        if let Some(closest) = similar.first() {
+            return Ok(CustomResult::success(
+                Some(format!(
+                    "与已录入的面容“{}”疑似同一个人（相似度 {:.2}）",
+                    closest["name"].as_str().unwrap_or_default(),
+                    closest["score"].as_f64().unwrap_or_default()
+                )),
+                Some(json!({
+                    "saved": false,
+                    "condition": SIMILAR_FACE_EXISTS,
+                    "similar": similar,
+                    "threshold": duplicate_threshold(),
+                })),
+            ));
+        }
+    }
+
+    let same_name = same_name_registrations(&name);
+    let mut descriptor = FaceDescriptor::from_mat(&name, &feature_mat)
+        .map_err(|e| CustomResult::error(Some(format!("特征描述失败: {}", e)), None))?;
+    descriptor.thumbnail = face_avatar(&ref_img, face);
+    // 录入图片用于重新生成特征（更换模型后）和导出备份，不保存时只能重新拍摄
+    let photo = keep_photo.unwrap_or(true).then_some(&ref_img);
+    let base_name = store_registration(descriptor, photo)?;
+
+    Ok(CustomResult::success(
+        None,
+        Some(json!({
+            "saved": true,
+            "file_name": base_name,
+            "confidence": confidence,
+            // 录入使用的人脸在检测结果中的序号，界面据此标出
+            "face_index": face_index,
+            "has_photo": photo.is_some(),
+            // 名称相同的其他面容，界面据此提示改名
+            "same_name": same_name,
+            // 对齐裁剪后的人脸，即识别模型实际使用的画面
+            "thumbnail_base64": mat_to_base64(&aligned, OutputFormat::Jpeg, preview_quality())
+                .map_err(|e| warn!("编码面容缩略图失败：{}", e))
+                .ok(),
+        })),
+    ))
+}
+
+// 头像的最大边长，保存在特征文件中，不宜过大
+const AVATAR_MAX_DIM: f32 = 120.0;
+// 缩略图和头像的 JPEG 质量，图片很小，质量低一些也看得清
+const THUMBNAIL_JPEG_QUALITY: i32 = 70;
+
+fn encode_thumbnail(img: &Mat) -> Option<Vec<u8>> {
+    mat_to_jpeg(img, THUMBNAIL_JPEG_QUALITY)
+        .map_err(|e| warn!("编码面容缩略图失败：{}", e))
+        .ok()
+}
+
+// 截取人脸区域作为头像，头像只用于显示，截取失败不影响录入
+// 人脸框可能超出图片边缘，先与图片范围取交集，完全在图片外时不生成头像
+fn face_avatar(img: &Mat, face: Rect) -> Option<Vec<u8>> {
+    let face = face & Rect::new(0, 0, img.cols(), img.rows());
+    if face.width <= 0 || face.height <= 0 {
+        warn!("人脸区域不在图片范围内，不生成面容头像");
+        return None;
+    }
+    match Mat::roi(img, face)
+        .map_err(|e| e.to_string())
+        .and_then(|roi| resize_mat(&roi, AVATAR_MAX_DIM))
+    {
+        Ok(avatar) => encode_thumbnail(&avatar),
+        Err(e) => {
+            warn!("截取面容头像失败：{}", e);
+            None
+        }
+    }
+}
+
+// 从 base64（或 data URL）解码参考图片，与检测图片使用同一套解码：透明通道铺白底、按 EXIF 方向转正、拒绝空图片
+pub fn decode_reference(reference_base64: String) -> Result<Mat, CustomResult> {
+    decode_base64_mat(&reference_base64)
+}
+
+// 录入时保存的图片，缩放后编码为 JPEG
+fn encode_registration_image(ref_img: &Mat) -> Result<Vector<u8>, CustomResult> {
+    let resize_mat: Mat = resize_mat(ref_img, 800.0)
+        .map_err(|e| CustomResult::error(Some(format!("图片缩放失败: {}", e)), None))?;
+
+    let mut buf = Vector::<u8>::new();
+    imgcodecs::imencode(".jpg", &resize_mat, &mut buf, &Vector::new())
+        .map_err(|e| CustomResult::error(Some(format!("图片编码失败: {}", e)), None))?;
+    Ok(buf)
+}
+
+// 保存新面容的特征和图片，返回生成的文件名；ref_img 为 None 时只保存特征
+pub fn store_registration(mut descriptor: FaceDescriptor, ref_img: Option<&Mat>) -> Result<Uuid, CustomResult> {
+    // 获取面容数据目录并创建 faces 文件夹
+    let path = faces_dir().to_path_buf();
+
+    if !path.exists() {
+        std::fs::create_dir_all(&path).map_err(|e| {
+            CustomResult::error(Some(format!("创建 faces 文件夹失败: {}", e)), None)
+        })?;
+    }
+
+    // 开启了模板保护时只保存变换后的特征
+    if template_protection_enabled() {
+        protect_descriptor(&mut descriptor)
+            .map_err(|e| CustomResult::error(Some(format!("保护面容模板失败: {}", e)), None))?;
+    }
+
+    let base_name = Uuid::new_v4();
+
+    // 保存特征
+    save_face_data(&base_name.to_string(), &descriptor)
+        .map_err(|e| CustomResult::error(Some(format!("保存特征数据失败: {}", e)), None))?;
+
+    // 保存图片
+    let Some(ref_img) = ref_img else {
+        return Ok(base_name);
+    };
+    let file_name = format!("{}.faceimg", base_name);
+    let mut file_path = path.clone();
+    file_path.push(file_name);
+    let buf = encode_registration_image(ref_img)?;
+    with_retry(|| fs::write(&file_path, buf.as_slice())).map_err(|e| {
+        // 图片保存失败删除面容特征，同时从缓存中移除
+        if let Err(err) = remove_face_files(&base_name.to_string()) {
+            CustomResult::error(
+                Some(format!(
+                    "特征数据删除失败: {} 面容：{}",
+                    err, base_name
+                )),
+                None,
+            )
+        } else {
+            CustomResult::error(Some(format!("图片保存失败: {}", e)), None)
+        }
+    })?;
+
+    Ok(base_name)
+}
+
+// 多帧录入时与其他样本的平均相似度低于该值的样本视为异常（侧脸、模糊、其他人）
+const ENROLL_OUTLIER_THRESHOLD: f32 = 0.5;
+// 从摄像头采集时默认和最多采集的有效帧数、帧间隔和最长采集时间
+const DEFAULT_ENROLL_FRAMES: usize = 5;
+const MAX_ENROLL_FRAMES: usize = 20;
+const ENROLL_FRAME_INTERVAL: Duration = Duration::from_millis(200);
+const ENROLL_TIMEOUT: Duration = Duration::from_secs(15);
+
+// 归一化后的特征，平均前需要保证每个样本的权重相同
+fn normalized(feature: &[f32]) -> Vec<f32> {
+    let norm = feature.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm <= f32::EPSILON {
+        return feature.to_vec();
+    }
+    feature.iter().map(|x| x / norm).collect()
+}
+
+// 录入时检测置信度的默认下限，侧脸、距离过远、模糊时置信度明显偏低，录入后识别效果差
+pub const DEFAULT_MIN_ENROLL_CONFIDENCE: f32 = 0.8;
+// 设置项：录入时检测置信度的下限（0-1）
+const MIN_ENROLL_CONFIDENCE_OPTION: &str = "minEnrollConfidence";
+// 置信度低于下限时的错误条件，界面据此提示靠近或正对摄像头
+pub const LOW_CONFIDENCE: &str = "LowConfidence";
+
+// 已保存的录入置信度下限
+pub fn min_enroll_confidence() -> f32 {
+    read_option(MIN_ENROLL_CONFIDENCE_OPTION)
+        .and_then(|v| v.parse::<f32>().ok())
+        .filter(|v| (0.0..=1.0).contains(v))
+        .unwrap_or(DEFAULT_MIN_ENROLL_CONFIDENCE)
+}
+
+fn ensure_enroll_confidence(confidence: f32) -> Result<(), CustomResult> {
+    let floor = min_enroll_confidence();
+    if confidence >= floor {
+        return Ok(());
+    }
+    Err(CustomResult::error(
+        Some(format!(
+            "人脸检测置信度 {:.2} 低于录入下限 {:.2}，请靠近并正对摄像头后重新拍摄",
+            confidence, floor
+        )),
+        Some(json!({"condition": LOW_CONFIDENCE, "confidence": confidence, "min_confidence": floor})),
+    ))
+}
+
+// 画面中只有一张人脸时返回归一化的特征，多人时无法确定录入的是谁；置信度低于录入下限时拒绝
+fn single_face_feature(img: &Mat, face_detection_threshold: f32) -> Result<Vec<f32>, String> {
+    let features = get_features(img, face_detection_threshold)?;
+    if features.len() != 1 {
+        return Err(format!("画面中有 {} 张人脸，录入时只能有一张", features.len()));
+    }
+    let floor = min_enroll_confidence();
+    if features[0].2 < floor {
+        return Err(format!("人脸检测置信度 {:.2} 低于录入下限 {:.2}", features[0].2, floor));
+    }
+    features[0]
+        .1
+        .data_typed::<f32>()
+        .map(normalized)
+        .map_err(|e| format!("读取特征失败: {}", e))
+}
+
+// 多帧录入：对多张图片（或从已打开的摄像头采集 frames 个有效帧，默认 5 个）分别提取特征，剔除异常样本后取平均值保存为一个面容
+// 从摄像头采集时没有人脸或有多张人脸的帧直接跳过，不计入帧数，超过 ENROLL_TIMEOUT 后按已采集的帧处理
+// 没有检测到人脸的图片和异常样本都计入 rejected，界面据此提示继续采集
+#[tauri::command]
+pub fn save_face_registration_averaged(
+    name: String,
+    references_base64: Option<Vec<String>>,
+    frames: Option<usize>,
+    face_detection_threshold: f32,
+) -> Result<CustomResult, CustomResult> {
+    ensure_ready()?;
+    // (图片或帧的序号, 图片, 归一化特征)
+    let mut samples: Vec<(usize, Mat, Vec<f32>)> = Vec::new();
+    let mut rejected = Vec::new();
+    let total = match references_base64 {
+        Some(references) if !references.is_empty() => {
+            let total = references.len();
+            for (index, reference) in references.into_iter().enumerate() {
+                let image = decode_reference(reference)?;
+                match single_face_feature(&image, face_detection_threshold) {
+                    Ok(feature) => samples.push((index, image, feature)),
+                    Err(e) => rejected.push(json!({"index": index, "reason": e})),
+                }
+            }
+            total
+        }
+        _ => {
+            ensure_not_paused()?;
+            let target = frames.unwrap_or(DEFAULT_ENROLL_FRAMES).clamp(1, MAX_ENROLL_FRAMES);
+            let started = Instant::now();
+            let mut captured = 0;
+            while samples.len() < target && started.elapsed() < ENROLL_TIMEOUT {
+                if captured > 0 {
+                    sleep(ENROLL_FRAME_INTERVAL);
+                }
+                let image = read_mat_from_camera().map_err(camera_error)?;
+                captured += 1;
+                // 跳过的帧不计入 rejected，只影响采集时间
+                if let Ok(feature) = single_face_feature(&image, face_detection_threshold) {
+                    samples.push((captured - 1, image, feature));
+                }
+            }
+            if samples.len() < target {
+                warn!("多帧录入超时：{} 帧中只有 {} 帧可用", captured, samples.len());
+            }
+            captured
+        }
+    };
+    let features: Vec<(usize, Vec<f32>)> = samples
+        .iter()
+        .map(|(index, _, feature)| (*index, feature.clone()))
+        .collect();
+    if features.is_empty() {
+        return Err(CustomResult::error(
+            Some(format!("{} 张图片中都没有检测到可用的人脸，请调整光线和角度后重试", total)),
+            Some(json!({"accepted": 0, "rejected": rejected})),
+        ));
+    }
+
+    // 与其他样本的平均相似度过低的视为异常样本；只有一个样本时无法比较，直接使用
+    let accepted: Vec<&(usize, Vec<f32>)> = if features.len() < 2 {
+        features.iter().collect()
+    } else {
+        features
+            .iter()
+            .filter(|(index, feature)| {
+                let others: Vec<f32> = features
+                    .iter()
+                    .filter(|(other, _)| other != index)
+                    .map(|(_, f)| cosine_score(feature, f))
+                    .collect();
+                let mean = others.iter().sum::<f32>() / others.len() as f32;
+                if mean >= ENROLL_OUTLIER_THRESHOLD {
+                    return true;
+                }
+                rejected.push(json!({"index": index, "reason": format!("与其他样本的平均相似度 {:.3} 过低", mean)}));
+                false
+            })
+            .collect()
+    };
+    if accepted.is_empty() {
+        return Err(CustomResult::error(
+            Some(String::from("采集的样本差异过大，请保持正脸并重新采集")),
+            Some(json!({"accepted": 0, "rejected": rejected})),
+        ));
+    }
+
+    let len = accepted[0].1.len();
+    let mut mean = vec![0.0f32; len];
+    for (_, feature) in &accepted {
+        for (sum, value) in mean.iter_mut().zip(feature) {
+            *sum += value / accepted.len() as f32;
+        }
+    }
+    // 平均后的向量长度小于 1，归一化后再保存
+    let descriptor = FaceDescriptor {
+        name,
+        samples: vec![normalized(&mean)],
+        key_fingerprint: None,
+        thumbnail: None,
+        model_version: recognizer_model_version(),
+        created_at: Some(engine::now_millis()),
+    };
+    // 保存第一张被采用的图片用于显示
+    let first = accepted[0].0;
+    let image = samples
+        .iter()
+        .find(|(index, _, _)| *index == first)
+        .map(|(_, image, _)| image)
+        .ok_or_else(|| CustomResult::error(Some(String::from("找不到录入图片")), None))?;
+    let same_name = same_name_registrations(&descriptor.name);
+    let base_name = store_registration(descriptor, Some(image))?;
+    info!("多帧录入面容 {}：采用 {} 个样本，剔除 {} 个", base_name, accepted.len(), rejected.len());
+
+    Ok(CustomResult::success(
+        None,
+        Some(json!({
+            "file_name": base_name,
+            "accepted": accepted.len(),
+            "rejected": rejected,
+            "total": total,
+            "same_name": same_name,
+        })),
+    ))
+}
+
+// 检查面容文件名，file_name 必须是录入时生成的 UUID（带连字符的标准格式），也用于拼接 .faceimg 图片路径
+// Uuid::parse_str 也接受 {…}、urn:uuid:… 等写法，其中的冒号在 Windows 文件名中有特殊含义，这里只接受标准格式
+fn registration_token(file_name: &str) -> Result<&str, CustomResult> {
+    let invalid = || CustomResult::error(Some(format!("无效的面容文件名 {}", file_name)), None);
+    let uuid = Uuid::parse_str(file_name).map_err(|_| invalid())?;
+    if !uuid.hyphenated().to_string().eq_ignore_ascii_case(file_name) {
+        return Err(invalid());
+    }
+    // UUID 不含路径分隔符，这里再确认一次图片路径仍在面容目录中
+    let image_path = faces_dir().join(format!("{}.faceimg", file_name));
+    if image_path.parent() != Some(faces_dir()) {
+        return Err(invalid());
+    }
+    Ok(file_name)
+}
+
+// 检查面容文件名，并确认面容已保存在数据库中
+fn existing_registration<'a>(conn: &Connection, file_name: &'a str) -> Result<&'a str, CustomResult> {
+    let file_stem = registration_token(file_name)?;
+    let exists = face_store::contains(conn, file_stem)
+        .map_err(|e| CustomResult::error(Some(format!("查询面容失败：{:?}", e)), None))?;
+    if !exists {
+        return Err(face_not_found(file_name));
+    }
+    Ok(file_stem)
+}
+
+// 使用面容数据库检查已录入的面容，删除、更新等命令使用
+fn registered_face(file_name: &str) -> Result<&str, CustomResult> {
+    face_store::with_store(|conn| Ok(existing_registration(conn, file_name)))
+        .map_err(|e| CustomResult::error(Some(e), None))?
+}
+
+fn face_not_found(file_name: &str) -> CustomResult {
+    CustomResult::error(
+        Some(format!("面容 {} 不存在", file_name)),
+        Some(json!({"condition": "not_found"})),
+    )
+}
+
+// 为已录入的面容追加一个样本，匹配时取分数最高的样本
+#[tauri::command]
+pub fn add_registration_sample(
+    file_name: String,
+    reference_base64: String,
+    face_detection_threshold: f32,
+    face_index: Option<usize>,
+) -> Result<CustomResult, CustomResult> {
+    ensure_ready()?;
+    let file_stem = registration_token(&file_name)?;
+    let mut descriptor = load_face_data(file_stem)
+        .map_err(|e| CustomResult::error(Some(format!("加载面容数据失败：{}", e)), None))?;
+
+    let ref_img = decode_reference(reference_base64)?;
+    let crop = get_feature_with_crop(&ref_img, face_detection_threshold, face_index)
+        .map_err(|e| CustomResult::error(Some(format!("特征提取失败: {}", e)), None))?;
+    ensure_enroll_confidence(crop.confidence)?;
+    let feature_mat = crop.feature;
+
+    // 已保护的面容，新样本用同一个密钥变换后再加入
+    let feature_mat = match probe_key(&descriptor).map_err(template_key_error)? {
+        Some(key) => key
+            .apply_mat(&feature_mat)
+            .map_err(|e| CustomResult::error(Some(e), None))?,
+        None => feature_mat,
+    };
+    descriptor
+        .push_sample(&feature_mat)
+        .map_err(|e| CustomResult::error(Some(e.to_string()), None))?;
+    if template_protection_enabled() {
+        protect_descriptor(&mut descriptor)
+            .map_err(|e| CustomResult::error(Some(format!("保护面容模板失败: {}", e)), None))?;
+    }
+    save_face_data(file_stem, &descriptor)
+        .map_err(|e| CustomResult::error(Some(format!("保存特征数据失败: {}", e)), None))?;
+
+    Ok(CustomResult::success(
+        None,
+        Some(json!({"samples": descriptor.samples.len(), "face_index": crop.face_index})),
+    ))
+}
+
+// 用新图片重新录入已有的面容：保留名称和文件名，用新特征替换所有样本，同时替换录入图片
+// 外貌变化后不需要删除再录入，也不会留下无用的面容；面容不存在时返回 not_found
+#[tauri::command]
+pub fn update_face_registration(
+    file_name: String,
+    reference_base64: String,
+    face_detection_threshold: f32,
+    quality_limits: Option<QualityLimits>,
+    face_index: Option<usize>,
+) -> Result<CustomResult, CustomResult> {
+    ensure_ready()?;
+    let file_stem = registered_face(&file_name)?;
+    let old = load_face_data(file_stem)
+        .map_err(|e| CustomResult::error(Some(format!("加载面容数据失败：{}", e)), None))?;
+
+    let ref_img = decode_reference(reference_base64)?;
+    ensure_face_quality(&ref_img, face_detection_threshold, face_index, quality_limits.unwrap_or_default())?;
+    let FeatureCrop { feature, rect, confidence, face_index, .. } =
+        get_feature_with_crop(&ref_img, face_detection_threshold, face_index)
+        .map_err(|e| CustomResult::error(Some(format!("特征提取失败: {}", e)), None))?;
+    ensure_enroll_confidence(confidence)?;
+
+    let mut descriptor = FaceDescriptor::from_mat(&old.name, &feature)
+        .map_err(|e| CustomResult::error(Some(format!("特征描述失败: {}", e)), None))?;
+    descriptor.thumbnail = face_avatar(&ref_img, rect);
+    if template_protection_enabled() {
+        protect_descriptor(&mut descriptor)
+            .map_err(|e| CustomResult::error(Some(format!("保护面容模板失败: {}", e)), None))?;
+    }
+    let image = encode_registration_image(&ref_img)?;
+    // 在一条语句中替换特征，失败时原特征不变
+    save_face_data(file_stem, &descriptor)
+        .map_err(|e| CustomResult::error(Some(format!("保存特征数据失败: {}", e)), None))?;
+    // 图片只用于显示，特征已经更新，替换失败只记录日志
+    let image_path = faces_dir().join(format!("{}.faceimg", file_name));
+    let temp_path = image_path.with_extension("faceimg.tmp");
+    let image_saved = with_retry(|| fs::write(&temp_path, image.as_slice()))
+        .and_then(|_| with_retry(|| fs::rename(&temp_path, &image_path)))
+        .map_err(|e| warn!("替换面容 {} 的图片失败：{}", file_name, e))
+        .is_ok();
+    info!("面容 {}（{}）已重新录入，替换了 {} 个样本", file_name, descriptor.name, old.samples.len());
+
+    Ok(CustomResult::success(
+        None,
+        Some(json!({
+            "file_name": file_name,
+            "name": descriptor.name,
+            "confidence": confidence,
+            "face_index": face_index,
+            "replaced_samples": old.samples.len(),
+            "protected": descriptor.key_fingerprint.is_some(),
+            "image_saved": image_saved,
+        })),
+    ))
+}
+
+// 删除表现差的样本，只保留 keep_indices 中的样本，至少保留一个
+#[tauri::command]
+pub fn prune_registration_samples(
+    file_name: String,
+    keep_indices: Vec<usize>,
+) -> Result<CustomResult, CustomResult> {
+    ensure_ready()?;
+    let file_stem = registration_token(&file_name)?;
+    let mut descriptor = load_face_data(file_stem)
+        .map_err(|e| CustomResult::error(Some(format!("加载面容数据失败：{}", e)), None))?;
+
+    let total = descriptor.samples.len();
+    if let Some(index) = keep_indices.iter().find(|&&i| i >= total) {
+        return Err(CustomResult::error(
+            Some(format!("样本序号 {} 超出范围（共 {} 个样本）", index, total)),
+            None,
+        ));
+    }
+    let mut keep = keep_indices;
+    keep.sort_unstable();
+    keep.dedup();
+    if keep.is_empty() {
+        return Err(CustomResult::error(
+            Some(String::from("至少需要保留一个样本")),
+            None,
+        ));
+    }
+
+    descriptor.samples = keep
+        .iter()
+        .map(|&i| std::mem::take(&mut descriptor.samples[i]))
+        .collect();
+    save_face_data(file_stem, &descriptor)
+        .map_err(|e| CustomResult::error(Some(format!("保存特征数据失败: {}", e)), None))?;
+    info!("面容 {} 保留 {} 个样本，删除 {} 个", file_name, keep.len(), total - keep.len());
+
+    Ok(CustomResult::success(
+        None,
+        Some(json!({"kept": keep, "removed": total - keep.len()})),
+    ))
+}
+
+// 列出数据库中的面容，无法解析的面容单独列出，不影响其他面容
+#[tauri::command]
+pub fn list_registered_faces() -> Result<CustomResult, CustomResult> {
+    ensure_ready()?;
+    let stored = face_store::with_store(face_store::load_all)
+        .map_err(|e| CustomResult::error(Some(e), None))?;
+
+    let mut faces = Vec::new();
+    let mut corrupt = Vec::new();
+    for face in stored {
+        let file_name = face.face_token.clone();
+        match decode_stored_face(&face) {
+            Ok(descriptor) => faces.push(json!({
+                "file_name": file_name,
+                "name": descriptor.name,
+                "feature_len": descriptor.samples.first().map_or(0, |s| s.len()),
+                "samples": descriptor.samples.len(),
+                "protected": descriptor.key_fingerprint.is_some(),
+                "thumbnail_base64": descriptor.thumbnail.as_deref().map(jpeg_to_data_url),
+                // 是否保存了录入图片（.faceimg），没有时无法导出备份或重新生成特征
+                "has_photo": faces_dir().join(format!("{}.faceimg", file_name)).exists(),
+            })),
+            Err(e) => {
+                let error = e.to_string();
+                // 无法解密、已损坏的特征单独标记，前端据此提示重新录入
+                let condition = [FACE_FILE_UNREADABLE, DESCRIPTOR_CORRUPTED]
+                    .into_iter()
+                    .find(|condition| error.starts_with(condition));
+                corrupt.push(json!({"file_name": file_name, "error": error, "condition": condition}))
+            }
+        }
+    }
+    // errors 与 corrupt 内容相同，设置页面使用 errors
+    Ok(CustomResult::success(
+        None,
+        Some(json!({"faces": faces, "errors": corrupt, "corrupt": corrupt})),
+    ))
+}
+
+// 删除一个面容的特征和图片，返回剩余的面容数量
+// 已损坏、无法解密的面容也可以删除；前端 database.db 中的记录由前端删除
+#[tauri::command]
+pub fn delete_face_registration(file_name: String) -> Result<CustomResult, CustomResult> {
+    ensure_ready()?;
+    // 文件名必须是 UUID，拒绝 ..\ 等路径；面容不存在时返回 not_found
+    let file_stem = registered_face(&file_name)?;
+    // remove_face_files 会同时把该面容从特征缓存中移除，解锁时不会再使用已删除的面容
+    remove_face_files(file_stem)
+        .map_err(|e| CustomResult::error(Some(format!("删除面容失败：{}", e)), None))?;
+    info!("已删除面容 {}", file_name);
+
+    let remaining = face_store::with_store(face_store::count).unwrap_or(0);
+    Ok(CustomResult::success(None, Some(json!({"remaining": remaining}))))
+}
+
+// 重新读取数据库中的面容，替换内存中的面容缓存
+// 软件会在保存/删除面容时更新缓存，这里用于手动恢复
+#[tauri::command]
+pub fn reload_face_cache() -> Result<CustomResult, CustomResult> {
+    ensure_ready()?;
+    let (loaded, invalid) = reload_cached_faces();
+    Ok(CustomResult::success(
+        None,
+        Some(json!({"loaded": loaded, "errors": invalid})),
+    ))
+}
+
+// 升级目录中所有旧格式或未加密的特征文件，单个文件失败时继续处理其他文件
+// 只用于导入数据库之前的旧文件，返回升级的数量和失败的文件（文件名, 原因）
+pub fn upgrade_face_files(dir: &Path) -> std::io::Result<(usize, Vec<(String, String)>)> {
+    let mut upgraded = 0;
+    let mut errors = Vec::new();
+    for path in fs::read_dir(dir)?.flatten().map(|e| e.path()) {
+        if path.extension().and_then(|e| e.to_str()) != Some("face") {
+            continue;
+        }
+        match upgrade_descriptor_file(&path) {
+            Ok(true) => upgraded += 1,
+            Ok(false) => {}
+            Err(e) => errors.push((path.file_name().unwrap_or_default().to_string_lossy().into_owned(), e.to_string())),
+        }
+    }
+    Ok((upgraded, errors))
+}
+
+// 导入的面容：文件名、特征、数据库中是否已有同名面容
+pub type ImportedFace = (String, FaceDescriptor, bool);
+
+// 把目录中的 .face 文件导入面容数据库，导入成功后删除文件，单个文件失败时保留该文件并继续处理其他文件
+// 返回导入的面容和失败的文件（文件名, 原因），只更新数据库，缓存由调用方更新
+pub fn import_face_files(
+    conn: &Connection,
+    dir: &Path,
+) -> std::io::Result<(Vec<ImportedFace>, Vec<(String, String)>)> {
+    let mut imported = Vec::new();
+    let mut errors = Vec::new();
+    for path in fs::read_dir(dir)?.flatten().map(|e| e.path()) {
+        if path.extension().and_then(|e| e.to_str()) != Some("face") {
+            continue;
+        }
+        let Some(file_stem) = path.file_stem().and_then(|s| s.to_str()).map(String::from) else {
+            continue;
+        };
+        match import_face_file(conn, &path, &file_stem) {
+            Ok((descriptor, existed)) => imported.push((file_stem, descriptor, existed)),
+            Err(e) => errors.push((file_stem, e.to_string())),
+        }
+    }
+    Ok((imported, errors))
+}
+
+pub fn import_face_file(
+    conn: &Connection,
+    path: &PathBuf,
+    file_stem: &str,
+) -> Result<(FaceDescriptor, bool), Box<dyn std::error::Error>> {
+    let (descriptor, _, _) = read_descriptor(path)?;
+    let existed = face_store::contains(conn, file_stem)?;
+    face_store::save(conn, &stored_face(file_stem, &descriptor)?)?;
+    // 数据库写入成功后才删除文件，删除失败时下次导入会再次写入相同的内容
+    mark_own_write(path);
+    with_retry(|| fs::remove_file(path))?;
+    Ok((descriptor, existed))
+}
+
+// 升级数据库中旧版本格式的特征，单个面容失败时继续处理其他面容
+// 返回升级的数量和失败的面容（文件名, 原因）
+pub fn upgrade_stored_faces() -> Result<(usize, Vec<(String, String)>), String> {
+    let stored = face_store::with_store(face_store::load_all)?;
+    let mut upgraded = 0;
+    let mut errors = Vec::new();
+    for face in stored {
+        match unseal_descriptor(&face.feature) {
+            Ok((_, version, true)) if version == DESCRIPTOR_VERSION => {}
+            Ok((descriptor, _, _)) => match save_face_data(&face.face_token, &descriptor) {
+                Ok(()) => upgraded += 1,
+                Err(e) => errors.push((face.face_token, e.to_string())),
+            },
+            Err(e) => errors.push((face.face_token, e.to_string())),
+        }
+    }
+    Ok((upgraded, errors))
+}
+
+// 手动导入和升级面容：启动时的迁移只执行一次，之后拷入面容目录的 .face 文件可以用这里导入
+// 同时把数据库中旧版本格式的特征升级为当前版本，并报告无法处理的面容
+#[tauri::command]
+pub fn migrate_face_files() -> Result<CustomResult, CustomResult> {
+    ensure_ready()?;
+    let (imported, mut errors) = match face_store::with_store(|conn| Ok(import_face_files(conn, faces_dir()))) {
+        Ok(Ok(result)) => result,
+        Ok(Err(e)) if e.kind() == std::io::ErrorKind::NotFound => (Vec::new(), Vec::new()),
+        Ok(Err(e)) => return Err(CustomResult::error(Some(format!("读取面容目录失败：{}", e)), None)),
+        Err(e) => return Err(CustomResult::error(Some(e), None)),
+    };
+    for (file_stem, descriptor, _) in &imported {
+        cache_saved_face(file_stem, descriptor);
+    }
+    let (upgraded, upgrade_errors) = upgrade_stored_faces().map_err(|e| CustomResult::error(Some(e), None))?;
+    errors.extend(upgrade_errors);
+    if !imported.is_empty() || upgraded > 0 {
+        info!("已导入 {} 个面容文件，升级 {} 个面容", imported.len(), upgraded);
+    }
+    let errors: Vec<_> = errors
+        .into_iter()
+        .map(|(file_name, error)| json!({"file_name": file_name, "error": error}))
+        .collect();
+    Ok(CustomResult::success(
+        None,
+        Some(json!({
+            "imported": imported.len(),
+            "upgraded": upgraded,
+            "version": DESCRIPTOR_VERSION,
+            "errors": errors,
+        })),
+    ))
+}
+
+// 修改面容保存的名称，特征不变
+#[tauri::command]
+pub fn rename_face_registration(file_name: String, name: String) -> Result<CustomResult, CustomResult> {
+    ensure_ready()?;
+    let name = name.trim().to_string();
+    if name.is_empty() {
+        return Err(CustomResult::error(Some(String::from("名称不能为空")), None));
+    }
+    let file_stem = registration_token(&file_name)?;
+    let mut descriptor = load_face_data(file_stem)
+        .map_err(|e| CustomResult::error(Some(format!("加载面容数据失败：{}", e)), None))?;
+    let old_name = std::mem::replace(&mut descriptor.name, name);
+    // 在一条语句中替换名称和特征，失败时原数据不变
+    save_face_data(file_stem, &descriptor)
+        .map_err(|e| CustomResult::error(Some(format!("保存特征数据失败: {}", e)), None))?;
+    info!("面容 {} 已从 {} 改名为 {}", file_name, old_name, descriptor.name);
+
+    Ok(CustomResult::success(
+        None,
+        Some(json!({
+            "file_name": file_name,
+            "name": descriptor.name,
+            "feature_len": descriptor.samples.first().map_or(0, |s| s.len()),
+            "samples": descriptor.samples.len(),
+            "protected": descriptor.key_fingerprint.is_some(),
+        })),
+    ))
+}
+
+// 保存面容对应的 Windows 用户名，前端添加或修改账户时调用，与 database.db 中的 user_name 保持一致
+#[tauri::command]
+pub fn set_face_username(file_name: String, user_name: String) -> Result<CustomResult, CustomResult> {
+    ensure_ready()?;
+    let user_name = user_name.trim().to_string();
+    if user_name.is_empty() {
+        return Err(CustomResult::error(Some(String::from("用户名不能为空")), None));
+    }
+    let file_stem = registration_token(&file_name)?;
+    let found = face_store::with_store(|conn| face_store::set_username(conn, file_stem, &user_name))
+        .map_err(|e| CustomResult::error(Some(format!("保存用户名失败: {}", e)), None))?;
+    if !found {
+        return Err(CustomResult::error(Some(format!("面容 {} 不存在", file_name)), None));
+    }
+
+    Ok(CustomResult::success(
+        None,
+        Some(json!({"file_name": file_name, "user_name": user_name})),
+    ))
+}
+
+// 余弦相似度，与 FaceRecognizerSF 的 FR_COSINE 一致
+fn cosine_score(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return f32::NAN;
+    }
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm = |v: &[f32]| v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    dot / (norm(a) * norm(b))
+}
+
+// 1:N 识别：读取一帧，与面容目录中所有面容比较，返回分数最高的面容和第二名的分数
+// 无法解析的面容文件跳过，不影响其他面容；最高分低于阈值时返回未匹配
+// 比较只使用缓存中的特征，识别模型只在提取特征时加锁
+// metric 与一致性验证相同，默认余弦；排名按余弦分数，归一化后余弦最高的也是 L2 距离最小的
+#[tauri::command]
+pub fn identify_face(
+    face_detection_threshold: f32,
+    threshold: Option<f32>,
+    metric: Option<MatchMetric>,
+    l2_threshold: Option<f64>,
+) -> Result<CustomResult, CustomResult> {
+    ensure_ready()?;
+    ensure_not_paused()?;
+    let _priority = PriorityGuard::new(WorkMode::Background);
+    let threshold = match threshold {
+        Some(threshold) => validate_match_threshold(threshold as f64)? as f32,
+        None => match_threshold() as f32,
+    };
+    let metric = metric.unwrap_or_default();
+    let l2_threshold = validate_l2_threshold(l2_threshold.unwrap_or(DEFAULT_L2_THRESHOLD))?;
+    // 使用缓存中的面容，不再每次读取面容目录；没有面容时不需要读取摄像头
+    let (faces, invalid) = active_cached_faces().map_err(|e| CustomResult::error(Some(e), None))?;
+    if faces.is_empty() {
+        return Err(CustomResult::error(
+            Some(String::from("没有可用于识别的面容，请先录入面容")),
+            Some(json!({"condition": NO_REGISTERED_FACES, "invalid": invalid})),
+        ));
+    }
+
+    let frame = read_mat_from_camera().map_err(camera_error)?;
+    // 画面中的每张人脸都参与识别，旁边有其他人时也能识别出已录入的人
+    let features = get_features(&frame, face_detection_threshold)
+        .map_err(|e| CustomResult::error(Some(format!("特征提取失败: {}", e)), None))?
+        .into_iter()
+        .map(|(index, mat, _)| mat.data_typed::<f32>().map(|data| (index, data.to_vec())))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| CustomResult::error(Some(format!("读取特征失败: {}", e)), None))?;
+    let face_count = features.len();
+
+    // 每个面容的最高分：(文件名, 名称, 分数, 样本序号, 人脸序号)
+    let mut ranked: Vec<(String, String, f32, usize, usize)> = Vec::new();
+    let mut candidates = 0;
+    let mut skipped: Vec<_> = invalid
+        .into_iter()
+        .map(|i| json!({"file_name": i.file_name, "reason": i.error}))
+        .collect();
+    for (file_stem, descriptor) in faces {
+        // 受保护的面容需要把摄像头特征做同样的变换
+        let key = match probe_key(&descriptor) {
+            Ok(key) => key,
+            Err(e) => {
+                warn!("{}", e);
+                skipped.push(json!({"file_name": file_stem, "reason": TEMPLATE_KEY_MISMATCH}));
+                continue;
+            }
+        };
+        candidates += 1;
+        let mut best: Option<(String, String, f32, usize, usize)> = None;
+        for (face_index, feature) in &features {
+            let probe = match &key {
+                Some(key) => key.apply(feature),
+                None => feature.clone(),
+            };
+            let top = descriptor
+                .samples
+                .iter()
+                .enumerate()
+                .map(|(index, sample)| (index, cosine_score(&probe, sample)))
+                .filter(|(_, score)| score.is_finite())
+                .max_by(|a, b| a.1.total_cmp(&b.1));
+            if let Some((sample_index, score)) = top {
+                if best.as_ref().map_or(true, |b| score > b.2) {
+                    best = Some((file_stem.clone(), descriptor.name.clone(), score, sample_index, *face_index));
+                }
+            }
+        }
+        ranked.extend(best);
+    }
+    ranked.sort_by(|a, b| b.2.total_cmp(&a.2));
+    // 第二名的分数，与最高分相差很小时说明两个面容难以区分
+    let runner_up = ranked.get(1).map(|r| (r.1.clone(), r.2));
+    let best = ranked.into_iter().next();
+
+    let l2_score = best.as_ref().map(|b| l2_from_cosine(b.2 as f64));
+    let matched = best.as_ref().zip(l2_score).is_some_and(|(b, l2)| {
+        metric.passed(b.2 >= threshold, l2 <= l2_threshold)
+    });
+    let margin = best.as_ref().zip(runner_up.as_ref()).map(|(b, r)| b.2 - r.1);
+    let (file_name, name, score, sample_index, face_index) = match best {
+        Some((file_name, name, score, sample_index, face_index)) => {
+            (Some(file_name), Some(name), Some(score), Some(sample_index), Some(face_index))
+        }
+        None => (None, None, None, None, None),
+    };
+    Ok(CustomResult::success(
+        (!matched).then(|| String::from("未匹配到已录入的面容")),
+        Some(json!({
+            "matched": matched,
+            // 未匹配时也返回最接近的面容，便于调整阈值
+            "name": name,
+            "file_name": file_name,
+            "score": score,
+            "l2_score": l2_score,
+            "metric": metric,
+            "sample_index": sample_index,
+            // 分数最高的人脸在检测结果中的序号
+            "face_index": face_index,
+            "face_count": face_count,
+            "runner_up_name": runner_up.as_ref().map(|r| r.0.clone()),
+            "runner_up_score": runner_up.as_ref().map(|r| r.1),
+            "margin": margin,
+            "threshold": threshold,
+            "l2_threshold": l2_threshold,
+            "candidates": candidates,
+            "skipped": skipped,
+        })),
+    ))
+}
+
+// 提取特征点，画面中有多张人脸时使用主人脸
+pub fn get_feature(img: &Mat, face_detection_threshold: f32) -> Result<Mat, String> {
+    get_feature_with_crop(img, face_detection_threshold, None).map(|crop| crop.feature)
+}
+
+// 提取特征的人脸和检测信息
+pub struct FeatureCrop {
+    pub feature: Mat,
+    // 对齐裁剪后的人脸，即识别模型实际看到的画面
+    pub aligned: Mat,
+    // 主人脸在图片中的位置
+    pub rect: Rect,
+    // 检测置信度（0-1）
+    pub confidence: f32,
+    // 使用的人脸在检测结果中的序号
+    pub face_index: usize,
+}
+
+// 确定使用的人脸：指定了序号时使用指定的人脸，否则使用主人脸（面积最大）
+fn select_face(faces: &Mat, size: Size, face_index: Option<usize>) -> Result<usize, String> {
+    let count = faces.rows().max(0) as usize;
+    match face_index {
+        Some(index) if index >= count => Err(format!("人脸序号 {} 超出范围（共 {} 张人脸）", index, count)),
+        Some(index) => Ok(index),
+        None => Ok(primary_face(faces, size)),
+    }
+}
+
+// 检测结果第 14 列为置信度
+fn face_confidence(faces: &Mat, row: usize) -> f32 {
+    faces.at_2d::<f32>(row as i32, 14).copied().unwrap_or(0.0)
+}
+
+// 提取主人脸（或 face_index 指定的人脸）的特征，同时返回对齐裁剪后的人脸、位置、置信度和人脸序号
+pub fn get_feature_with_crop(
+    img: &Mat,
+    face_detection_threshold: f32,
+    face_index: Option<usize>,
+) -> Result<FeatureCrop, String> {
+    let mut app_state = APP_STATE
+        .lock()
+        .map_err(|e| format!("获取app状态失败 {}", e))?;
+    let state = &mut *app_state;
+    ensure_models_loaded(state)?;
+    let Some(detector) = state.detector.as_mut() else {
+        return Err(String::from("人脸检测模型未初始化"));
+    };
+    let Some(recognizer) = state.recognizer.as_mut() else {
+        return Err(String::from("人脸识别模型未初始化"));
+    };
+
+    let faces = detect_faces(&mut detector.inner, img, face_detection_threshold)?;
+    let size = img.size().map_err(|e| format!("获取Mat尺寸失败: {}", e))?;
+    let row = select_face(&faces, size, face_index)?;
+    let value = |col| faces.at_2d::<f32>(row as i32, col).copied().unwrap_or(0.0) as i32;
+    let rect = Rect::new(value(0), value(1), value(2), value(3)) & Rect::new(0, 0, size.width, size.height);
+    let (feature, aligned) = extract_feature_with_crop(&mut recognizer.inner, img, &faces, row)?;
+    Ok(FeatureCrop { feature, aligned, rect, confidence: face_confidence(&faces, row), face_index: row })
+}
+
+// 提取画面中每张人脸的特征，返回 (人脸序号, 特征, 置信度)，主人脸排在最前面
+pub fn get_features(img: &Mat, face_detection_threshold: f32) -> Result<Vec<(usize, Mat, f32)>, String> {
+    let mut app_state = APP_STATE
+        .lock()
+        .map_err(|e| format!("获取app状态失败 {}", e))?;
+    let state = &mut *app_state;
+    ensure_models_loaded(state)?;
+    let Some(detector) = state.detector.as_mut() else {
+        return Err(String::from("人脸检测模型未初始化"));
+    };
+    let Some(recognizer) = state.recognizer.as_mut() else {
+        return Err(String::from("人脸识别模型未初始化"));
+    };
+
+    let faces = detect_faces(&mut detector.inner, img, face_detection_threshold)?;
+    let size = img.size().map_err(|e| format!("获取Mat尺寸失败: {}", e))?;
+    let primary = primary_face(&faces, size);
+    let count = faces.rows().max(0) as usize;
+    std::iter::once(primary)
+        .chain((0..count).filter(|&i| i != primary))
+        .map(|index| {
+            let feature = extract_feature(&mut recognizer.inner, img, &faces, index)?;
+            Ok((index, feature, face_confidence(&faces, index)))
+        })
+        .collect()
+}
+
+// 从摄像头中读取一帧并分配帧序号，需要编码画面的使用方把序号和画面一起传递，同一帧只编码一次
+pub fn read_camera_frame() -> Result<(u64, Mat), String> {
+    let frame = read_mat_from_camera()?;
+    Ok((next_frame_id(), frame))
+}
+
+// 从摄像头中读取视频帧
+pub fn read_mat_from_camera() -> Result<Mat, String> {
+    // 校准在其他处理之前应用，录入和解锁看到的画面必须一致
+    let frame = active_calibration().apply(grab_frame()?)?;
+
+    // 隐私挡板、红外补光关闭时，摄像头会返回正常但全黑的帧
+    // 连续多帧全黑时返回单独的错误，而不是一直报未检测到人脸
+    let config = BLACK_FRAME_CONFIG
+        .lock()
+        .map(|c| c.clone())
+        .unwrap_or_default();
+    if is_black_frame(&frame, &config)? {
+        let count = BLACK_FRAME_COUNT.fetch_add(1, Ordering::SeqCst) + 1;
+        if count >= config.limit {
+            return Err(format!(
+                "{}: 连续 {} 帧画面全黑，请检查摄像头挡板或红外补光",
+                CAMERA_OBSTRUCTED, count
+            ));
+        }
+    } else {
+        BLACK_FRAME_COUNT.store(0, Ordering::SeqCst);
+    }
+
+    Ok(frame)
+}
+
+// 从摄像头读取一帧原始画面（未校准），单通道和四通道的画面转换为 BGR
+pub fn grab_frame() -> Result<Mat, String> {
+    // 此处在 proc中，face_recog_type == "operation" 时，如果系统进入睡眠状态
+    // 这里会变成死锁，而Win + L锁屏就不会，并且按延迟时间的解锁，即便进入睡眠状态
+    // 也不会变成死锁，具体原因不明，真让人头大...
+    // 所以这里改成 try_lock，不堵塞主线程了
+    let mut app_state = None;
+    for _ in 0..3 {
+        if let Ok(inner) = APP_STATE.try_lock() {
+            app_state = Some(inner);
+            break;
+        }
+        sleep(Duration::from_millis(100));
+    }
+    
+    // 重试3次还拿不到，再返回错误
+    let mut app_state = app_state.ok_or_else(|| {
+        format!("获取app状态失败（锁被占用超过300ms）")
+    })?;
+
+    // 如果摄像头没打开
+    if app_state.camera.is_none() {
+        return Err(String::from("请先打开摄像头"));
+    }
+
+    let cam = app_state.camera.as_mut().unwrap();
+    let mut frame = Mat::default();
+
+    cam.inner
+        .read(&mut frame)
+        .map_err(|e| format!("摄像头读取失败: {}", e))?;
+
+    if frame.empty() {
+        return Err(String::from("抓取到空帧"));
+    }
+    drop(app_state);
+    to_bgr(frame)
+}
+
+// 红外摄像头输出单通道画面，部分虚拟摄像头输出 BGRA，检测和对齐只支持 BGR，统一转换
+pub fn to_bgr(frame: Mat) -> Result<Mat, String> {
+    let code = match frame.channels() {
+        3 => return Ok(frame),
+        1 => imgproc::COLOR_GRAY2BGR,
+        4 => imgproc::COLOR_BGRA2BGR,
+        channels => return Err(format!("不支持 {} 通道的画面", channels)),
+    };
+    let mut bgr = Mat::default();
+    imgproc::cvt_color_def(&frame, &mut bgr, code).map_err(|e| format!("画面颜色转换失败: {}", e))?;
+    Ok(bgr)
+}
+
+// 判断是否为黑帧，缩小后转灰度计算均值和标准差，开销很小
+pub fn is_black_frame(frame: &Mat, config: &BlackFrameConfig) -> Result<bool, String> {
+    let small = resize_mat(frame, 64.0)?;
+    let gray = if small.channels() == 1 {
+        small
+    } else {
+        let mut gray = Mat::default();
+        imgproc::cvt_color_def(&small, &mut gray, imgproc::COLOR_BGR2GRAY)
+            .map_err(|e| format!("灰度转换失败: {}", e))?;
+        gray
+    };
+
+    let mut mean = Mat::default();
+    let mut stddev = Mat::default();
+    core::mean_std_dev(&gray, &mut mean, &mut stddev, &core::no_array())
+        .map_err(|e| format!("计算画面亮度失败: {}", e))?;
+    let mean = *mean
+        .at::<f64>(0)
+        .map_err(|e| format!("计算画面亮度失败: {}", e))?;
+    let stddev = *stddev
+        .at::<f64>(0)
+        .map_err(|e| format!("计算画面亮度失败: {}", e))?;
+
+    Ok(mean < config.mean_threshold && stddev < config.stddev_threshold)
+}
+
+// 等比例缩放Mat
+pub fn resize_mat(src: &Mat, max_dim: f32) -> Result<Mat, String> {
+    // 空帧无法缩放和编码，直接返回错误而不是得到空图片
+    if src.empty() {
+        return Err(String::from("图片为空"));
+    }
+    let size = src.size().map_err(|e| e.to_string())?;
+    let scale = (max_dim / (size.width.max(size.height) as f32)).min(1.0);
+
+    let mut resize_mat = Mat::default();
+    if scale < 1.0 {
+        let new_size = Size::new(
+            (size.width as f32 * scale) as i32,
+            (size.height as f32 * scale) as i32,
+        );
+        imgproc::resize(
+            &src,
+            &mut resize_mat,
+            new_size,
+            0.0,
+            0.0,
+            imgproc::INTER_AREA,
+        )
+        .map_err(|e| format!("图片缩放失败: {}", e))?;
+    } else {
+        resize_mat = src.clone();
+    }
+
+    Ok(resize_mat)
+}
+
+// 处理人脸特征点
+// frame_id 不为空时，原图的 JPEG 编码结果会按帧缓存；quality 为画框预览图的 JPEG 质量，原图保持默认质量用于录入
+// format 为 PNG 时两张图片都使用 PNG，不经过缓存
+// 检测在缩放到 max_dim 的图片上进行，返回的坐标和两张图片的尺寸一致
+fn detect_and_format(
+    src: Mat,
+    face_detection_threshold: f32,
+    frame_id: Option<u64>,
+    format: OutputFormat,
+    quality: i32,
+    max_dim: f32,
+) -> Result<CaptureResponse, String> {
+    let mut app_state = APP_STATE
+        .lock()
+        .map_err(|e| format!("获取app状态失败 {}", e))?;
+
+    ensure_models_loaded(&mut app_state)?;
+    let Some(detector) = app_state.detector.as_mut() else {
+        return Err(String::from("人脸检测模型未初始化"));
+    };
+
+    // 等比例缩放
+    let raw_mat = resize_mat(&src, max_dim)?;
+
+    // 检测
+    let mut display_mat = raw_mat.clone(); // 用于显示的副本
+    let mut faces = Mat::default();
+    detector
+        .inner
+        .set_input_size(
+            display_mat
+                .size()
+                .map_err(|e| format!("获取Mat尺寸失败: {}", e))?,
+        )
+        .map_err(|e| format!("设置输入尺寸失败: {}", e))?;
+    detector
+        .inner
+        .set_score_threshold(face_detection_threshold)
+        .map_err(|e| format!("设置分数阈值失败: {}", e))?;
+    detector
+        .inner
+        .detect(&display_mat, &mut faces)
+        .map_err(|e| format!("OpenCV 检测失败: {}", e))?;
+
+    if faces.rows() < 1 {
+        return Err(String::from("未检测到人脸"));
+    }
+    let size = display_mat
+        .size()
+        .map_err(|e| format!("获取Mat尺寸失败: {}", e))?;
+    let primary = primary_face(&faces, size) as i32;
+
+    // 所有人脸都画框，主人脸和其他人脸用不同颜色区分
+    let mut boxes = Vec::with_capacity(faces.rows() as usize);
+    for row in 0..faces.rows() {
+        let value = |col| {
+            faces
+                .at_2d::<f32>(row, col)
+                .copied()
+                .map_err(|e| format!("图片坐标获取失败: {}", e))
+        };
+        let mut landmarks = [Landmark::default(); 5];
+        for (i, landmark) in landmarks.iter_mut().enumerate() {
+            let col = 4 + i as i32 * 2;
+            *landmark = Landmark { x: value(col)?, y: value(col + 1)? };
+        }
+        let face = FaceBox {
+            x: value(0)?,
+            y: value(1)?,
+            width: value(2)?,
+            height: value(3)?,
+            score: value(14)?,
+            primary: row == primary,
+            landmarks,
+        };
+        let color = if face.primary {
+            Scalar::new(255.0, 242.0, 0.0, 0.0)
+        } else {
+            Scalar::new(0.0, 165.0, 255.0, 0.0) // 橙色
+        };
+        imgproc::rectangle(
+            &mut display_mat,
+            Rect::new(face.x as i32, face.y as i32, face.width as i32, face.height as i32),
+            color,
+            2,
+            imgproc::LINE_8,
+            0,
+        )
+        .map_err(|e| format!("图片绘制失败: {}", e))?;
+
+        // 绘制五官
+        for landmark in &face.landmarks {
+            // 五官不影响检测结果，所以绘制失败可以忽略
+            imgproc::circle(
+                &mut display_mat,
+                Point::new(landmark.x as i32, landmark.y as i32),
+                4,
+                Scalar::new(0.0, 255.0, 0.0, 0.0), // 绿色
+                -1,
+                imgproc::LINE_AA,
+                0,
+            )
+            .ok();
+        }
+        boxes.push(face);
+    }
+
+    Ok(CaptureResponse {
+        display_base64: mat_to_base64(&display_mat, format, quality)?,
+        raw_base64: match (format, frame_id) {
+            (OutputFormat::Jpeg, Some(frame_id)) => jpeg_to_data_url(&encode_jpeg_cached(
+                frame_id,
+                &src,
+                max_dim,
+                DEFAULT_JPEG_QUALITY,
+            )?),
+            _ => mat_to_base64(&raw_mat, format, DEFAULT_JPEG_QUALITY)?,
+        },
+        faces: boxes,
+        width: size.width,
+        height: size.height,
+    })
+}
+
+fn to_data_url(bytes: &[u8], format: OutputFormat) -> String {
+    format!(
+        "data:{};base64,{}",
+        format.mime(),
+        general_purpose::STANDARD.encode(bytes)
+    )
+}
+
+fn jpeg_to_data_url(bytes: &[u8]) -> String {
+    to_data_url(bytes, OutputFormat::Jpeg)
+}
+
+// 按指定格式编码，quality 只对 JPEG 有效
+fn encode_mat(mat: &Mat, format: OutputFormat, quality: i32) -> Result<Vec<u8>, String> {
+    let mut buf = Vector::<u8>::new();
+    let params = match format {
+        OutputFormat::Jpeg => Vector::<i32>::from_slice(&[imgcodecs::IMWRITE_JPEG_QUALITY, quality]),
+        OutputFormat::Png => Vector::<i32>::new(),
+    };
+    match imgcodecs::imencode(format.extension(), mat, &mut buf, &params) {
+        Ok(true) => Ok(buf.to_vec()),
+        Ok(false) => Err(String::from("图片编码失败")),
+        Err(e) => Err(format!("图片编码失败: {}", e)),
+    }
+}
+
+// 按指定质量编码为 JPEG
+fn mat_to_jpeg(mat: &Mat, quality: i32) -> Result<Vec<u8>, String> {
+    encode_mat(mat, OutputFormat::Jpeg, quality)
+}
+
+fn mat_to_base64(mat: &Mat, format: OutputFormat, quality: i32) -> Result<String, String> {
+    encode_mat(mat, format, quality).map(|bytes| to_data_url(&bytes, format))
+}
+
+// 面容特征文件头：标识 + 格式版本（u16 小端），之后是 bincode 编码的 FaceDescriptor
+// 版本 1 为旧格式，没有文件头；版本 2 只有一个样本；版本 3 支持多个样本；版本 4 记录模板保护的密钥指纹；版本 5 保存头像；版本 6 记录识别模型和录入时间
+// 版本 7 的内容与版本 6 相同，只是版本号从 1 字节改为 2 字节
+// 版本 8 的内容与版本 6 相同，改用 VERSIONED_MAGIC 文件头
+// 保存时整个内容再用 DPAPI 加密，见 CHECKED_MAGIC
+const DESCRIPTOR_MAGIC: [u8; 4] = *b"FWFD";
+// 版本 8 起的文件头，版本号总是 2 字节，不会与 FWFD 文件头中 1 字节的版本号混淆
+const VERSIONED_MAGIC: [u8; 4] = *b"FWFV";
+pub const DESCRIPTOR_VERSION: u16 = 8;
+// FWFD 文件头中版本 2-6 的版本号只有 1 字节
+const SINGLE_BYTE_VERSIONS: std::ops::RangeInclusive<u8> = 2..=6;
+// FWFD 文件头中唯一使用 2 字节版本号的版本
+const WIDE_LEGACY_VERSION: u16 = 7;
+
+// 旧的加密特征文件头，之后是 DPAPI 加密的特征文件内容（包括 FWFD 文件头），没有校验和
+const ENCRYPTED_MAGIC: [u8; 4] = *b"FWFE";
+// 加密特征文件头，之后是 DPAPI 加密内容的 CRC32（小端）和加密内容
+// 写入中断或磁盘错误导致的截断、损坏在解密前就能发现，不会被误报为其他电脑加密的文件
+const CHECKED_MAGIC: [u8; 4] = *b"FWFC";
+// 特征文件损坏（校验和不一致、内容被截断）时的错误前缀，列表和识别时跳过这些文件
+pub const DESCRIPTOR_CORRUPTED: &str = "DescriptorCorrupted";
+
+fn corrupted(reason: impl std::fmt::Display) -> Box<dyn std::error::Error> {
+    format!("{}: 面容特征文件已损坏，请重新录入（{}）", DESCRIPTOR_CORRUPTED, reason).into()
+}
+
+// 加密特征文件内容并附加校验和
+fn seal_descriptor(encoded: &[u8]) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let protected = protect_file_data(encoded)?;
+    let mut sealed = Vec::with_capacity(CHECKED_MAGIC.len() + 4 + protected.len());
+    sealed.extend_from_slice(&CHECKED_MAGIC);
+    sealed.extend_from_slice(&crc32fast::hash(&protected).to_le_bytes());
+    sealed.extend_from_slice(&protected);
+    Ok(sealed)
+}
+
+// 读取特征文件，见 unseal_descriptor
+fn read_descriptor(path: &PathBuf) -> Result<(FaceDescriptor, u16, bool), Box<dyn std::error::Error>> {
+    unseal_descriptor(&read_file_fully(path)?)
+}
+
+// 解析保存的特征（数据库中的 feature 或旧的 .face 文件），返回特征、格式版本和是否已按当前格式（加密并带校验和）保存
+// 没有加密文件头时按未加密的旧文件解析，校验和不一致或无法解析时返回 DESCRIPTOR_CORRUPTED 错误
+fn unseal_descriptor(buffer: &[u8]) -> Result<(FaceDescriptor, u16, bool), Box<dyn std::error::Error>> {
+    if buffer.len() >= CHECKED_MAGIC.len() && buffer[..CHECKED_MAGIC.len()] == CHECKED_MAGIC {
+        let body = &buffer[CHECKED_MAGIC.len()..];
+        if body.len() <= 4 {
+            return Err(corrupted("文件被截断"));
+        }
+        let (checksum, protected) = body.split_at(4);
+        let expected = u32::from_le_bytes([checksum[0], checksum[1], checksum[2], checksum[3]]);
+        let actual = crc32fast::hash(protected);
+        if actual != expected {
+            return Err(corrupted(format!("校验和不一致 {:08x} != {:08x}", actual, expected)));
+        }
+        let plain = unprotect_file_data(protected)?;
+        let (descriptor, version) = decode_descriptor(&plain)?;
+        return Ok((descriptor, version, true));
+    }
+    if buffer.len() > ENCRYPTED_MAGIC.len() && buffer[..ENCRYPTED_MAGIC.len()] == ENCRYPTED_MAGIC {
+        let plain = unprotect_file_data(&buffer[ENCRYPTED_MAGIC.len()..])?;
+        let (descriptor, version) = decode_descriptor(&plain)?;
+        return Ok((descriptor, version, false));
+    }
+    let (descriptor, version) = decode_descriptor(buffer)?;
+    Ok((descriptor, version, false))
+}
+
+// 编码为当前版本的特征文件内容
+fn encode_descriptor(data: &FaceDescriptor) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let mut encoded = Vec::with_capacity(VERSIONED_MAGIC.len() + 2);
+    encoded.extend_from_slice(&VERSIONED_MAGIC);
+    encoded.extend_from_slice(&DESCRIPTOR_VERSION.to_le_bytes());
+    encoded.extend_from_slice(&bincode::serialize(data)?);
+    Ok(encoded)
+}
+
+// 拆分 VERSIONED_MAGIC 文件头中的版本号（2 字节小端）和之后的内容
+fn split_version(header: &[u8]) -> Result<(u16, &[u8]), Box<dyn std::error::Error>> {
+    match header {
+        [low, high, payload @ ..] => Ok((u16::from_le_bytes([*low, *high]), payload)),
+        _ => Err(corrupted("文件头被截断")),
+    }
+}
+
+// 拆分 FWFD 文件头中的版本号和之后的内容，版本 2-6 为 1 字节，版本 7 为 2 字节（小端）
+fn split_legacy_version(header: &[u8]) -> Result<(u16, &[u8]), Box<dyn std::error::Error>> {
+    match header {
+        [first, payload @ ..] if SINGLE_BYTE_VERSIONS.contains(first) => Ok((*first as u16, payload)),
+        _ => split_version(header),
+    }
+}
+
+// 解码特征文件内容，返回特征和文件的格式版本，内容被截断或损坏时返回 DESCRIPTOR_CORRUPTED 错误
+fn decode_descriptor(buffer: &[u8]) -> Result<(FaceDescriptor, u16), Box<dyn std::error::Error>> {
+    if buffer.len() > VERSIONED_MAGIC.len() && buffer[..VERSIONED_MAGIC.len()] == VERSIONED_MAGIC {
+        let (version, payload) = split_version(&buffer[VERSIONED_MAGIC.len()..])?;
+        let decoded = match version {
+            DESCRIPTOR_VERSION => bincode::deserialize(payload),
+            _ => return Err(format!("不支持的面容特征文件版本 {}", version).into()),
+        };
+        return Ok((decoded.map_err(corrupted)?, version));
+    }
+    if buffer.len() > DESCRIPTOR_MAGIC.len() && buffer[..DESCRIPTOR_MAGIC.len()] == DESCRIPTOR_MAGIC {
+        let (version, payload) = split_legacy_version(&buffer[DESCRIPTOR_MAGIC.len()..])?;
+        let decoded = match version {
+            2 => bincode::deserialize::<SingleSampleDescriptor>(payload).map(Into::into),
+            3 => bincode::deserialize::<MultiSampleDescriptor>(payload).map(Into::into),
+            4 => bincode::deserialize::<ProtectedDescriptor>(payload).map(Into::into),
+            5 => bincode::deserialize::<ThumbnailDescriptor>(payload).map(Into::into),
+            6 | WIDE_LEGACY_VERSION => bincode::deserialize(payload),
+            _ => return Err(format!("不支持的面容特征文件版本 {}", version).into()),
+        };
+        return Ok((decoded.map_err(corrupted)?, version));
+    }
+    // 旧格式
+    let decoded = bincode::deserialize::<SingleSampleDescriptor>(buffer).map_err(corrupted)?;
+    Ok((decoded.into(), 1))
+}
+
+impl From<SingleSampleDescriptor> for FaceDescriptor {
+    fn from(old: SingleSampleDescriptor) -> Self {
+        FaceDescriptor {
+            name: old.name,
+            samples: vec![old.feature],
+            key_fingerprint: None,
+            thumbnail: None,
+            model_version: None,
+            created_at: None,
+        }
+    }
+}
+
+impl From<MultiSampleDescriptor> for FaceDescriptor {
+    fn from(old: MultiSampleDescriptor) -> Self {
+        FaceDescriptor {
+            name: old.name,
+            samples: old.samples,
+            key_fingerprint: None,
+            thumbnail: None,
+            model_version: None,
+            created_at: None,
+        }
+    }
+}
+
+impl From<ProtectedDescriptor> for FaceDescriptor {
+    fn from(old: ProtectedDescriptor) -> Self {
+        FaceDescriptor {
+            name: old.name,
+            samples: old.samples,
+            key_fingerprint: old.key_fingerprint,
+            thumbnail: None,
+            model_version: None,
+            created_at: None,
+        }
+    }
+}
+
+impl From<ThumbnailDescriptor> for FaceDescriptor {
+    fn from(old: ThumbnailDescriptor) -> Self {
+        FaceDescriptor {
+            name: old.name,
+            samples: old.samples,
+            key_fingerprint: old.key_fingerprint,
+            thumbnail: old.thumbnail,
+            model_version: None,
+            created_at: None,
+        }
+    }
+}
+
+// 没有已录入的面容时的错误条件
+pub const NO_REGISTERED_FACES: &str = "no_registered_faces";
+
+// 与已录入的面容比较得到的最高分
+pub struct RegisteredMatch {
+    pub file_name: String,
+    pub name: String,
+    pub score: f64,
+    pub sample_index: usize,
+}
+
+// 把特征与已录入的面容逐一比较，返回分数最高的面容，所有面容都无法比较时返回 None
+// 识别模型的锁只在比较期间持有；无法比较的面容跳过并记录到 warnings
+pub fn best_registered_match(
+    feature: &Mat,
+    faces: Vec<(String, FaceDescriptor)>,
+    warnings: &mut Vec<serde_json::Value>,
+) -> Result<Option<RegisteredMatch>, CustomResult> {
+    let mut app_state = APP_STATE
+        .lock()
+        .map_err(|e| CustomResult::error(Some(format!("获取app状态失败 {}", e)), None))?;
+    ensure_models_loaded(&mut app_state).map_err(|e| CustomResult::error(Some(e), None))?;
+    let Some(recognizer) = app_state.recognizer.as_mut() else {
+        return Err(CustomResult::error(Some(String::from("人脸识别模型未初始化")), None));
+    };
+
+    let mut best: Option<RegisteredMatch> = None;
+    for (file_stem, descriptor) in faces {
+        let mut skip = |reason: String| {
+            warn!("验证时跳过面容 {}：{}", file_stem, reason);
+            warnings.push(json!({"file_name": file_stem, "reason": reason}));
+        };
+        // 受保护的面容需要把摄像头特征做同样的变换
+        let probe = match probe_key(&descriptor) {
+            Ok(Some(key)) => match key.apply_mat(feature) {
+                Ok(probe) => probe,
+                Err(e) => {
+                    skip(e);
+                    continue;
+                }
+            },
+            Ok(None) => feature.clone(),
+            Err(_) => {
+                skip(String::from(TEMPLATE_KEY_MISMATCH));
+                continue;
+            }
+        };
+        let references = match descriptor.to_mats() {
+            Ok(references) => references,
+            Err(e) => {
+                skip(e.to_string());
+                continue;
+            }
+        };
+        for (sample_index, reference) in references.iter().enumerate() {
+            let score = recognizer
+                .inner
+                .match_(reference, &probe, FaceRecognizerSF_DisType::FR_COSINE.into())
+                .map_err(|e| CustomResult::error(Some(format!("特征匹配失败: {}", e)), None))?;
+            // 维度不一致等情况下分数无效，不参与比较
+            if score.is_finite() && best.as_ref().map_or(true, |b| score > b.score) {
+                best = Some(RegisteredMatch {
+                    file_name: file_stem.clone(),
+                    name: descriptor.name.clone(),
+                    score,
+                    sample_index,
+                });
+            }
+        }
+    }
+    Ok(best)
+}
+
+// 一致性验证：读取一帧，与所有已保存的 .face 面容比较，不需要前端提供参考图片
+// 使用识别模型的 match_ 计算分数，与 verify_face 的分数一致；无法解析的面容文件跳过并在 warnings 中列出
+#[tauri::command]
+pub fn verify_face_against_registered(face_detection_threshold: f32) -> Result<CustomResult, CustomResult> {
+    ensure_ready()?;
+    ensure_not_paused()?;
+    let _priority = PriorityGuard::new(WorkMode::Background);
+    let (faces, invalid) = active_cached_faces().map_err(|e| CustomResult::error(Some(e), None))?;
+    let mut warnings: Vec<_> = invalid
+        .into_iter()
+        .map(|i| json!({"file_name": i.file_name, "reason": i.error}))
+        .collect();
+    if faces.is_empty() {
+        return Err(CustomResult::error(
+            Some(String::from("没有可用于验证的面容，请先录入面容")),
+            Some(json!({"condition": NO_REGISTERED_FACES, "warnings": warnings})),
+        ));
+    }
+
+    let frame = read_mat_from_camera().map_err(camera_error)?;
+    let feature = get_feature(&frame, face_detection_threshold)
+        .map_err(|e| CustomResult::error(Some(format!("特征提取失败: {}", e)), None))?;
+
+    let Some(RegisteredMatch { file_name, name, score, sample_index }) =
+        best_registered_match(&feature, faces, &mut warnings)?
+    else {
+        return Err(CustomResult::error(
+            Some(String::from("所有面容文件都无法用于验证")),
+            Some(json!({"condition": NO_REGISTERED_FACES, "warnings": warnings})),
+        ));
+    };
+    let threshold = match_threshold();
+    Ok(CustomResult::success(
+        None,
+        Some(json!({
+            "score": score,
+            "threshold": threshold,
+            "passed": score >= threshold,
+            "name": name,
+            "file_name": file_name,
+            "sample_index": sample_index,
+            "warnings": warnings,
+        })),
+    ))
+}
+
+// 把导入数据库之前的旧格式特征文件升级为当前版本，已经是当前版本时返回 false
+// 先写临时文件再替换，升级中断不会损坏原文件
+pub fn upgrade_descriptor_file(path: &PathBuf) -> Result<bool, Box<dyn std::error::Error>> {
+    let (descriptor, version, sealed) = read_descriptor(path)?;
+    if version == DESCRIPTOR_VERSION && sealed {
+        return Ok(false);
+    }
+
+    write_descriptor_atomic(path, &seal_descriptor(&encode_descriptor(&descriptor)?)?)?;
+    Ok(true)
+}
+
+// 先写临时文件再替换，写入中断不会损坏原文件，面容目录监视也不会读到写了一半的文件
+fn write_descriptor_atomic(path: &PathBuf, encoded: &[u8]) -> std::io::Result<()> {
+    mark_own_write(path);
+    let temp_path = path.with_extension("face.tmp");
+    // OneDrive/受控文件夹访问可能短暂占用文件，失败时重试
+    with_retry(|| {
+        let mut file = std::fs::File::create(&temp_path)?;
+        file.write_all(encoded)
+    })?;
+    with_retry(|| fs::rename(&temp_path, path))
+}
+
+// 数据库中保存的一条面容，特征加密并带校验和
+fn stored_face(file_stem: &str, data: &FaceDescriptor) -> Result<StoredFace, Box<dyn std::error::Error>> {
+    Ok(StoredFace {
+        face_token: file_stem.to_string(),
+        name: data.name.clone(),
+        // 用户名由前端保存账户时写入，重新保存特征时不会覆盖
+        username: String::new(),
+        feature: seal_descriptor(&encode_descriptor(data)?)?,
+        created_at: data.created_at.unwrap_or_else(engine::now_millis),
+    })
+}
+
+// 保存人脸数据到面容数据库
+fn save_face_data(file_stem: &str, data: &FaceDescriptor) -> Result<(), Box<dyn std::error::Error>> {
+    let stored = stored_face(file_stem, data)?;
+    face_store::with_store(|conn| face_store::save(conn, &stored))?;
+    cache_saved_face(file_stem, data);
+    Ok(())
+}
+
+fn template_protection_enabled() -> bool {
+    read_option(TEMPLATE_PROTECTION_OPTION).as_deref() == Some("true")
+}
+
+// 模板密钥不一致时的错误，界面据此提示重新录入
+pub fn template_key_error(e: String) -> CustomResult {
+    CustomResult::error(Some(e), Some(json!({"condition": TEMPLATE_KEY_MISMATCH})))
+}
+
+// 把未保护的面容文件转换为受保护的模板，已保护时返回 false
+pub fn protect_registration_file(file_stem: &str) -> Result<bool, String> {
+    let mut descriptor = load_face_data(file_stem).map_err(|e| format!("加载面容数据失败：{}", e))?;
+    if !protect_descriptor(&mut descriptor)? {
+        return Ok(false);
+    }
+    save_face_data(file_stem, &descriptor).map_err(|e| format!("保存特征数据失败：{}", e))?;
+    info!("面容 {} 已转换为受保护的模板", file_stem);
+    Ok(true)
+}
+
+// 从面容数据库加载人脸数据
+pub fn load_face_data(file_stem: &str) -> Result<FaceDescriptor, Box<dyn std::error::Error>> {
+    let stored = face_store::with_store(|conn| face_store::load(conn, file_stem))?
+        .ok_or_else(|| format!("面容 {} 不存在", file_stem))?;
+    decode_stored_face(&stored)
+}
+
+// 解析数据库中的一条面容，已损坏或无法解密时返回对应的错误前缀
+pub fn decode_stored_face(stored: &StoredFace) -> Result<FaceDescriptor, Box<dyn std::error::Error>> {
+    unseal_descriptor(&stored.feature).map(|(descriptor, _, _)| descriptor)
+}
+
+// 删除面容的特征和图片文件，不存在视为成功
+pub fn remove_face_files(file_stem: &str) -> std::io::Result<()> {
+    face_store::with_store(|conn| face_store::remove(conn, file_stem)).map_err(std::io::Error::other)?;
+    cache_removed_face(file_stem);
+    let path = faces_dir().join(format!("{}.faceimg", file_stem));
+    match with_retry(|| fs::remove_file(&path)) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+// 完整读取文件，云端占位文件会在读取时被下载到本地
+fn read_file_fully(path: &PathBuf) -> std::io::Result<Vec<u8>> {
+    with_retry(|| {
+        let mut file = std::fs::File::open(path)?;
+        let mut buffer = Vec::new();
+        file.read_to_end(&mut buffer)?;
+        Ok(buffer)
+    })
+}
+
+// 获取面容数据目录
+#[tauri::command]
+pub fn get_faces_dir() -> Result<CustomResult, CustomResult> {
+    let path = faces_dir();
+    Ok(CustomResult::success(
+        None,
+        Some(json!({"path": path.to_string_lossy()})),
+    ))
+}
+
+// 检查面容数据目录，发现云同步、受控文件夹访问等干扰时返回警告
+#[tauri::command]
+pub fn validate_face_store() -> Result<CustomResult, CustomResult> {
+    ensure_ready()?;
+    let path = faces_dir();
+    let mut warnings = Vec::new();
+    let mut placeholders = Vec::new();
+    let face_count = face_store::with_store(face_store::count)
+        .map_err(|e| CustomResult::error(Some(e), None))?;
+
+    if is_cloud_synced(path) {
+        warnings.push(json!({
+            "code": "CloudSyncInterference",
+            "msg": "面容数据目录处于云同步目录中，文件可能被同步软件占用或释放本地空间"
+        }));
+    }
+
+    if is_controlled_folder_access_enabled() {
+        warnings.push(json!({
+            "code": "ControlledFolderAccess",
+            "msg": "已开启受控文件夹访问，如保存面容失败，请将本软件添加到允许的应用"
+        }));
+    }
+
+    if path.exists() {
+        let entries = fs::read_dir(path).map_err(|e| {
+            CustomResult::error(Some(format!("读取 faces 文件夹失败: {}", e)), None)
+        })?;
+
+        for entry in entries.flatten() {
+            let file_path = entry.path();
+            if is_cloud_placeholder(&file_path) {
+                placeholders.push(entry.file_name().to_string_lossy().to_string());
+            }
+        }
+    }
+
+    if !placeholders.is_empty() {
+        warnings.push(json!({
+            "code": "CloudSyncInterference",
+            "msg": format!("{} 个面容图片仅存在于云端，请执行下载到本地", placeholders.len()),
+            "files": placeholders
+        }));
+    }
+
+    Ok(CustomResult::success(
+        None,
+        Some(json!({
+            "path": path.to_string_lossy(),
+            "database": face_store_path().to_string_lossy(),
+            "face_count": face_count,
+            "warnings": warnings
+        })),
+    ))
+}
+
+// 完整读取每个面容文件，强制云端占位文件下载到本地
+#[tauri::command]
+pub fn materialize_face_files() -> Result<CustomResult, CustomResult> {
+    ensure_ready()?;
+    let path = faces_dir();
+    if !path.exists() {
+        return Ok(CustomResult::success(None, Some(json!({"count": 0, "failed": []}))));
+    }
+
+    let entries = fs::read_dir(path)
+        .map_err(|e| CustomResult::error(Some(format!("读取 faces 文件夹失败: {}", e)), None))?;
+
+    let mut count = 0;
+    let mut failed = Vec::new();
+    for entry in entries.flatten() {
+        let file_path = entry.path();
+        if !file_path.is_file() {
+            continue;
+        }
+        match read_file_fully(&file_path) {
+            Ok(_) => count += 1,
+            Err(e) => failed.push(json!({
+                "file": entry.file_name().to_string_lossy(),
+                "msg": e.to_string()
+            })),
+        }
+    }
+
+    Ok(CustomResult::success(
+        None,
+        Some(json!({"count": count, "failed": failed})),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::face_policy::tests::detections;
+
+    // 只有 EXIF 方向标签的最小 JPEG 文件头（小端 TIFF）
+    fn jpeg_with_orientation(orientation: u16) -> Vec<u8> {
+        let mut tiff = Vec::new();
+        tiff.extend_from_slice(b"II");
+        tiff.extend_from_slice(&42u16.to_le_bytes());
+        tiff.extend_from_slice(&8u32.to_le_bytes());
+        tiff.extend_from_slice(&1u16.to_le_bytes());
+        tiff.extend_from_slice(&EXIF_ORIENTATION_TAG.to_le_bytes());
+        tiff.extend_from_slice(&3u16.to_le_bytes());
+        tiff.extend_from_slice(&1u32.to_le_bytes());
+        tiff.extend_from_slice(&orientation.to_le_bytes());
+        tiff.extend_from_slice(&[0, 0]);
+
+        let mut segment = b"Exif\0\0".to_vec();
+        segment.extend_from_slice(&tiff);
+        let mut jpeg = vec![0xFF, 0xD8, 0xFF, 0xE1];
+        jpeg.extend_from_slice(&((segment.len() + 2) as u16).to_be_bytes());
+        jpeg.extend_from_slice(&segment);
+        jpeg.extend_from_slice(&[0xFF, 0xDA, 0x00, 0x02]);
+        jpeg
+    }
+
+    // 2 行 3 列的单通道画面，每个像素的值不同，便于检查旋转方向
+    fn sample_mat() -> Mat {
+        Mat::from_slice_2d(&[[0u8, 1, 2], [3, 4, 5]]).unwrap()
+    }
+
+    fn rows_of(mat: &Mat) -> Vec<Vec<u8>> {
+        (0..mat.rows())
+            .map(|r| (0..mat.cols()).map(|c| *mat.at_2d::<u8>(r, c).unwrap()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn reads_exif_orientation_from_jpeg() {
+        for orientation in [3, 6, 8] {
+            assert_eq!(exif_orientation(&jpeg_with_orientation(orientation)), Some(orientation));
+        }
+    }
+
+    #[test]
+    fn reads_big_endian_exif_orientation() {
+        let mut jpeg = jpeg_with_orientation(6);
+        // 把 TIFF 部分改为大端
+        let tiff = 4 + 2 + 6;
+        jpeg[tiff..tiff + 2].copy_from_slice(b"MM");
+        jpeg[tiff + 2..tiff + 4].copy_from_slice(&42u16.to_be_bytes());
+        jpeg[tiff + 4..tiff + 8].copy_from_slice(&8u32.to_be_bytes());
+        jpeg[tiff + 8..tiff + 10].copy_from_slice(&1u16.to_be_bytes());
+        jpeg[tiff + 10..tiff + 12].copy_from_slice(&EXIF_ORIENTATION_TAG.to_be_bytes());
+        jpeg[tiff + 12..tiff + 14].copy_from_slice(&3u16.to_be_bytes());
+        jpeg[tiff + 14..tiff + 18].copy_from_slice(&1u32.to_be_bytes());
+        jpeg[tiff + 18..tiff + 20].copy_from_slice(&6u16.to_be_bytes());
+        assert_eq!(exif_orientation(&jpeg), Some(6));
+    }
+
+    #[test]
+    fn ignores_missing_or_invalid_exif_orientation() {
+        assert_eq!(exif_orientation(&[0xFF, 0xD8, 0xFF, 0xDA, 0x00, 0x02]), None);
+        assert_eq!(exif_orientation(&jpeg_with_orientation(9)), None);
+        assert_eq!(exif_orientation(b"not an image"), None);
+        let jpeg = jpeg_with_orientation(6);
+        assert_eq!(exif_orientation(&jpeg[..jpeg.len() - 8]), None);
+    }
+
+    #[test]
+    fn orientation_3_rotates_180() {
+        let rotated = apply_exif_orientation(sample_mat(), 3).unwrap();
+        assert_eq!(rows_of(&rotated), vec![vec![5, 4, 3], vec![2, 1, 0]]);
+    }
+
+    #[test]
+    fn orientation_6_rotates_clockwise() {
+        let rotated = apply_exif_orientation(sample_mat(), 6).unwrap();
+        assert_eq!(rows_of(&rotated), vec![vec![3, 0], vec![4, 1], vec![5, 2]]);
+    }
+
+    #[test]
+    fn orientation_8_rotates_counterclockwise() {
+        let rotated = apply_exif_orientation(sample_mat(), 8).unwrap();
+        assert_eq!(rows_of(&rotated), vec![vec![2, 5], vec![1, 4], vec![0, 3]]);
+    }
+
+    #[test]
+    fn orientation_1_keeps_image() {
+        let img = apply_exif_orientation(sample_mat(), 1).unwrap();
+        assert_eq!(rows_of(&img), rows_of(&sample_mat()));
+    }
+
+    #[test]
+    fn to_bgr_expands_single_channel_frame() {
+        let gray = Mat::new_rows_cols_with_default(4, 6, core::CV_8UC1, Scalar::all(90.0)).unwrap();
+        let bgr = to_bgr(gray).unwrap();
+        assert_eq!(bgr.channels(), 3);
+        assert_eq!((bgr.rows(), bgr.cols()), (4, 6));
+        assert_eq!(*bgr.at_2d::<core::Vec3b>(2, 3).unwrap(), core::Vec3b::from([90, 90, 90]));
+    }
+
+    #[test]
+    fn to_bgr_drops_alpha_and_keeps_bgr() {
+        let bgra = Mat::new_rows_cols_with_default(2, 2, core::CV_8UC4, Scalar::new(10.0, 20.0, 30.0, 40.0)).unwrap();
+        let bgr = to_bgr(bgra).unwrap();
+        assert_eq!(bgr.channels(), 3);
+        assert_eq!(*bgr.at_2d::<core::Vec3b>(1, 1).unwrap(), core::Vec3b::from([10, 20, 30]));
+
+        let color = Mat::new_rows_cols_with_default(2, 2, core::CV_8UC3, Scalar::all(7.0)).unwrap();
+        assert_eq!(to_bgr(color).unwrap().channels(), 3);
+    }
+
+    #[test]
+    fn to_bgr_rejects_unsupported_channels() {
+        let two = Mat::new_rows_cols_with_default(2, 2, core::CV_8UC2, Scalar::all(0.0)).unwrap();
+        assert!(to_bgr(two).is_err());
+    }
+
+    #[test]
+    fn select_face_uses_largest_face_by_default() {
+        let faces = detections(&[[10.0, 10.0, 40.0, 40.0], [200.0, 120.0, 90.0, 100.0]]);
+        assert_eq!(select_face(&faces, Size::new(640, 480), None), Ok(1));
+    }
+
+    #[test]
+    fn select_face_honours_requested_index() {
+        let faces = detections(&[[10.0, 10.0, 40.0, 40.0], [200.0, 120.0, 90.0, 100.0]]);
+        assert_eq!(select_face(&faces, Size::new(640, 480), Some(0)), Ok(0));
+        assert!(select_face(&faces, Size::new(640, 480), Some(2)).is_err());
+    }
+
+    fn descriptor() -> FaceDescriptor {
+        FaceDescriptor {
+            name: String::from("张三"),
+            samples: vec![vec![0.25, -0.5, 1.0], vec![0.75, 0.0, -1.0]],
+            key_fingerprint: Some(String::from("0123abcd")),
+            thumbnail: Some(vec![0xFF, 0xD8, 0xFF, 0xD9]),
+            model_version: Some(String::from("face_recognition_sface_2021dec.onnx#00000000")),
+            created_at: Some(1_700_000_000_000),
+        }
+    }
+
+    // 版本号只有 1 字节的旧版本特征文件内容
+    fn with_header(version: u8, payload: Vec<u8>) -> Vec<u8> {
+        let mut encoded = DESCRIPTOR_MAGIC.to_vec();
+        encoded.push(version);
+        encoded.extend_from_slice(&payload);
+        encoded
+    }
+
+    #[test]
+    fn current_descriptor_round_trips() {
+        let original = descriptor();
+        let encoded = encode_descriptor(&original).unwrap();
+        assert_eq!(encoded[..6], [b'F', b'W', b'F', b'V', 8, 0]);
+        let (decoded, version) = decode_descriptor(&encoded).unwrap();
+        assert_eq!(version, DESCRIPTOR_VERSION);
+        assert_eq!(decoded.name, original.name);
+        assert_eq!(decoded.samples, original.samples);
+        assert_eq!(decoded.key_fingerprint, original.key_fingerprint);
+        assert_eq!(decoded.thumbnail, original.thumbnail);
+        assert_eq!(decoded.model_version, original.model_version);
+        assert_eq!(decoded.created_at, original.created_at);
+    }
+
+    #[test]
+    fn decodes_every_older_version() {
+        let d = descriptor();
+        let single = bincode::serialize(&(&d.name, &d.samples[0])).unwrap();
+        let multi = bincode::serialize(&(&d.name, &d.samples)).unwrap();
+        let protected = bincode::serialize(&(&d.name, &d.samples, &d.key_fingerprint)).unwrap();
+        let thumbnail = bincode::serialize(&(&d.name, &d.samples, &d.key_fingerprint, &d.thumbnail)).unwrap();
+        let current = bincode::serialize(&d).unwrap();
+        let mut wide = DESCRIPTOR_MAGIC.to_vec();
+        wide.extend_from_slice(&WIDE_LEGACY_VERSION.to_le_bytes());
+        wide.extend_from_slice(&current);
+
+        let cases = [
+            (single.clone(), 1),
+            (with_header(2, single), 2),
+            (with_header(3, multi), 3),
+            (with_header(4, protected), 4),
+            (with_header(5, thumbnail), 5),
+            (with_header(6, current), 6),
+            (wide, 7),
+        ];
+        for (buffer, expected) in cases {
+            let (decoded, version) = decode_descriptor(&buffer).unwrap();
+            assert_eq!(version, expected);
+            assert_eq!(decoded.name, d.name);
+            let samples = if version <= 2 { &d.samples[..1] } else { &d.samples[..] };
+            assert_eq!(decoded.samples, samples);
+            assert_eq!(decoded.key_fingerprint.is_some(), version >= 4);
+            assert_eq!(decoded.thumbnail.is_some(), version >= 5);
+            assert_eq!(decoded.model_version.is_some(), version >= 6);
+            assert_eq!(decoded.created_at.is_some(), version >= 6);
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_version() {
+        let encoded = encode_descriptor(&descriptor()).unwrap();
+        for version in [0u16, 1, DESCRIPTOR_VERSION + 1, 0x0100, u16::MAX] {
+            let mut unknown = encoded.clone();
+            unknown[VERSIONED_MAGIC.len()..VERSIONED_MAGIC.len() + 2].copy_from_slice(&version.to_le_bytes());
+            let err = decode_descriptor(&unknown).unwrap_err().to_string();
+            assert!(err.contains(&format!("不支持的面容特征文件版本 {}", version)), "{}", err);
+        }
+    }
+
+    #[test]
+    fn wide_version_is_not_read_as_single_byte() {
+        // 258 的低字节为 2，在新文件头中仍按 2 字节读取，不会被当成版本 2
+        let mut future = VERSIONED_MAGIC.to_vec();
+        future.extend_from_slice(&258u16.to_le_bytes());
+        future.extend_from_slice(&bincode::serialize(&descriptor()).unwrap());
+        let err = decode_descriptor(&future).unwrap_err().to_string();
+        assert!(err.contains("不支持的面容特征文件版本 258"), "{}", err);
+        assert_eq!(split_version(&future[VERSIONED_MAGIC.len()..]).unwrap().0, 258);
+        // FWFD 文件头中除版本 7 外的 2 字节版本号都不支持
+        let mut legacy = DESCRIPTOR_MAGIC.to_vec();
+        legacy.extend_from_slice(&(WIDE_LEGACY_VERSION + 1).to_le_bytes());
+        legacy.extend_from_slice(&bincode::serialize(&descriptor()).unwrap());
+        let err = decode_descriptor(&legacy).unwrap_err().to_string();
+        assert!(err.contains("不支持的面容特征文件版本 8"), "{}", err);
+    }
+
+    #[test]
+    fn rejects_corrupt_header() {
+        // 只有文件头
+        let err = decode_descriptor(&with_header(6, Vec::new())).unwrap_err();
+        assert!(err.to_string().starts_with(DESCRIPTOR_CORRUPTED));
+        let encoded = encode_descriptor(&descriptor()).unwrap();
+        let err = decode_descriptor(&encoded[..VERSIONED_MAGIC.len() + 2]).unwrap_err();
+        assert!(err.to_string().starts_with(DESCRIPTOR_CORRUPTED));
+        // 2 字节的版本号被截断
+        let err = decode_descriptor(&encoded[..VERSIONED_MAGIC.len() + 1]).unwrap_err();
+        assert!(err.to_string().starts_with(DESCRIPTOR_CORRUPTED));
+        // 文件头之后的内容被截断
+        let err = decode_descriptor(&encoded[..encoded.len() / 2]).unwrap_err();
+        assert!(err.to_string().starts_with(DESCRIPTOR_CORRUPTED));
+        // 文件头标识损坏时按旧格式解析，同样报告损坏
+        let mut broken = encoded.clone();
+        broken[0] ^= 0xFF;
+        let err = decode_descriptor(&broken).unwrap_err();
+        assert!(err.to_string().starts_with(DESCRIPTOR_CORRUPTED));
+    }
+
+    // 加密特征文件头、校验和和加密内容
+    fn checked(checksum: u32, protected: &[u8]) -> Vec<u8> {
+        let mut sealed = CHECKED_MAGIC.to_vec();
+        sealed.extend_from_slice(&checksum.to_le_bytes());
+        sealed.extend_from_slice(protected);
+        sealed
+    }
+
+    #[test]
+    fn sealed_descriptor_round_trips() {
+        let original = descriptor();
+        let sealed = seal_descriptor(&encode_descriptor(&original).unwrap()).unwrap();
+        let (decoded, version, is_sealed) = unseal_descriptor(&sealed).unwrap();
+        assert!(is_sealed);
+        assert_eq!(version, DESCRIPTOR_VERSION);
+        assert_eq!(decoded.samples, original.samples);
+    }
+
+    #[test]
+    fn rejects_checksum_mismatch() {
+        let protected = b"not really encrypted".to_vec();
+        let err = unseal_descriptor(&checked(crc32fast::hash(&protected) ^ 1, &protected)).unwrap_err();
+        assert!(err.to_string().starts_with(DESCRIPTOR_CORRUPTED), "{}", err);
+
+        // 加密内容被修改，校验和在解密前就能发现
+        let mut sealed = seal_descriptor(&encode_descriptor(&descriptor()).unwrap()).unwrap();
+        let last = sealed.len() - 1;
+        sealed[last] ^= 0x01;
+        let err = unseal_descriptor(&sealed).unwrap_err();
+        assert!(err.to_string().starts_with(DESCRIPTOR_CORRUPTED), "{}", err);
+    }
+
+    #[test]
+    fn rejects_truncated_sealed_descriptor() {
+        for len in [CHECKED_MAGIC.len(), CHECKED_MAGIC.len() + 2, CHECKED_MAGIC.len() + 4] {
+            let err = unseal_descriptor(&checked(0, &[])[..len]).unwrap_err();
+            assert!(err.to_string().starts_with(DESCRIPTOR_CORRUPTED), "{}", err);
+        }
+        let sealed = seal_descriptor(&encode_descriptor(&descriptor()).unwrap()).unwrap();
+        let err = unseal_descriptor(&sealed[..sealed.len() - 16]).unwrap_err();
+        assert!(err.to_string().starts_with(DESCRIPTOR_CORRUPTED), "{}", err);
+    }
+
+    fn uniform(typ: i32, value: f64) -> Mat {
+        Mat::new_rows_cols_with_default(480, 640, typ, Scalar::all(value)).unwrap()
+    }
+
+    // 缩放后图片中亮块的外接矩形
+    fn bright_rect(img: &Mat) -> Rect {
+        let mut mask = Mat::default();
+        imgproc::threshold(img, &mut mask, 127.0, 255.0, imgproc::THRESH_BINARY).unwrap();
+        let mut points = Vector::<Point>::new();
+        core::find_non_zero(&mask, &mut points).unwrap();
+        imgproc::bounding_rect(&points).unwrap()
+    }
+
+    #[test]
+    fn small_max_dim_keeps_coordinates_proportional() {
+        let max_dim = *PREVIEW_MAX_DIM_RANGE.start();
+        assert_eq!(validate_preview_max_dim(max_dim).unwrap(), max_dim);
+
+        let face = Rect::new(800, 400, 320, 320);
+        let mut src = Mat::new_rows_cols_with_default(1080, 1920, core::CV_8UC1, Scalar::all(0.0)).unwrap();
+        imgproc::rectangle(&mut src, face, Scalar::all(255.0), imgproc::FILLED, imgproc::LINE_8, 0).unwrap();
+
+        let small = resize_mat(&src, max_dim as f32).unwrap();
+        assert_eq!(small.size().unwrap(), Size::new(240, 135));
+
+        // 缩放后图片上的坐标按比例换算回原图，误差不超过一个缩放后的像素
+        let scale = 1920.0 / 240.0;
+        let found = bright_rect(&small);
+        for (scaled, original) in [
+            (found.x, face.x),
+            (found.y, face.y),
+            (found.width, face.width),
+            (found.height, face.height),
+        ] {
+            assert!((scaled as f64 * scale - original as f64).abs() <= scale, "{} vs {}", scaled, original);
+        }
+
+        // 小于最大边长的图片不放大
+        assert_eq!(resize_mat(&small, max_dim as f32).unwrap().size().unwrap(), Size::new(240, 135));
+        assert!(validate_preview_max_dim(max_dim - 1).is_err());
+    }
+
+    #[test]
+    fn avatar_clips_face_to_image_bounds() {
+        let img = uniform(core::CV_8UC3, 128.0);
+        // 超出左上角和右下角的人脸框只截取图片内的部分
+        assert!(face_avatar(&img, Rect::new(-40, -30, 200, 200)).is_some());
+        assert!(face_avatar(&img, Rect::new(560, 400, 200, 200)).is_some());
+        // 完全在图片外
+        assert!(face_avatar(&img, Rect::new(700, 500, 100, 100)).is_none());
+        assert!(face_avatar(&img, Rect::new(-200, 0, 100, 100)).is_none());
+    }
+
+    #[test]
+    fn black_frame_is_detected() {
+        let config = BlackFrameConfig::default();
+        assert!(is_black_frame(&uniform(core::CV_8UC3, 0.0), &config).unwrap());
+        // 红外摄像头的单通道画面
+        assert!(is_black_frame(&uniform(core::CV_8UC1, 0.0), &config).unwrap());
+    }
+
+    #[test]
+    fn near_black_frame_is_detected() {
+        let config = BlackFrameConfig::default();
+        assert!(is_black_frame(&uniform(core::CV_8UC3, 8.0), &config).unwrap());
+
+        // 带少量噪点的遮挡画面
+        let mut noisy = uniform(core::CV_8UC3, 0.0);
+        core::randu(&mut noisy, &Scalar::all(0.0), &Scalar::all(10.0)).unwrap();
+        assert!(is_black_frame(&noisy, &config).unwrap());
+    }
+
+    #[test]
+    fn dim_frame_is_not_black() {
+        let config = BlackFrameConfig::default();
+        assert!(!is_black_frame(&uniform(core::CV_8UC3, 30.0), &config).unwrap());
+
+        // 平均亮度低于阈值，但画面有内容
+        let mut dim = uniform(core::CV_8UC3, 0.0);
+        imgproc::rectangle(&mut dim, Rect::new(0, 0, 320, 480), Scalar::all(20.0), imgproc::FILLED, imgproc::LINE_8, 0)
+            .unwrap();
+        assert!(!is_black_frame(&dim, &config).unwrap());
+
+        // 环境较暗的用户调高阈值后，暗画面视为黑帧
+        let config = BlackFrameConfig { mean_threshold: 40.0, ..config };
+        assert!(is_black_frame(&uniform(core::CV_8UC3, 30.0), &config).unwrap());
+    }
+
+    #[test]
+    fn empty_frame_is_an_error() {
+        assert!(is_black_frame(&Mat::default(), &BlackFrameConfig::default()).is_err());
+    }
+
+    // 带随机噪声的画面，模拟真实摄像头即使不动也存在的传感器噪声
+    fn noisy_frame() -> Mat {
+        let mut frame = uniform(core::CV_8UC3, 0.0);
+        core::randu(&mut frame, &Scalar::all(100.0), &Scalar::all(110.0)).unwrap();
+        frame
+    }
+
+    #[test]
+    fn identical_static_frames_are_rejected() {
+        let face = Rect::new(200, 120, 160, 200);
+        // 照片或冻结的虚拟摄像头：每一帧完全相同
+        let frame = noisy_frame();
+        let frames = vec![frame.clone(); MOTION_FRAMES];
+        assert_eq!(face_motion(&frames, face).unwrap(), 0.0);
+        let err = check_face_motion(&frames, face, DEFAULT_MIN_MOTION).unwrap_err();
+        assert_eq!(err.data["condition"], STATIC_SCENE);
+
+        // 单通道的红外画面同样拒绝
+        let frames = vec![uniform(core::CV_8UC1, 80.0); MOTION_FRAMES];
+        assert!(check_face_motion(&frames, face, DEFAULT_MIN_MOTION).is_err());
+    }
+
+    #[test]
+    fn sensor_noise_counts_as_motion() {
+        let face = Rect::new(200, 120, 160, 200);
+        let frames: Vec<_> = (0..MOTION_FRAMES).map(|_| noisy_frame()).collect();
+        let motion = check_face_motion(&frames, face, DEFAULT_MIN_MOTION).unwrap();
+        assert!(motion > DEFAULT_MIN_MOTION);
+    }
+
+    fn store_with(file_stem: &str) -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(face_store::FACES_TABLE).unwrap();
+        face_store::save(&conn, &stored_face(file_stem, &descriptor()).unwrap()).unwrap();
+        conn
+    }
+
+    // 与 save_face_data/load_face_data 相同的步骤，使用内存数据库代替全局连接
+    fn save_to(conn: &Connection, file_stem: &str, data: &FaceDescriptor) {
+        face_store::save(conn, &stored_face(file_stem, data).unwrap()).unwrap();
+    }
+
+    fn load_from(conn: &Connection, file_stem: &str) -> FaceDescriptor {
+        decode_stored_face(&face_store::load(conn, file_stem).unwrap().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn rename_round_trips_through_the_store() {
+        let file_stem = Uuid::new_v4().to_string();
+        let conn = store_with(&file_stem);
+        face_store::set_username(&conn, &file_stem, "alice").unwrap();
+        let original = load_from(&conn, &file_stem);
+        assert_eq!(original.name, "张三");
+
+        let mut renamed = original.clone();
+        renamed.name = String::from("办公室 Office 😀");
+        save_to(&conn, &file_stem, &renamed);
+
+        let loaded = load_from(&conn, &file_stem);
+        assert_eq!(loaded.name, "办公室 Office 😀");
+        // 只改名称，特征和其他信息不变
+        assert_eq!(loaded.samples, original.samples);
+        assert_eq!(loaded.key_fingerprint, original.key_fingerprint);
+        assert_eq!(loaded.thumbnail, original.thumbnail);
+        assert_eq!(loaded.created_at, original.created_at);
+
+        let stored = face_store::load(&conn, &file_stem).unwrap().unwrap();
+        assert_eq!(stored.name, "办公室 Office 😀");
+        assert_eq!(stored.username, "alice");
+        assert_eq!(face_store::count(&conn).unwrap(), 1);
+    }
+
+    #[test]
+    fn registration_token_rejects_path_traversal() {
+        let id = Uuid::new_v4().to_string();
+        for name in [
+            String::from(r"..\..\Windows\System32\config"),
+            String::from("../faces.db"),
+            format!(r"..\{}", id),
+            format!("{}/../x", id),
+            format!(r"C:\{}", id),
+            format!("{{{}}}", id),
+            format!("urn:uuid:{}", id),
+            id.replace('-', ""),
+            String::new(),
+        ] {
+            let err = registration_token(&name).unwrap_err();
+            assert!(err.msg.contains("无效的面容文件名"), "{} -> {}", name, err.msg);
+        }
+        assert_eq!(registration_token(&id).unwrap(), id);
+        assert!(registration_token(&id.to_uppercase()).is_ok());
+    }
+
+    #[test]
+    fn missing_registration_is_not_found() {
+        let saved = Uuid::new_v4().to_string();
+        let conn = store_with(&saved);
+        assert_eq!(existing_registration(&conn, &saved).unwrap(), saved);
+
+        let missing = Uuid::new_v4().to_string();
+        let err = existing_registration(&conn, &missing).unwrap_err();
+        assert_eq!(err.data["condition"], "not_found");
+        assert!(err.msg.contains(&missing));
+
+        // 无效的文件名在查询数据库之前拒绝
+        let err = existing_registration(&conn, "../faces.db").unwrap_err();
+        assert!(err.msg.contains("无效的面容文件名"));
+    }
+
+    #[test]
+    fn motion_needs_two_frames_inside_the_image() {
+        let face = Rect::new(200, 120, 160, 200);
+        assert!(face_motion(&[noisy_frame()], face).is_err());
+        assert!(face_motion(&[noisy_frame(), noisy_frame()], Rect::new(700, 500, 50, 50)).is_err());
+    }
 }
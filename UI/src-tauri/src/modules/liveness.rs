@@ -0,0 +1,553 @@
+// 颜色闪烁挑战活体检测
+// 屏幕按随机顺序闪烁几种颜色，真实人脸会反射对应的颜色，事先录好的视频无法预知闪烁顺序
+// 这里只负责帧分析：按闪烁时间表计算每帧人脸区域的色度，再与预期颜色做相关性评分
+// 闪烁目前由前端在验证流程中驱动，锁屏界面的闪烁之后再接入
+// 另外提供眨眼检测：连续采集画面，眼部区域的对比度先下降再恢复视为一次眨眼，打印的照片无法通过
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+use lazy_static::lazy_static;
+use opencv::{
+    core::{self, Mat, Rect},
+    imgproc,
+    prelude::*,
+};
+use serde::Serialize;
+use serde_json::json;
+use tauri_plugin_log::log::{info, warn};
+use uuid::Uuid;
+
+use crate::{
+    modules::{
+        conference::ensure_not_paused,
+        engine::now_millis,
+        face_policy::{detect_faces, primary_face},
+        faces::{camera_error, read_mat_from_camera},
+    },
+    utils::{
+        api::{ensure_models_loaded, ensure_ready},
+        custom_result::CustomResult,
+        priority::{PriorityGuard, WorkMode},
+    },
+    APP_STATE,
+};
+
+// 可选的闪烁颜色（RGB），相邻两步不会相同
+const PALETTE: [[u8; 3]; 4] = [[255, 0, 0], [0, 255, 0], [0, 0, 255], [255, 255, 255]];
+// 闪烁步数和每步持续时间
+const STEP_COUNT: usize = 6;
+const STEP_MS: u64 = 400;
+// 返回时间表到开始闪烁之间的准备时间，留给前端显示遮罩
+const LEAD_MS: u64 = 600;
+// 颜色切换后的这段时间内，摄像头曝光和屏幕刷新还没稳定，不参与评分
+pub const BOUNDARY_TOLERANCE_MS: u64 = 100;
+// 摄像头画面相对屏幕的最大延迟，按此范围搜索最佳对齐
+const MAX_LAG_MS: u64 = 250;
+const LAG_STEP_MS: u64 = 25;
+// 参与评分的最少帧数
+const MIN_FRAMES: usize = 8;
+// 通过挑战的最低分数
+pub const PASS_SCORE: f64 = 0.5;
+// 挑战结果在验证结果中保留的时间
+const RESULT_TTL_MS: u64 = 30_000;
+// 人脸框向内收缩的比例，去掉头发和背景
+const FACE_INSET: f64 = 0.2;
+// 检测人脸使用的阈值
+const DETECTION_THRESHOLD: f32 = 0.6;
+// 等待眨眼的最长时间
+pub const BLINK_TIMEOUT: Duration = Duration::from_secs(5);
+// verify_liveness 默认的采集时间
+const DEFAULT_LIVENESS_WINDOW: Duration = Duration::from_secs(2);
+// 眼部对比度低于基线的这个比例视为闭眼，恢复到这个比例以上视为睁眼
+const BLINK_CLOSED_RATIO: f64 = 0.65;
+const BLINK_OPEN_RATIO: f64 = 0.9;
+// 判断眨眼至少需要的有效帧数
+const MIN_BLINK_FRAMES: usize = 5;
+// 眼部区域的半径占两眼距离的比例
+const EYE_PATCH_RATIO: f32 = 0.2;
+
+// 眨眼检测的结果，未要求活体检测时为 skipped
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BlinkLiveness {
+    Passed,
+    Timeout,
+    Skipped,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct FlashStep {
+    // 相对 start_at 的开始时间（毫秒）
+    pub start_ms: u64,
+    pub duration_ms: u64,
+    pub color: [u8; 3],
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FlashSchedule {
+    pub id: String,
+    // 开始闪烁的时间（Unix 毫秒）
+    pub start_at: u64,
+    pub steps: Vec<FlashStep>,
+}
+
+impl FlashSchedule {
+    pub fn duration_ms(&self) -> u64 {
+        self.steps.last().map_or(0, |s| s.start_ms + s.duration_ms)
+    }
+
+    // 指定时刻显示的颜色，距离切换时间小于 tolerance 时返回 None
+    fn color_at(&self, t: i64, tolerance: u64) -> Option<[u8; 3]> {
+        if t < 0 {
+            return None;
+        }
+        let t = t as u64;
+        self.steps
+            .iter()
+            .find(|s| t >= s.start_ms + tolerance && t + tolerance < s.start_ms + s.duration_ms)
+            .map(|s| s.color)
+    }
+}
+
+// 一帧中人脸区域的颜色均值（RGB）和拍摄时间
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct ChromaSample {
+    // 相对 start_at 的时间（毫秒）
+    pub timestamp_ms: i64,
+    pub rgb: [f64; 3],
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ChallengeResult {
+    pub challenge_liveness_score: f64,
+    pub passed: bool,
+    // 最佳对齐时摄像头相对屏幕的延迟
+    pub lag_ms: u64,
+    pub used_frames: usize,
+    pub total_frames: usize,
+}
+
+// 生成随机的闪烁时间表
+pub fn generate_schedule(start_at: u64) -> FlashSchedule {
+    let random = Uuid::new_v4();
+    let mut steps = Vec::with_capacity(STEP_COUNT);
+    let mut last: Option<usize> = None;
+    for (i, byte) in random.as_bytes().iter().take(STEP_COUNT).enumerate() {
+        let mut index = *byte as usize % PALETTE.len();
+        if Some(index) == last {
+            index = (index + 1) % PALETTE.len();
+        }
+        last = Some(index);
+        steps.push(FlashStep {
+            start_ms: i as u64 * STEP_MS,
+            duration_ms: STEP_MS,
+            color: PALETTE[index],
+        });
+    }
+    FlashSchedule { id: random.to_string(), start_at, steps }
+}
+
+// 归一化色度，去掉整体亮度的影响
+fn chromaticity(rgb: [f64; 3]) -> [f64; 3] {
+    let sum = rgb[0] + rgb[1] + rgb[2];
+    if sum <= f64::EPSILON {
+        return [0.0; 3];
+    }
+    [rgb[0] / sum, rgb[1] / sum, rgb[2] / sum]
+}
+
+// 皮尔逊相关系数，任一序列没有变化时返回 None
+fn correlation(xs: &[f64], ys: &[f64]) -> Option<f64> {
+    let n = xs.len() as f64;
+    let mean_x = xs.iter().sum::<f64>() / n;
+    let mean_y = ys.iter().sum::<f64>() / n;
+    let (mut cov, mut var_x, mut var_y) = (0.0, 0.0, 0.0);
+    for (x, y) in xs.iter().zip(ys) {
+        cov += (x - mean_x) * (y - mean_y);
+        var_x += (x - mean_x).powi(2);
+        var_y += (y - mean_y).powi(2);
+    }
+    if var_x <= f64::EPSILON || var_y <= f64::EPSILON {
+        return None;
+    }
+    Some(cov / (var_x.sqrt() * var_y.sqrt()))
+}
+
+// 在指定延迟下计算评分：各通道预期色度与实际色度的相关系数取平均
+fn score_with_lag(schedule: &FlashSchedule, samples: &[ChromaSample], lag: u64, tolerance: u64) -> Option<(f64, usize)> {
+    let mut expected: [Vec<f64>; 3] = Default::default();
+    let mut observed: [Vec<f64>; 3] = Default::default();
+    for sample in samples {
+        let Some(color) = schedule.color_at(sample.timestamp_ms - lag as i64, tolerance) else {
+            continue;
+        };
+        let e = chromaticity(color.map(|c| c as f64));
+        let o = chromaticity(sample.rgb);
+        for channel in 0..3 {
+            expected[channel].push(e[channel]);
+            observed[channel].push(o[channel]);
+        }
+    }
+    let used = expected[0].len();
+    if used < MIN_FRAMES {
+        return None;
+    }
+    let scores: Vec<f64> = (0..3)
+        .filter_map(|c| correlation(&expected[c], &observed[c]))
+        .collect();
+    if scores.is_empty() {
+        // 人脸区域颜色完全没有变化，按最低分处理
+        return Some((-1.0, used));
+    }
+    Some((scores.iter().sum::<f64>() / scores.len() as f64, used))
+}
+
+// 计算挑战分数，在 0..=MAX_LAG_MS 范围内搜索摄像头延迟，取分数最高的对齐
+// 有效帧不足时返回 None
+pub fn challenge_liveness_score(
+    schedule: &FlashSchedule,
+    samples: &[ChromaSample],
+    tolerance: u64,
+) -> Option<ChallengeResult> {
+    let mut best: Option<(f64, usize, u64)> = None;
+    for lag in (0..=MAX_LAG_MS).step_by(LAG_STEP_MS as usize) {
+        if let Some((score, used)) = score_with_lag(schedule, samples, lag, tolerance) {
+            if best.map_or(true, |(s, _, _)| score > s) {
+                best = Some((score, used, lag));
+            }
+        }
+    }
+    best.map(|(score, used_frames, lag_ms)| ChallengeResult {
+        challenge_liveness_score: score,
+        passed: score >= PASS_SCORE,
+        lag_ms,
+        used_frames,
+        total_frames: samples.len(),
+    })
+}
+
+// 人脸区域（向内收缩）的 RGB 均值
+pub fn face_chroma(img: &Mat, face: Rect) -> Result<[f64; 3], String> {
+    let inset_x = (face.width as f64 * FACE_INSET) as i32;
+    let inset_y = (face.height as f64 * FACE_INSET) as i32;
+    // 与画面求交集
+    let left = (face.x + inset_x).max(0);
+    let top = (face.y + inset_y).max(0);
+    let right = (face.x + face.width - inset_x).min(img.cols());
+    let bottom = (face.y + face.height - inset_y).min(img.rows());
+    let roi = Rect::new(left, top, right - left, bottom - top);
+    if roi.width <= 0 || roi.height <= 0 {
+        return Err(String::from("人脸区域超出画面"));
+    }
+    let region = Mat::roi(img, roi).map_err(|e| format!("截取人脸区域失败: {}", e))?;
+    let mean = core::mean(&region, &core::no_array()).map_err(|e| format!("计算颜色均值失败: {}", e))?;
+    // OpenCV 为 BGR 顺序
+    Ok([mean[2], mean[1], mean[0]])
+}
+
+// 以 center 为中心、半径为 radius 的灰度区域的标准差
+fn patch_contrast(gray: &Mat, center: (f32, f32), radius: i32) -> Result<f64, String> {
+    let rect = Rect::new(center.0 as i32 - radius, center.1 as i32 - radius, radius * 2, radius * 2)
+        & Rect::new(0, 0, gray.cols(), gray.rows());
+    if rect.width <= 0 || rect.height <= 0 {
+        return Err(String::from("眼部区域超出画面"));
+    }
+    let region = Mat::roi(gray, rect).map_err(|e| format!("截取眼部区域失败: {}", e))?;
+    let mut mean = Mat::default();
+    let mut stddev = Mat::default();
+    core::mean_std_dev(&region, &mut mean, &mut stddev, &core::no_array())
+        .map_err(|e| format!("计算眼部对比度失败: {}", e))?;
+    Ok(*stddev.at::<f64>(0).map_err(|e| format!("计算眼部对比度失败: {}", e))?)
+}
+
+// 读取一帧，计算两眼区域的平均对比度（除以整张人脸的对比度，去掉光线变化的影响）
+// YuNet 只给出眼睛中心点，没有眼睑轮廓，闭眼时虹膜和眼白被遮住，眼部对比度明显下降
+fn eye_openness() -> Result<Option<f64>, String> {
+    let frame = read_mat_from_camera()?;
+    let mut app_state = APP_STATE
+        .lock()
+        .map_err(|e| format!("获取app状态失败 {}", e))?;
+    ensure_models_loaded(&mut app_state)?;
+    let Some(detector) = app_state.detector.as_mut() else {
+        return Err(String::from("人脸检测模型未初始化"));
+    };
+    let faces = match detect_faces(&mut detector.inner, &frame, DETECTION_THRESHOLD) {
+        Ok(faces) => faces,
+        Err(_) => return Ok(None),
+    };
+    drop(app_state);
+    let size = frame.size().map_err(|e| format!("获取Mat尺寸失败: {}", e))?;
+    let index = primary_face(&faces, size) as i32;
+    let value = |col| faces.at_2d::<f32>(index, col).copied().unwrap_or(0.0);
+    // 第 5-8 列为右眼和左眼的坐标
+    let right_eye = (value(4), value(5));
+    let left_eye = (value(6), value(7));
+    let distance = ((right_eye.0 - left_eye.0).powi(2) + (right_eye.1 - left_eye.1).powi(2)).sqrt();
+    let radius = (distance * EYE_PATCH_RATIO).round() as i32;
+    if radius < 2 {
+        return Ok(None);
+    }
+
+    let mut gray = Mat::default();
+    imgproc::cvt_color_def(&frame, &mut gray, imgproc::COLOR_BGR2GRAY)
+        .map_err(|e| format!("灰度转换失败: {}", e))?;
+    let face = Rect::new(value(0) as i32, value(1) as i32, value(2) as i32, value(3) as i32);
+    let face_contrast = patch_contrast(
+        &gray,
+        ((face.x + face.width / 2) as f32, (face.y + face.height / 2) as f32),
+        face.width.min(face.height) / 2,
+    )?;
+    if face_contrast <= f64::EPSILON {
+        return Ok(None);
+    }
+    let eyes = (patch_contrast(&gray, right_eye, radius)? + patch_contrast(&gray, left_eye, radius)?) / 2.0;
+    Ok(Some(eyes / face_contrast))
+}
+
+// 序列中是否出现 睁眼 -> 闭眼 -> 睁眼，基线取中位数（大部分时间是睁眼的）
+fn has_blink(series: &[f64]) -> bool {
+    if series.len() < MIN_BLINK_FRAMES {
+        return false;
+    }
+    let mut sorted = series.to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let baseline = sorted[sorted.len() / 2];
+    let mut opened = false;
+    let mut closed = false;
+    for &value in series {
+        if value >= baseline * BLINK_OPEN_RATIO {
+            if closed {
+                return true;
+            }
+            opened = true;
+        } else if opened && value < baseline * BLINK_CLOSED_RATIO {
+            closed = true;
+        }
+    }
+    false
+}
+
+// 在 timeout 内连续采集画面，检测到一次眨眼即返回 Passed，同时返回每帧的睁眼程度，摄像头需要已经打开
+fn collect_blink(timeout: Duration) -> Result<(BlinkLiveness, Vec<f64>), String> {
+    let started = Instant::now();
+    let mut series = Vec::new();
+    while started.elapsed() < timeout {
+        if let Some(openness) = eye_openness()? {
+            series.push(openness);
+            if has_blink(&series) {
+                info!("检测到眨眼，共采集 {} 帧", series.len());
+                return Ok((BlinkLiveness::Passed, series));
+            }
+        }
+    }
+    warn!("{:?} 内没有检测到眨眼，有效帧 {}", timeout, series.len());
+    Ok((BlinkLiveness::Timeout, series))
+}
+
+pub fn wait_for_blink(timeout: Duration) -> Result<BlinkLiveness, String> {
+    collect_blink(timeout).map(|(result, _)| result)
+}
+
+// 单独的眨眼检测，默认采集约 2 秒，返回是否检测到眨眼和每帧的睁眼程度（眼部对比度 / 人脸对比度）
+#[tauri::command]
+pub async fn verify_liveness(timeout_ms: Option<u64>) -> Result<CustomResult, CustomResult> {
+    ensure_ready()?;
+    ensure_not_paused()?;
+    let _priority = PriorityGuard::new(WorkMode::Background);
+    let timeout = timeout_ms.map_or(DEFAULT_LIVENESS_WINDOW, Duration::from_millis).min(BLINK_TIMEOUT * 2);
+    let (result, series) = collect_blink(timeout).map_err(camera_error)?;
+    Ok(CustomResult::success(
+        (result != BlinkLiveness::Passed).then(|| String::from("没有检测到眨眼，请正对摄像头眨眼后重试")),
+        Some(json!({
+            "live": result == BlinkLiveness::Passed,
+            "liveness": result,
+            "openness": series,
+        })),
+    ))
+}
+
+lazy_static! {
+    // 已发出、等待分析的挑战
+    static ref PENDING: Mutex<HashMap<String, FlashSchedule>> = Mutex::new(HashMap::new());
+    // 最近一次挑战的结果和完成时间
+    static ref LAST_RESULT: Mutex<Option<(u64, ChallengeResult)>> = Mutex::new(None);
+}
+
+// 最近完成的挑战分数，超过有效期后返回 None
+pub fn recent_challenge_score() -> Option<f64> {
+    let guard = LAST_RESULT.lock().ok()?;
+    let (finished_at, result) = guard.as_ref()?;
+    (now_millis().saturating_sub(*finished_at) < RESULT_TTL_MS).then_some(result.challenge_liveness_score)
+}
+
+// 读取一帧并计算主人脸区域的颜色，没有人脸时返回 None
+fn sample_frame(start_at: u64) -> Result<Option<ChromaSample>, String> {
+    let frame = read_mat_from_camera()?;
+    let timestamp_ms = now_millis() as i64 - start_at as i64;
+    let mut app_state = APP_STATE
+        .lock()
+        .map_err(|e| format!("获取app状态失败 {}", e))?;
+    ensure_models_loaded(&mut app_state)?;
+    let Some(detector) = app_state.detector.as_mut() else {
+        return Err(String::from("人脸检测模型未初始化"));
+    };
+    let faces = match detect_faces(&mut detector.inner, &frame, DETECTION_THRESHOLD) {
+        Ok(faces) => faces,
+        Err(_) => return Ok(None),
+    };
+    drop(app_state);
+    let size = frame.size().map_err(|e| format!("获取Mat尺寸失败: {}", e))?;
+    let index = primary_face(&faces, size) as i32;
+    let value = |col| faces.at_2d::<f32>(index, col).copied().unwrap_or(0.0) as i32;
+    let rgb = face_chroma(&frame, Rect::new(value(0), value(1), value(2), value(3)))?;
+    Ok(Some(ChromaSample { timestamp_ms, rgb }))
+}
+
+// 开始一次颜色挑战，前端按返回的时间表全屏闪烁颜色，同时调用 finish_flash_challenge
+#[tauri::command]
+pub fn start_flash_challenge() -> Result<CustomResult, CustomResult> {
+    ensure_ready()?;
+    let schedule = generate_schedule(now_millis() + LEAD_MS);
+    if let Ok(mut pending) = PENDING.lock() {
+        // 同一时间只有一个挑战
+        pending.clear();
+        pending.insert(schedule.id.clone(), schedule.clone());
+    }
+    Ok(CustomResult::success(None, Some(json!(schedule))))
+}
+
+// 在闪烁期间采集画面并计算挑战分数，摄像头需要已经打开
+#[tauri::command]
+pub async fn finish_flash_challenge(challenge_id: String) -> Result<CustomResult, CustomResult> {
+    ensure_ready()?;
+    ensure_not_paused()?;
+    let schedule = PENDING
+        .lock()
+        .ok()
+        .and_then(|mut pending| pending.remove(&challenge_id))
+        .ok_or_else(|| CustomResult::error(Some(String::from("挑战不存在或已结束")), None))?;
+    let _priority = PriorityGuard::new(WorkMode::Background);
+
+    let now = now_millis();
+    if now < schedule.start_at {
+        sleep(Duration::from_millis(schedule.start_at - now));
+    }
+    let end = schedule.start_at + schedule.duration_ms() + MAX_LAG_MS;
+    let mut samples = Vec::new();
+    while now_millis() < end {
+        match sample_frame(schedule.start_at) {
+            Ok(Some(sample)) => samples.push(sample),
+            Ok(None) => {}
+            Err(e) => return Err(camera_error(e)),
+        }
+    }
+
+    let result = challenge_liveness_score(&schedule, &samples, BOUNDARY_TOLERANCE_MS).ok_or_else(|| {
+        warn!("颜色挑战有效帧不足：共 {} 帧", samples.len());
+        CustomResult::error(
+            Some(format!("有效画面不足（{} 帧），请保持脸部在画面中并重试", samples.len())),
+            Some(json!({"total_frames": samples.len()})),
+        )
+    })?;
+    info!(
+        "颜色挑战分数 {:.3}，延迟 {}ms，有效帧 {}/{}",
+        result.challenge_liveness_score, result.lag_ms, result.used_frames, result.total_frames
+    );
+    if let Ok(mut last) = LAST_RESULT.lock() {
+        *last = Some((now_millis(), result.clone()));
+    }
+    Ok(CustomResult::success(None, Some(json!(result))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SKIN: [f64; 3] = [180.0, 140.0, 120.0];
+    const FRAME_INTERVAL_MS: usize = 33;
+
+    fn schedule(colors: &[usize]) -> FlashSchedule {
+        let steps = colors
+            .iter()
+            .enumerate()
+            .map(|(i, &c)| FlashStep { start_ms: i as u64 * STEP_MS, duration_ms: STEP_MS, color: PALETTE[c] })
+            .collect();
+        FlashSchedule { id: String::from("test"), start_at: 0, steps }
+    }
+
+    // 模拟摄像头拍到的人脸：肤色加上屏幕 lag 毫秒前显示的颜色的反射
+    fn captured(shown: &FlashSchedule, lag: u64, reflectance: f64) -> Vec<ChromaSample> {
+        let end = (shown.duration_ms() + MAX_LAG_MS) as usize;
+        (0..end)
+            .step_by(FRAME_INTERVAL_MS)
+            .map(|t| {
+                let color = shown.color_at(t as i64 - lag as i64, 0).unwrap_or([0; 3]);
+                let rgb = [0, 1, 2].map(|c| SKIN[c] + reflectance * color[c] as f64);
+                ChromaSample { timestamp_ms: t as i64, rgb }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn reflected_flashes_pass() {
+        let shown = schedule(&[0, 1, 2, 3, 0, 1]);
+        let result = challenge_liveness_score(&shown, &captured(&shown, 200, 0.3), BOUNDARY_TOLERANCE_MS).unwrap();
+        assert!(result.passed, "score {}", result.challenge_liveness_score);
+        assert!(result.challenge_liveness_score > 0.9);
+        assert!(result.lag_ms.abs_diff(200) <= BOUNDARY_TOLERANCE_MS);
+        assert!(result.used_frames >= MIN_FRAMES);
+    }
+
+    #[test]
+    fn photo_without_reflection_is_rejected() {
+        let shown = schedule(&[0, 1, 2, 3, 0, 1]);
+        let result = challenge_liveness_score(&shown, &captured(&shown, 100, 0.0), BOUNDARY_TOLERANCE_MS).unwrap();
+        assert!(!result.passed);
+        assert_eq!(result.challenge_liveness_score, -1.0);
+    }
+
+    #[test]
+    fn replayed_video_of_another_schedule_is_rejected() {
+        let expected = schedule(&[0, 1, 2, 3, 0, 1]);
+        let recorded = schedule(&[1, 2, 0, 1, 2, 0]);
+        let result = challenge_liveness_score(&expected, &captured(&recorded, 100, 0.3), BOUNDARY_TOLERANCE_MS).unwrap();
+        assert!(!result.passed, "score {}", result.challenge_liveness_score);
+    }
+
+    #[test]
+    fn too_few_frames_gives_no_score() {
+        let shown = schedule(&[0, 1, 2, 3, 0, 1]);
+        let samples: Vec<_> = captured(&shown, 0, 0.3).into_iter().take(MIN_FRAMES).collect();
+        assert!(challenge_liveness_score(&shown, &samples, BOUNDARY_TOLERANCE_MS).is_none());
+    }
+
+    #[test]
+    fn generated_schedule_never_repeats_a_color() {
+        for _ in 0..50 {
+            let schedule = generate_schedule(0);
+            assert_eq!(schedule.steps.len(), STEP_COUNT);
+            assert!(schedule.steps.windows(2).all(|w| w[0].color != w[1].color));
+        }
+    }
+
+    #[test]
+    fn blink_passes() {
+        assert!(has_blink(&[1.0, 1.02, 0.98, 0.5, 0.42, 0.97, 1.0]));
+    }
+
+    #[test]
+    fn static_or_noisy_eyes_are_rejected() {
+        // 照片：睁眼程度不变
+        assert!(!has_blink(&[1.0; 10]));
+        // 轻微抖动不算眨眼
+        assert!(!has_blink(&[1.0, 0.93, 1.02, 0.88, 0.97, 1.0, 0.91]));
+        // 闭眼后没有再睁开
+        assert!(!has_blink(&[1.0, 1.0, 1.0, 1.0, 0.4, 0.4]));
+        // 帧数不足
+        assert!(!has_blink(&[1.0, 0.4, 1.0]));
+    }
+}
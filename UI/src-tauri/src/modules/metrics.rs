@@ -0,0 +1,249 @@
+use std::{
+    collections::BTreeMap,
+    sync::{atomic::Ordering, Mutex},
+    time::Instant,
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{
+    utils::{
+        api::ensure_ready,
+        custom_result::CustomResult,
+        db_writer,
+        frame_cache::{ENCODE_HITS, ENCODE_MISSES},
+    },
+    DB_POOL,
+};
+
+// 解锁各阶段名称，会写入数据库，不要修改已有的值
+/// 收到 WTS 锁屏事件
+pub const STAGE_LOCK: &str = "lock";
+/// 摄像头读到第一帧
+pub const STAGE_FIRST_FRAME: &str = "first_frame";
+/// 第一次检测到人脸
+pub const STAGE_FIRST_DETECTION: &str = "first_detection";
+/// 面容匹配通过
+pub const STAGE_MATCH: &str = "match";
+/// 连接到 DLL 的管道
+pub const STAGE_PIPE_CONNECTED: &str = "pipe_connected";
+/// DLL 确认接收凭据
+pub const STAGE_CREDENTIALS_ACKNOWLEDGED: &str = "credentials_acknowledged";
+/// 收到 WTS 解锁事件
+pub const STAGE_UNLOCK: &str = "unlock";
+
+// 按时间顺序排列的所有阶段
+pub const STAGES: [&str; 7] = [
+    STAGE_LOCK,
+    STAGE_FIRST_FRAME,
+    STAGE_FIRST_DETECTION,
+    STAGE_MATCH,
+    STAGE_PIPE_CONNECTED,
+    STAGE_CREDENTIALS_ACKNOWLEDGED,
+    STAGE_UNLOCK,
+];
+
+// 一次锁屏期间的计时信息
+pub struct UnlockTrace {
+    start: Instant,
+    // 阶段 -> 距离锁屏的毫秒数，同一阶段只记录第一次
+    marks: BTreeMap<&'static str, u64>,
+    // 对应的解锁日志id，收到解锁事件后补充写入
+    log_id: Option<i64>,
+    // 锁屏前是否已经根据屏保/熄屏提前打开了摄像头
+    pre_warmed: bool,
+}
+
+// 写入数据库的耗时明细
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LatencyBreakdown {
+    pub stages: BTreeMap<String, u64>,
+    #[serde(default)]
+    pub pre_warmed: bool,
+}
+
+lazy_static::lazy_static! {
+    static ref CURRENT_TRACE: Mutex<Option<UnlockTrace>> = Mutex::new(None);
+}
+
+// 开始一次新的计时，锁屏时调用
+pub fn begin_trace() {
+    if let Ok(mut guard) = CURRENT_TRACE.lock() {
+        let mut marks = BTreeMap::new();
+        marks.insert(STAGE_LOCK, 0);
+        *guard = Some(UnlockTrace {
+            start: Instant::now(),
+            marks,
+            log_id: None,
+            pre_warmed: false,
+        });
+    }
+}
+
+// 记录某个阶段的时间，没有进行中的计时则忽略
+pub fn mark(stage: &'static str) {
+    if let Ok(mut guard) = CURRENT_TRACE.lock() {
+        if let Some(trace) = guard.as_mut() {
+            let elapsed = trace.start.elapsed().as_millis() as u64;
+            trace.marks.entry(stage).or_insert(elapsed);
+        }
+    }
+}
+
+// 标记本次锁屏已经预热
+pub fn set_pre_warmed() {
+    if let Ok(mut guard) = CURRENT_TRACE.lock() {
+        if let Some(trace) = guard.as_mut() {
+            trace.pre_warmed = true;
+        }
+    }
+}
+
+// 获取当前计时的明细，用于写入解锁日志
+pub fn current_breakdown() -> Option<String> {
+    let guard = CURRENT_TRACE.lock().ok()?;
+    let trace = guard.as_ref()?;
+    breakdown_to_json(trace)
+}
+
+// 记录解锁日志id，解锁事件到达后更新该条日志
+pub fn attach_log_id(log_id: i64) {
+    if let Ok(mut guard) = CURRENT_TRACE.lock() {
+        if let Some(trace) = guard.as_mut() {
+            trace.log_id = Some(log_id);
+        }
+    }
+}
+
+// 结束计时，解锁时调用，把完整的明细写回解锁日志
+pub fn finish_trace() {
+    let trace = match CURRENT_TRACE.lock() {
+        Ok(mut guard) => guard.take(),
+        Err(_) => None,
+    };
+    let Some(mut trace) = trace else {
+        return;
+    };
+
+    let elapsed = trace.start.elapsed().as_millis() as u64;
+    trace.marks.entry(STAGE_UNLOCK).or_insert(elapsed);
+
+    let (Some(log_id), Some(json_str)) = (trace.log_id, breakdown_to_json(&trace)) else {
+        return;
+    };
+
+    db_writer::write_detached("写入解锁耗时失败", move |tx| {
+        tx.execute(
+            "UPDATE unlock_log SET latency = ?1 WHERE id = ?2",
+            r2d2_sqlite::rusqlite::params![json_str, log_id],
+        )
+        .map(|_| ())
+        .map_err(|e| format!("{:?}", e))
+    });
+}
+
+fn breakdown_to_json(trace: &UnlockTrace) -> Option<String> {
+    let breakdown = LatencyBreakdown {
+        stages: trace
+            .marks
+            .iter()
+            .map(|(k, v)| (k.to_string(), *v))
+            .collect(),
+        pre_warmed: trace.pre_warmed,
+    };
+    serde_json::to_string(&breakdown).ok()
+}
+
+// 计算百分位数，values 需要已排序
+fn percentile(values: &[u64], p: f64) -> u64 {
+    if values.is_empty() {
+        return 0;
+    }
+    let rank = ((p / 100.0) * (values.len() - 1) as f64).round() as usize;
+    values[rank.min(values.len() - 1)]
+}
+
+// 获取最近的解锁耗时明细，以及每个阶段的百分位统计
+#[tauri::command]
+pub fn get_unlock_latency_breakdown(limit: Option<u32>) -> Result<CustomResult, CustomResult> {
+    ensure_ready()?;
+    let limit = limit.unwrap_or(50);
+    let pool_guard = DB_POOL
+        .lock()
+        .map_err(|e| CustomResult::error(Some(format!("获取连接池锁失败 {}", e)), None))?;
+    let Some(pool) = pool_guard.as_ref() else {
+        return Err(CustomResult::error(
+            Some(String::from("数据库连接池不存在")),
+            None,
+        ));
+    };
+    let conn = pool
+        .get()
+        .map_err(|e| CustomResult::error(Some(format!("从连接池获取连接失败 {:?}", e)), None))?;
+
+    let mut stmt = conn
+        .prepare(
+            "SELECT id, face_id, is_unlock, latency, lastTime FROM unlock_log \
+             WHERE latency IS NOT NULL ORDER BY id DESC LIMIT ?1",
+        )
+        .map_err(|e| CustomResult::error(Some(format!("准备查询解锁耗时失败 {:?}", e)), None))?;
+    let rows = stmt
+        .query_map([limit], |row| {
+            Ok((
+                row.get::<&str, i64>("id")?,
+                row.get::<&str, Option<i64>>("face_id")?,
+                row.get::<&str, i32>("is_unlock")?,
+                row.get::<&str, String>("latency")?,
+                row.get::<&str, String>("lastTime")?,
+            ))
+        })
+        .map_err(|e| CustomResult::error(Some(format!("查询解锁耗时失败 {:?}", e)), None))?;
+
+    let mut traces = Vec::new();
+    let mut per_stage: BTreeMap<&'static str, Vec<u64>> = BTreeMap::new();
+    for row in rows.flatten() {
+        let (id, face_id, is_unlock, latency, last_time) = row;
+        let Ok(breakdown) = serde_json::from_str::<LatencyBreakdown>(&latency) else {
+            continue;
+        };
+        for stage in STAGES {
+            if let Some(ms) = breakdown.stages.get(stage) {
+                per_stage.entry(stage).or_default().push(*ms);
+            }
+        }
+        traces.push(json!({
+            "id": id,
+            "face_id": face_id,
+            "is_unlock": is_unlock == 1,
+            "time": last_time,
+            "stages": breakdown.stages,
+            "pre_warmed": breakdown.pre_warmed,
+        }));
+    }
+
+    let mut aggregate = Vec::new();
+    for stage in STAGES {
+        let mut values = per_stage.remove(stage).unwrap_or_default();
+        values.sort_unstable();
+        aggregate.push(json!({
+            "stage": stage,
+            "count": values.len(),
+            "p50": percentile(&values, 50.0),
+            "p90": percentile(&values, 90.0),
+            "p99": percentile(&values, 99.0),
+        }));
+    }
+
+    Ok(CustomResult::success(
+        None,
+        Some(json!({
+            "traces": traces,
+            "aggregate": aggregate,
+            "encode": {
+                "hits": ENCODE_HITS.load(Ordering::Relaxed),
+                "misses": ENCODE_MISSES.load(Ordering::Relaxed),
+            }
+        })),
+    ))
+}
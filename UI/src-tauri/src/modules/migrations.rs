@@ -0,0 +1,536 @@
+// 启动时的一次性数据迁移
+// 迁移按注册顺序执行，成功后记录到 migrations.json，之后不再执行
+// 数据库连接池在前端初始化后才创建，所以迁移记录保存在文件中
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use r2d2_sqlite::rusqlite::{Connection, OpenFlags};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tauri_plugin_log::log::{error, info, warn};
+
+use crate::{
+    modules::{
+        credential::move_passwords_to_vault,
+        face_search::REGISTRATION_INDEXES,
+        face_store,
+        faces::{import_face_files, upgrade_face_files},
+    },
+    utils::{
+        custom_result::CustomResult,
+        storage::{copy_missing_faces, face_store_path, faces_dir, legacy_faces_dir, with_retry},
+    },
+    ROOT_DIR,
+};
+
+// 迁移执行时可以使用的路径，不直接读取全局变量，方便在其他目录上执行
+pub struct MigrationContext {
+    // 软件目录下的旧面容目录
+    pub legacy_faces_dir: PathBuf,
+    // 当前使用的面容目录
+    pub faces_dir: PathBuf,
+    // 数据库文件，表由前端创建
+    pub database_path: PathBuf,
+    // 面容特征数据库
+    pub face_store_path: PathBuf,
+}
+
+impl MigrationContext {
+    pub fn current() -> Self {
+        Self {
+            legacy_faces_dir: legacy_faces_dir(),
+            faces_dir: faces_dir().to_path_buf(),
+            database_path: ROOT_DIR.join("database.db"),
+            face_store_path: face_store_path(),
+        }
+    }
+}
+
+// 单个迁移的执行结果
+pub enum MigrationOutcome {
+    // 已完成，不再执行
+    Applied(String),
+    // 当前环境不需要执行，不记录，下次启动重新检查
+    NotApplicable,
+}
+
+pub struct Migration {
+    pub id: &'static str,
+    pub description: &'static str,
+    // 关键迁移失败时不再执行后面的迁移，后面的迁移可能依赖它的结果
+    pub critical: bool,
+    pub run: fn(&MigrationContext) -> Result<MigrationOutcome, String>,
+}
+
+// 已注册的迁移，只能在末尾追加，不能修改已发布迁移的 id
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        id: "0001_faces_dir_relocation",
+        description: "软件目录处于云同步目录中时，把面容数据复制到 LocalAppData",
+        critical: false,
+        run: relocate_faces_dir,
+    },
+    Migration {
+        id: "0002_descriptor_v2",
+        description: "把面容特征文件升级为带版本号的格式",
+        critical: false,
+        run: upgrade_descriptors,
+    },
+    Migration {
+        id: "0003_registration_indexes",
+        description: "为面容搜索创建用户名、创建时间和解锁记录的索引",
+        critical: false,
+        run: create_registration_indexes,
+    },
+    Migration {
+        id: "0004_face_store_import",
+        description: "把面容目录中的 .face 特征文件导入面容数据库",
+        critical: false,
+        run: import_face_store,
+    },
+    Migration {
+        id: "0005_vault_passwords",
+        description: "把数据库中明文保存的账户密码移到 Windows 凭据管理器",
+        critical: false,
+        run: move_passwords,
+    },
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum RecordStatus {
+    Applied,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct MigrationRecord {
+    status: RecordStatus,
+    // 执行次数，失败后每次启动重试
+    attempts: u32,
+    // 成功时的说明或失败原因
+    detail: String,
+    // 最后一次执行的时间（Unix 时间戳，秒）
+    timestamp: u64,
+}
+
+fn record_path() -> PathBuf {
+    ROOT_DIR.join("migrations.json")
+}
+
+fn load_records(path: &Path) -> BTreeMap<String, MigrationRecord> {
+    match fs::read_to_string(path) {
+        Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+            warn!("迁移记录解析失败，按未执行处理: {}", e);
+            BTreeMap::new()
+        }),
+        Err(_) => BTreeMap::new(),
+    }
+}
+
+fn save_records(path: &Path, records: &BTreeMap<String, MigrationRecord>) {
+    let content = match serde_json::to_string_pretty(records) {
+        Ok(content) => content,
+        Err(e) => {
+            error!("序列化迁移记录失败: {}", e);
+            return;
+        }
+    };
+    if let Err(e) = with_retry(|| fs::write(path, &content)) {
+        error!("保存迁移记录失败: {}", e);
+    }
+}
+
+fn now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+// 执行所有未完成的迁移，单个迁移失败不影响启动
+pub fn run_pending_migrations(ctx: &MigrationContext) {
+    run_migrations(MIGRATIONS, ctx, &record_path());
+}
+
+// 按顺序执行未完成的迁移，执行结果记录在 path 中
+fn run_migrations(migrations: &[Migration], ctx: &MigrationContext, path: &Path) {
+    let mut records = load_records(path);
+
+    for migration in migrations {
+        if matches!(records.get(migration.id), Some(r) if matches!(r.status, RecordStatus::Applied)) {
+            continue;
+        }
+
+        let attempts = records.get(migration.id).map_or(0, |r| r.attempts) + 1;
+        info!("执行迁移 {}（第 {} 次）：{}", migration.id, attempts, migration.description);
+        let result = (migration.run)(ctx);
+        match result {
+            Ok(MigrationOutcome::Applied(detail)) => {
+                info!("迁移 {} 完成：{}", migration.id, detail);
+                records.insert(
+                    migration.id.to_string(),
+                    MigrationRecord { status: RecordStatus::Applied, attempts, detail, timestamp: now() },
+                );
+            }
+            Ok(MigrationOutcome::NotApplicable) => {
+                info!("迁移 {} 在当前环境下不需要执行", migration.id);
+            }
+            Err(e) => {
+                error!("迁移 {} 失败，下次启动重试：{}", migration.id, e);
+                records.insert(
+                    migration.id.to_string(),
+                    MigrationRecord { status: RecordStatus::Failed, attempts, detail: e, timestamp: now() },
+                );
+                if migration.critical {
+                    warn!("关键迁移失败，跳过后续迁移");
+                    break;
+                }
+            }
+        }
+        save_records(path, &records);
+    }
+}
+
+// 获取迁移状态：已完成、待执行、失败
+#[tauri::command]
+pub fn get_migration_status() -> Result<CustomResult, CustomResult> {
+    let records = load_records(&record_path());
+    let list: Vec<_> = MIGRATIONS
+        .iter()
+        .map(|migration| {
+            let record = records.get(migration.id);
+            let status = match record.map(|r| &r.status) {
+                Some(RecordStatus::Applied) => "applied",
+                Some(RecordStatus::Failed) => "failed",
+                None => "pending",
+            };
+            json!({
+                "id": migration.id,
+                "description": migration.description,
+                "critical": migration.critical,
+                "status": status,
+                "attempts": record.map_or(0, |r| r.attempts),
+                "detail": record.map(|r| r.detail.clone()),
+                "timestamp": record.map(|r| r.timestamp),
+            })
+        })
+        .collect();
+    Ok(CustomResult::success(None, Some(json!({"migrations": list}))))
+}
+
+// 面容目录改到 LocalAppData 后，把旧目录中的文件复制过去
+fn relocate_faces_dir(ctx: &MigrationContext) -> Result<MigrationOutcome, String> {
+    if ctx.faces_dir == ctx.legacy_faces_dir || !ctx.legacy_faces_dir.exists() {
+        // 没有改变目录，以后目录变化时再执行
+        return Ok(MigrationOutcome::NotApplicable);
+    }
+    let copied = copy_missing_faces(&ctx.legacy_faces_dir, &ctx.faces_dir)
+        .map_err(|e| format!("复制面容文件失败: {}", e))?;
+    Ok(MigrationOutcome::Applied(format!(
+        "已从 {:?} 复制 {} 个文件到 {:?}",
+        ctx.legacy_faces_dir, copied, ctx.faces_dir
+    )))
+}
+
+// 升级所有旧格式的特征文件，单个文件失败时继续处理其他文件，最后整体报告失败
+fn upgrade_descriptors(ctx: &MigrationContext) -> Result<MigrationOutcome, String> {
+    let (upgraded, errors) = match upgrade_face_files(&ctx.faces_dir) {
+        Ok(result) => result,
+        // 新安装还没有面容目录
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Ok(MigrationOutcome::Applied(String::from("没有面容数据")))
+        }
+        Err(e) => return Err(format!("读取面容目录失败: {}", e)),
+    };
+
+    if !errors.is_empty() {
+        let errors: Vec<_> = errors.iter().map(|(file, e)| format!("{:?}: {}", file, e)).collect();
+        return Err(format!("{} 个文件升级失败：{}", errors.len(), errors.join("；")));
+    }
+    Ok(MigrationOutcome::Applied(format!("已升级 {} 个特征文件", upgraded)))
+}
+
+// 创建面容搜索使用的索引
+fn create_registration_indexes(ctx: &MigrationContext) -> Result<MigrationOutcome, String> {
+    // 首次启动时数据库和表还没有由前端创建，下次启动再执行
+    let Ok(conn) = Connection::open_with_flags(&ctx.database_path, OpenFlags::SQLITE_OPEN_READ_WRITE) else {
+        return Ok(MigrationOutcome::NotApplicable);
+    };
+    let tables: i64 = conn
+        .query_row(
+            "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name IN ('faces', 'unlock_log');",
+            [],
+            |row| row.get(0),
+        )
+        .map_err(|e| format!("查询数据表失败: {:?}", e))?;
+    if tables < 2 {
+        return Ok(MigrationOutcome::NotApplicable);
+    }
+    conn.execute_batch(REGISTRATION_INDEXES)
+        .map_err(|e| format!("创建索引失败: {:?}", e))?;
+    Ok(MigrationOutcome::Applied(String::from("已创建面容搜索索引")))
+}
+
+// 把特征文件导入面容数据库，导入成功的文件被删除，失败的文件保留，下次启动重试
+// 录入图片（.faceimg）留在面容目录中
+fn import_face_store(ctx: &MigrationContext) -> Result<MigrationOutcome, String> {
+    let conn = face_store::open(&ctx.face_store_path)?;
+    let (imported, errors) = match import_face_files(&conn, &ctx.faces_dir) {
+        Ok(result) => result,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Ok(MigrationOutcome::Applied(String::from("没有面容数据")))
+        }
+        Err(e) => return Err(format!("读取面容目录失败: {}", e)),
+    };
+
+    if !errors.is_empty() {
+        let errors: Vec<_> = errors.iter().map(|(file, e)| format!("{}: {}", file, e)).collect();
+        return Err(format!(
+            "已导入 {} 个，{} 个文件导入失败：{}",
+            imported.len(),
+            errors.len(),
+            errors.join("；")
+        ));
+    }
+    let named = fill_usernames(&conn, &ctx.database_path)?;
+    Ok(MigrationOutcome::Applied(format!(
+        "已导入 {} 个特征文件，{} 个面容已填写用户名",
+        imported.len(),
+        named
+    )))
+}
+
+// 按 face_token 从前端数据库复制用户名，数据库或表还不存在时跳过
+fn fill_usernames(conn: &Connection, database_path: &Path) -> Result<usize, String> {
+    let Ok(accounts) = Connection::open_with_flags(database_path, OpenFlags::SQLITE_OPEN_READ_ONLY) else {
+        return Ok(0);
+    };
+    let Ok(mut stmt) = accounts.prepare("SELECT face_token, user_name FROM faces;") else {
+        return Ok(0);
+    };
+    let rows = stmt
+        .query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))
+        .and_then(|rows| rows.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| format!("读取账户失败: {:?}", e))?;
+
+    let mut named = 0;
+    for (face_token, user_name) in rows {
+        let found = face_store::set_username(conn, &face_token, &user_name)
+            .map_err(|e| format!("保存用户名失败: {:?}", e))?;
+        if found {
+            named += 1;
+        }
+    }
+    Ok(named)
+}
+
+// 密码改为保存在凭据管理器中，清空数据库中的明文密码
+fn move_passwords(ctx: &MigrationContext) -> Result<MigrationOutcome, String> {
+    // 首次启动时数据库和表还没有由前端创建，下次启动再执行
+    let Ok(conn) = Connection::open_with_flags(&ctx.database_path, OpenFlags::SQLITE_OPEN_READ_WRITE) else {
+        return Ok(MigrationOutcome::NotApplicable);
+    };
+    let tables: i64 = conn
+        .query_row(
+            "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = 'faces';",
+            [],
+            |row| row.get(0),
+        )
+        .map_err(|e| format!("查询数据表失败: {:?}", e))?;
+    if tables == 0 {
+        return Ok(MigrationOutcome::NotApplicable);
+    }
+    let moved = move_passwords_to_vault(&conn)?;
+    Ok(MigrationOutcome::Applied(format!("已把 {} 个面容的密码移到凭据管理器", moved)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::{Cell, RefCell};
+
+    use super::*;
+
+    thread_local! {
+        // 测试迁移的执行顺序
+        static EXECUTED: RefCell<Vec<&'static str>> = RefCell::new(Vec::new());
+        // 下一次执行 flaky 时是否失败
+        static FLAKY_FAILS: Cell<bool> = Cell::new(true);
+    }
+
+    fn executed(id: &'static str) {
+        EXECUTED.with(|e| e.borrow_mut().push(id));
+    }
+
+    fn take_executed() -> Vec<&'static str> {
+        EXECUTED.with(|e| std::mem::take(&mut *e.borrow_mut()))
+    }
+
+    fn first(_: &MigrationContext) -> Result<MigrationOutcome, String> {
+        executed("first");
+        Ok(MigrationOutcome::Applied(String::from("first done")))
+    }
+
+    fn second(_: &MigrationContext) -> Result<MigrationOutcome, String> {
+        executed("second");
+        Ok(MigrationOutcome::Applied(String::from("second done")))
+    }
+
+    fn not_applicable(_: &MigrationContext) -> Result<MigrationOutcome, String> {
+        executed("not_applicable");
+        Ok(MigrationOutcome::NotApplicable)
+    }
+
+    fn flaky(_: &MigrationContext) -> Result<MigrationOutcome, String> {
+        executed("flaky");
+        if FLAKY_FAILS.with(|f| f.replace(false)) {
+            return Err(String::from("disk busy"));
+        }
+        Ok(MigrationOutcome::Applied(String::from("flaky done")))
+    }
+
+    fn migration(
+        id: &'static str,
+        critical: bool,
+        run: fn(&MigrationContext) -> Result<MigrationOutcome, String>,
+    ) -> Migration {
+        Migration { id, description: id, critical, run }
+    }
+
+    // 每个测试使用独立的临时目录
+    fn temp_context() -> (MigrationContext, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("facewinunlock-migrations-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let ctx = MigrationContext {
+            legacy_faces_dir: dir.join("legacy"),
+            faces_dir: dir.join("faces"),
+            database_path: dir.join("database.db"),
+            face_store_path: dir.join("faces.db"),
+        };
+        (ctx, dir)
+    }
+
+    fn status(records: &BTreeMap<String, MigrationRecord>, id: &str) -> Option<(bool, u32)> {
+        records.get(id).map(|r| (matches!(r.status, RecordStatus::Applied), r.attempts))
+    }
+
+    #[test]
+    fn runs_in_registration_order_and_persists_applied_ids() {
+        let (ctx, dir) = temp_context();
+        let path = dir.join("migrations.json");
+        let migrations = [migration("0001_first", false, first), migration("0002_second", false, second)];
+
+        run_migrations(&migrations, &ctx, &path);
+        assert_eq!(take_executed(), vec!["first", "second"]);
+        let records = load_records(&path);
+        assert_eq!(status(&records, "0001_first"), Some((true, 1)));
+        assert_eq!(status(&records, "0002_second"), Some((true, 1)));
+
+        // 已完成的迁移下次启动不再执行，新追加的迁移照常执行
+        let migrations = [
+            migration("0001_first", false, first),
+            migration("0002_second", false, second),
+            migration("0003_later", false, not_applicable),
+        ];
+        run_migrations(&migrations, &ctx, &path);
+        assert_eq!(take_executed(), vec!["not_applicable"]);
+        // 不需要执行的迁移不记录，下次启动重新检查
+        assert_eq!(status(&load_records(&path), "0003_later"), None);
+        fs::remove_dir_all(dir).ok();
+    }
+
+    #[test]
+    fn failed_non_critical_migration_is_retried() {
+        let (ctx, dir) = temp_context();
+        let path = dir.join("migrations.json");
+        FLAKY_FAILS.with(|f| f.set(true));
+        let migrations = [migration("0001_flaky", false, flaky), migration("0002_second", false, second)];
+
+        run_migrations(&migrations, &ctx, &path);
+        // 非关键迁移失败不影响后面的迁移
+        assert_eq!(take_executed(), vec!["flaky", "second"]);
+        let records = load_records(&path);
+        assert_eq!(status(&records, "0001_flaky"), Some((false, 1)));
+        assert_eq!(records["0001_flaky"].detail, "disk busy");
+
+        run_migrations(&migrations, &ctx, &path);
+        assert_eq!(take_executed(), vec!["flaky"]);
+        assert_eq!(status(&load_records(&path), "0001_flaky"), Some((true, 2)));
+        fs::remove_dir_all(dir).ok();
+    }
+
+    #[test]
+    fn failed_critical_migration_stops_later_ones() {
+        let (ctx, dir) = temp_context();
+        let path = dir.join("migrations.json");
+        FLAKY_FAILS.with(|f| f.set(true));
+        let migrations = [migration("0001_flaky", true, flaky), migration("0002_second", false, second)];
+
+        run_migrations(&migrations, &ctx, &path);
+        assert_eq!(take_executed(), vec!["flaky"]);
+        assert_eq!(status(&load_records(&path), "0002_second"), None);
+
+        run_migrations(&migrations, &ctx, &path);
+        assert_eq!(take_executed(), vec!["flaky", "second"]);
+        fs::remove_dir_all(dir).ok();
+    }
+
+    #[test]
+    fn registration_indexes_wait_for_frontend_tables() {
+        let (ctx, dir) = temp_context();
+        // 数据库还不存在
+        assert!(matches!(create_registration_indexes(&ctx), Ok(MigrationOutcome::NotApplicable)));
+
+        let conn = Connection::open(&ctx.database_path).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE faces (id INTEGER PRIMARY KEY, user_name TEXT, createTime TEXT);\
+             CREATE TABLE unlock_log (id INTEGER PRIMARY KEY, face_id INTEGER, is_unlock INTEGER, lastTime TEXT);",
+        )
+        .unwrap();
+        assert!(matches!(create_registration_indexes(&ctx), Ok(MigrationOutcome::Applied(_))));
+        // 重复执行不会出错
+        assert!(matches!(create_registration_indexes(&ctx), Ok(MigrationOutcome::Applied(_))));
+        let indexes: i64 = conn
+            .query_row("SELECT COUNT(*) FROM sqlite_master WHERE type = 'index' AND name LIKE 'idx_%';", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(indexes, 3);
+        drop(conn);
+        fs::remove_dir_all(dir).ok();
+    }
+
+    #[test]
+    fn usernames_are_copied_from_frontend_accounts() {
+        let (ctx, dir) = temp_context();
+        let store = face_store::open(&ctx.face_store_path).unwrap();
+        // 前端数据库还不存在
+        assert_eq!(fill_usernames(&store, &ctx.database_path).unwrap(), 0);
+
+        for token in ["a", "b"] {
+            let face = face_store::StoredFace {
+                face_token: token.to_string(),
+                name: token.to_string(),
+                username: String::new(),
+                feature: vec![1],
+                created_at: 0,
+            };
+            face_store::save(&store, &face).unwrap();
+        }
+        let accounts = Connection::open(&ctx.database_path).unwrap();
+        accounts
+            .execute_batch(
+                "CREATE TABLE faces (id INTEGER PRIMARY KEY, user_name TEXT, face_token TEXT);\
+                 INSERT INTO faces (user_name, face_token) VALUES ('alice', 'a'), ('carol', 'missing');",
+            )
+            .unwrap();
+        drop(accounts);
+
+        assert_eq!(fill_usernames(&store, &ctx.database_path).unwrap(), 1);
+        assert_eq!(face_store::load(&store, "a").unwrap().unwrap().username, "alice");
+        assert_eq!(face_store::load(&store, "b").unwrap().unwrap().username, "");
+        drop(store);
+        fs::remove_dir_all(dir).ok();
+    }
+}
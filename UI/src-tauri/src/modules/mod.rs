@@ -1,3 +1,28 @@
+pub mod auto_unlock;
+pub mod backup;
+pub mod calibration;
+pub mod capabilities;
+pub mod conference;
+pub mod continuous_verify;
+pub mod control;
+pub mod credential;
+pub mod drift;
+pub mod engine;
+pub mod face_policy;
+pub mod face_search;
+pub mod face_store;
+pub mod face_watch;
 pub mod faces;
 pub mod init;
+pub mod liveness;
+pub mod metrics;
+pub mod migrations;
+pub mod model_check;
 pub mod options;
+pub mod presets;
+pub mod replay;
+pub mod retention;
+pub mod statistics;
+pub mod supervisor;
+pub mod support;
+pub mod template;
@@ -0,0 +1,145 @@
+// 模型加载后的推理自检
+// 损坏但仍能解析的模型文件、OpenCL 驱动问题等，可能输出全 0 或 NaN 的特征，导致所有匹配分数失去意义
+use opencv::{
+    core::{self, Mat, Ptr, Scalar, Size, CV_8UC3},
+    imgcodecs,
+    objdetect::{FaceDetectorYN, FaceRecognizerSF, FaceRecognizerSF_DisType},
+    prelude::*,
+};
+use serde_json::json;
+use tauri_plugin_log::log::{info, warn};
+
+use crate::{utils::custom_result::CustomResult, ROOT_DIR};
+
+// 错误条件，返回给前端
+pub const MODEL_SANITY_CHECK_FAILED: &str = "ModelSanityCheckFailed";
+// 自检阶段
+const STAGE_DETECTOR: &str = "detector";
+const STAGE_RECOGNIZER: &str = "recognizer";
+const STAGE_FEATURE: &str = "feature";
+const STAGE_SELF_MATCH: &str = "self_match";
+// 自检图片，包含一张正脸
+const SANITY_IMAGE: &str = "model_sanity_face.jpg";
+// 自检使用的检测阈值，比日常使用的低，只要求能找到人脸
+const SANITY_DETECTION_THRESHOLD: f32 = 0.5;
+// 同一特征自身匹配的最低分数，正常情况下应非常接近 1.0
+const MIN_SELF_MATCH: f64 = 0.99;
+// 特征方差下限，低于此值视为全 0 或常量输出
+const MIN_FEATURE_VARIANCE: f64 = 1e-8;
+
+pub struct SanityFailure {
+    pub stage: &'static str,
+    pub detail: String,
+}
+
+impl SanityFailure {
+    fn new(stage: &'static str, detail: impl Into<String>) -> Self {
+        Self { stage, detail: detail.into() }
+    }
+
+    pub fn into_result(self) -> CustomResult {
+        CustomResult::error(
+            Some(format!(
+                "模型自检失败（{}）：{}，请重新安装软件或重新下载模型文件以修复",
+                self.stage, self.detail
+            )),
+            Some(json!({
+                "condition": MODEL_SANITY_CHECK_FAILED,
+                "stage": self.stage,
+                "detail": self.detail,
+                "suggestion": "repair_models",
+            })),
+        )
+    }
+}
+
+// 检查特征向量：不能包含 NaN/Inf，并且不能是常量
+pub fn validate_feature(feature: &Mat) -> Result<(), String> {
+    let values = feature
+        .data_typed::<f32>()
+        .map_err(|e| format!("读取特征数据失败: {}", e))?;
+    if values.is_empty() {
+        return Err(String::from("特征为空"));
+    }
+    if let Some(index) = values.iter().position(|v| !v.is_finite()) {
+        return Err(format!("特征第 {} 维不是有效数值", index));
+    }
+    let mean = values.iter().map(|v| *v as f64).sum::<f64>() / values.len() as f64;
+    let variance = values
+        .iter()
+        .map(|v| (*v as f64 - mean).powi(2))
+        .sum::<f64>()
+        / values.len() as f64;
+    if variance < MIN_FEATURE_VARIANCE {
+        return Err(format!("特征方差过小（{:.3e}），模型输出可能是常量", variance));
+    }
+    Ok(())
+}
+
+// 用自检图片跑一次完整的推理：检测 -> 对齐 -> 提取特征 -> 自身匹配
+// 自检图片不存在时跳过检测阶段，用随机图片直接检查识别模型的输出
+pub fn run_sanity_check(
+    detector: &mut Ptr<FaceDetectorYN>,
+    recognizer: &mut Ptr<FaceRecognizerSF>,
+) -> Result<(), SanityFailure> {
+    let image_path = ROOT_DIR.join("resources").join(SANITY_IMAGE);
+    let image = imgcodecs::imread(image_path.to_str().unwrap_or(""), imgcodecs::IMREAD_COLOR)
+        .ok()
+        .filter(|img| !img.empty());
+
+    let aligned = match image {
+        Some(image) => {
+            let mut faces = Mat::default();
+            let size = image
+                .size()
+                .map_err(|e| SanityFailure::new(STAGE_DETECTOR, e.to_string()))?;
+            detector
+                .set_input_size(size)
+                .and_then(|_| detector.set_score_threshold(SANITY_DETECTION_THRESHOLD))
+                .and_then(|_| detector.detect(&image, &mut faces))
+                .map_err(|e| SanityFailure::new(STAGE_DETECTOR, e.to_string()))?;
+            if faces.rows() < 1 {
+                return Err(SanityFailure::new(STAGE_DETECTOR, "自检图片中未检测到人脸"));
+            }
+            let face = faces
+                .row(0)
+                .map_err(|e| SanityFailure::new(STAGE_DETECTOR, e.to_string()))?;
+            let mut aligned = Mat::default();
+            recognizer
+                .align_crop(&image, &face, &mut aligned)
+                .map_err(|e| SanityFailure::new(STAGE_RECOGNIZER, e.to_string()))?;
+            aligned
+        }
+        None => {
+            warn!("缺少模型自检图片 {:?}，跳过检测模型自检", image_path);
+            let mut aligned = Mat::new_size_with_default(Size::new(112, 112), CV_8UC3, Scalar::all(0.0))
+                .map_err(|e| SanityFailure::new(STAGE_RECOGNIZER, e.to_string()))?;
+            core::randu(&mut aligned, &Scalar::all(0.0), &Scalar::all(255.0))
+                .map_err(|e| SanityFailure::new(STAGE_RECOGNIZER, e.to_string()))?;
+            aligned
+        }
+    };
+
+    let mut feature = Mat::default();
+    recognizer
+        .feature(&aligned, &mut feature)
+        .map_err(|e| SanityFailure::new(STAGE_RECOGNIZER, e.to_string()))?;
+    // feature 返回的 Mat 引用模型内部的缓冲区，复制一份再比较
+    let feature = feature
+        .try_clone()
+        .map_err(|e| SanityFailure::new(STAGE_FEATURE, e.to_string()))?;
+    validate_feature(&feature).map_err(|e| SanityFailure::new(STAGE_FEATURE, e))?;
+
+    let score = recognizer
+        .match_(&feature, &feature, FaceRecognizerSF_DisType::FR_COSINE.into())
+        .map_err(|e| SanityFailure::new(STAGE_SELF_MATCH, e.to_string()))?;
+    if !score.is_finite() || score < MIN_SELF_MATCH {
+        return Err(SanityFailure::new(
+            STAGE_SELF_MATCH,
+            format!("自身匹配分数为 {}，应接近 1.0", score),
+        ));
+    }
+
+    info!("模型自检通过，自身匹配分数 {:.4}", score);
+    Ok(())
+}
@@ -1,7 +1,31 @@
-use crate::utils::custom_result::CustomResult;
+use std::collections::BTreeMap;
+
+use crate::{
+    modules::faces::{load_black_frame_config, load_match_config, load_preview_settings},
+    utils::{
+        api::{ensure_ready, load_pipe_name},
+        custom_result::CustomResult,
+        db_writer,
+    },
+    DB_POOL, ROOT_DIR,
+};
+use r2d2::PooledConnection;
+use r2d2_sqlite::{rusqlite::{self, Connection}, SqliteConnectionManager};
+use serde_json::{json, Value};
+use tauri_plugin_log::log::{info, warn};
 use winreg::enums::*;
 use winreg::RegKey;
 
+// 最多保留的配置快照数量
+const MAX_SNAPSHOTS: i64 = 5;
+// 快照中记录的模型文件
+const MODEL_FILES: [&str; 2] = [
+    "face_detection_yunet_2023mar.onnx",
+    "face_recognition_sface_2021dec.onnx",
+];
+// 不属于用户配置的设置项，不参与快照
+const SNAPSHOT_EXCLUDED_KEYS: [&str; 1] = ["is_initialized"];
+
 // 注册表结构体
 #[derive(serde::Deserialize)]
 pub struct RegistryItem {
@@ -29,3 +53,296 @@ pub fn write_to_registry(items: Vec<RegistryItem>) -> Result<CustomResult, Custo
 
     Ok(CustomResult::success(None, None))
 }
+
+// 从全局连接池获取一个连接
+// 注意：调用方不能持有 DB_POOL 的锁，否则会死锁
+pub fn get_conn() -> Result<PooledConnection<SqliteConnectionManager>, String> {
+    let pool_guard = DB_POOL
+        .lock()
+        .map_err(|e| format!("获取连接池锁失败 {}", e))?;
+    let pool = pool_guard
+        .as_ref()
+        .ok_or_else(|| String::from("数据库连接池不存在"))?;
+    pool.get()
+        .map_err(|e| format!("从连接池获取连接失败 {:?}", e))
+}
+
+// 从数据库读取设置项，不存在或读取失败时返回 None
+// 注意：调用方不能持有 DB_POOL 的锁，否则会死锁
+pub fn read_option(key: &str) -> Option<String> {
+    let conn = get_conn().ok()?;
+    query_option(&conn, key)
+}
+
+// 使用已有的连接读取设置项，已经持有连接时使用，避免占用连接池的第二个连接
+pub fn query_option(conn: &Connection, key: &str) -> Option<String> {
+    conn.query_row(
+        "SELECT val FROM options WHERE key = ?1;",
+        [key],
+        |row| row.get::<&str, String>("val"),
+    )
+    .ok()
+}
+
+// 使用已有的连接保存设置项
+pub fn save_option(conn: &Connection, key: &str, val: &str) -> Result<(), String> {
+    conn.execute(
+        "INSERT INTO options (key, val) VALUES (?1, ?2) \
+         ON CONFLICT(key) DO UPDATE SET val = excluded.val, lastTime = datetime('now', 'localtime')",
+        rusqlite::params![key, val],
+    )
+    .map(|_| ())
+    .map_err(|e| format!("保存设置 {} 失败：{:?}", key, e))
+}
+
+// 设置修改后，让后端缓存的配置重新读取，立即生效
+// 黑帧检测配置、检测和匹配阈值、解锁管道名称、预览画面设置
+pub fn reload_backend_options() {
+    load_black_frame_config();
+    load_match_config();
+    load_pipe_name();
+    load_preview_settings();
+}
+
+// 读取当前所有用户设置
+fn current_options(conn: &Connection) -> Result<BTreeMap<String, String>, String> {
+    let mut stmt = conn
+        .prepare("SELECT key, val FROM options;")
+        .map_err(|e| format!("准备查询设置失败：{:?}", e))?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok((row.get::<&str, String>("key")?, row.get::<&str, String>("val")?))
+        })
+        .map_err(|e| format!("查询设置失败：{:?}", e))?;
+
+    let mut options = BTreeMap::new();
+    for (key, val) in rows.flatten() {
+        if !SNAPSHOT_EXCLUDED_KEYS.contains(&key.as_str()) {
+            options.insert(key, val);
+        }
+    }
+    Ok(options)
+}
+
+// 模型文件的名称和大小，用于区分模型是否被替换
+fn model_identifiers() -> Vec<Value> {
+    MODEL_FILES
+        .iter()
+        .map(|name| {
+            let size = std::fs::metadata(ROOT_DIR.join("resources").join(name))
+                .map(|m| m.len())
+                .ok();
+            json!({"name": name, "size": size})
+        })
+        .collect()
+}
+
+// 保存当前配置为快照，并删除多余的旧快照
+fn insert_snapshot(conn: &Connection, name: &str) -> Result<i64, String> {
+    let options = current_options(conn)?;
+    let data = json!({
+        "options": options,
+        "models": model_identifiers(),
+        "camera": options.get("camera").cloned().unwrap_or(String::from("0")),
+    });
+
+    conn.execute(
+        "INSERT INTO config_snapshot (name, json_data) VALUES (?1, ?2)",
+        rusqlite::params![name, data.to_string()],
+    )
+    .map_err(|e| format!("保存配置快照失败：{:?}", e))?;
+    let id = conn.last_insert_rowid();
+
+    conn.execute(
+        "DELETE FROM config_snapshot WHERE id NOT IN (SELECT id FROM config_snapshot ORDER BY id DESC LIMIT ?1)",
+        [MAX_SNAPSHOTS],
+    )
+    .map_err(|e| format!("清理旧配置快照失败：{:?}", e))?;
+
+    Ok(id)
+}
+
+// 把设置恢复为快照中的值，快照中没有的设置项删除，恢复为默认值
+fn restore_options(conn: &Connection, snapshot: &BTreeMap<String, String>) -> Result<(), String> {
+    for key in current_options(conn)?.keys() {
+        if !snapshot.contains_key(key) {
+            conn.execute("DELETE FROM options WHERE key = ?1;", [key])
+                .map_err(|e| format!("删除设置 {} 失败：{:?}", key, e))?;
+        }
+    }
+    for (key, val) in snapshot {
+        save_option(conn, key, val)?;
+    }
+    Ok(())
+}
+
+// 删除超过指定天数的配置快照，返回删除的数量
+pub fn prune_snapshots_older_than(conn: &Connection, days: u32) -> Result<usize, String> {
+    conn.execute(
+        "DELETE FROM config_snapshot WHERE createTime < datetime('now', 'localtime', ?1)",
+        [format!("-{} days", days)],
+    )
+    .map_err(|e| format!("清理过期配置快照失败：{:?}", e))
+}
+
+// 读取快照中的设置项
+fn snapshot_options(json_data: &str) -> BTreeMap<String, String> {
+    serde_json::from_str::<Value>(json_data)
+        .ok()
+        .and_then(|v| serde_json::from_value(v["options"].clone()).ok())
+        .unwrap_or_default()
+}
+
+// 快照与当前设置的差异
+fn diff_options(current: &BTreeMap<String, String>, snapshot: &BTreeMap<String, String>) -> Vec<Value> {
+    let mut diff = Vec::new();
+    for (key, val) in snapshot {
+        match current.get(key) {
+            Some(cur) if cur == val => {}
+            cur => diff.push(json!({"key": key, "current": cur, "snapshot": val})),
+        }
+    }
+    for (key, cur) in current {
+        if !snapshot.contains_key(key) {
+            diff.push(json!({"key": key, "current": cur, "snapshot": null}));
+        }
+    }
+    diff
+}
+
+// 解锁成功后调用，如果设置和最近的快照不同，说明修改后的设置可用，自动保存快照
+pub fn mark_known_good_if_changed(conn: &Connection) -> Result<bool, String> {
+    let current = current_options(conn)?;
+    let latest: Option<String> = match conn.query_row(
+        "SELECT json_data FROM config_snapshot ORDER BY id DESC LIMIT 1;",
+        [],
+        |row| row.get::<&str, String>("json_data"),
+    ) {
+        Ok(val) => Some(val),
+        Err(rusqlite::Error::QueryReturnedNoRows) => None,
+        Err(e) => return Err(format!("查询配置快照失败：{:?}", e)),
+    };
+
+    if let Some(latest) = latest {
+        if diff_options(&current, &snapshot_options(&latest)).is_empty() {
+            return Ok(false);
+        }
+    }
+
+    insert_snapshot(conn, "解锁成功后自动保存")?;
+    info!("设置修改后首次解锁成功，已自动保存配置快照");
+    Ok(true)
+}
+
+// 将当前配置标记为可用配置
+#[tauri::command]
+pub fn mark_configuration_known_good(name: Option<String>) -> Result<CustomResult, CustomResult> {
+    ensure_ready()?;
+    let name = name.unwrap_or(String::from("手动保存"));
+    let id = db_writer::write(move |tx| insert_snapshot(tx, &name))
+        .map_err(|e| CustomResult::error(Some(e), None))?;
+    Ok(CustomResult::success(None, Some(json!({"id": id}))))
+}
+
+// 获取配置快照列表，并附带与当前设置的差异
+#[tauri::command]
+pub fn list_config_snapshots() -> Result<CustomResult, CustomResult> {
+    ensure_ready()?;
+    let conn = get_conn().map_err(|e| CustomResult::error(Some(e), None))?;
+    let current = current_options(&conn).map_err(|e| CustomResult::error(Some(e), None))?;
+
+    let mut stmt = conn
+        .prepare("SELECT id, name, json_data, createTime FROM config_snapshot ORDER BY id DESC;")
+        .map_err(|e| CustomResult::error(Some(format!("准备查询配置快照失败：{:?}", e)), None))?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get::<&str, i64>("id")?,
+                row.get::<&str, String>("name")?,
+                row.get::<&str, String>("json_data")?,
+                row.get::<&str, String>("createTime")?,
+            ))
+        })
+        .map_err(|e| CustomResult::error(Some(format!("查询配置快照失败：{:?}", e)), None))?;
+
+    let mut list = Vec::new();
+    for (id, name, json_data, create_time) in rows.flatten() {
+        let data: Value = serde_json::from_str(&json_data).unwrap_or(Value::Null);
+        list.push(json!({
+            "id": id,
+            "name": name,
+            "createTime": create_time,
+            "models": data["models"],
+            "camera": data["camera"],
+            "diff": diff_options(&current, &snapshot_options(&json_data)),
+        }));
+    }
+
+    Ok(CustomResult::success(None, Some(json!(list))))
+}
+
+// 恢复到指定的配置快照
+#[tauri::command]
+pub fn revert_to_known_good(snapshot_id: i64) -> Result<CustomResult, CustomResult> {
+    ensure_ready()?;
+    let conn = get_conn().map_err(|e| CustomResult::error(Some(e), None))?;
+    let json_data: String = conn
+        .query_row(
+            "SELECT json_data FROM config_snapshot WHERE id = ?1;",
+            [snapshot_id],
+            |row| row.get::<&str, String>("json_data"),
+        )
+        .map_err(|e| CustomResult::error(Some(format!("快照不存在：{:?}", e)), None))?;
+
+    let data: Value = serde_json::from_str(&json_data).unwrap_or(Value::Null);
+    if data["models"] != json!(model_identifiers()) {
+        warn!("快照保存时的模型与当前模型不同，只恢复设置项");
+    }
+
+    drop(conn);
+
+    let options = snapshot_options(&json_data);
+    let restored = options.clone();
+    db_writer::write(move |tx| restore_options(tx, &restored))
+    .map_err(|e| CustomResult::error(Some(e), None))?;
+
+    reload_backend_options();
+    info!("已恢复配置快照 {}", snapshot_id);
+
+    Ok(CustomResult::success(None, Some(json!({"options": options}))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 与前端创建的 options 表结构一致的内存数据库
+    fn options_db() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE options (id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT, key TEXT NOT NULL UNIQUE, \
+             val TEXT NOT NULL, lastTime TEXT DEFAULT (datetime('now', 'localtime')));",
+        )
+        .unwrap();
+        conn
+    }
+
+    #[test]
+    fn restore_removes_keys_missing_from_snapshot() {
+        let conn = options_db();
+        save_option(&conn, "is_initialized", "true").unwrap();
+        save_option(&conn, "matchThreshold", "0.5").unwrap();
+        save_option(&conn, "retryDelay", "3").unwrap();
+        let snapshot = BTreeMap::from([
+            (String::from("matchThreshold"), String::from("0.4")),
+            (String::from("camera"), String::from("1")),
+        ]);
+
+        restore_options(&conn, &snapshot).unwrap();
+        assert_eq!(current_options(&conn).unwrap(), snapshot);
+        assert!(diff_options(&current_options(&conn).unwrap(), &snapshot).is_empty());
+        // 不参与快照的设置项保留
+        assert_eq!(query_option(&conn, "is_initialized").as_deref(), Some("true"));
+        assert_eq!(query_option(&conn, "retryDelay"), None);
+    }
+}
@@ -0,0 +1,352 @@
+// 面容解锁策略预设：便捷、均衡、严格
+// 预设只是一组设置值，应用时按普通设置写入数据库，解锁流程仍然读取各个设置项
+use r2d2_sqlite::rusqlite::Connection;
+use serde::Serialize;
+use serde_json::{json, Value};
+use tauri_plugin_log::log::info;
+
+use crate::{
+    modules::{
+        face_policy::MultiFacePolicy,
+        faces::{DEFAULT_MATCH_THRESHOLD, MATCH_THRESHOLD_OPTION},
+        options::{get_conn, query_option, reload_backend_options, save_option},
+    },
+    proc::DEFAULT_PREWARM_TIMEOUT,
+    utils::{api::ensure_ready, custom_result::CustomResult, db_writer},
+};
+
+// 记录当前预设的设置项
+const PRESET_OPTION: &str = "policyPreset";
+// 单独修改过任意一项后的状态
+pub const CUSTOM_PRESET: &str = "custom";
+// 默认的重试时间（秒），与界面默认值一致
+const DEFAULT_RETRY_DELAY: f32 = 10.0;
+
+// 受预设控制的设置
+// 新增字段时 to_options 和 from_conn 中的解构会编译失败，必须同时给每个预设补上取值
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct PolicySettings {
+    // 多人脸策略，设置项 multiFacePolicy
+    pub multi_face_policy: MultiFacePolicy,
+    // 余弦匹配阈值，越高越严格，设置项 matchThreshold
+    pub match_threshold: f32,
+    // 匹配失败后允许重试的间隔（秒），设置项 retryDelay
+    pub retry_delay: f32,
+    // 熄屏/屏保时预热摄像头，设置项 preWarm
+    pub pre_warm: bool,
+    // 预热后等待锁屏的时间（秒），设置项 preWarmTimeout
+    pub pre_warm_timeout: f32,
+    // 验证时进行颜色闪烁挑战，设置项 challengeLiveness
+    pub challenge_liveness: bool,
+}
+
+pub struct PolicyPreset {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub settings: PolicySettings,
+}
+
+// 已定义的预设，均衡预设与各设置项的默认值一致
+pub const PRESETS: &[PolicyPreset] = &[
+    PolicyPreset {
+        name: "convenience",
+        description: "优先解锁速度，任一人脸匹配即可解锁，失败后很快可以重试",
+        settings: PolicySettings {
+            multi_face_policy: MultiFacePolicy::BestMatch,
+            match_threshold: 0.33,
+            retry_delay: 3.0,
+            pre_warm: true,
+            pre_warm_timeout: 120.0,
+            challenge_liveness: false,
+        },
+    },
+    PolicyPreset {
+        name: "balanced",
+        description: "默认设置，只验证主人脸",
+        settings: PolicySettings {
+            multi_face_policy: MultiFacePolicy::PrimaryFaceOnly,
+            match_threshold: DEFAULT_MATCH_THRESHOLD as f32,
+            retry_delay: DEFAULT_RETRY_DELAY,
+            pre_warm: true,
+            pre_warm_timeout: DEFAULT_PREWARM_TIMEOUT,
+            challenge_liveness: false,
+        },
+    },
+    PolicyPreset {
+        name: "strict",
+        description: "画面中有其他人时拒绝解锁，失败后等待更久，不提前打开摄像头，验证时进行颜色闪烁挑战",
+        settings: PolicySettings {
+            multi_face_policy: MultiFacePolicy::RejectIfMultiple,
+            match_threshold: 0.42,
+            retry_delay: 30.0,
+            pre_warm: false,
+            pre_warm_timeout: DEFAULT_PREWARM_TIMEOUT,
+            challenge_liveness: true,
+        },
+    },
+];
+
+impl PolicySettings {
+    // 转换为设置项键值，顺序固定
+    pub fn to_options(&self) -> Vec<(&'static str, String)> {
+        let PolicySettings {
+            multi_face_policy,
+            match_threshold,
+            retry_delay,
+            pre_warm,
+            pre_warm_timeout,
+            challenge_liveness,
+        } = *self;
+        vec![
+            ("multiFacePolicy", multi_face_policy.as_str().to_string()),
+            (MATCH_THRESHOLD_OPTION, match_threshold.to_string()),
+            ("retryDelay", retry_delay.to_string()),
+            ("preWarm", pre_warm.to_string()),
+            ("preWarmTimeout", pre_warm_timeout.to_string()),
+            ("challengeLiveness", challenge_liveness.to_string()),
+        ]
+    }
+
+    // 读取当前生效的设置，未设置的项按解锁流程使用的默认值
+    pub fn from_conn(conn: &Connection) -> Self {
+        let read_f32 = |key: &str, default: f32| {
+            query_option(conn, key)
+                .and_then(|v| v.parse::<f32>().ok())
+                .unwrap_or(default)
+        };
+        PolicySettings {
+            multi_face_policy: MultiFacePolicy::from_option(query_option(conn, "multiFacePolicy")),
+            match_threshold: read_f32(MATCH_THRESHOLD_OPTION, DEFAULT_MATCH_THRESHOLD as f32),
+            retry_delay: read_f32("retryDelay", DEFAULT_RETRY_DELAY),
+            pre_warm: query_option(conn, "preWarm").as_deref() != Some("false"),
+            pre_warm_timeout: read_f32("preWarmTimeout", DEFAULT_PREWARM_TIMEOUT),
+            challenge_liveness: query_option(conn, "challengeLiveness").as_deref() == Some("true"),
+        }
+    }
+
+    // 与另一组设置不同的项：设置项名、当前值、对方的值
+    pub fn diff(&self, other: &PolicySettings) -> Vec<Value> {
+        self.to_options()
+            .into_iter()
+            .zip(other.to_options())
+            .filter(|((_, current), (_, target))| !same_value(current, target))
+            .map(|((key, current), (_, target))| json!({"key": key, "current": current, "preset": target}))
+            .collect()
+    }
+}
+
+// 数值按数值比较，界面保存的 "10" 和预设的 "10.0" 视为相同
+fn same_value(a: &str, b: &str) -> bool {
+    match (a.parse::<f64>(), b.parse::<f64>()) {
+        (Ok(x), Ok(y)) => (x - y).abs() < 1e-6,
+        _ => a == b,
+    }
+}
+
+pub fn find_preset(name: &str) -> Option<&'static PolicyPreset> {
+    PRESETS.iter().find(|p| p.name == name)
+}
+
+// 与当前设置差异最少的预设，差异相同时取靠前的
+fn nearest_preset(settings: &PolicySettings) -> &'static PolicyPreset {
+    PRESETS
+        .iter()
+        .min_by_key(|p| settings.diff(&p.settings).len())
+        .unwrap_or(&PRESETS[0])
+}
+
+// 应用预设：写入各个设置项并记录当前预设
+#[tauri::command]
+pub fn apply_policy_preset(name: String) -> Result<CustomResult, CustomResult> {
+    ensure_ready()?;
+    let preset = find_preset(&name).ok_or_else(|| {
+        CustomResult::error(
+            Some(format!("未知的策略预设：{}", name)),
+            Some(json!({"presets": PRESETS.iter().map(|p| p.name).collect::<Vec<_>>()})),
+        )
+    })?;
+    db_writer::write(move |tx| {
+        for (key, val) in preset.settings.to_options() {
+            save_option(tx, key, &val)?;
+        }
+        save_option(tx, PRESET_OPTION, preset.name)
+    })
+    .map_err(|e| CustomResult::error(Some(format!("保存策略预设失败：{}", e)), None))?;
+
+    // 匹配阈值等设置在后端有缓存，重新读取后立即生效
+    reload_backend_options();
+    info!("已应用策略预设 {}", preset.name);
+    Ok(CustomResult::success(
+        None,
+        Some(json!({"preset": preset.name, "settings": preset.settings})),
+    ))
+}
+
+// 当前生效的预设，设置与记录的预设不同时为 None（custom）
+fn active_preset(settings: &PolicySettings, recorded: Option<&str>) -> Option<&'static PolicyPreset> {
+    match recorded {
+        // 从未应用过预设时，设置与某个预设完全相同就视为该预设（默认设置即均衡预设）
+        None => PRESETS.iter().find(|p| settings.diff(&p.settings).is_empty()),
+        Some(name) => find_preset(name).filter(|p| settings.diff(&p.settings).is_empty()),
+    }
+}
+
+// 记录的预设已被修改，需要改为记录 custom
+fn needs_custom_record(settings: &PolicySettings, recorded: Option<&str>) -> bool {
+    active_preset(settings, recorded).is_none() && recorded.is_some_and(|r| r != CUSTOM_PRESET)
+}
+
+// 界面保存设置后调用：修改了预设控制的设置时把当前预设记录为 custom
+// 之后即使改回相同的值也保持 custom，需要重新应用预设
+#[tauri::command]
+pub fn sync_policy_preset() -> Result<CustomResult, CustomResult> {
+    ensure_ready()?;
+    let changed = db_writer::write(|tx| {
+        let settings = PolicySettings::from_conn(tx);
+        let recorded = query_option(tx, PRESET_OPTION);
+        let changed = needs_custom_record(&settings, recorded.as_deref());
+        if changed {
+            save_option(tx, PRESET_OPTION, CUSTOM_PRESET)?;
+        }
+        Ok(changed)
+    })
+    .map_err(|e| CustomResult::error(Some(format!("记录自定义预设失败：{}", e)), None))?;
+    if changed {
+        info!("已修改预设控制的设置，当前预设改为 {}", CUSTOM_PRESET);
+    }
+    Ok(CustomResult::success(None, Some(json!({"changed": changed}))))
+}
+
+// 获取受预设控制的设置和当前预设，只读取
+// 设置与记录的预设不同时当前预设为 custom，并返回与最接近预设的差异
+#[tauri::command]
+pub fn get_settings() -> Result<CustomResult, CustomResult> {
+    ensure_ready()?;
+    let conn = get_conn().map_err(|e| CustomResult::error(Some(e), None))?;
+    let settings = PolicySettings::from_conn(&conn);
+    let recorded = query_option(&conn, PRESET_OPTION);
+    let active = active_preset(&settings, recorded.as_deref());
+
+    let nearest = nearest_preset(&settings);
+    let presets: Vec<_> = PRESETS
+        .iter()
+        .map(|p| json!({"name": p.name, "description": p.description, "settings": p.settings}))
+        .collect();
+    Ok(CustomResult::success(
+        None,
+        Some(json!({
+            "settings": settings,
+            "preset": active.map_or(CUSTOM_PRESET, |p| p.name),
+            "nearest": nearest.name,
+            "diff": active.map_or_else(|| settings.diff(&nearest.settings), |_| Vec::new()),
+            "presets": presets,
+        })),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn preset(name: &str) -> PolicySettings {
+        find_preset(name).unwrap().settings
+    }
+
+    // 与前端创建的 options 表结构一致的内存数据库
+    fn options_db() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE options (id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT, key TEXT NOT NULL UNIQUE, \
+             val TEXT NOT NULL, lastTime TEXT DEFAULT (datetime('now', 'localtime')));",
+        )
+        .unwrap();
+        conn
+    }
+
+    #[test]
+    fn diff_lists_changed_options() {
+        let balanced = preset("balanced");
+        assert!(balanced.diff(&balanced).is_empty());
+
+        let keys: Vec<_> = balanced
+            .diff(&preset("strict"))
+            .iter()
+            .map(|d| d["key"].as_str().unwrap().to_string())
+            .collect();
+        assert_eq!(keys, vec!["multiFacePolicy", "matchThreshold", "retryDelay", "preWarm", "challengeLiveness"]);
+
+        let diff = balanced.diff(&PolicySettings { retry_delay: 3.0, ..balanced });
+        assert_eq!(diff, vec![json!({"key": "retryDelay", "current": "10", "preset": "3"})]);
+    }
+
+    #[test]
+    fn every_preset_sets_match_threshold() {
+        let thresholds: Vec<_> = PRESETS.iter().map(|p| p.settings.match_threshold).collect();
+        // 越严格的预设阈值越高
+        assert!(thresholds.windows(2).all(|w| w[0] < w[1]));
+        assert_eq!(preset("balanced").match_threshold, DEFAULT_MATCH_THRESHOLD as f32);
+        for p in PRESETS {
+            let options = p.settings.to_options();
+            let (_, val) = options.iter().find(|(key, _)| *key == MATCH_THRESHOLD_OPTION).unwrap();
+            assert_eq!(val.parse::<f32>().unwrap(), p.settings.match_threshold);
+        }
+    }
+
+    #[test]
+    fn numbers_compare_by_value() {
+        assert!(same_value("10", "10.0"));
+        assert!(same_value("0.5", "0.50"));
+        assert!(!same_value("10", "10.5"));
+        assert!(!same_value("true", "false"));
+    }
+
+    #[test]
+    fn nearest_preset_has_fewest_differences() {
+        assert_eq!(nearest_preset(&preset("strict")).name, "strict");
+        let mostly_strict = PolicySettings { retry_delay: 20.0, ..preset("strict") };
+        assert_eq!(nearest_preset(&mostly_strict).name, "strict");
+        let mostly_convenient = PolicySettings { pre_warm_timeout: 60.0, ..preset("convenience") };
+        assert_eq!(nearest_preset(&mostly_convenient).name, "convenience");
+    }
+
+    #[test]
+    fn changed_knob_flips_to_custom() {
+        let balanced = preset("balanced");
+        assert_eq!(active_preset(&balanced, Some("balanced")).map(|p| p.name), Some("balanced"));
+        assert!(!needs_custom_record(&balanced, Some("balanced")));
+
+        let changed = PolicySettings { challenge_liveness: true, ..balanced };
+        assert!(active_preset(&changed, Some("balanced")).is_none());
+        assert!(needs_custom_record(&changed, Some("balanced")));
+
+        // 改回相同的值后仍然是 custom，不需要重复记录
+        assert!(active_preset(&balanced, Some(CUSTOM_PRESET)).is_none());
+        assert!(!needs_custom_record(&balanced, Some(CUSTOM_PRESET)));
+
+        // 从未应用过预设时按设置推断，不记录 custom
+        assert_eq!(active_preset(&balanced, None).map(|p| p.name), Some("balanced"));
+        assert!(active_preset(&changed, None).is_none());
+        assert!(!needs_custom_record(&changed, None));
+    }
+
+    #[test]
+    fn settings_round_trip_through_options() {
+        let conn = options_db();
+        // 没有任何设置时与均衡预设相同
+        assert_eq!(PolicySettings::from_conn(&conn), preset("balanced"));
+
+        for (key, val) in preset("strict").to_options() {
+            save_option(&conn, key, &val).unwrap();
+        }
+        assert_eq!(PolicySettings::from_conn(&conn), preset("strict"));
+
+        // 界面保存的整数形式也视为同一个预设
+        save_option(&conn, "retryDelay", "30").unwrap();
+        let settings = PolicySettings::from_conn(&conn);
+        assert!(active_preset(&settings, Some("strict")).is_some());
+
+        save_option(&conn, "retryDelay", "45").unwrap();
+        let settings = PolicySettings::from_conn(&conn);
+        assert!(needs_custom_record(&settings, Some("strict")));
+    }
+}
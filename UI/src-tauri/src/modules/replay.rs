@@ -0,0 +1,551 @@
+// 调试记录与回放：保存锁屏期间失败的识别（缩小后的画面和当时的参数），之后离线换一组设置重新判定
+// 记录模式需要手动开启，到期自动关闭；回放只做判定，不会发送凭据
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use opencv::{
+    core::{Mat, Vector},
+    imgcodecs,
+    objdetect::{FaceDetectorYN, FaceRecognizerSF},
+    prelude::*,
+};
+use r2d2_sqlite::rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tauri_plugin_log::log::{info, warn};
+use uuid::Uuid;
+
+use crate::{
+    modules::{
+        face_policy::{match_frame_with, MultiFacePolicy, BYSTANDER_DETECTED},
+        faces::{is_black_frame, resize_mat, BlackFrameConfig, FaceDescriptor},
+        options::{get_conn, query_option, save_option},
+    },
+    proc::{MatchCounter, MatchStep},
+    utils::{
+        api::{create_detector, create_recognizer, ensure_ready},
+        custom_result::CustomResult,
+        db_writer,
+        storage::{create_private_dir, debug_dir, with_retry},
+    },
+};
+
+// 回放文件头：标识 + 格式版本，之后是 bincode 编码的 ReplayFile
+const REPLAY_MAGIC: [u8; 4] = *b"FWRP";
+pub const REPLAY_VERSION: u8 = 1;
+const REPLAY_EXT: &str = "replay";
+// 记录的画面缩放到的最大边长
+const FRAME_MAX_DIM: f32 = 640.0;
+// 单次识别最多记录的帧数，超过后不再记录
+const MAX_FRAMES: usize = 120;
+// 记录模式默认持续的天数
+const DEFAULT_CAPTURE_DAYS: u32 = 3;
+const SECS_PER_DAY: u64 = 24 * 60 * 60;
+
+// 设置项：是否开启、持续天数、开启时间（Unix 秒）
+const OPTION_CAPTURE: &str = "debugCapture";
+const OPTION_CAPTURE_DAYS: &str = "debugCaptureDays";
+const OPTION_CAPTURE_SINCE: &str = "debugCaptureSince";
+
+// 识别时的参数
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplayParams {
+    pub multi_face_policy: String,
+    pub max_success: usize,
+    pub max_fail: usize,
+    pub black_frame: BlackFrameConfig,
+}
+
+// 一个面容的参考样本和匹配时读取的画面（PNG）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplayRegistration {
+    pub face_id: i32,
+    pub face_token: String,
+    pub alias: String,
+    pub threshold: f32,
+    pub face_detection_threshold: f32,
+    pub samples: Vec<Vec<f32>>,
+    pub frames: Vec<Vec<u8>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplayFile {
+    // 记录时间（Unix 秒）
+    pub created: u64,
+    pub app_version: String,
+    // 当时的结果，例如 no_match、bystander_detected
+    pub outcome: String,
+    // 帧数超过上限，后面的画面没有记录
+    pub truncated: bool,
+    pub params: ReplayParams,
+    pub registrations: Vec<ReplayRegistration>,
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+fn replay_dir() -> PathBuf {
+    debug_dir().join("replays")
+}
+
+fn encode_replay(file: &ReplayFile) -> Result<Vec<u8>, String> {
+    let mut encoded = Vec::new();
+    encoded.extend_from_slice(&REPLAY_MAGIC);
+    encoded.push(REPLAY_VERSION);
+    encoded.extend_from_slice(&bincode::serialize(file).map_err(|e| format!("编码回放文件失败：{}", e))?);
+    Ok(encoded)
+}
+
+fn decode_replay(buffer: &[u8]) -> Result<ReplayFile, String> {
+    if buffer.len() <= REPLAY_MAGIC.len() || buffer[..REPLAY_MAGIC.len()] != REPLAY_MAGIC {
+        return Err(String::from("不是回放文件"));
+    }
+    match buffer[REPLAY_MAGIC.len()] {
+        REPLAY_VERSION => bincode::deserialize(&buffer[REPLAY_MAGIC.len() + 1..])
+            .map_err(|e| format!("解析回放文件失败：{}", e)),
+        version => Err(format!("不支持的回放文件版本 {}", version)),
+    }
+}
+
+// 记录模式的状态
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct CaptureStatus {
+    pub enabled: bool,
+    pub days: u32,
+    pub since: Option<u64>,
+    // 自动关闭的时间（Unix 秒）
+    pub expires_at: Option<u64>,
+}
+
+impl CaptureStatus {
+    fn load(conn: &Connection) -> Self {
+        let days = query_option(conn, OPTION_CAPTURE_DAYS)
+            .and_then(|v| v.parse::<u32>().ok())
+            .filter(|d| *d > 0)
+            .unwrap_or(DEFAULT_CAPTURE_DAYS);
+        let since = query_option(conn, OPTION_CAPTURE_SINCE).and_then(|v| v.parse::<u64>().ok());
+        CaptureStatus {
+            enabled: query_option(conn, OPTION_CAPTURE).as_deref() == Some("true"),
+            days,
+            since,
+            expires_at: since.map(|s| s + days as u64 * SECS_PER_DAY),
+        }
+    }
+
+    fn is_expired(&self) -> bool {
+        self.expires_at.map_or(true, |at| now_secs() >= at)
+    }
+}
+
+// 关闭记录模式，并删除超过保留天数的回放文件
+fn expire_capture(status: &CaptureStatus) {
+    warn!("调试记录模式已到期（{} 天），自动关闭", status.days);
+    db_writer::write_detached("关闭调试记录模式失败", |tx| save_option(tx, OPTION_CAPTURE, "false"));
+    prune_replays(status.days);
+}
+
+// 删除超过指定天数的回放文件
+fn prune_replays(days: u32) {
+    let Ok(entries) = fs::read_dir(replay_dir()) else {
+        return;
+    };
+    let max_age = std::time::Duration::from_secs(days as u64 * SECS_PER_DAY);
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let expired = entry
+            .metadata()
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|t| t.elapsed().ok())
+            .is_some_and(|age| age >= max_age);
+        if expired && path.extension().and_then(|e| e.to_str()) == Some(REPLAY_EXT) {
+            if let Err(e) = with_retry(|| fs::remove_file(&path)) {
+                warn!("删除过期回放文件 {:?} 失败：{}", path, e);
+            }
+        }
+    }
+}
+
+fn replay_files() -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(replay_dir()) else {
+        return Vec::new();
+    };
+    let mut files: Vec<_> = entries
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| p.extension().and_then(|e| e.to_str()) == Some(REPLAY_EXT))
+        .collect();
+    files.sort();
+    files
+}
+
+// 诊断信息中的记录模式状态，开启时附带醒目的警告
+pub fn capture_diagnostics(conn: &Connection) -> Value {
+    let status = CaptureStatus::load(conn);
+    let files = replay_files();
+    json!({
+        "enabled": status.enabled && !status.is_expired(),
+        "warning": (status.enabled && !status.is_expired())
+            .then_some("调试记录模式已开启：锁屏期间失败的识别会保存摄像头画面"),
+        "status": status,
+        "replay_files": files.len(),
+        "replay_dir": replay_dir(),
+    })
+}
+
+// 记录一次锁屏识别，只在失败时保存
+pub struct ReplayRecorder {
+    file: ReplayFile,
+    // 缩小后的画面，保存时再编码，不拖慢识别
+    frames: Vec<Vec<Mat>>,
+    frame_count: usize,
+}
+
+impl ReplayRecorder {
+    // 记录模式开启时返回记录器；已到期时关闭记录模式
+    pub fn start(
+        conn: &Connection,
+        policy: MultiFacePolicy,
+        max_success: usize,
+        max_fail: usize,
+        black_frame: BlackFrameConfig,
+    ) -> Option<Self> {
+        let status = CaptureStatus::load(conn);
+        if !status.enabled {
+            return None;
+        }
+        if status.is_expired() {
+            expire_capture(&status);
+            return None;
+        }
+        Some(ReplayRecorder {
+            file: ReplayFile {
+                created: now_secs(),
+                app_version: env!("CARGO_PKG_VERSION").to_string(),
+                outcome: String::new(),
+                truncated: false,
+                params: ReplayParams {
+                    multi_face_policy: policy.as_str().to_string(),
+                    max_success,
+                    max_fail,
+                    black_frame,
+                },
+                registrations: Vec::new(),
+            },
+            frames: Vec::new(),
+            frame_count: 0,
+        })
+    }
+
+    // 开始匹配一个面容，之后读取的画面都属于该面容
+    pub fn begin_registration(
+        &mut self,
+        face_id: i32,
+        face_token: &str,
+        alias: &str,
+        threshold: f32,
+        face_detection_threshold: f32,
+        samples: &[Vec<f32>],
+    ) {
+        self.file.registrations.push(ReplayRegistration {
+            face_id,
+            face_token: face_token.to_string(),
+            alias: alias.to_string(),
+            threshold,
+            face_detection_threshold,
+            samples: samples.to_vec(),
+            frames: Vec::new(),
+        });
+        self.frames.push(Vec::new());
+    }
+
+    pub fn push_frame(&mut self, frame: &Mat) {
+        if self.frame_count >= MAX_FRAMES {
+            self.file.truncated = true;
+            return;
+        }
+        let Some(frames) = self.frames.last_mut() else {
+            return;
+        };
+        match resize_mat(frame, FRAME_MAX_DIM) {
+            Ok(small) => {
+                frames.push(small);
+                self.frame_count += 1;
+            }
+            Err(e) => warn!("缩放调试画面失败：{}", e),
+        }
+    }
+
+    // 在后台编码并保存，不影响解锁失败的通知
+    pub fn save(mut self, outcome: &str) {
+        self.file.outcome = outcome.to_string();
+        std::thread::spawn(move || match self.write() {
+            Ok(path) => warn!("调试记录模式已开启，失败的识别已保存到 {:?}", path),
+            Err(e) => warn!("保存调试记录失败：{}", e),
+        });
+    }
+
+    fn write(mut self) -> Result<PathBuf, String> {
+        for (registration, frames) in self.file.registrations.iter_mut().zip(&self.frames) {
+            for frame in frames {
+                let mut buf = Vector::<u8>::new();
+                imgcodecs::imencode(".png", frame, &mut buf, &Vector::new())
+                    .map_err(|e| format!("编码画面失败：{}", e))?;
+                registration.frames.push(buf.to_vec());
+            }
+        }
+        let dir = replay_dir();
+        create_private_dir(&dir).map_err(|e| format!("创建调试目录失败：{}", e))?;
+        let path = dir.join(format!("{}-{}.{}", self.file.created, Uuid::new_v4().simple(), REPLAY_EXT));
+        let encoded = encode_replay(&self.file)?;
+        with_retry(|| fs::write(&path, &encoded)).map_err(|e| format!("写入回放文件失败：{}", e))?;
+        Ok(path)
+    }
+}
+
+// 回放时覆盖的设置，未设置的项使用记录时的值
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct ReplayOverrides {
+    pub multi_face_policy: Option<String>,
+    // 置信度阈值（0-100），覆盖所有面容
+    pub threshold: Option<f32>,
+    pub face_detection_threshold: Option<f32>,
+    pub max_success: Option<usize>,
+    pub max_fail: Option<usize>,
+    pub black_frame_mean: Option<f64>,
+    pub black_frame_stddev: Option<f64>,
+    pub black_frame_limit: Option<i32>,
+}
+
+impl ReplayOverrides {
+    fn apply(&self, params: &ReplayParams) -> ReplayParams {
+        ReplayParams {
+            multi_face_policy: self.multi_face_policy.clone().unwrap_or_else(|| params.multi_face_policy.clone()),
+            max_success: self.max_success.unwrap_or(params.max_success).max(1),
+            max_fail: self.max_fail.unwrap_or(params.max_fail).max(1),
+            black_frame: BlackFrameConfig {
+                mean_threshold: self.black_frame_mean.unwrap_or(params.black_frame.mean_threshold),
+                stddev_threshold: self.black_frame_stddev.unwrap_or(params.black_frame.stddev_threshold),
+                limit: self.black_frame_limit.unwrap_or(params.black_frame.limit),
+            },
+        }
+    }
+}
+
+// 单个面容的回放结果
+#[derive(Debug, Serialize)]
+struct RegistrationReplay {
+    face_id: i32,
+    face_token: String,
+    threshold: f32,
+    face_detection_threshold: f32,
+    frames: Vec<Value>,
+    // matched / no_match / frames_exhausted，或导致整次识别结束的原因
+    decision: &'static str,
+    score: Option<f64>,
+}
+
+// 按锁屏解锁的流程重新判定，与 proc::run 的顺序一致：逐个面容读取画面，达到成功次数即解锁
+fn evaluate(
+    detector: &mut opencv::core::Ptr<FaceDetectorYN>,
+    recognizer: &mut opencv::core::Ptr<FaceRecognizerSF>,
+    file: &ReplayFile,
+    overrides: &ReplayOverrides,
+) -> Result<Value, String> {
+    let params = overrides.apply(&file.params);
+    let policy = MultiFacePolicy::from_option(Some(params.multi_face_policy.clone()));
+    let mut registrations = Vec::new();
+    let mut outcome: Option<(&'static str, Option<i32>, Option<f64>)> = None;
+    let mut black_count = 0;
+
+    'registrations: for registration in &file.registrations {
+        let threshold = overrides.threshold.unwrap_or(registration.threshold);
+        let face_detection_threshold = overrides
+            .face_detection_threshold
+            .unwrap_or(registration.face_detection_threshold);
+        // 记录的是原始特征，不需要模板保护的密钥
+        let references = FaceDescriptor { name: registration.alias.clone(), samples: registration.samples.clone(), key_fingerprint: None, thumbnail: None, model_version: None, created_at: None }
+            .to_mats()
+            .map_err(|e| format!("转换参考面容失败：{}", e))?;
+        let mut result = RegistrationReplay {
+            face_id: registration.face_id,
+            face_token: registration.face_token.clone(),
+            threshold,
+            face_detection_threshold,
+            frames: Vec::new(),
+            decision: "frames_exhausted",
+            score: None,
+        };
+        let mut counter = MatchCounter::new(params.max_success, params.max_fail);
+
+        for (index, png) in registration.frames.iter().enumerate() {
+            let frame = imgcodecs::imdecode(&Vector::<u8>::from_slice(png), imgcodecs::IMREAD_COLOR)
+                .map_err(|e| format!("解码第 {} 帧失败：{}", index, e))?;
+            let black = is_black_frame(&frame, &params.black_frame)?;
+            black_count = if black { black_count + 1 } else { 0 };
+            if black_count >= params.black_frame.limit {
+                result.frames.push(json!({"index": index, "black_frame": true}));
+                result.decision = "camera_obstructed";
+                registrations.push(result);
+                outcome = Some(("camera_obstructed", None, None));
+                break 'registrations;
+            }
+
+            let matched = match match_frame_with(detector, recognizer, &frame, &references, None, face_detection_threshold, policy, true) {
+                Ok(matched) => matched,
+                Err(e) if e.starts_with(BYSTANDER_DETECTED) => {
+                    result.frames.push(json!({"index": index, "black_frame": black, "error": e}));
+                    result.decision = "bystander_detected";
+                    registrations.push(result);
+                    outcome = Some(("bystander_detected", Some(registration.face_id), None));
+                    break 'registrations;
+                }
+                Err(e) => {
+                    // 未检测到人脸时继续读下一帧，与锁屏流程一致
+                    result.frames.push(json!({"index": index, "black_frame": black, "error": e}));
+                    continue;
+                }
+            };
+            result.frames.push(json!({"index": index, "black_frame": black, "match": matched}));
+            if !matched.score.is_finite() {
+                result.decision = "invalid_score";
+                registrations.push(result);
+                outcome = Some(("invalid_score", Some(registration.face_id), None));
+                break 'registrations;
+            }
+            if result.score.map_or(true, |s| matched.score > s) {
+                result.score = Some(matched.score);
+            }
+            match counter.record(matched.score, threshold) {
+                MatchStep::Matched(score) => {
+                    result.decision = "matched";
+                    registrations.push(result);
+                    outcome = Some(("would_unlock", Some(registration.face_id), Some(score)));
+                    break 'registrations;
+                }
+                MatchStep::GiveUp => {
+                    result.decision = "no_match";
+                    break;
+                }
+                MatchStep::Continue => {}
+            }
+        }
+        registrations.push(result);
+    }
+
+    let (decision, face_id, score) = outcome.unwrap_or(("no_match", None, None));
+    Ok(json!({
+        "version": REPLAY_VERSION,
+        "created": file.created,
+        "app_version": file.app_version,
+        "recorded_outcome": file.outcome,
+        "truncated": file.truncated,
+        "recorded_params": file.params,
+        "params": params,
+        "decision": decision,
+        "face_id": face_id,
+        "score": score,
+        "registrations": registrations,
+        // 回放只做判定，从不发送凭据
+        "credentials_sent": false,
+    }))
+}
+
+// 回放一个记录文件，使用单独加载的模型，不占用摄像头和解锁流程的模型
+pub fn replay_file(path: &Path, overrides: &ReplayOverrides) -> Result<Value, String> {
+    let buffer = fs::read(path).map_err(|e| format!("读取回放文件失败：{}", e))?;
+    let file = decode_replay(&buffer)?;
+    let mut detector = create_detector().map_err(|e| e.msg)?;
+    let mut recognizer = create_recognizer().map_err(|e| e.msg)?;
+    let result = evaluate(&mut detector, &mut recognizer, &file, overrides)?;
+    info!("已回放 {:?}：{}", path, result["decision"]);
+    Ok(result)
+}
+
+// 用不同的设置回放一次失败的识别
+#[tauri::command]
+pub fn replay_unlock_attempt(
+    replay_path: String,
+    override_settings: Option<ReplayOverrides>,
+) -> Result<CustomResult, CustomResult> {
+    ensure_ready()?;
+    let result = replay_file(Path::new(&replay_path), &override_settings.unwrap_or_default())
+        .map_err(|e| CustomResult::error(Some(e), None))?;
+    Ok(CustomResult::success(None, Some(result)))
+}
+
+// 开启或关闭调试记录模式，开启时重新开始计算到期时间
+#[tauri::command]
+pub fn set_debug_capture(enabled: bool, days: Option<u32>) -> Result<CustomResult, CustomResult> {
+    ensure_ready()?;
+    let days = days.filter(|d| *d > 0).unwrap_or(DEFAULT_CAPTURE_DAYS);
+    db_writer::write(move |tx| {
+        save_option(tx, OPTION_CAPTURE, &enabled.to_string())?;
+        save_option(tx, OPTION_CAPTURE_DAYS, &days.to_string())?;
+        if enabled {
+            save_option(tx, OPTION_CAPTURE_SINCE, &now_secs().to_string())?;
+        }
+        Ok(())
+    })
+    .map_err(|e| CustomResult::error(Some(e), None))?;
+    if enabled {
+        warn!("已开启调试记录模式，{} 天后自动关闭", days);
+    } else {
+        info!("已关闭调试记录模式");
+    }
+    get_debug_capture()
+}
+
+// 获取调试记录模式的状态和已保存的回放文件
+#[tauri::command]
+pub fn get_debug_capture() -> Result<CustomResult, CustomResult> {
+    ensure_ready()?;
+    let conn = get_conn().map_err(|e| CustomResult::error(Some(e), None))?;
+    let mut status = CaptureStatus::load(&conn);
+    drop(conn);
+    if status.enabled && status.is_expired() {
+        expire_capture(&status);
+        status.enabled = false;
+    }
+    Ok(CustomResult::success(
+        None,
+        Some(json!({"status": status, "dir": replay_dir(), "files": replay_files()})),
+    ))
+}
+
+// 命令行回放：--replay-unlock <文件> [--override <JSON>]，结果以 JSON 输出
+pub fn run_cli(args: &[String]) -> Option<i32> {
+    let index = args.iter().position(|arg| arg == "--replay-unlock")?;
+    let Some(path) = args.get(index + 1).filter(|arg| !arg.starts_with("--")) else {
+        eprintln!("用法: --replay-unlock <回放文件> [--override <JSON>]");
+        return Some(2);
+    };
+    let overrides = match args.iter().position(|arg| arg == "--override").and_then(|i| args.get(i + 1)) {
+        Some(raw) => match serde_json::from_str::<ReplayOverrides>(raw) {
+            Ok(overrides) => overrides,
+            Err(e) => {
+                eprintln!("解析 --override 失败: {}", e);
+                return Some(2);
+            }
+        },
+        None => ReplayOverrides::default(),
+    };
+    match replay_file(Path::new(path), &overrides) {
+        Ok(result) => {
+            println!("{}", serde_json::to_string_pretty(&result).unwrap_or_default());
+            Some(0)
+        }
+        Err(e) => {
+            eprintln!("回放失败: {}", e);
+            Some(1)
+        }
+    }
+}
@@ -0,0 +1,426 @@
+use std::{collections::HashSet, sync::atomic::Ordering, thread::sleep, time::Duration};
+
+use r2d2_sqlite::rusqlite::{self, Connection};
+use serde::Serialize;
+use serde_json::json;
+use tauri_plugin_log::log::{error, info, warn};
+
+use crate::{
+    modules::{
+        conference::ensure_not_paused,
+        face_policy::{match_frame, FaceMatch, MultiFacePolicy, BYSTANDER_DETECTED},
+        face_watch::{cached_faces, InvalidFace},
+        faces::{camera_error, load_face_data, read_mat_from_camera, remove_face_files, template_key_error, FaceDescriptor},
+        options::{get_conn, prune_snapshots_older_than, query_option},
+        statistics::{apply_score_precision, is_score_bucketed},
+        template::probe_key,
+    },
+    proc::FaceExtraData,
+    utils::{
+        api::{emit_event, ensure_ready},
+        custom_result::CustomResult,
+        db_writer,
+    },
+    IS_RUN,
+};
+
+// 启动后多久进行第一次检查
+const FIRST_CHECK_DELAY: Duration = Duration::from_secs(5 * 60);
+// 后台检查间隔，距离上次维护超过一天才会真正执行
+const CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+// 重新启用面容时最多读取的帧数
+const REACTIVATE_MAX_FRAMES: usize = 30;
+
+// 面容过期后的处理方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum FaceAction {
+    /// 停用，保留数据，可以重新启用
+    Disable,
+    /// 删除数据库记录和特征、图片文件
+    Delete,
+}
+
+// 数据保留策略，天数为 0 表示不处理
+#[derive(Debug, Serialize)]
+struct RetentionPolicy {
+    face_days: u32,
+    face_action: FaceAction,
+    log_days: u32,
+    snapshot_days: u32,
+}
+
+impl RetentionPolicy {
+    fn load(conn: &Connection) -> Self {
+        let days = |key: &str| {
+            query_option(conn, key)
+                .and_then(|v| v.parse::<f64>().ok())
+                .map(|v| v.max(0.0) as u32)
+                .unwrap_or(0)
+        };
+        let face_action = match query_option(conn, "retentionFaceAction").as_deref() {
+            Some("delete") => FaceAction::Delete,
+            _ => FaceAction::Disable,
+        };
+        Self {
+            face_days: days("retentionFaceDays"),
+            face_action,
+            log_days: days("retentionLogDays"),
+            snapshot_days: days("retentionSnapshotDays"),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.face_days == 0 && self.log_days == 0 && self.snapshot_days == 0
+    }
+}
+
+// 一次维护的结果，写入 maintenance_log
+#[derive(Debug, Serialize)]
+pub struct MaintenanceReport {
+    /// manual 手动执行，schedule 后台定时执行
+    trigger: &'static str,
+    policy: RetentionPolicy,
+    disabled_faces: Vec<String>,
+    deleted_faces: Vec<String>,
+    purged_logs: usize,
+    /// 低精度模式下本次降低精度的解锁日志数
+    bucketed_logs: usize,
+    purged_snapshots: usize,
+    errors: Vec<String>,
+}
+
+// 按保留策略清理数据，并写入维护记录
+fn run_maintenance(conn: &Connection, trigger: &'static str) -> Result<MaintenanceReport, String> {
+    let mut report = MaintenanceReport {
+        trigger,
+        policy: RetentionPolicy::load(conn),
+        disabled_faces: Vec::new(),
+        deleted_faces: Vec::new(),
+        purged_logs: 0,
+        bucketed_logs: 0,
+        purged_snapshots: 0,
+        errors: Vec::new(),
+    };
+
+    // 先处理面容，再清理日志，避免先删掉了使用记录
+    if report.policy.face_days > 0 {
+        for (id, face_token) in stale_faces(conn, report.policy.face_days)? {
+            let result = match report.policy.face_action {
+                FaceAction::Disable => disable_face(conn, id),
+                FaceAction::Delete => delete_face(conn, id, &face_token),
+            };
+            match (result, report.policy.face_action) {
+                (Ok(()), FaceAction::Disable) => report.disabled_faces.push(face_token),
+                (Ok(()), FaceAction::Delete) => report.deleted_faces.push(face_token),
+                (Err(e), _) => report.errors.push(e),
+            }
+        }
+    }
+
+    if report.policy.log_days > 0 {
+        match conn.execute(
+            "DELETE FROM unlock_log WHERE lastTime < datetime('now', 'localtime', ?1)",
+            [format!("-{} days", report.policy.log_days)],
+        ) {
+            Ok(count) => report.purged_logs = count,
+            Err(e) => report.errors.push(format!("清理解锁日志失败：{:?}", e)),
+        }
+    }
+
+    // 清理之后再处理剩下的日志
+    match apply_score_precision(conn) {
+        Ok(count) => report.bucketed_logs = count,
+        Err(e) => report.errors.push(e),
+    }
+
+    if report.policy.snapshot_days > 0 {
+        match prune_snapshots_older_than(conn, report.policy.snapshot_days) {
+            Ok(count) => report.purged_snapshots = count,
+            Err(e) => report.errors.push(e),
+        }
+    }
+
+    let json_data = serde_json::to_string(&report).map_err(|e| format!("序列化维护记录失败：{}", e))?;
+    conn.execute(
+        "INSERT INTO maintenance_log (json_data) VALUES (?1)",
+        [&json_data],
+    )
+    .map_err(|e| format!("写入维护记录失败：{:?}", e))?;
+
+    info!("数据保留维护完成：{}", json_data);
+    emit_event("retention-maintenance", &report);
+    Ok(report)
+}
+
+// 超过指定天数没有使用的面容（已停用的不再重复处理）
+// 最后使用时间取注册时间、成功解锁日志、匹配分数统计、重新启用时间中最晚的一个
+// 匹配分数统计不随日志清理删除，日志被清理后仍能知道最后使用时间
+fn stale_faces(conn: &Connection, days: u32) -> Result<Vec<(i64, String)>, String> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT f.id, f.face_token FROM faces f \
+             WHERE COALESCE(json_extract(f.json_data, '$.expired'), 0) = 0 \
+             AND MAX( \
+                COALESCE(f.createTime, ''), \
+                COALESCE((SELECT MAX(l.lastTime) FROM unlock_log l WHERE l.face_id = f.id AND l.is_unlock = 1), ''), \
+                COALESCE((SELECT s.lastTime FROM face_score_stats s WHERE s.face_token = f.face_token), ''), \
+                COALESCE(json_extract(f.json_data, '$.reactivatedTime'), '') \
+             ) < datetime('now', 'localtime', ?1);",
+        )
+        .map_err(|e| format!("准备查询过期面容失败：{:?}", e))?;
+    let rows = stmt
+        .query_map([format!("-{} days", days)], |row| {
+            Ok((
+                row.get::<&str, i64>("id")?,
+                row.get::<&str, String>("face_token")?,
+            ))
+        })
+        .map_err(|e| format!("查询过期面容失败：{:?}", e))?;
+    Ok(rows.flatten().collect())
+}
+
+// 停用面容，数据保留，不再参与识别
+fn disable_face(conn: &Connection, id: i64) -> Result<(), String> {
+    conn.execute(
+        "UPDATE faces SET json_data = json_set(json_data, '$.expired', json('true')) WHERE id = ?1",
+        [id],
+    )
+    .map(|_| ())
+    .map_err(|e| format!("停用面容 {} 失败：{:?}", id, e))
+}
+
+// 被保留策略停用的面容标识
+fn expired_face_tokens(conn: &Connection) -> Result<HashSet<String>, String> {
+    let mut stmt = conn
+        .prepare("SELECT face_token FROM faces WHERE COALESCE(json_extract(json_data, '$.expired'), 0) != 0;")
+        .map_err(|e| format!("准备查询停用面容失败：{:?}", e))?;
+    let rows = stmt
+        .query_map([], |row| row.get::<&str, String>("face_token"))
+        .map_err(|e| format!("查询停用面容失败：{:?}", e))?;
+    Ok(rows.flatten().collect())
+}
+
+fn without_expired<T>(faces: Vec<(String, T)>, expired: &HashSet<String>) -> Vec<(String, T)> {
+    faces.into_iter().filter(|(token, _)| !expired.contains(token)).collect()
+}
+
+// 可以参与识别和验证的面容：缓存中的面容去掉被保留策略停用的面容
+// 所有与已录入面容比较的入口都使用这里的结果，停用的面容不能识别或验证任何人
+pub fn active_cached_faces() -> Result<(Vec<(String, FaceDescriptor)>, Vec<InvalidFace>), String> {
+    let conn = get_conn()?;
+    let expired = expired_face_tokens(&conn)?;
+    drop(conn);
+    let (faces, invalid) = cached_faces();
+    Ok((without_expired(faces, &expired), invalid))
+}
+
+// 删除面容，文件删除失败时保留数据库记录，下次维护时再试
+fn delete_face(conn: &Connection, id: i64, face_token: &str) -> Result<(), String> {
+    remove_face_files(face_token).map_err(|e| format!("删除面容 {} 文件失败：{}", face_token, e))?;
+    conn.execute("DELETE FROM faces WHERE id = ?1", [id])
+        .map_err(|e| format!("删除面容 {} 失败：{:?}", face_token, e))?;
+    conn.execute(
+        "DELETE FROM face_score_stats WHERE face_token = ?1",
+        [face_token],
+    )
+    .map_err(|e| format!("删除面容 {} 分数统计失败：{:?}", face_token, e))?;
+    Ok(())
+}
+
+// 距离上次维护是否已超过一天
+fn is_maintenance_due(conn: &Connection) -> Result<bool, String> {
+    conn.query_row(
+        "SELECT COUNT(id) AS count FROM maintenance_log WHERE createTime > datetime('now', 'localtime', '-1 day');",
+        [],
+        |row| row.get::<&str, i64>("count"),
+    )
+    .map(|count| count == 0)
+    .map_err(|e| format!("查询维护记录失败：{:?}", e))
+}
+
+// 后台定时维护，每天最多执行一次
+pub fn spawn_retention_scheduler() {
+    std::thread::spawn(|| {
+        sleep(FIRST_CHECK_DELAY);
+        loop {
+            // 正在面容识别时不抢占数据库连接
+            if !IS_RUN.load(Ordering::SeqCst) {
+                // 连接池在 init_model 之后才存在，拿不到连接就等下一次
+                if let Ok(conn) = get_conn() {
+                    let policy = RetentionPolicy::load(&conn);
+                    match is_maintenance_due(&conn) {
+                        // 低精度模式下即使没有保留策略，也要定期降低新增日志的精度
+                        Ok(true) if !policy.is_empty() || is_score_bucketed(&conn) => {
+                            drop(conn);
+                            if let Err(e) = db_writer::write(|tx| run_maintenance(tx, "schedule")) {
+                                error!("后台数据保留维护失败：{}", e);
+                            }
+                        }
+                        Ok(_) => {}
+                        Err(e) => warn!("{}", e),
+                    }
+                }
+            }
+            sleep(CHECK_INTERVAL);
+        }
+    });
+}
+
+// 立即按保留策略执行一次维护
+#[tauri::command]
+pub fn run_retention_maintenance() -> Result<CustomResult, CustomResult> {
+    ensure_ready()?;
+    let report = db_writer::write(|tx| run_maintenance(tx, "manual")).map_err(|e| CustomResult::error(Some(e), None))?;
+    Ok(CustomResult::success(None, Some(json!(report))))
+}
+
+// 重新启用被保留策略停用的面容，需要摄像头已打开，并且当场匹配成功
+#[tauri::command]
+pub fn reactivate_registration(file_name: String) -> Result<CustomResult, CustomResult> {
+    ensure_ready()?;
+    ensure_not_paused()?;
+    // 读取完成后立即归还连接，读取摄像头期间不占用连接池
+    let (json_data, policy) = {
+        let conn = get_conn().map_err(|e| CustomResult::error(Some(e), None))?;
+        let json_data = conn
+            .query_row(
+                "SELECT json_data FROM faces WHERE face_token = ?1;",
+                [&file_name],
+                |row| row.get::<&str, String>("json_data"),
+            )
+            .map_err(|e| match e {
+                rusqlite::Error::QueryReturnedNoRows => {
+                    CustomResult::error(Some(format!("面容 {} 不存在", file_name)), None)
+                }
+                e => CustomResult::error(Some(format!("查询面容数据失败：{:?}", e)), None),
+            })?;
+        (json_data, MultiFacePolicy::from_option(query_option(&conn, "multiFacePolicy")))
+    };
+    let extra: FaceExtraData = serde_json::from_str(&json_data)
+        .map_err(|e| CustomResult::error(Some(format!("解析面容数据失败：{}", e)), None))?;
+    if !extra.expired {
+        return Err(CustomResult::error(
+            Some(format!("面容 {} 未被停用", file_name)),
+            None,
+        ));
+    }
+
+    let face = load_face_data(&file_name)
+        .map_err(|e| CustomResult::error(Some(format!("加载面容数据失败：{}", e)), None))?;
+    // 模板密钥已更换的面容无法验证，只能重新录入
+    let face_key = probe_key(&face).map_err(template_key_error)?;
+    let dst_feature = face
+        .to_mats()
+        .map_err(|e| CustomResult::error(Some(format!("加载面容数据失败：{}", e)), None))?;
+
+    let mut best: Option<FaceMatch> = None;
+    for _ in 0..REACTIVATE_MAX_FRAMES {
+        // 匹配过程中开始会议或屏幕共享时立即停止读取摄像头
+        ensure_not_paused()?;
+        let frame = read_mat_from_camera().map_err(camera_error)?;
+        // 用户主动操作，不在解锁路径上，总是返回各样本的分数
+        let matched = match match_frame(&frame, &dst_feature, face_key.as_deref(), extra.face_detection_threshold, policy, true) {
+            Ok(matched) => matched,
+            Err(e) if e.contains("未检测到人脸") => {
+                sleep(Duration::from_millis(100));
+                continue;
+            }
+            Err(e) if e.starts_with(BYSTANDER_DETECTED) => {
+                return Err(CustomResult::error(
+                    Some(e),
+                    Some(json!({"condition": BYSTANDER_DETECTED, "policy": policy.as_str()})),
+                ));
+            }
+            Err(e) => {
+                return Err(CustomResult::error(Some(format!("特征匹配失败: {}", e)), None));
+            }
+        };
+        let score = matched.score;
+        if best.as_ref().map_or(true, |b| score > b.score) {
+            best = Some(matched.clone());
+        }
+
+        if score * 100.0 >= extra.threshold.into() {
+            // 匹配成功后才写入，由写入线程执行；记录重新启用时间，作为最后使用时间，避免下次维护立即再次停用
+            let token = file_name.clone();
+            db_writer::write(move |tx| {
+                tx.execute(
+                    "UPDATE faces SET json_data = json_set(json_data, '$.expired', json('false'), \
+                     '$.reactivatedTime', datetime('now', 'localtime')) WHERE face_token = ?1",
+                    [&token],
+                )
+                .map_err(|e| format!("重新启用面容失败：{:?}", e))
+            })
+            .map_err(|e| CustomResult::error(Some(e), None))?;
+            info!("面容 {} 已重新启用，匹配分数 {:.3}", file_name, score);
+            return Ok(CustomResult::success(None, Some(json!({"score": score, "match": matched}))));
+        }
+        sleep(Duration::from_millis(50));
+    }
+
+    let best_score = best.as_ref().map_or(0.0, |b| b.score);
+    Err(CustomResult::error(
+        Some(format!(
+            "面容匹配未通过（最高分数 {:.3}），请正对摄像头后重试",
+            best_score
+        )),
+        Some(json!({"score": best_score, "match": best})),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 与前端 sqlite.js 中的 faces 表一致
+    fn faces_db() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE faces (id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL, user_name TEXT NOT NULL, \
+             user_pwd TEXT NOT NULL, account_type TEXT NOT NULL, face_token TEXT NOT NULL, json_data TEXT NOT NULL, \
+             createTime TEXT DEFAULT (datetime('now', 'localtime')));",
+        )
+        .unwrap();
+        for (token, json_data) in [
+            ("active", r#"{"alias":"a","threshold":60}"#),
+            ("disabled", r#"{"alias":"b","threshold":60}"#),
+            ("reactivated", r#"{"alias":"c","threshold":60,"expired":false}"#),
+        ] {
+            conn.execute(
+                "INSERT INTO faces (user_name, user_pwd, account_type, face_token, json_data) VALUES ('u', '', 'local', ?1, ?2)",
+                [token, json_data],
+            )
+            .unwrap();
+        }
+        conn
+    }
+
+    #[test]
+    fn disabled_faces_are_excluded_from_matching() {
+        let conn = faces_db();
+        let id: i64 = conn
+            .query_row("SELECT id FROM faces WHERE face_token = 'disabled';", [], |row| row.get(0))
+            .unwrap();
+        disable_face(&conn, id).unwrap();
+
+        let expired = expired_face_tokens(&conn).unwrap();
+        assert_eq!(expired, HashSet::from([String::from("disabled")]));
+
+        let faces = vec![
+            (String::from("active"), 1),
+            (String::from("disabled"), 2),
+            (String::from("reactivated"), 3),
+        ];
+        let active: Vec<_> = without_expired(faces, &expired).into_iter().map(|(token, _)| token).collect();
+        assert_eq!(active, ["active", "reactivated"]);
+    }
+
+    #[test]
+    fn no_expired_faces_keeps_every_face() {
+        let conn = faces_db();
+        let expired = expired_face_tokens(&conn).unwrap();
+        assert!(expired.is_empty());
+        assert_eq!(without_expired(vec![(String::from("active"), ())], &expired).len(), 1);
+    }
+}
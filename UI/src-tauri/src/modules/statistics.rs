@@ -0,0 +1,206 @@
+use std::collections::BTreeMap;
+
+use r2d2_sqlite::rusqlite::{self, Connection};
+use serde_json::json;
+use tauri_plugin_log::log::info;
+
+use crate::{
+    modules::options::{get_conn, query_option},
+    utils::{api::ensure_ready, custom_result::CustomResult, db_writer},
+};
+
+// 分数分箱宽度，统计中的直方图使用相同宽度，保证精确和分箱数据的统计结果一致
+const SCORE_BUCKET_WIDTH: f64 = 0.05;
+// 低精度模式下默认保留精确值的最近记录数
+const DEFAULT_EXACT_KEEP: i64 = 50;
+// 精度模式
+const PRECISION_EXACT: &str = "exact";
+const PRECISION_BUCKETED: &str = "bucketed";
+
+// 当前的精度模式和保留精确值的记录数
+fn precision_mode(conn: &Connection) -> (&'static str, i64) {
+    let mode = match query_option(conn, "scorePrecision").as_deref() {
+        Some(PRECISION_BUCKETED) => PRECISION_BUCKETED,
+        _ => PRECISION_EXACT,
+    };
+    let keep = query_option(conn, "scoreExactKeep")
+        .and_then(|v| v.parse::<f64>().ok())
+        .map(|v| v.max(0.0) as i64)
+        .unwrap_or(DEFAULT_EXACT_KEEP);
+    (mode, keep)
+}
+
+// 是否为低精度模式
+pub fn is_score_bucketed(conn: &Connection) -> bool {
+    precision_mode(conn).0 == PRECISION_BUCKETED
+}
+
+// 低精度模式下，把最近 N 条以外的解锁日志降低精度：分数分箱，时间精确到小时
+// 已经处理过的记录不会重复处理，返回本次处理的数量
+pub fn apply_score_precision(conn: &Connection) -> Result<usize, String> {
+    let (mode, keep) = precision_mode(conn);
+    if mode != PRECISION_BUCKETED {
+        return Ok(0);
+    }
+    conn.execute(
+        "UPDATE unlock_log SET \
+         score = CASE WHEN score IS NULL THEN NULL ELSE ROUND(ROUND(score / ?1) * ?1, 4) END, \
+         lastTime = strftime('%Y-%m-%d %H:00:00', lastTime), \
+         bucketed = 1 \
+         WHERE bucketed = 0 AND id NOT IN (SELECT id FROM unlock_log ORDER BY id DESC LIMIT ?2)",
+        rusqlite::params![SCORE_BUCKET_WIDTH, keep],
+    )
+    .map_err(|e| format!("降低解锁日志精度失败：{:?}", e))
+}
+
+// 设置分数精度模式，切换到低精度时立即处理已有记录
+#[tauri::command]
+pub fn set_score_precision(
+    mode: String,
+    exact_keep: Option<u32>,
+) -> Result<CustomResult, CustomResult> {
+    ensure_ready()?;
+    if mode != PRECISION_EXACT && mode != PRECISION_BUCKETED {
+        return Err(CustomResult::error(
+            Some(format!("未知的精度模式 {}", mode)),
+            None,
+        ));
+    }
+    let mut items = vec![("scorePrecision", mode.clone())];
+    if let Some(keep) = exact_keep {
+        items.push(("scoreExactKeep", keep.to_string()));
+    }
+    // 保存设置和降低精度在同一个事务中
+    let migrated = db_writer::write(move |tx| {
+        for (key, val) in items {
+            tx.execute(
+                "INSERT INTO options (key, val) VALUES (?1, ?2) \
+                 ON CONFLICT(key) DO UPDATE SET val = excluded.val, lastTime = datetime('now', 'localtime')",
+                rusqlite::params![key, val],
+            )
+            .map_err(|e| format!("保存设置 {} 失败：{:?}", key, e))?;
+        }
+        apply_score_precision(tx)
+    })
+    .map_err(|e| CustomResult::error(Some(e), None))?;
+    if migrated > 0 {
+        info!("已降低 {} 条解锁日志的精度", migrated);
+    }
+    Ok(CustomResult::success(None, Some(json!({"migrated": migrated}))))
+}
+
+// 解锁统计：成功/失败次数、分数直方图、按小时分布、按面容统计
+// 直方图按分箱宽度统计，时间按小时统计，精确数据和分箱数据得到相同结构的结果
+// 只读取数据，新增日志的降低精度在 set_score_precision 和数据保留维护中进行
+#[tauri::command]
+pub fn get_unlock_statistics(days: Option<u32>) -> Result<CustomResult, CustomResult> {
+    ensure_ready()?;
+    let conn = get_conn().map_err(|e| CustomResult::error(Some(e), None))?;
+
+    let since = format!("-{} days", days.unwrap_or(30));
+    let mut stmt = conn
+        .prepare(
+            "SELECT face_id, is_unlock, score, CAST(strftime('%H', lastTime) AS INTEGER) AS hour \
+             FROM unlock_log WHERE lastTime >= datetime('now', 'localtime', ?1)",
+        )
+        .map_err(|e| CustomResult::error(Some(format!("准备查询解锁统计失败 {:?}", e)), None))?;
+    let rows = stmt
+        .query_map([since], |row| {
+            Ok((
+                row.get::<&str, Option<i64>>("face_id")?,
+                row.get::<&str, i32>("is_unlock")?,
+                row.get::<&str, Option<f64>>("score")?,
+                row.get::<&str, Option<i64>>("hour")?,
+            ))
+        })
+        .map_err(|e| CustomResult::error(Some(format!("查询解锁统计失败 {:?}", e)), None))?;
+
+    let mut success = 0;
+    let mut failure = 0;
+    let mut by_hour = [0u32; 24];
+    // 分箱中心（放大 100 倍取整，避免浮点作为 key） -> (成功次数, 失败次数)
+    let mut histogram: BTreeMap<i64, (u32, u32)> = BTreeMap::new();
+    let mut by_face: BTreeMap<i64, u32> = BTreeMap::new();
+    for (face_id, is_unlock, score, hour) in rows.flatten() {
+        let is_unlock = is_unlock == 1;
+        if is_unlock {
+            success += 1;
+            if let Some(face_id) = face_id {
+                *by_face.entry(face_id).or_default() += 1;
+            }
+        } else {
+            failure += 1;
+        }
+        if let Some(hour) = hour.filter(|h| (0..24).contains(h)) {
+            by_hour[hour as usize] += 1;
+        }
+        if let Some(score) = score {
+            let bucket = ((score / SCORE_BUCKET_WIDTH).round() * SCORE_BUCKET_WIDTH * 100.0).round() as i64;
+            let entry = histogram.entry(bucket).or_default();
+            if is_unlock {
+                entry.0 += 1;
+            } else {
+                entry.1 += 1;
+            }
+        }
+    }
+
+    let histogram: Vec<_> = histogram
+        .into_iter()
+        .map(|(bucket, (success, failure))| {
+            json!({"score": bucket as f64 / 100.0, "success": success, "failure": failure})
+        })
+        .collect();
+    let by_face: Vec<_> = by_face
+        .into_iter()
+        .map(|(face_id, count)| json!({"face_id": face_id, "success": count}))
+        .collect();
+
+    Ok(CustomResult::success(
+        None,
+        Some(json!({
+            "success": success,
+            "failure": failure,
+            "bucket_width": SCORE_BUCKET_WIDTH,
+            "histogram": histogram,
+            "by_hour": by_hour,
+            "by_face": by_face,
+        })),
+    ))
+}
+
+// 已保存数据的概况，包括当前的分数精度模式
+#[tauri::command]
+pub fn get_stored_data_summary() -> Result<CustomResult, CustomResult> {
+    ensure_ready()?;
+    let conn = get_conn().map_err(|e| CustomResult::error(Some(e), None))?;
+    let (mode, keep) = precision_mode(&conn);
+
+    let count = |sql: &str| -> Result<i64, CustomResult> {
+        conn.query_row(sql, [], |row| row.get::<usize, i64>(0))
+            .map_err(|e| CustomResult::error(Some(format!("统计数据失败 {:?}", e)), None))
+    };
+
+    Ok(CustomResult::success(
+        None,
+        Some(json!({
+            "faces": count("SELECT COUNT(id) FROM faces;")?,
+            "unlock_log": {
+                "total": count("SELECT COUNT(id) FROM unlock_log;")?,
+                "exact": count("SELECT COUNT(id) FROM unlock_log WHERE bucketed = 0;")?,
+                "bucketed": count("SELECT COUNT(id) FROM unlock_log WHERE bucketed = 1;")?,
+                "oldest": conn
+                    .query_row("SELECT MIN(lastTime) FROM unlock_log;", [], |row| row.get::<usize, Option<String>>(0))
+                    .ok()
+                    .flatten(),
+            },
+            "score_stats": count("SELECT COUNT(id) FROM face_score_stats;")?,
+            "config_snapshots": count("SELECT COUNT(id) FROM config_snapshot;")?,
+            "precision": {
+                "mode": mode,
+                "exact_keep": keep,
+                "bucket_width": SCORE_BUCKET_WIDTH,
+            },
+        })),
+    ))
+}
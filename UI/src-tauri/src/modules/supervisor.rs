@@ -0,0 +1,192 @@
+// 守护模式：以 --supervised 启动时，当前进程只负责启动真正的软件（同一个 exe），异常退出后按退避时间重启
+// 子进程的退出码决定是否重启：正常退出（托盘“退出”）和初始化失败不重启，设置中要求的重启立即执行
+use std::{
+    env,
+    fs::{self, OpenOptions},
+    io::Write,
+    process::Command,
+    sync::atomic::{AtomicBool, Ordering},
+    thread::sleep,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use r2d2_sqlite::rusqlite::{self, params, Connection, OpenFlags};
+use serde_json::{json, Value};
+use tauri_plugin_log::log::error;
+
+use crate::ROOT_DIR;
+
+// 正常退出，守护进程随之退出
+pub const EXIT_CLEAN: i32 = 0;
+// 设置中要求重启，守护进程立即重新启动
+pub const EXIT_RESTART: i32 = 75;
+// 初始化失败，重启也无法恢复，不再重启
+pub const EXIT_FATAL_INIT: i32 = 78;
+// 发生 panic，与 Rust 默认的 panic 退出码一致
+pub const EXIT_PANIC: i32 = 101;
+
+const SUPERVISED_ARG: &str = "--supervised";
+// 子进程通过该环境变量知道自己由守护进程启动
+const SUPERVISED_ENV: &str = "FACEWINUNLOCK_SUPERVISED";
+// 连续异常退出后最多重启的次数
+const MAX_RESTARTS: u32 = 5;
+// 退避时间从 1 秒开始翻倍，最长 60 秒
+const BACKOFF_BASE: Duration = Duration::from_secs(1);
+const BACKOFF_MAX: Duration = Duration::from_secs(60);
+// 运行超过该时间后视为已恢复稳定，重新计算重启次数
+const STABLE_UPTIME: Duration = Duration::from_secs(10 * 60);
+// 诊断信息中附带的最近事件数量
+const RECENT_EVENTS: usize = 20;
+
+// 用户已要求退出，退出过程中发生的 panic 也按正常退出处理
+static QUIT_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+// 当前进程是否由守护进程启动
+pub fn is_supervised() -> bool {
+    env::var_os(SUPERVISED_ENV).is_some()
+}
+
+pub fn mark_quit_requested() {
+    QUIT_REQUESTED.store(true, Ordering::SeqCst);
+}
+
+// release 使用 panic = "abort"，panic 后进程直接结束，退出码不固定
+// 这里先记录日志，再以约定的退出码结束，守护进程据此重启
+pub fn install_panic_hook() {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        error!("软件发生 panic：{}", info);
+        default_hook(info);
+        if cfg!(panic = "abort") {
+            let code = if QUIT_REQUESTED.load(Ordering::SeqCst) { EXIT_CLEAN } else { EXIT_PANIC };
+            std::process::exit(code);
+        }
+    }));
+}
+
+// 子进程退出后的处理方式
+enum Verdict {
+    Stop,
+    RestartNow,
+    Restart,
+}
+
+fn classify(code: Option<i32>) -> Verdict {
+    match code {
+        Some(EXIT_CLEAN) | Some(EXIT_FATAL_INIT) => Verdict::Stop,
+        Some(EXIT_RESTART) => Verdict::RestartNow,
+        // panic、崩溃、被结束进程等
+        _ => Verdict::Restart,
+    }
+}
+
+fn backoff(attempt: u32) -> Duration {
+    BACKOFF_BASE
+        .saturating_mul(1 << attempt.saturating_sub(1).min(16))
+        .min(BACKOFF_MAX)
+}
+
+// 记录守护事件，数据库不可用（例如首次启动还没有建表）时写入日志文件
+fn record_event(event: &str, exit_code: Option<i32>, attempt: u32, uptime: Duration) {
+    let result = Connection::open_with_flags(ROOT_DIR.join("database.db"), OpenFlags::SQLITE_OPEN_READ_WRITE)
+        .and_then(|conn| {
+            conn.busy_timeout(Duration::from_secs(5))?;
+            conn.execute(
+                "INSERT INTO supervisor_log (event, exit_code, attempt, uptime) VALUES (?1, ?2, ?3, ?4);",
+                params![event, exit_code, attempt, uptime.as_secs() as i64],
+            )
+        });
+    if let Err(e) = result {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        let line = format!(
+            "{} {} exit_code={:?} attempt={} uptime={}s（写入数据库失败：{:?}）\n",
+            now, event, exit_code, attempt, uptime.as_secs(), e
+        );
+        let dir = ROOT_DIR.join("logs");
+        let _ = fs::create_dir_all(&dir);
+        let _ = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(dir.join("supervisor.log"))
+            .and_then(|mut file| file.write_all(line.as_bytes()));
+    }
+}
+
+// 守护模式入口，没有 --supervised 参数时返回 None
+pub fn run_cli(args: &[String]) -> Option<i32> {
+    if !args.iter().any(|arg| arg == SUPERVISED_ARG) {
+        return None;
+    }
+    let Ok(exe) = env::current_exe() else {
+        return Some(EXIT_FATAL_INIT);
+    };
+    // 其余参数（例如 --silent）原样传给子进程
+    let child_args: Vec<&String> = args.iter().skip(1).filter(|arg| *arg != SUPERVISED_ARG).collect();
+
+    let mut attempt = 0;
+    loop {
+        let started = Instant::now();
+        let status = Command::new(&exe).args(&child_args).env(SUPERVISED_ENV, "1").status();
+        let uptime = started.elapsed();
+        let code = match status {
+            Ok(status) => status.code(),
+            Err(_) => {
+                record_event("spawn_failed", None, attempt, uptime);
+                return Some(EXIT_FATAL_INIT);
+            }
+        };
+        if uptime >= STABLE_UPTIME {
+            attempt = 0;
+        }
+
+        match classify(code) {
+            Verdict::Stop => {
+                if code == Some(EXIT_FATAL_INIT) {
+                    record_event("fatal_init", code, attempt, uptime);
+                }
+                return Some(code.unwrap_or(EXIT_CLEAN));
+            }
+            Verdict::RestartNow => {
+                record_event("restart_requested", code, 0, uptime);
+                attempt = 0;
+            }
+            Verdict::Restart => {
+                if attempt >= MAX_RESTARTS {
+                    record_event("gave_up", code, attempt, uptime);
+                    return Some(code.unwrap_or(EXIT_PANIC));
+                }
+                attempt += 1;
+                record_event("restart", code, attempt, uptime);
+                sleep(backoff(attempt));
+            }
+        }
+    }
+}
+
+// 诊断信息中的守护状态和最近的重启记录
+pub fn supervisor_diagnostics(conn: &Connection) -> Value {
+    let events = conn
+        .prepare(
+            "SELECT event, exit_code, attempt, uptime, createTime FROM supervisor_log \
+             ORDER BY id DESC LIMIT ?1;",
+        )
+        .and_then(|mut stmt| {
+            stmt.query_map([RECENT_EVENTS as i64], |row| {
+                Ok(json!({
+                    "event": row.get::<&str, String>("event")?,
+                    "exit_code": row.get::<&str, Option<i32>>("exit_code")?,
+                    "attempt": row.get::<&str, u32>("attempt")?,
+                    "uptime": row.get::<&str, i64>("uptime")?,
+                    "time": row.get::<&str, Option<String>>("createTime")?,
+                }))
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()
+        });
+    json!({
+        "supervised": is_supervised(),
+        "recent_events": events.unwrap_or_else(|e| vec![json!({"error": format!("{:?}", e)})]),
+    })
+}
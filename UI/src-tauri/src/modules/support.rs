@@ -0,0 +1,422 @@
+use std::{
+    fs::{self, File},
+    io::{Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::atomic::Ordering,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use r2d2_sqlite::rusqlite::{Connection, OpenFlags};
+use regex::Regex;
+use serde::Serialize;
+use serde_json::{json, Value};
+use tauri_plugin_log::log::info;
+use winreg::{enums::HKEY_LOCAL_MACHINE, RegKey};
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipWriter};
+
+use crate::{
+    modules::{
+        capabilities::run_capability_check, drift::reenrollment_status, faces::validate_face_store,
+        options::get_conn, replay::capture_diagnostics, supervisor::supervisor_diagnostics,
+    },
+    utils::{
+        api::ensure_ready,
+        custom_result::CustomResult,
+        db_writer::quick_check,
+        frame_cache::{ENCODE_HITS, ENCODE_MISSES},
+        priority::current_mode,
+        storage::{faces_dir, is_cloud_synced, is_controlled_folder_access_enabled, redact_name},
+    },
+    APP_STATE, ROOT_DIR,
+};
+
+// 每个日志文件最多打包末尾多少字节
+const MAX_LOG_BYTES: u64 = 2 * 1024 * 1024;
+// 需要打包的日志：(文件名, 打包后的名称, 说明)
+const LOG_FILES: [(&str, &str, &str); 2] = [
+    ("app.log", "logs/app.log", "软件日志（已脱敏）"),
+    ("facewinunlock.log", "logs/dll.log", "DLL 部署与锁屏日志（已脱敏）"),
+];
+// 名称中包含这些字段的设置项不打包
+const SECRET_OPTION_KEYS: [&str; 4] = ["pwd", "password", "secret", "token"];
+// 脱敏后的替换文本
+const REDACTED: &str = "[已移除]";
+
+lazy_static::lazy_static! {
+    // data:image/xxx;base64,... 图片
+    static ref DATA_URL: Regex = Regex::new(r"data:image/[A-Za-z0-9+.\-]+;base64,[A-Za-z0-9+/=]+").unwrap();
+    // 较长的 base64 数据块
+    static ref BASE64_BLOB: Regex = Regex::new(r"[A-Za-z0-9+/]{200,}={0,2}").unwrap();
+    // 密码类字段，兼容 JSON 和 key=value 形式
+    static ref SECRET_FIELD: Regex = Regex::new(
+        r#"(?i)("?\b(?:password|passwd|pwd|user_pwd|secret|token)\b"?\s*[:=]\s*)("[^"]*"|[^\s,}&]+)"#
+    ).unwrap();
+    static ref SECRET_FIELD_ZH: Regex = Regex::new(r"(密码\s*[:：=]\s*)\S+").unwrap();
+    // 旧版管道消息中的密码
+    static ref PIPE_CREDENTIALS: Regex = Regex::new(r"::FaceWinUnlock::\S*").unwrap();
+    // 16 个以上的连续数字，视为特征向量
+    static ref FEATURE_VECTOR: Regex = Regex::new(
+        r"\[\s*-?\d+(?:\.\d+)?(?:[eE][+-]?\d+)?(?:\s*,\s*-?\d+(?:\.\d+)?(?:[eE][+-]?\d+)?){15,}\s*\]"
+    ).unwrap();
+    // 用户目录中的用户名，兼容 \ 和 / 分隔符
+    static ref USER_DIR: Regex = Regex::new(r#"(?i)([\\/]users[\\/]+)[^\\/"\s]+"#).unwrap();
+    // 账户名字段，兼容 JSON 和 key=value 形式
+    static ref USER_FIELD: Regex = Regex::new(
+        r#"(?i)("?\b(?:user_name|username|userName)\b"?\s*[:=]\s*)("[^"]*"|[^\s,}&]+)"#
+    ).unwrap();
+}
+
+// 支持包清单，说明包含和未包含的内容
+#[derive(Debug, Serialize)]
+pub struct BundleManifest {
+    pub version: &'static str,
+    pub created: u64,
+    pub include_images: bool,
+    pub included: Vec<Value>,
+    pub excluded: Vec<Value>,
+}
+
+impl BundleManifest {
+    fn include(&mut self, file: &str, description: &str) {
+        self.included.push(json!({"file": file, "description": description}));
+    }
+
+    fn exclude(&mut self, item: &str, reason: &str) {
+        self.excluded.push(json!({"item": item, "reason": reason}));
+    }
+}
+
+// 对一段文本脱敏：图片数据、密码、特征向量、账户名、用户目录
+pub fn scrub_text(text: &str) -> String {
+    let text = DATA_URL.replace_all(text, format!("data:image/*;base64,{}", REDACTED).as_str());
+    let text = BASE64_BLOB.replace_all(&text, REDACTED);
+    let text = PIPE_CREDENTIALS.replace_all(&text, format!("::FaceWinUnlock::{}", REDACTED).as_str());
+    let text = SECRET_FIELD.replace_all(&text, format!("${{1}}\"{}\"", REDACTED).as_str());
+    let text = SECRET_FIELD_ZH.replace_all(&text, format!("${{1}}{}", REDACTED).as_str());
+    let text = FEATURE_VECTOR.replace_all(&text, REDACTED);
+    let text = USER_FIELD.replace_all(&text, "${1}\"<user>\"");
+    USER_DIR.replace_all(&text, "${1}<user>").into_owned()
+}
+
+// 支持包的保存路径：dest 为目录时在其中按时间生成文件名，没有扩展名时追加 .zip
+fn bundle_path(dest: &Path, created: u64) -> PathBuf {
+    if dest.is_dir() {
+        dest.join(format!("facewinunlock-support-{}.zip", created))
+    } else if dest.extension().is_none() {
+        dest.with_extension("zip")
+    } else {
+        dest.to_path_buf()
+    }
+}
+
+// 生成支持包
+pub fn create_bundle(dest: &Path, include_images: bool) -> Result<(PathBuf, BundleManifest), String> {
+    let created = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    let path = bundle_path(dest, created);
+
+    let file = File::create(&path).map_err(|e| format!("创建支持包文件失败: {}", e))?;
+    let mut zip = ZipWriter::new(file);
+    let mut manifest = BundleManifest {
+        version: env!("CARGO_PKG_VERSION"),
+        created,
+        include_images,
+        included: Vec::new(),
+        excluded: Vec::new(),
+    };
+
+    add_json(&mut zip, "version.json", &version_info())?;
+    manifest.include("version.json", "软件、OpenCV 和系统版本");
+
+    add_json(&mut zip, "capabilities.json", &json!(run_capability_check()))?;
+    manifest.include("capabilities.json", "OpenCV 能力检测结果");
+
+    // 数据库在 init_model 之前没有连接池，命令行模式下直接只读打开
+    let snapshot = match get_conn() {
+        Ok(conn) => database_snapshot(&conn),
+        Err(_) => Connection::open_with_flags(ROOT_DIR.join("database.db"), OpenFlags::SQLITE_OPEN_READ_ONLY)
+            .map_err(|e| format!("打开数据库失败: {:?}", e))
+            .and_then(|conn| database_snapshot(&conn)),
+    };
+    match snapshot {
+        Ok((settings, reenrollment, database)) => {
+            add_json(&mut zip, "settings.json", &settings)?;
+            manifest.include("settings.json", "软件设置（不含密码类设置项）");
+            add_json(&mut zip, "diagnostics.json", &diagnostics(reenrollment, database))?;
+            manifest.include("diagnostics.json", "运行状态、数据库完整性检查和重新录入建议（面容名称已替换为哈希）");
+        }
+        Err(e) => {
+            add_json(&mut zip, "diagnostics.json", &diagnostics(json!(null), json!(null)))?;
+            manifest.include("diagnostics.json", "运行状态");
+            manifest.exclude("settings.json", &format!("读取数据库失败: {}", e));
+        }
+    }
+
+    match validate_face_store() {
+        Ok(result) => {
+            add_json(&mut zip, "face_store.json", &redact_face_store(result.data))?;
+            manifest.include("face_store.json", "面容目录检查结果（文件名已替换为哈希）");
+        }
+        Err(e) => manifest.exclude("face_store.json", &e.msg),
+    }
+
+    for (file_name, entry_name, description) in LOG_FILES {
+        match read_log_tail(&ROOT_DIR.join("logs").join(file_name)) {
+            Ok(text) => {
+                add_file(&mut zip, entry_name, scrub_text(&text).as_bytes())?;
+                manifest.include(entry_name, description);
+            }
+            Err(e) => manifest.exclude(entry_name, &format!("读取失败: {}", e)),
+        }
+    }
+
+    manifest.exclude("database.db", "包含加密后的 Windows 密码，不打包");
+    manifest.exclude("faces.db, faces/*.faceimg", "面容特征和注册图片属于生物特征数据，不打包");
+    if include_images {
+        manifest.exclude("intruder_snapshots", "当前版本不保存入侵者快照");
+    } else {
+        manifest.exclude("intruder_snapshots", "未指定 include_images，不打包任何图片");
+    }
+
+    add_json(&mut zip, "manifest.json", &json!(manifest))?;
+    zip.finish().map_err(|e| format!("写入支持包失败: {}", e))?;
+
+    info!("支持包已生成: {:?}", path);
+    Ok((path, manifest))
+}
+
+fn add_file(zip: &mut ZipWriter<File>, name: &str, data: &[u8]) -> Result<(), String> {
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    zip.start_file(name, options)
+        .map_err(|e| format!("写入 {} 失败: {}", name, e))?;
+    zip.write_all(data)
+        .map_err(|e| format!("写入 {} 失败: {}", name, e))
+}
+
+fn add_json(zip: &mut ZipWriter<File>, name: &str, value: &Value) -> Result<(), String> {
+    let text = serde_json::to_string_pretty(value).map_err(|e| format!("序列化 {} 失败: {}", name, e))?;
+    add_file(zip, name, scrub_text(&text).as_bytes())
+}
+
+// 读取日志末尾，从完整的一行开始
+fn read_log_tail(path: &Path) -> std::io::Result<String> {
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+    let start = len.saturating_sub(MAX_LOG_BYTES);
+    file.seek(SeekFrom::Start(start))?;
+    let mut buf = Vec::new();
+    file.read_to_end(&mut buf)?;
+    let text = String::from_utf8_lossy(&buf);
+    if start > 0 {
+        if let Some(pos) = text.find('\n') {
+            return Ok(text[pos + 1..].to_string());
+        }
+    }
+    Ok(text.into_owned())
+}
+
+// 从数据库读取设置、重新录入建议、完整性检查结果和调试记录模式状态
+fn database_snapshot(conn: &Connection) -> Result<(Value, Value, Value), String> {
+    let mut stmt = conn
+        .prepare("SELECT key, val FROM options;")
+        .map_err(|e| format!("准备查询设置失败：{:?}", e))?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok((row.get::<&str, String>("key")?, row.get::<&str, String>("val")?))
+        })
+        .map_err(|e| format!("查询设置失败：{:?}", e))?;
+    let mut settings = serde_json::Map::new();
+    for (key, val) in rows.flatten() {
+        let lower = key.to_lowercase();
+        if SECRET_OPTION_KEYS.iter().any(|k| lower.contains(k)) {
+            continue;
+        }
+        settings.insert(key, Value::String(val));
+    }
+
+    let reenrollment = reenrollment_status(conn)
+        .map(|list| {
+            list.into_iter()
+                .map(|mut item| {
+                    if let Some(name) = item["file_name"].as_str() {
+                        item["file_name"] = json!(redact_name(name));
+                    }
+                    item
+                })
+                .collect::<Vec<_>>()
+        })
+        .map(|list| json!(list))
+        .unwrap_or_else(|e| json!({"error": e}));
+
+    let database = json!({
+        "integrity": database_integrity(conn),
+        "debug_capture": capture_diagnostics(conn),
+        "supervisor": supervisor_diagnostics(conn),
+    });
+    Ok((Value::Object(settings), reenrollment, database))
+}
+
+// 数据库完整性检查和日志模式，正常时 quick_check 为 ["ok"]
+fn database_integrity(conn: &Connection) -> Value {
+    let journal_mode = conn
+        .query_row("PRAGMA journal_mode;", [], |row| row.get::<usize, String>(0))
+        .unwrap_or_else(|e| format!("{:?}", e));
+    match quick_check(conn) {
+        Ok(result) => json!({
+            "ok": result.len() == 1 && result[0] == "ok",
+            "quick_check": result,
+            "journal_mode": journal_mode,
+        }),
+        Err(e) => json!({"ok": false, "error": e, "journal_mode": journal_mode}),
+    }
+}
+
+fn diagnostics(reenrollment: Value, database: Value) -> Value {
+    let phase = APP_STATE.try_lock().ok().map(|state| state.phase);
+    // 需要引起注意的状态单独列出
+    let warnings: Vec<&Value> = [&database["debug_capture"]["warning"]]
+        .into_iter()
+        .filter(|w| !w.is_null())
+        .collect();
+    json!({
+        "warnings": warnings,
+        "phase": phase,
+        "work_mode": current_mode(),
+        "faces_dir_cloud_synced": is_cloud_synced(faces_dir()),
+        "controlled_folder_access": is_controlled_folder_access_enabled(),
+        "encode": {
+            "hits": ENCODE_HITS.load(Ordering::Relaxed),
+            "misses": ENCODE_MISSES.load(Ordering::Relaxed),
+        },
+        "database_integrity": database["integrity"],
+        "debug_capture": database["debug_capture"],
+        "supervisor": database["supervisor"],
+        "reenrollment": reenrollment,
+    })
+}
+
+// 面容目录检查结果中的文件名替换为哈希
+fn redact_face_store(mut data: Value) -> Value {
+    if let Some(warnings) = data["warnings"].as_array_mut() {
+        for warning in warnings {
+            if let Some(files) = warning["files"].as_array_mut() {
+                for file in files {
+                    if let Some(name) = file.as_str() {
+                        *file = json!(redact_name(name));
+                    }
+                }
+            }
+        }
+    }
+    data
+}
+
+fn version_info() -> Value {
+    let windows = RegKey::predef(HKEY_LOCAL_MACHINE)
+        .open_subkey("SOFTWARE\\Microsoft\\Windows NT\\CurrentVersion")
+        .ok();
+    let read = |name: &str| {
+        windows
+            .as_ref()
+            .and_then(|key| key.get_value::<String, _>(name).ok())
+    };
+    json!({
+        "app": env!("CARGO_PKG_VERSION"),
+        "opencv": opencv::core::get_version_string().ok(),
+        "windows": {
+            "product": read("ProductName"),
+            "display_version": read("DisplayVersion"),
+            "build": read("CurrentBuild"),
+        },
+    })
+}
+
+// 生成诊断支持包
+#[tauri::command]
+pub fn create_support_bundle(
+    dest_path: String,
+    include_images: bool,
+) -> Result<CustomResult, CustomResult> {
+    ensure_ready()?;
+    let (path, manifest) = create_bundle(Path::new(&dest_path), include_images)
+        .map_err(|e| CustomResult::error(Some(e), None))?;
+    Ok(CustomResult::success(
+        None,
+        Some(json!({"path": path.to_string_lossy(), "manifest": manifest})),
+    ))
+}
+
+// 命令行：facewinunlock-tauri.exe --support-bundle <目录或文件> [--include-images]
+// 返回 None 表示不是该命令，继续正常启动
+pub fn run_cli(args: &[String]) -> Option<i32> {
+    let index = args.iter().position(|arg| arg == "--support-bundle")?;
+    let dest = args
+        .get(index + 1)
+        .filter(|arg| !arg.starts_with("--"))
+        .map(PathBuf::from)
+        .unwrap_or_else(|| ROOT_DIR.to_path_buf());
+    let include_images = args.iter().any(|arg| arg == "--include-images");
+
+    if !dest.exists() {
+        if let Some(parent) = dest.parent() {
+            let _ = fs::create_dir_all(parent);
+        }
+    }
+    match create_bundle(&dest, include_images) {
+        Ok((path, _)) => {
+            println!("支持包已生成: {}", path.display());
+            Some(0)
+        }
+        Err(e) => {
+            eprintln!("生成支持包失败: {}", e);
+            Some(1)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scrub_removes_user_names_and_profile_paths() {
+        let text = scrub_text(r#"加载 C:\Users\alice\AppData\Local\facewinunlock-tauri\faces.db 与 D:/users/bob/x"#);
+        assert!(!text.contains("alice"));
+        assert!(text.contains(r"C:\Users\<user>\AppData\Local\facewinunlock-tauri\faces.db"));
+        assert!(text.contains("D:/users/<user>/x"));
+
+        let text = scrub_text(r#"{"user_name":"张三","account_type":0} userName=bob, username: "carol""#);
+        assert!(!text.contains("张三") && !text.contains("bob") && !text.contains("carol"));
+        assert!(text.contains(r#""user_name":"<user>""#));
+        assert!(text.contains("account_type"));
+    }
+
+    #[test]
+    fn scrub_removes_secrets_and_tokens() {
+        let text = scrub_text(r#"{"user_pwd":"hunter2","token":"abc.def"} password=p@ss 密码：123456 ::FaceWinUnlock::alice::hunter2"#);
+        for secret in ["hunter2", "abc.def", "p@ss", "123456"] {
+            assert!(!text.contains(secret), "{} 未移除：{}", secret, text);
+        }
+        assert!(text.contains(&format!("::FaceWinUnlock::{}", REDACTED)));
+
+        let image = format!("data:image/jpeg;base64,{}", "A".repeat(64));
+        assert_eq!(scrub_text(&image), format!("data:image/*;base64,{}", REDACTED));
+        assert_eq!(scrub_text(&"QUJD".repeat(60)), REDACTED);
+        let feature = format!("[{}]", vec!["0.125"; 16].join(", "));
+        assert_eq!(scrub_text(&feature), REDACTED);
+        // 普通日志不受影响
+        assert_eq!(scrub_text("摄像头 0 已打开，耗时 35ms"), "摄像头 0 已打开，耗时 35ms");
+    }
+
+    #[test]
+    fn bundle_path_adds_zip_extension() {
+        let dir = std::env::temp_dir().join(format!("facewinunlock-support-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        assert_eq!(bundle_path(&dir, 7), dir.join("facewinunlock-support-7.zip"));
+        assert_eq!(bundle_path(&dir.join("report"), 7), dir.join("report.zip"));
+        assert_eq!(bundle_path(&dir.join("report.zip"), 7), dir.join("report.zip"));
+        fs::remove_dir_all(dir).ok();
+    }
+}
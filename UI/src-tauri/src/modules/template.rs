@@ -0,0 +1,380 @@
+// 面容模板保护：保存特征前用本机密钥做一次随机置换和符号翻转
+// 置换 + 符号翻转是正交变换，两个向量同时变换后余弦相似度不变，所以匹配时只需要对摄像头特征做同样的变换
+// 密钥用 DPAPI 加密保存，只有当前 Windows 用户能解开；特征文件被拷走后没有密钥就无法用于其他系统
+use std::{
+    fs,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use lazy_static::lazy_static;
+use opencv::{core::Mat, prelude::*};
+use tauri_plugin_log::log::{info, warn};
+use uuid::Uuid;
+use windows::{
+    core::PCWSTR,
+    Win32::{
+        Foundation::{LocalFree, ERROR_INVALID_DATA, HLOCAL},
+        Security::Cryptography::{
+            BCryptHash, CryptProtectData, CryptUnprotectData, BCRYPT_SHA256_ALG_HANDLE,
+            CRYPTPROTECT_LOCAL_MACHINE, CRYPTPROTECT_UI_FORBIDDEN, CRYPT_INTEGER_BLOB,
+        },
+    },
+};
+
+use crate::{
+    modules::faces::FaceDescriptor,
+    utils::storage::{faces_dir, with_retry},
+    ROOT_DIR,
+};
+
+// 密钥不可用或与特征文件不一致时的错误前缀，调用方据此提示重新录入
+pub const TEMPLATE_KEY_MISMATCH: &str = "TemplateKeyMismatch";
+// 加密的面容文件无法解密时的错误前缀：文件被篡改，或是从其他电脑拷贝的
+pub const FACE_FILE_UNREADABLE: &str = "FaceFileUnreadable";
+// 是否对新录入的面容启用模板保护
+pub const TEMPLATE_PROTECTION_OPTION: &str = "templateProtection";
+const KEY_FILE: &str = "template.key";
+const SECRET_LEN: usize = 32;
+// 加密面容特征文件时附加的熵，其他程序直接调用 DPAPI 无法解密
+const FILE_ENTROPY: &[u8] = b"facewinunlock-face-descriptor";
+
+lazy_static! {
+    // 已解密的密钥，进程内只读取一次
+    static ref TEMPLATE_KEY: Mutex<Option<Arc<TemplateKey>>> = Mutex::new(None);
+}
+
+pub struct TemplateKey {
+    // 用于判断特征文件是用哪个密钥保护的，不能反推出密钥
+    pub fingerprint: String,
+    // 生成置换的种子
+    seed: [u8; 32],
+}
+
+// SHA-256，使用系统自带的 CNG
+fn sha256(parts: &[&[u8]]) -> Result<[u8; 32], String> {
+    let input: Vec<u8> = parts.concat();
+    let mut output = [0u8; 32];
+    unsafe { BCryptHash(BCRYPT_SHA256_ALG_HANDLE, None, &input, &mut output) }
+        .ok()
+        .map_err(|e| format!("计算 SHA-256 失败：{:?}", e))?;
+    Ok(output)
+}
+
+// xoshiro256**，只用于从种子确定性地生成置换
+struct Xoshiro256([u64; 4]);
+
+impl Xoshiro256 {
+    fn new(seed: &[u8; 32]) -> Self {
+        let mut state = [0u64; 4];
+        for (i, chunk) in seed.chunks_exact(8).enumerate() {
+            state[i] = u64::from_le_bytes(chunk.try_into().unwrap_or_default());
+        }
+        // 全零状态无法产生输出
+        if state == [0; 4] {
+            state[0] = 0x9e3779b97f4a7c15;
+        }
+        Xoshiro256(state)
+    }
+
+    fn next(&mut self) -> u64 {
+        let s = &mut self.0;
+        let result = s[1].wrapping_mul(5).rotate_left(7).wrapping_mul(9);
+        let t = s[1] << 17;
+        s[2] ^= s[0];
+        s[3] ^= s[1];
+        s[1] ^= s[2];
+        s[0] ^= s[3];
+        s[2] ^= t;
+        s[3] = s[3].rotate_left(45);
+        result
+    }
+
+    // [0, n) 内的随机数，n 很小，取模的偏差可以忽略
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+}
+
+impl TemplateKey {
+    pub fn from_secret(secret: &[u8]) -> Result<Self, String> {
+        let fingerprint = sha256(&[b"facewinunlock-template-fingerprint", secret])?;
+        Ok(TemplateKey {
+            fingerprint: fingerprint[..8].iter().map(|b| format!("{:02x}", b)).collect(),
+            seed: sha256(&[b"facewinunlock-template-transform", secret])?,
+        })
+    }
+
+    // 长度为 n 的向量使用的置换和符号，输出第 i 位 = signs[i] * 输入第 permutation[i] 位
+    fn plan(&self, n: usize) -> (Vec<usize>, Vec<f32>) {
+        let mut rng = Xoshiro256::new(&self.seed);
+        let mut permutation: Vec<usize> = (0..n).collect();
+        for i in (1..n).rev() {
+            permutation.swap(i, rng.below(i + 1));
+        }
+        let signs = (0..n)
+            .map(|_| if rng.next() & 1 == 0 { 1.0 } else { -1.0 })
+            .collect();
+        (permutation, signs)
+    }
+
+    // 对特征向量做变换
+    pub fn apply(&self, sample: &[f32]) -> Vec<f32> {
+        let (permutation, signs) = self.plan(sample.len());
+        permutation
+            .iter()
+            .zip(&signs)
+            .map(|(&from, sign)| sign * sample[from])
+            .collect()
+    }
+
+    // 还原变换，调试记录需要保存原始特征
+    pub fn invert(&self, protected: &[f32]) -> Vec<f32> {
+        let (permutation, signs) = self.plan(protected.len());
+        let mut sample = vec![0.0; protected.len()];
+        for (i, &from) in permutation.iter().enumerate() {
+            sample[from] = signs[i] * protected[i];
+        }
+        sample
+    }
+
+    // 对识别模型输出的 1 行特征 Mat 做变换
+    pub fn apply_mat(&self, feature: &Mat) -> Result<Mat, String> {
+        let data = feature
+            .data_typed::<f32>()
+            .map_err(|e| format!("读取特征失败：{}", e))?;
+        let transformed = self.apply(data);
+        Mat::from_slice(&transformed)
+            .and_then(|m| m.reshape(1, 1)?.try_clone())
+            .map_err(|e| format!("转换特征失败：{}", e))
+    }
+}
+
+fn key_path() -> PathBuf {
+    faces_dir().parent().unwrap_or(&ROOT_DIR).join(KEY_FILE)
+}
+
+fn blob(data: &[u8]) -> CRYPT_INTEGER_BLOB {
+    CRYPT_INTEGER_BLOB { cbData: data.len() as u32, pbData: data.as_ptr() as *mut u8 }
+}
+
+// 取出 DPAPI 返回的数据并释放
+fn take_blob(out: CRYPT_INTEGER_BLOB) -> Vec<u8> {
+    let data = unsafe { std::slice::from_raw_parts(out.pbData, out.cbData as usize) }.to_vec();
+    let _ = unsafe { LocalFree(Some(HLOCAL(out.pbData as *mut _))) };
+    data
+}
+
+fn protect_secret(secret: &[u8]) -> Result<Vec<u8>, String> {
+    let mut out = CRYPT_INTEGER_BLOB::default();
+    unsafe {
+        CryptProtectData(&blob(secret), PCWSTR::null(), None, None, None, CRYPTPROTECT_UI_FORBIDDEN, &mut out)
+    }
+    .map_err(|e| format!("加密模板密钥失败：{:?}", e))?;
+    Ok(take_blob(out))
+}
+
+fn unprotect_secret(encrypted: &[u8]) -> Result<Vec<u8>, String> {
+    let mut out = CRYPT_INTEGER_BLOB::default();
+    unsafe {
+        CryptUnprotectData(&blob(encrypted), None, None, None, None, CRYPTPROTECT_UI_FORBIDDEN, &mut out)
+    }
+    .map_err(|e| format!("解密模板密钥失败（可能是在其他电脑或其他用户下）：{:?}", e))?;
+    Ok(take_blob(out))
+}
+
+// 加密面容特征文件内容，使用本机范围：拷贝到其他电脑后无法解密，同一电脑的其他账户可以读取
+pub fn protect_file_data(data: &[u8]) -> Result<Vec<u8>, String> {
+    let entropy = blob(FILE_ENTROPY);
+    let mut out = CRYPT_INTEGER_BLOB::default();
+    unsafe {
+        CryptProtectData(
+            &blob(data),
+            PCWSTR::null(),
+            Some(&entropy as *const _),
+            None,
+            None,
+            CRYPTPROTECT_LOCAL_MACHINE | CRYPTPROTECT_UI_FORBIDDEN,
+            &mut out,
+        )
+    }
+    .map_err(|e| format!("加密面容特征失败：{:?}", e))?;
+    Ok(take_blob(out))
+}
+
+pub fn unprotect_file_data(encrypted: &[u8]) -> Result<Vec<u8>, String> {
+    let entropy = blob(FILE_ENTROPY);
+    let mut out = CRYPT_INTEGER_BLOB::default();
+    unsafe {
+        CryptUnprotectData(
+            &blob(encrypted),
+            None,
+            Some(&entropy as *const _),
+            None,
+            None,
+            CRYPTPROTECT_UI_FORBIDDEN,
+            &mut out,
+        )
+    }
+    .map_err(|e| {
+        // 加密数据被修改时 DPAPI 返回 ERROR_INVALID_DATA，其他电脑加密的文件通常是密钥错误
+        let reason = if e.code() == ERROR_INVALID_DATA.to_hresult() {
+            "文件已损坏或被篡改"
+        } else {
+            "文件不是在本机加密的，可能是从其他电脑拷贝的"
+        };
+        format!("{}: 面容特征无法解密，{}，请重新录入（{:?}）", FACE_FILE_UNREADABLE, reason, e)
+    })?;
+    Ok(take_blob(out))
+}
+
+// 读取密钥，没有密钥文件时返回 None
+pub fn load_key() -> Result<Option<Arc<TemplateKey>>, String> {
+    let mut cached = TEMPLATE_KEY.lock().map_err(|e| format!("获取模板密钥锁失败 {}", e))?;
+    if let Some(key) = cached.as_ref() {
+        return Ok(Some(key.clone()));
+    }
+    let encrypted = match fs::read(key_path()) {
+        Ok(data) => data,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(format!("读取模板密钥失败：{}", e)),
+    };
+    let secret = unprotect_secret(&encrypted)?;
+    let key = Arc::new(TemplateKey::from_secret(&secret)?);
+    *cached = Some(key.clone());
+    Ok(Some(key))
+}
+
+// 读取密钥，没有时生成一个新的
+pub fn load_or_create_key() -> Result<Arc<TemplateKey>, String> {
+    if let Some(key) = load_key()? {
+        return Ok(key);
+    }
+    let secret: Vec<u8> = [Uuid::new_v4().into_bytes(), Uuid::new_v4().into_bytes()].concat();
+    debug_assert_eq!(secret.len(), SECRET_LEN);
+    let encrypted = protect_secret(&secret)?;
+    let path = key_path();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| format!("创建目录失败：{}", e))?;
+    }
+    with_retry(|| fs::write(&path, &encrypted)).map_err(|e| format!("保存模板密钥失败：{}", e))?;
+
+    let key = Arc::new(TemplateKey::from_secret(&secret)?);
+    info!("已生成模板保护密钥 {}", key.fingerprint);
+    if let Ok(mut cached) = TEMPLATE_KEY.lock() {
+        *cached = Some(key.clone());
+    }
+    Ok(key)
+}
+
+// 匹配该面容时摄像头特征需要使用的密钥，未保护的面容返回 None
+// 密钥丢失或已更换时返回 TemplateKeyMismatch，只能重新录入
+pub fn probe_key(descriptor: &FaceDescriptor) -> Result<Option<Arc<TemplateKey>>, String> {
+    let Some(fingerprint) = descriptor.key_fingerprint.as_deref() else {
+        return Ok(None);
+    };
+    let key = load_key().map_err(|e| format!("{}: {}", TEMPLATE_KEY_MISMATCH, e))?;
+    match key {
+        Some(key) if key.fingerprint == fingerprint => Ok(Some(key)),
+        Some(key) => Err(format!(
+            "{}: 面容 {} 使用密钥 {} 保护，当前密钥为 {}，请重新录入",
+            TEMPLATE_KEY_MISMATCH, descriptor.name, fingerprint, key.fingerprint
+        )),
+        None => Err(format!(
+            "{}: 面容 {} 使用密钥 {} 保护，但模板密钥已丢失，请重新录入",
+            TEMPLATE_KEY_MISMATCH, descriptor.name, fingerprint
+        )),
+    }
+}
+
+// 对未保护的面容做变换，已保护时返回 false
+pub fn protect_descriptor(descriptor: &mut FaceDescriptor) -> Result<bool, String> {
+    if descriptor.key_fingerprint.is_some() {
+        return Ok(false);
+    }
+    let key = load_or_create_key()?;
+    descriptor.samples = descriptor.samples.iter().map(|s| key.apply(s)).collect();
+    descriptor.key_fingerprint = Some(key.fingerprint.clone());
+    Ok(true)
+}
+
+// 调试记录等需要原始特征的地方使用，密钥不可用时返回 None
+pub fn raw_samples(descriptor: &FaceDescriptor) -> Option<Vec<Vec<f32>>> {
+    match probe_key(descriptor) {
+        Ok(None) => Some(descriptor.samples.clone()),
+        Ok(Some(key)) => Some(descriptor.samples.iter().map(|s| key.invert(s)).collect()),
+        Err(e) => {
+            warn!("{}", e);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::face_search::FEATURE_DIM;
+
+    fn random_vector(rng: &mut Xoshiro256, n: usize) -> Vec<f32> {
+        (0..n).map(|_| (rng.next() >> 40) as f32 / (1u64 << 24) as f32 * 2.0 - 1.0).collect()
+    }
+
+    fn cosine(a: &[f32], b: &[f32]) -> f32 {
+        let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+        let norm = |v: &[f32]| v.iter().map(|x| x * x).sum::<f32>().sqrt();
+        dot / (norm(a) * norm(b))
+    }
+
+    fn descriptor(key_fingerprint: Option<String>) -> FaceDescriptor {
+        FaceDescriptor {
+            name: String::from("test"),
+            samples: vec![vec![0.0; FEATURE_DIM]],
+            key_fingerprint,
+            thumbnail: None,
+            model_version: None,
+            created_at: None,
+        }
+    }
+
+    #[test]
+    fn apply_preserves_cosine_similarity() {
+        let key = TemplateKey::from_secret(b"template-test-secret").unwrap();
+        let mut rng = Xoshiro256::new(&[7; 32]);
+        for _ in 0..100 {
+            let a = random_vector(&mut rng, FEATURE_DIM);
+            let b = random_vector(&mut rng, FEATURE_DIM);
+            let (pa, pb) = (key.apply(&a), key.apply(&b));
+            assert!((cosine(&a, &b) - cosine(&pa, &pb)).abs() < 1e-5);
+            assert_ne!(pa, a);
+            assert_eq!(key.invert(&pa), a);
+        }
+    }
+
+    #[test]
+    fn different_keys_give_different_transforms() {
+        let a = TemplateKey::from_secret(b"first secret").unwrap();
+        let b = TemplateKey::from_secret(b"second secret").unwrap();
+        assert_ne!(a.fingerprint, b.fingerprint);
+        assert_eq!(a.fingerprint, TemplateKey::from_secret(b"first secret").unwrap().fingerprint);
+
+        let sample = random_vector(&mut Xoshiro256::new(&[3; 32]), FEATURE_DIM);
+        assert_ne!(a.apply(&sample), b.apply(&sample));
+    }
+
+    #[test]
+    fn probe_key_detects_fingerprint_mismatch() {
+        let key = Arc::new(TemplateKey::from_secret(b"current key").unwrap());
+        *TEMPLATE_KEY.lock().unwrap() = Some(key.clone());
+
+        assert!(probe_key(&descriptor(None)).unwrap().is_none());
+        let matched = probe_key(&descriptor(Some(key.fingerprint.clone()))).unwrap().unwrap();
+        assert_eq!(matched.fingerprint, key.fingerprint);
+
+        let other = TemplateKey::from_secret(b"replaced key").unwrap();
+        let err = probe_key(&descriptor(Some(other.fingerprint.clone()))).unwrap_err();
+        assert!(err.starts_with(TEMPLATE_KEY_MISMATCH), "{}", err);
+        assert!(err.contains(&other.fingerprint) && err.contains(&key.fingerprint), "{}", err);
+        // 密钥不一致时无法还原原始特征
+        assert!(raw_samples(&descriptor(Some(other.fingerprint))).is_none());
+    }
+}
@@ -1,20 +1,21 @@
-use opencv::{objdetect::FaceRecognizerSF_DisType, prelude::FaceRecognizerSFTraitConst};
 use serde::Deserialize;
 use std::{sync::atomic::Ordering, thread::sleep, time::{Duration, SystemTime, UNIX_EPOCH}};
 use tauri_plugin_log::log::{error, info, warn};
 use windows::{core::HSTRING, Win32::{
     Foundation::{HWND, LPARAM, LRESULT, WPARAM},
+    System::{Power::POWERBROADCAST_SETTING, SystemServices::GUID_CONSOLE_DISPLAY_STATE},
     UI::{
         Shell::DefSubclassProc,
         WindowsAndMessaging::{
-            KillTimer, SetTimer, WM_TIMER, WM_WTSSESSION_CHANGE, WTS_SESSION_LOCK,
+            KillTimer, SetTimer, PBT_POWERSETTINGCHANGE, SC_SCREENSAVE, PBT_APMRESUMEAUTOMATIC, PBT_APMSUSPEND, WM_POWERBROADCAST,
+            WM_DISPLAYCHANGE, WM_DPICHANGED, WM_SYSCOMMAND, WM_TIMER, WM_WTSSESSION_CHANGE, WTS_SESSION_LOCK,
             WTS_SESSION_UNLOCK,
         },
     },
 }};
 
 use crate::{
-    modules::faces::{get_feature, load_face_data, read_mat_from_camera}, utils::{api::{open_camera, stop_camera, unlock}, pipe::{read, Client, Server}}, APP_STATE, CAMERA_INDEX, DB_POOL, IS_BREAK_THREAD, IS_LOCKED, IS_RUN, MATCH_FAIL_COUNT, RETRY_DELAY, ROOT_DIR, TIMER_ID_LOCK_CHECK
+    modules::{auto_unlock::{self, AUTO_UNLOCK_STOPPED}, control::is_armed, credential::validate_after_resume, engine::{self, EngineEvent}, face_policy::{match_frame, FaceMatch, MultiFacePolicy, BYSTANDER_DETECTED}, face_watch::cached_face_data, faces::{load_black_frame_config, protect_registration_file, read_mat_from_camera, CAMERA_OBSTRUCTED}, metrics::{self, STAGE_FIRST_DETECTION, STAGE_FIRST_FRAME, STAGE_MATCH}, options::{mark_known_good_if_changed, query_option, read_option}, drift::record_match_score, replay::ReplayRecorder, statistics::apply_score_precision, template::{probe_key, raw_samples, TEMPLATE_PROTECTION_OPTION}}, utils::{api::{emit_event, open_camera, stop_camera, unlock}, db_writer, pipe::{read_frame, Client, Server}, protocol::{decode, Message}, priority::{PriorityGuard, WorkMode}}, window_placement::ensure_on_screen, APP_STATE, BLACK_FRAME_CONFIG, BLACK_FRAME_COUNT, CAMERA_INDEX, DB_POOL, IS_BREAK_THREAD, IS_CAMERA_OBSTRUCTED, IS_CONFERENCE_PAUSED, IS_LOCKED, IS_PRE_WARMED, IS_RUN, IS_SESSION_LOCKED, MATCH_FAIL_COUNT, RETRY_DELAY, TIMER_ID_LOCK_CHECK, TIMER_ID_PREWARM
 };
 
 // 最大成功次数，超过这个次数判断为面容匹配
@@ -22,9 +23,11 @@ const MAX_SUCCESS: usize = 3;
 // 最大失败次数，超过这个次数判断为面容不匹配
 const MAX_FAIL: usize = 3;
 // 最大重试次数，这不能让用户自己输入，如果错误次数太多，微软会锁定账户的，很危险
-const MAX_RETRY: i32 = 3;
+pub(crate) const MAX_RETRY: i32 = 3;
 // 记录上一次发送管道消息的时间戳（毫秒）
 static mut LAST_SEND_TIME: u128 = 0;
+// 预热后等待锁屏的默认时间（秒）
+pub const DEFAULT_PREWARM_TIMEOUT: f32 = 60.0;
 
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")] // 适配 JSON 中的驼峰命名
@@ -38,6 +41,9 @@ pub struct FaceExtraData {
     // 是否锁定面容？为true时不参与判定
     #[serde(default)] // 0.2.0 以下版本的用户没有这一项，默认为false
     pub lock: bool,
+    // 是否因长时间未使用被数据保留策略停用？为true时不参与判定
+    #[serde(default)]
+    pub expired: bool,
     /// 人脸检测置信度阈值
     pub face_detection_threshold: f32,
 }
@@ -80,14 +86,34 @@ pub unsafe extern "system" fn wnd_proc_subclass(
 ) -> LRESULT {
     if msg == WM_WTSSESSION_CHANGE {
         let event_type = wparam.0 as u32;
-        let _session_id = lparam.0 as u32;
+        let session_id = lparam.0 as u32;
 
         match event_type {
             WTS_SESSION_LOCK => {
+                // 开始新的一轮锁屏，先同步启用状态
+                engine::sync_armed(is_armed());
+                engine::fire(EngineEvent::SessionLocked);
                 // 重置尝试次数
                 MATCH_FAIL_COUNT.store(0, Ordering::SeqCst);
-                // 屏幕锁屏，关闭摄像头，因为不确定用户是否开启了摄像头
-                if let Err(e) = stop_camera() {
+                // 重置遮挡状态，并重新读取黑帧阈值
+                BLACK_FRAME_COUNT.store(0, Ordering::SeqCst);
+                IS_CAMERA_OBSTRUCTED.store(false, Ordering::SeqCst);
+                load_black_frame_config();
+                // 开始记录本次锁屏到解锁的耗时
+                metrics::begin_trace();
+                IS_SESSION_LOCKED.store(true, Ordering::SeqCst);
+                // 通知界面，用于显示连续识别的状态、停止摄像头预览
+                emit_event("session-locked", serde_json::json!({"session_id": session_id}));
+                // 已经预热的摄像头是自己打开的，直接沿用，预热计时器到期时如果没在识别会自动释放
+                let camera_result = if IS_PRE_WARMED.load(Ordering::SeqCst) {
+                    metrics::set_pre_warmed();
+                    info!("摄像头已预热，沿用已打开的摄像头");
+                    Ok(())
+                } else {
+                    // 屏幕锁屏，关闭摄像头，因为不确定用户是否开启了摄像头
+                    stop_camera().map(|_| ())
+                };
+                if let Err(e) = camera_result {
                     error!("关闭摄像头失败: {}", e.to_string());
                 } else {
                     // 摄像头处于关闭状态，可以进行面容识别
@@ -202,8 +228,15 @@ pub unsafe extern "system" fn wnd_proc_subclass(
                                                                     break; 
                                                                 }
                                                                 // 等待管道的run命令
-                                                                if let Ok(content) =  read(server.handle) {
-                                                                    if content.contains("run") && !IS_RUN.load(Ordering::SeqCst) && MATCH_FAIL_COUNT.load(Ordering::SeqCst) < MAX_RETRY {
+                                                                if let Ok(frame) = read_frame(server.handle) {
+                                                                    let message = match decode(&frame) {
+                                                                        Ok(message) => message,
+                                                                        Err(e) => {
+                                                                            warn!("收到无法解析的管道消息: {}", e);
+                                                                            continue;
+                                                                        }
+                                                                    };
+                                                                    if message == Message::Run && !IS_RUN.load(Ordering::SeqCst) && !IS_CAMERA_OBSTRUCTED.load(Ordering::SeqCst) && MATCH_FAIL_COUNT.load(Ordering::SeqCst) < MAX_RETRY {
                                                                         if can_retry() {
                                                                             info!("运行面容识别代码");
                                                                             run_before();
@@ -278,6 +311,16 @@ pub unsafe extern "system" fn wnd_proc_subclass(
                 // println!("[会话{}] 屏幕已锁屏", session_id);
             }
             WTS_SESSION_UNLOCK => {
+                // 记录解锁耗时
+                metrics::finish_trace();
+                IS_SESSION_LOCKED.store(false, Ordering::SeqCst);
+                emit_event("session-unlocked", serde_json::json!({"session_id": session_id}));
+                // 已解锁，不再需要预热的摄像头
+                unsafe {
+                    let _ = KillTimer(Some(hwnd), TIMER_ID_PREWARM);
+                };
+                release_pre_warm();
+                engine::fire(EngineEvent::SessionUnlocked);
                 // 终止线程
                 if !IS_BREAK_THREAD.load(Ordering::SeqCst) {
                     IS_BREAK_THREAD.store(true, Ordering::SeqCst);
@@ -305,27 +348,177 @@ pub unsafe extern "system" fn wnd_proc_subclass(
             if IS_LOCKED.load(Ordering::SeqCst) {
                 run_before();
             }
+        } else if wparam.0 == TIMER_ID_PREWARM {
+            unsafe {
+                let _ = KillTimer(Some(hwnd), TIMER_ID_PREWARM);
+            };
+            release_pre_warm();
+        }
+    } else if msg == WM_SYSCOMMAND {
+        // 低4位系统保留，需要屏蔽
+        if (wparam.0 & 0xFFF0) as u32 == SC_SCREENSAVE {
+            pre_warm(hwnd);
+        }
+    } else if msg == WM_POWERBROADCAST {
+        if wparam.0 as u32 == PBT_APMSUSPEND {
+            // 即将睡眠，释放预热的摄像头
+            unsafe {
+                let _ = KillTimer(Some(hwnd), TIMER_ID_PREWARM);
+            };
+            release_pre_warm();
+            engine::fire(EngineEvent::Suspended);
+        } else if wparam.0 as u32 == PBT_APMRESUMEAUTOMATIC {
+            // 睡眠期间密码可能在其他设备上被修改
+            validate_after_resume();
+        } else if wparam.0 as u32 == PBT_POWERSETTINGCHANGE && lparam.0 != 0 {
+            let setting = unsafe { &*(lparam.0 as *const POWERBROADCAST_SETTING) };
+            // Data[0]: 0 熄屏 1 亮屏 2 变暗
+            if setting.PowerSetting == GUID_CONSOLE_DISPLAY_STATE && setting.Data[0] == 0 {
+                pre_warm(hwnd);
+            }
         }
+    } else if msg == WM_DPICHANGED {
+        // 窗口移动到其他缩放比例的显示器时，Tauri 按建议矩形调整窗口大小
+        // 调整后再检查一次，建议矩形在显示器边缘时窗口可能跑出工作区
+        let result = DefSubclassProc(hwnd, msg, wparam, lparam);
+        ensure_on_screen(hwnd);
+        return result;
+    } else if msg == WM_DISPLAYCHANGE {
+        // 分辨率变化或显示器断开后，可见的窗口可能位于屏幕外
+        let result = DefSubclassProc(hwnd, msg, wparam, lparam);
+        ensure_on_screen(hwnd);
+        return result;
     }
     DefSubclassProc(hwnd, msg, wparam, lparam)
 }
 
-fn run_before() {
+// 屏保启动/熄屏通常意味着即将锁屏，提前打开摄像头，让摄像头完成自动曝光
+// 这里只打开摄像头，不会发送任何凭据
+// DLL 的管道只在锁屏界面加载后才存在，所以管道无法提前连接
+fn pre_warm(hwnd: HWND) {
+    if IS_SESSION_LOCKED.load(Ordering::SeqCst) || IS_PRE_WARMED.load(Ordering::SeqCst) {
+        return;
+    }
+    // 会议/录屏期间不抢占摄像头，停用面容解锁时也不需要预热
+    if IS_CONFERENCE_PAUSED.load(Ordering::SeqCst) || !is_armed() {
+        return;
+    }
+    if read_option("is_initialized").as_deref() != Some("true")
+        || read_option("preWarm").as_deref() == Some("false")
+    {
+        return;
+    }
+    // 摄像头被界面占用时不预热，避免超时后关闭用户正在使用的摄像头
+    match APP_STATE.try_lock() {
+        Ok(app_state) if app_state.camera.is_none() => {}
+        _ => return,
+    }
+
+    let camera_index: i32 = read_option("camera")
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(0);
+    CAMERA_INDEX.store(camera_index, Ordering::SeqCst);
+    let timeout: f32 = read_option("preWarmTimeout")
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_PREWARM_TIMEOUT);
+
+    if engine::fire(EngineEvent::PreWarmStarted).is_none() {
+        return;
+    }
+    IS_PRE_WARMED.store(true, Ordering::SeqCst);
+    unsafe {
+        SetTimer(
+            Some(hwnd),
+            TIMER_ID_PREWARM,
+            (timeout * 1000.0) as u32,
+            None,
+        )
+    };
+
+    // 打开摄像头比较耗时，不能阻塞窗口消息
+    std::thread::spawn(move || {
+        let _priority = PriorityGuard::new(WorkMode::Background);
+        if let Err(e) = open_camera(None, Some(camera_index)) {
+            warn!("预热摄像头失败: {}", e.msg);
+            IS_PRE_WARMED.store(false, Ordering::SeqCst);
+            engine::fire(EngineEvent::PreWarmReleased);
+        } else {
+            info!("检测到屏保/熄屏，摄像头已预热");
+        }
+    });
+}
+
+// 预热超时，没有正在进行的识别则释放摄像头
+fn release_pre_warm() {
+    if !IS_PRE_WARMED.swap(false, Ordering::SeqCst) {
+        return;
+    }
+    if IS_RUN.load(Ordering::SeqCst) {
+        // 正在识别，识别结束时会关闭摄像头
+        return;
+    }
+    if let Err(e) = stop_camera() {
+        warn!("释放预热摄像头失败: {}", e.msg);
+    } else {
+        info!("预热超时，摄像头已释放");
+    }
+    engine::fire(EngineEvent::PreWarmReleased);
+}
+
+// 下一次允许重试的时间（Unix 毫秒）
+fn retry_until() -> u64 {
+    engine::now_millis() + RETRY_DELAY.load(Ordering::SeqCst).max(0) as u64
+}
+
+pub(crate) fn run_before() {
+    // 面容解锁已停用（例如脚本通过控制管道停用）
+    if !is_armed() {
+        info!("面容解锁已停用，跳过本次识别");
+        return;
+    }
+    // 本次锁屏已经结束（解锁成功、被遮挡、失败次数用完）时不再识别
+    if engine::fire(EngineEvent::AttemptStarted).is_none() {
+        return;
+    }
+    // 锁屏解锁对延迟敏感，提高优先级
+    let _priority = PriorityGuard::new(WorkMode::LockScreen);
     // 先打开摄像头
-    let result = open_camera(None, CAMERA_INDEX.load(Ordering::SeqCst));
+    let result = open_camera(None, Some(CAMERA_INDEX.load(Ordering::SeqCst)));
     if let Err(e) = result {
         error!("打开摄像头失败 {}", e.msg);
+        engine::fire(EngineEvent::AttemptErrored { until: retry_until() });
     } else {
         // 摄像头成功打开
         IS_RUN.store(true, Ordering::SeqCst);
-        if let Err(e) = run() {
-            error!("运行面容解锁失败: {:?}", e);
+        BLACK_FRAME_COUNT.store(0, Ordering::SeqCst);
+        let event = match run() {
+            Ok(true) => EngineEvent::MatchSucceeded,
+            Ok(false) => EngineEvent::MatchFailed {
+                failures: MATCH_FAIL_COUNT.load(Ordering::SeqCst).max(0) as u32,
+                until: retry_until(),
+            },
+            Err(e) if e.contains(CAMERA_OBSTRUCTED) => {
+                // 摄像头被遮挡，本次锁屏期间不再尝试，也不计入失败次数
+                IS_CAMERA_OBSTRUCTED.store(true, Ordering::SeqCst);
+                warn!("摄像头被遮挡，本次锁屏期间停止面容识别: {}", e);
+                EngineEvent::CameraObstructed
+            }
+            Err(e) if e.starts_with(AUTO_UNLOCK_STOPPED) => {
+                info!("连续识别已停止，结束本次识别");
+                EngineEvent::AttemptErrored { until: retry_until() }
+            }
+            Err(e) => {
+                error!("运行面容解锁失败: {:?}", e);
+                EngineEvent::AttemptErrored { until: retry_until() }
+            }
         };
 
         if let Err(e) = stop_camera() {
             error!("停止摄像头失败: {}", e.msg);
         };
         IS_RUN.store(false, Ordering::SeqCst);
+        // 摄像头关闭后再离开 Attempting 状态
+        engine::fire(event);
     }
 }
 
@@ -346,7 +539,6 @@ fn run() -> Result<bool, String> {
                     // 读取基础字段
                     let id = row.get::<&str, i32>("id")?;
                     let user_name = row.get::<&str, String>("user_name")?;
-                    let user_pwd = row.get::<&str, String>("user_pwd")?;
                     let account_type = row.get::<&str, String>("account_type")?;
                     let face_token = row.get::<&str, String>("face_token")?;
                     let json_data_str = row.get::<&str, String>("json_data")?;
@@ -360,7 +552,6 @@ fn run() -> Result<bool, String> {
                     Ok((
                         id,
                         user_name,
-                        user_pwd,
                         account_type,
                         face_token,
                         json_data,
@@ -369,52 +560,99 @@ fn run() -> Result<bool, String> {
                 })
                 .map_err(|e| format!("查询面容数据失败：{:?}", e))?;
 
+            // 本次所有面容中的最高分数，解锁失败时记录
+            let mut best_match: Option<FaceMatch> = None;
+            // 画面中有多张人脸时的匹配策略
+            let policy = MultiFacePolicy::from_option(query_option(&conn, "multiFacePolicy"));
+            // 说明模式下记录每个样本的分数，用于找出表现差的样本，平时只记录最高分的样本
+            let explain = query_option(&conn, "matchExplain").as_deref() == Some("true");
+            // 调试记录模式下保存失败的识别，用于离线回放
+            let black_frame = BLACK_FRAME_CONFIG.lock().map(|c| c.clone()).unwrap_or_default();
+            let mut recorder = ReplayRecorder::start(&conn, policy, MAX_SUCCESS, MAX_FAIL, black_frame);
+            let mut registrations: Vec<serde_json::Value> = Vec::new();
             for row in rows {
                 let (
                     id,
                     user_name,
-                    user_pwd,
                     account_type,
-                    mut face_token,
+                    face_token,
                     json_data,
                     _create_time,
                 ) = row.map_err(|e| format!("获取1条面容数据失败：{:?}", e))?;
 
-                if json_data.lock {
-                    // 锁定了账户，直接跳过
+                if json_data.lock || json_data.expired {
+                    // 锁定了账户或已过期停用，直接跳过
                     continue;
                 }
                 
-                // 加载数据
-                face_token.push_str(".face");
-                let path = ROOT_DIR.join("faces").join(face_token);
-                // 解析面容数据
-                let face = load_face_data(&path);
-                if face.is_err() {
-                    error!("加载面容数据失败：{:?}", path);
+                // 解析面容数据，面容目录中放入的文件导入后缓存会自动更新
+                let face = cached_face_data(&face_token);
+                if let Err(e) = &face {
+                    error!("加载面容 {} 失败：{}", face_token, e);
                     continue;
                 }
 
                 let face = face.unwrap();
                 // 参考面容转换失败，跳过当前用户
-                let dst_feature = face.to_mat();
+                let dst_feature = face.to_mats();
                 if dst_feature.is_err() {
                     error!("{}, 转换参考面容数据失败：{:?}", json_data.alias, path);
                     continue;
                 }
                 let dst_feature = dst_feature.unwrap();
+                // 启用了模板保护的面容，摄像头特征需要用同一个密钥变换；密钥不一致时只能重新录入
+                let face_key = match probe_key(&face) {
+                    Ok(key) => key,
+                    Err(e) => {
+                        error!("{}", e);
+                        emit_event("template-key-mismatch", serde_json::json!({"face_id": id, "alias": json_data.alias, "message": e}));
+                        continue;
+                    }
+                };
 
-                let mut success_count = 0;
-                let mut fail_count = 0;
+                let mut counter = MatchCounter::new(MAX_SUCCESS, MAX_FAIL);
+                // 当前面容各帧中分数最高的一次匹配
+                let mut registration_best: Option<FaceMatch> = None;
+                if let Some(recorder) = recorder.as_mut() {
+                    // 回放需要原始特征，密钥可用时还原
+                    let samples = raw_samples(&face).unwrap_or_default();
+                    recorder.begin_registration(id, &file_name, &json_data.alias, json_data.threshold, json_data.face_detection_threshold, &samples);
+                }
 
                 loop {
+                    // 连续识别被停止或超时
+                    if auto_unlock::should_stop() {
+                        return Err(String::from(AUTO_UNLOCK_STOPPED));
+                    }
                     // 读取一帧，摄像头的操作一旦失败，必须退出函数
                     let frame =
                         read_mat_from_camera().map_err(|e| format!("摄像头读取失败: {}", e))?;
-                    // 提取特征点
-                    let cur_feature = match get_feature(&frame, json_data.face_detection_threshold)
+                    metrics::mark(STAGE_FIRST_FRAME);
+                    if let Some(recorder) = recorder.as_mut() {
+                        recorder.push_frame(&frame);
+                    }
+                    // 按多人脸策略提取特征并匹配
+                    let matched = match match_frame(&frame, &dst_feature, face_key.as_deref(), json_data.face_detection_threshold, policy, explain)
                     {
-                        Ok(feature) => feature,
+                        Ok(matched) => {
+                            metrics::mark(STAGE_FIRST_DETECTION);
+                            matched
+                        }
+                        Err(e) if e.starts_with(BYSTANDER_DETECTED) => {
+                            // 身后有其他人，本次直接按失败处理并通知用户
+                            warn!("{}", e);
+                            if let Err(e) = unlock(String::from("null"), Some(String::from("null"))) {
+                                return Err(format!("调用解锁函数失败：{}", e));
+                            }
+                            if let Err(e) = db_writer::write(move |tx| insert_unlock_log(tx, id, false, None, Some("bystander_detected"), MatchAudit::policy(policy))) {
+                                warn!("插入解锁日志失败：{}", e);
+                            };
+                            if let Some(recorder) = recorder.take() {
+                                recorder.save("bystander_detected");
+                            }
+                            MATCH_FAIL_COUNT.fetch_add(1, Ordering::SeqCst);
+                            return Ok(false);
+                        }
                         Err(e) => {
                             let err_msg = format!("特征提取失败: {}", e);
                             if err_msg.contains("未检测到人脸") {
@@ -427,65 +665,101 @@ fn run() -> Result<bool, String> {
                             }
                         }
                     };
-
-                    let score = {
-                        // 必须实时获取，否则会死锁
-                        let app_state = APP_STATE
-                            .lock()
-                            .map_err(|e| format!("获取app状态失败 {}", e))?;
-
-                        let Some(recognizer) = app_state.recognizer.as_ref() else {
-                            return Err(String::from("人脸识别模型未初始化"));
+                    let score = matched.score;
+                    // 模型输出异常时分数可能是 NaN，任何比较都不可信，直接按失败处理
+                    if !score.is_finite() {
+                        let audit = MatchAudit::from_match(&matched);
+                        if let Err(e) = db_writer::write(move |tx| insert_unlock_log(tx, id, false, None, Some("invalid_score"), audit)) {
+                            warn!("插入解锁日志失败：{}", e);
                         };
+                        if let Some(recorder) = recorder.take() {
+                            recorder.save("invalid_score");
+                        }
+                        return Err(format!("匹配分数无效（{}），模型可能已损坏", score));
+                    }
+                    if registration_best.as_ref().map_or(true, |best| score > best.score) {
+                        registration_best = Some(matched.clone());
+                    }
+                    if best_match.as_ref().map_or(true, |best| score > best.score) {
+                        best_match = Some(matched.clone());
+                    }
 
-                        recognizer
-                            .inner
-                            .match_(
-                                &dst_feature,
-                                &cur_feature,
-                                FaceRecognizerSF_DisType::FR_COSINE.into(),
-                            )
-                            .map_err(|e| format!("特征匹配失败: {}", e))?
-                    };
-
-                    if score * 100.0 >= json_data.threshold.into() {
-                        // 匹配成功，次数+1
-                        success_count += 1;
-                        if success_count >= MAX_SUCCESS {
-                            // 大于3次，算面容匹配成功
+                    match counter.record(score, json_data.threshold) {
+                        MatchStep::Matched(score) => {
+                            // 连续成功达到次数，算面容匹配成功
+                            metrics::mark(STAGE_MATCH);
                             let user_name = if account_type == "local" {
                                 format!(".\\{}", user_name)
                             } else {
                                 user_name
                             };
 
-                            if let Err(e) = unlock(user_name, user_pwd) {
-                                return Err(format!("调用解锁函数失败：{}", e));
-                            } else {
-                                if let Err(e) = insert_unlock_log(&conn, id, true) {
-                                    warn!("插入解锁日志失败：{}", e);
+                            // 只有引擎处于识别状态时才发送凭据，例如识别期间用户已经手动解锁
+                            engine::ensure_attempting()?;
+                            // 密码从凭据管理器读取
+                            match unlock(user_name, None) {
+                                Err(e) => return Err(format!("调用解锁函数失败：{}", e)),
+                                // 凭据已发送，DLL 没有确认时只记录，旧版本的 DLL 不回复
+                                Ok(reply) if !reply.succeeded() => warn!("DLL 没有确认收到解锁凭据：{:?}", reply),
+                                Ok(_) => {}
+                            }
+                            // 本次识别的日志、分数统计、配置快照在同一个事务中写入
+                            let threshold = json_data.threshold as f64 / 100.0;
+                            let audit = MatchAudit::from_match(&matched);
+                            let face_token = file_name.clone();
+                            let result = db_writer::write(move |tx| {
+                                insert_unlock_log(tx, id, true, Some(score), None, audit)?;
+                                // 记录匹配分数，分数持续下降时提示重新录入
+                                if let Err(e) = record_match_score(tx, &file_name, score, threshold) {
+                                    warn!("记录匹配分数失败：{}", e);
+                                };
+                                // 修改设置后首次解锁成功，保存为可用配置
+                                if let Err(e) = mark_known_good_if_changed(tx) {
+                                    warn!("保存可用配置失败：{}", e);
                                 };
-                                return Ok(true);
+                                Ok(())
+                            });
+                            if let Err(e) = result {
+                                warn!("插入解锁日志失败：{}", e);
+                            };
+                            // 开启模板保护后，旧的面容在成功解锁后转换，不需要重新录入
+                            if query_option(&conn, TEMPLATE_PROTECTION_OPTION).as_deref() == Some("true") {
+                                if let Err(e) = protect_registration_file(&face_token) {
+                                    warn!("转换面容 {} 为受保护的模板失败：{}", face_token, e);
+                                }
                             }
+                            return Ok(true);
                         }
-                    } else {
-                        success_count = 0;
-                        fail_count += 1;
-                        if fail_count >= MAX_FAIL {
-                            break;
-                        }
+                        MatchStep::GiveUp => break,
+                        MatchStep::Continue => {}
                     }
 
                     sleep(Duration::from_millis(50));
                 }
+                if let Some(best) = registration_best.filter(|_| explain) {
+                    registrations.push(serde_json::json!({
+                        "face_id": id,
+                        "score": best.score,
+                        "sample_index": best.sample_index,
+                        "sample_scores": best.sample_scores,
+                    }));
+                }
             }
             // 发个假的用户名密码，通知用户解锁失败
-            if let Err(e) = unlock(String::from("null"), String::from("null")) {
+            if let Err(e) = unlock(String::from("null"), Some(String::from("null"))) {
                 return Err(format!("调用解锁函数失败：{}", e));
             }
-            if let Err(e) = insert_unlock_log(&conn, -1, false) {
+            let best_score = best_match.as_ref().map(|m| m.score);
+            let audit = MatchAudit {
+                detail: explain.then(|| serde_json::json!({"registrations": registrations}).to_string()),
+                ..best_match.as_ref().map_or(MatchAudit::policy(policy), MatchAudit::from_match)
+            };
+            if let Err(e) = db_writer::write(move |tx| insert_unlock_log(tx, -1, false, best_score, None, audit)) {
                 warn!("插入解锁日志失败：{}", e);
             };
+            if let Some(recorder) = recorder.take() {
+                recorder.save("no_match");
+            }
             // 匹配失败，次数+1
             let now_count = MATCH_FAIL_COUNT.load(Ordering::SeqCst);
             MATCH_FAIL_COUNT.store(now_count + 1, Ordering::SeqCst);
@@ -498,24 +772,114 @@ fn run() -> Result<bool, String> {
     }
 }
 
-// 插入解锁日志到数据库
+// 连续匹配的计数，锁屏解锁和调试回放使用同一套判定
+pub(crate) struct MatchCounter {
+    max_success: usize,
+    max_fail: usize,
+    success_count: usize,
+    fail_count: usize,
+    // 连续匹配成功时的分数总和，用于统计分数变化
+    success_score_sum: f64,
+}
+
+pub(crate) enum MatchStep {
+    // 连续成功达到次数，附带这几次的平均分数
+    Matched(f64),
+    Continue,
+    // 失败次数达到上限，换下一个面容
+    GiveUp,
+}
+
+impl MatchCounter {
+    pub(crate) fn new(max_success: usize, max_fail: usize) -> Self {
+        Self { max_success, max_fail, success_count: 0, fail_count: 0, success_score_sum: 0.0 }
+    }
+
+    // 记录一帧的分数，threshold 为面容的置信度阈值（0-100）
+    pub(crate) fn record(&mut self, score: f64, threshold: f32) -> MatchStep {
+        if score * 100.0 >= threshold as f64 {
+            self.success_count += 1;
+            self.success_score_sum += score;
+            if self.success_count >= self.max_success {
+                return MatchStep::Matched(self.success_score_sum / self.success_count as f64);
+            }
+        } else {
+            // 成功必须是连续的
+            self.success_count = 0;
+            self.success_score_sum = 0.0;
+            self.fail_count += 1;
+            if self.fail_count >= self.max_fail {
+                return MatchStep::GiveUp;
+            }
+        }
+        MatchStep::Continue
+    }
+}
+
+// 解锁日志中记录的匹配信息
+#[derive(Default)]
+struct MatchAudit {
+    policy: Option<MultiFacePolicy>,
+    face_index: Option<usize>,
+    sample_index: Option<usize>,
+    // 说明模式下的样本分数明细（JSON）
+    detail: Option<String>,
+}
+
+impl MatchAudit {
+    fn policy(policy: MultiFacePolicy) -> Self {
+        Self { policy: Some(policy), ..Default::default() }
+    }
+
+    fn from_match(matched: &FaceMatch) -> Self {
+        Self {
+            policy: Some(matched.policy),
+            face_index: Some(matched.face_index),
+            sample_index: Some(matched.sample_index),
+            detail: matched.sample_scores.as_ref().map(|scores| {
+                serde_json::json!({"sample_index": matched.sample_index, "sample_scores": scores}).to_string()
+            }),
+        }
+    }
+}
+
+// 插入解锁日志到数据库，在写入线程的事务中调用
 // 为了统一，这里其实应该前端添加数据，可以实现rust只读，前端读写，并实现响应式数据的同步更新
 // 但是需要包装一个全局变量，存储app，然后向前端发送通知，这里我懒得做了，所以直接后端插入数据了，前端不更新
 fn insert_unlock_log(
     conn: &r2d2_sqlite::rusqlite::Connection,
     face_id: i32,
     is_unlock: bool,
+    score: Option<f64>,
+    note: Option<&'static str>,
+    audit: MatchAudit,
 ) -> Result<(), String> {
     let mut insert_stmt = conn
-        .prepare("INSERT INTO unlock_log (face_id, is_unlock) VALUES (?1, ?2)")
+        .prepare(
+            "INSERT INTO unlock_log (face_id, is_unlock, latency, score, note, face_policy, face_index, sample_index, match_detail) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        )
         .map_err(|e| format!("准备插入解锁日志语句失败：{:?}", e))?;
 
-    // 插入数据
+    // 插入数据，附带目前为止的耗时明细，解锁事件到达后再补全
     insert_stmt
         .execute(r2d2_sqlite::rusqlite::params![
             face_id,
-            if is_unlock { 1 } else { 0 }
+            if is_unlock { 1 } else { 0 },
+            metrics::current_breakdown(),
+            score,
+            note,
+            audit.policy.map(|p| p.as_str()),
+            audit.face_index.map(|i| i as i64),
+            audit.sample_index.map(|i| i as i64),
+            audit.detail
         ])
         .map_err(|e| format!("插入解锁日志失败：{:?}", e))?;
+    metrics::attach_log_id(conn.last_insert_rowid());
+
+    // 低精度模式下，超出最近 N 条的记录降低精度
+    if let Err(e) = apply_score_precision(conn) {
+        warn!("降低解锁日志精度失败：{}", e);
+    }
     Ok(())
 }
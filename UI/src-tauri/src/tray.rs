@@ -12,9 +12,28 @@ use windows::Win32::{
     UI::Shell::{SHAppBarMessage, ABM_GETTASKBARPOS, APPBARDATA},
 };
 
+use crate::window_placement::show_main_window;
 use crate::TRAY_IS_READY;
 use crate::{utils::api::close_app, GLOBAL_TRAY};
 
+// 托盘默认提示文字
+const TRAY_TOOLTIP: &str = "facewinunlock-tauri";
+
+/// 修改托盘提示文字，传入 None 恢复默认
+pub fn set_tray_tooltip(status: Option<&str>) {
+    if let Ok(guard) = GLOBAL_TRAY.lock() {
+        if let Some(tray) = guard.as_ref() {
+            let tooltip = match status {
+                Some(status) => format!("{}（{}）", TRAY_TOOLTIP, status),
+                None => TRAY_TOOLTIP.to_string(),
+            };
+            if let Err(e) = tray.set_tooltip(Some(tooltip)) {
+                warn!("修改托盘提示失败：{}", e);
+            }
+        }
+    }
+}
+
 /// 检测 Windows 托盘（任务栏）服务是否就绪
 fn is_tray_service_ready() -> bool {
     unsafe {
@@ -37,21 +56,20 @@ fn try_create_tray(app: &AppHandle<Wry>) -> Result<Arc<TrayIcon<Wry>>, Box<dyn s
     let menu = create_tray_menu(app)?;
     let tray = Arc::new(
         TrayIconBuilder::new()
-            .icon(app.default_window_icon().unwrap().clone())
+            .icon(app.default_window_icon().ok_or("缺少默认图标")?.clone())
             .menu(&menu)
             .show_menu_on_left_click(false)
-            .tooltip("facewinunlock-tauri")
+            .tooltip(TRAY_TOOLTIP)
             .build(app)?,
     );
 
     // 绑定托盘事件
-    let window = app.get_webview_window("main").unwrap().clone();
+    let window = app.get_webview_window("main").ok_or("找不到主窗口")?.clone();
     let tray_clone = tray.clone();
     
     tray.on_menu_event(move |app, event| match event.id.as_ref() {
         "show-window" => {
-            let _ = window.show();
-            let _ = window.set_focus();
+            show_main_window(&window);
         }
         "quit" => {
             let _ = close_app(app.clone());
@@ -69,8 +87,7 @@ fn try_create_tray(app: &AppHandle<Wry>) -> Result<Arc<TrayIcon<Wry>>, Box<dyn s
         } => {
             let app = tray.app_handle();
             if let Some(window) = app.get_webview_window("main") {
-                let _ = window.show();
-                let _ = window.set_focus();
+                show_main_window(&window);
             }
         }
         _ => {}
@@ -1,8 +1,28 @@
-use std::{os::windows::process::CommandExt, process::Command};
-
-use crate::{utils::custom_result::CustomResult, OpenCVResource, APP_STATE, DB_POOL, GLOBAL_TRAY, ROOT_DIR};
+use std::{os::windows::process::CommandExt, path::{Path, PathBuf}, process::Command, time::Duration};
+
+use crate::{
+    modules::{
+        calibration::activate_calibration,
+        credential::read_vault_password,
+        capabilities::{
+            with_capability_hint, CAP_FACE_DETECTOR, CAP_FACE_RECOGNIZER, CAP_VIDEOIO_DSHOW,
+            CAP_VIDEOIO_MSMF,
+        },
+        face_watch::cached_faces,
+        faces::{
+            is_current_model, recognizer_model_version, set_recognizer_model_version, MatchConfig,
+        },
+        model_check::run_sanity_check,
+        metrics::{self, STAGE_CREDENTIALS_ACKNOWLEDGED, STAGE_PIPE_CONNECTED},
+        options::{read_option, reload_backend_options, save_option},
+        supervisor::{is_supervised, mark_quit_requested, EXIT_CLEAN, EXIT_RESTART},
+    },
+    utils::custom_result::CustomResult,
+    AppPhase, AppState, OpenCVResource, APP_HANDLE, APP_STATE, CAMERA_INDEX, DB_POOL, GLOBAL_TRAY,
+    IS_RUN, ROOT_DIR,
+};
 use opencv::{
-    core::{Mat, MatTraitConst, Size},
+    core::{Mat, MatTraitConst, Ptr, Size},
     objdetect::{FaceDetectorYN, FaceRecognizerSF},
     videoio::{self, VideoCapture, VideoCaptureTrait, VideoCaptureTraitConst},
 };
@@ -10,8 +30,9 @@ use r2d2::Pool;
 use r2d2_sqlite::rusqlite;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
-use tauri::{AppHandle, Manager};
+use tauri::{AppHandle, Emitter, Manager};
 use tauri_plugin_log::log::{error, info, warn};
+use zeroize::Zeroize;
 use windows::{
     core::{BSTR, HSTRING, PWSTR},
     Win32::{
@@ -29,12 +50,19 @@ use windows::{
     },
 };
 
-use super::pipe::Client;
+use super::{
+    db_writer,
+    pipe::{read_frame_timeout, write_frame, Client},
+    priority::current_mode,
+    protocol::{decode, encode, encode_legacy, Message},
+};
 
 #[derive(Debug, Clone, Serialize)]
 struct ValidCameraInfo {
     camera_name: String,
     capture_index: String,
+    // 设备路径，用于保存摄像头校准，序号会随插拔变化
+    device_id: Option<String>,
     is_valid: bool,
 }
 
@@ -58,88 +86,252 @@ impl From<CameraBackend> for i32 {
     }
 }
 
+// 命令在 setup 完成前或退出过程中被调用时的错误标识
+pub const NOT_READY: &str = "NotReady";
+
+// 软件未就绪时的返回值，前端可以根据 retryable 稍后重试
+pub fn not_ready(phase: AppPhase) -> CustomResult {
+    CustomResult::error(
+        Some(format!("{}: 软件尚未就绪（{:?}），请稍后重试", NOT_READY, phase)),
+        Some(json!({"condition": NOT_READY, "phase": phase, "retryable": true})),
+    )
+}
+
+// 检查软件是否已经完成启动，命令在开头调用，未就绪时返回可重试的错误
+// 以下命令不检查运行阶段，在 Starting/ShuttingDown 阶段也可以安全调用：
+//   退出和查询阶段：get_app_phase、close_app、restart_app
+//   只释放资源或修改标记：stop_camera、stop_preview、stop_auto_unlock、stop_continuous_verify、unload_models
+//   只读取内存中的状态：get_pause_status、get_unlock_engine_trace、get_pipeline_priority、get_faces_dir、get_migration_status
+//   只查询或修改系统设置，不依赖模型、数据库和窗口：get_now_username、get_camera、list_cameras、open_directory、
+//   check_opencv_capabilities、enable_global_autostart、disable_global_autostart、check_global_autostart、
+//   初始化向导使用的 check_admin_privileges、check_camera_status、deploy_core_components、uninstall_init、write_to_registry
+// 新增的命令默认需要调用 ensure_ready
+pub fn ensure_ready() -> Result<(), CustomResult> {
+    let app_state = APP_STATE
+        .lock()
+        .map_err(|e| CustomResult::error(Some(format!("获取app状态失败 {}", e)), None))?;
+    app_state.ensure_ready()
+}
+
+// 切换运行阶段，切换到 Ready 时通知前端
+pub fn set_app_phase(phase: AppPhase) {
+    match APP_STATE.lock() {
+        Ok(mut app_state) => app_state.phase = phase,
+        Err(e) => {
+            error!("设置运行阶段失败 {}", e);
+            return;
+        }
+    }
+    info!("软件运行阶段切换为 {:?}", phase);
+    if phase == AppPhase::Ready {
+        emit_event("app-ready", json!({"phase": phase}));
+    }
+}
+
+// 获取当前运行阶段，前端在错过 app-ready 事件时使用
+#[tauri::command]
+pub fn get_app_phase() -> Result<CustomResult, CustomResult> {
+    let app_state = APP_STATE
+        .lock()
+        .map_err(|e| CustomResult::error(Some(format!("获取app状态失败 {}", e)), None))?;
+    Ok(CustomResult::success(
+        None,
+        Some(json!({"phase": app_state.phase})),
+    ))
+}
+
 // 获取当前用户名
 #[tauri::command]
 pub fn get_now_username() -> Result<CustomResult, CustomResult> {
-    // buffer大小，256应该够了
-    let mut buffer = [0u16; 256];
-    let mut size = buffer.len() as u32;
-    unsafe {
-        let succuess = GetUserNameW(Some(PWSTR(buffer.as_mut_ptr())), &mut size);
-        if succuess.is_err() {
-            return Err(CustomResult::error(
-                Some(format!("获取用户名失败: {:?}", succuess.err())),
-                None,
-            ));
-        }
-
-        let name = String::from_utf16_lossy(&buffer[..size as usize - 1]);
-        return Ok(CustomResult::success(None, Some(json!({"username": name}))));
+    // 先查询需要的缓冲区大小（包括结尾的 0），再按大小分配，用户名长度不受限制
+    let mut size = 0u32;
+    let _ = unsafe { GetUserNameW(None, &mut size) };
+    if size == 0 {
+        return Err(CustomResult::error(Some(String::from("获取用户名失败: 无法获取用户名长度")), None));
+    }
+    let mut buffer = vec![0u16; size as usize];
+    unsafe { GetUserNameW(Some(PWSTR(buffer.as_mut_ptr())), &mut size) }.map_err(|e| {
+        CustomResult::error(Some(format!("获取用户名失败: {:?}", e)), None)
+    })?;
+
+    // size 包括结尾的 0，按第一个 0 截断，不依赖 size 的边界
+    let len = buffer.iter().position(|&c| c == 0).unwrap_or(buffer.len());
+    if len == 0 {
+        return Err(CustomResult::error(Some(String::from("获取用户名失败: 用户名为空")), None));
     }
+    let name = String::from_utf16_lossy(&buffer[..len]);
+    Ok(CustomResult::success(None, Some(json!({"username": name}))))
 }
 
 // 测试 WinLogon 是否加载成功
+// 锁屏后异步等待，不占用命令线程；管道读写是同步调用，放到阻塞任务中执行
 #[tauri::command]
-pub fn test_win_logon(user_name: String, password: String) -> Result<CustomResult, CustomResult> {
+pub async fn test_win_logon(user_name: String, password: Option<String>) -> Result<CustomResult, CustomResult> {
+    ensure_ready()?;
     // 锁定屏幕
-    unsafe {
-        let succuess = LockWorkStation();
-        if succuess.is_err() {
-            return Err(CustomResult::error(
-                Some(format!("锁定屏幕失败: {:?}", succuess.err())),
-                None,
-            ));
-        }
+    unsafe { LockWorkStation() }.map_err(|e| {
+        CustomResult::error(Some(format!("锁定屏幕失败: {:?}", e)), None)
+    })?;
+
+    // 等待5秒
+    tokio::time::sleep(Duration::from_secs(5)).await;
+    // 解锁
+    let reply = tauri::async_runtime::spawn_blocking(move || unlock(user_name, password))
+        .await
+        .map_err(|e| CustomResult::error(Some(format!("解锁屏幕失败: {:?}", e)), None))?
+        .map_err(|e| CustomResult::error(Some(format!("解锁屏幕失败: {:?}", e)), None))?;
+    Ok(CustomResult::success(
+        None,
+        Some(json!({"unlock_succeeded": reply.succeeded(), "reply": reply})),
+    ))
+}
+
+// 立即锁定屏幕
+#[tauri::command]
+pub fn lock_now() -> Result<CustomResult, CustomResult> {
+    ensure_ready()?;
+    unsafe { LockWorkStation() }.map_err(|e| {
+        CustomResult::error(Some(format!("锁定屏幕失败: {:?}", e)), None)
+    })?;
+    Ok(CustomResult::success(None, None))
+}
+
+// 设置项：自定义的检测、识别模型文件路径，为空时使用安装目录中的模型
+const DETECTOR_PATH_OPTION: &str = "detectorModelPath";
+const RECOGNIZER_PATH_OPTION: &str = "recognizerModelPath";
+const DEFAULT_DETECTOR_MODEL: &str = "face_detection_yunet_2023mar.onnx";
+const DEFAULT_RECOGNIZER_MODEL: &str = "face_recognition_sface_2021dec.onnx";
+
+// 模型文件路径：设置了自定义路径时使用自定义路径，否则使用安装目录中的模型
+fn model_path(option: &str, default_file: &str) -> PathBuf {
+    read_option(option)
+        .filter(|path| !path.trim().is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(|| ROOT_DIR.join("resources").join(default_file))
+}
 
-        // 等待5秒
-        std::thread::sleep(std::time::Duration::from_secs(5));
-        // 解锁
-        unlock(user_name, password)
-            .map_err(|e| CustomResult::error(Some(format!("解锁屏幕失败: {:?}", e)), None))?;
+// 检查自定义的模型文件，返回规范化的路径；空字符串表示恢复为安装目录中的模型
+fn validate_model_path(path: &str) -> Result<String, CustomResult> {
+    let path = path.trim();
+    if path.is_empty() {
+        return Ok(String::new());
+    }
+    let file = Path::new(path);
+    if !file.is_file() {
+        return Err(CustomResult::error(Some(format!("模型文件不存在：{}", path)), None));
     }
-    return Ok(CustomResult::success(None, None));
+    if file.extension().and_then(|e| e.to_str()).map(|e| e.eq_ignore_ascii_case("onnx")) != Some(true) {
+        return Err(CustomResult::error(Some(format!("模型文件必须是 .onnx 文件：{}", path)), None));
+    }
+    Ok(file.to_string_lossy().to_string())
 }
 
-// 初始化模型
-#[tauri::command]
-pub fn init_model() -> Result<CustomResult, CustomResult> {
-    // 加载模型
-    let mut app_state = APP_STATE
-        .lock()
-        .map_err(|e| CustomResult::error(Some(format!("获取app状态 {}", e)), None))?;
-    if app_state.detector.is_none() {
-        let resource_path = ROOT_DIR
-            .join("resources")
-            .join("face_detection_yunet_2023mar.onnx");
-
-        // 这个不用检查文件是否存在，不存在opencv会报错
-        let detector = FaceDetectorYN::create(
-            resource_path.to_str().unwrap_or(""),
-            "",
-            Size::new(320, 320), // 初始尺寸，后面会动态更新
-            0.9,
-            0.3,
-            5000,
-            0,
-            0,
+// 加载人脸检测模型
+pub fn create_detector() -> Result<Ptr<FaceDetectorYN>, CustomResult> {
+    create_detector_from(&model_path(DETECTOR_PATH_OPTION, DEFAULT_DETECTOR_MODEL))
+}
+
+fn create_detector_from(resource_path: &Path) -> Result<Ptr<FaceDetectorYN>, CustomResult> {
+
+    // 先使用默认阈值，init_model 读取设置后再更新
+    let config = MatchConfig::default();
+    // 这个不用检查文件是否存在，不存在opencv会报错
+    FaceDetectorYN::create(
+        resource_path.to_str().unwrap_or(""),
+        "",
+        Size::new(320, 320), // 初始尺寸，后面会动态更新
+        config.score_threshold,
+        config.nms_threshold,
+        config.top_k,
+        0,
+        0,
+    )
+    .map_err(|e| {
+        CustomResult::error(
+            Some(with_capability_hint(
+                CAP_FACE_DETECTOR,
+                format!("初始化检测器模型失败（{}）: {:?}", resource_path.display(), e),
+            )),
+            None,
         )
-        .map_err(|e| CustomResult::error(Some(format!("初始化检测器模型失败: {:?}", e)), None))?;
+    })
+}
 
-        app_state.detector = Some(OpenCVResource { inner: detector });
-    }
+// 加载人脸识别模型
+pub fn create_recognizer() -> Result<Ptr<FaceRecognizerSF>, CustomResult> {
+    create_recognizer_from(&model_path(RECOGNIZER_PATH_OPTION, DEFAULT_RECOGNIZER_MODEL))
+}
 
-    if app_state.recognizer.is_none() {
-        let resource_path = ROOT_DIR
-            .join("resources")
-            .join("face_recognition_sface_2021dec.onnx");
-        let recognizer = FaceRecognizerSF::create(resource_path.to_str().unwrap_or(""), "", 0, 0)
-            .map_err(|e| {
-            CustomResult::error(Some(format!("初始化识别器模型失败: {:?}", e)), None)
-        })?;
+fn create_recognizer_from(resource_path: &Path) -> Result<Ptr<FaceRecognizerSF>, CustomResult> {
+    FaceRecognizerSF::create(resource_path.to_str().unwrap_or(""), "", 0, 0).map_err(|e| {
+        CustomResult::error(
+            Some(with_capability_hint(
+                CAP_FACE_RECOGNIZER,
+                format!("初始化识别器模型失败（{}）: {:?}", resource_path.display(), e),
+            )),
+            None,
+        )
+    })
+}
 
-        app_state.recognizer = Some(OpenCVResource { inner: recognizer });
+// 识别模型的标识：文件名 + 内容的 CRC32，同名但内容不同的模型也能区分
+fn model_version_of(path: &Path) -> String {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    match std::fs::read(path) {
+        Ok(data) => format!("{}#{:08x}", name, crc32fast::hash(&data)),
+        Err(e) => {
+            warn!("读取识别模型文件失败，模型标识只使用文件名：{}", e);
+            name
+        }
+    }
+}
+
+// 模型被 unload_models 卸载后，下次使用前按保存的模型路径重新加载并自检；两个模型都已加载时直接返回
+// 调用方持有 APP_STATE 的锁，锁屏界面解锁时也经过这里，卸载模型不会导致无法解锁
+pub fn ensure_models_loaded(state: &mut AppState) -> Result<(), String> {
+    if state.detector.is_some() && state.recognizer.is_some() {
+        return Ok(());
+    }
+    if state.detector.is_none() {
+        state.detector = Some(OpenCVResource { inner: create_detector().map_err(|e| e.msg)? });
     }
+    let reload_recognizer = state.recognizer.is_none();
+    if reload_recognizer {
+        state.recognizer = Some(OpenCVResource { inner: create_recognizer().map_err(|e| e.msg)? });
+    }
+    if let (Some(detector), Some(recognizer)) = (state.detector.as_mut(), state.recognizer.as_mut()) {
+        if let Err(failure) = run_sanity_check(&mut detector.inner, &mut recognizer.inner) {
+            error!("重新加载的模型自检失败（{}）：{}", failure.stage, failure.detail);
+            state.detector = None;
+            state.recognizer = None;
+            return Err(failure.into_result().msg);
+        }
+    }
+    if reload_recognizer {
+        set_recognizer_model_version(model_version_of(&model_path(RECOGNIZER_PATH_OPTION, DEFAULT_RECOGNIZER_MODEL)));
+    }
+    // 重新加载的检测器使用默认阈值，恢复当前的检测设置
+    let config = state.match_config;
+    if let Some(detector) = state.detector.as_mut() {
+        let _ = detector
+            .inner
+            .set_score_threshold(config.score_threshold)
+            .and_then(|_| detector.inner.set_nms_threshold(config.nms_threshold))
+            .and_then(|_| detector.inner.set_top_k(config.top_k));
+    }
+    info!("已重新加载人脸检测和识别模型");
+    Ok(())
+}
 
+// 初始化模型
+// detector_path、recognizer_path 为自定义的模型文件（.onnx），加载并通过自检后保存，之后的初始化也使用；空字符串恢复为安装目录中的模型
+#[tauri::command]
+pub fn init_model(detector_path: Option<String>, recognizer_path: Option<String>) -> Result<CustomResult, CustomResult> {
+    ensure_ready()?;
+    // 先创建连接池，加载模型时需要读取设置中的模型路径
     let db_path = ROOT_DIR.join("database.db");
 
     // 创建连接池
@@ -152,7 +344,8 @@ pub fn init_model() -> Result<CustomResult, CustomResult> {
         let manager = r2d2_sqlite::SqliteConnectionManager::file(&db_path).with_flags(
             rusqlite::OpenFlags::SQLITE_OPEN_READ_WRITE
                 | rusqlite::OpenFlags::SQLITE_OPEN_FULL_MUTEX,
-        );
+        )
+        .with_init(|c| c.execute_batch(db_writer::CONNECTION_PRAGMAS));
 
         let pool = Pool::builder()
             .max_size(2) // 回调函数使用，不需要太多连接
@@ -161,8 +354,102 @@ pub fn init_model() -> Result<CustomResult, CustomResult> {
 
         *pool_guard = Some(pool);
     }
+    drop(pool_guard);
+
+    // 后台写入统一交给写入线程
+    db_writer::start(&db_path).map_err(|e| CustomResult::error(Some(e), None))?;
+
+    // 指定了模型文件时先检查文件，加载并通过自检后再保存路径
+    let detector_path = detector_path.as_deref().map(validate_model_path).transpose()?;
+    let recognizer_path = recognizer_path.as_deref().map(validate_model_path).transpose()?;
+    let resolve = |path: &Option<String>, option: &str, default_file: &str| match path {
+        Some(path) if !path.is_empty() => PathBuf::from(path),
+        Some(_) => ROOT_DIR.join("resources").join(default_file),
+        None => model_path(option, default_file),
+    };
+    let detector_file = resolve(&detector_path, DETECTOR_PATH_OPTION, DEFAULT_DETECTOR_MODEL);
+    let recognizer_file = resolve(&recognizer_path, RECOGNIZER_PATH_OPTION, DEFAULT_RECOGNIZER_MODEL);
 
-    Ok(CustomResult::success(None, None))
+    // 加载模型
+    let mut app_state = APP_STATE
+        .lock()
+        .map_err(|e| CustomResult::error(Some(format!("获取app状态 {}", e)), None))?;
+    app_state.ensure_ready()?;
+    // 更换了模型文件或尚未加载时先加载到局部变量，自检通过后再替换，加载或自检失败时继续使用原来的模型
+    let mut detector = if detector_path.is_some() || app_state.detector.is_none() {
+        Some(OpenCVResource { inner: create_detector_from(&detector_file)? })
+    } else {
+        None
+    };
+    let mut recognizer = if recognizer_path.is_some() || app_state.recognizer.is_none() {
+        Some(OpenCVResource { inner: create_recognizer_from(&recognizer_file)? })
+    } else {
+        None
+    };
+
+    let recognizer_version = recognizer.as_ref().map(|_| model_version_of(&recognizer_file));
+
+    // 本次新加载了模型时需要自检，没有更换的模型使用当前已加载的
+    if detector.is_some() || recognizer.is_some() {
+        let state = &mut *app_state;
+        let check = match (
+            detector.as_mut().or(state.detector.as_mut()),
+            recognizer.as_mut().or(state.recognizer.as_mut()),
+        ) {
+            (Some(detector), Some(recognizer)) => run_sanity_check(&mut detector.inner, &mut recognizer.inner),
+            _ => Ok(()),
+        };
+        if let Err(failure) = check {
+            // 自检不通过时不使用新模型，避免使用无意义的匹配分数
+            error!("模型自检失败（{}）：{}", failure.stage, failure.detail);
+            return Err(failure.into_result());
+        }
+        if let Some(detector) = detector {
+            state.detector = Some(detector);
+        }
+        if let Some(recognizer) = recognizer {
+            state.recognizer = Some(recognizer);
+        }
+    }
+    drop(app_state);
+
+    // 更换了识别模型时更新模型标识，已录入的面容由其他模型生成时需要重新录入
+    let mut model_change = None;
+    if let Some(version) = recognizer_version {
+        let previous = recognizer_model_version();
+        set_recognizer_model_version(version.clone());
+        if previous.is_some_and(|previous| previous != version) {
+            let (faces, _) = cached_faces();
+            let mismatched = faces
+                .iter()
+                .filter(|(_, descriptor)| !is_current_model(descriptor.model_version.as_deref()))
+                .count();
+            if mismatched > 0 {
+                warn!("已更换识别模型（{}），{} 个已录入的面容由其他模型生成，需要重新录入", version, mismatched);
+            }
+            model_change = Some(json!({"model_changed": true, "model_version": version, "mismatched_faces": mismatched}));
+        }
+    }
+
+    // 自检通过后保存自定义的模型路径
+    if detector_path.is_some() || recognizer_path.is_some() {
+        db_writer::write(move |tx| {
+            if let Some(path) = &detector_path {
+                save_option(tx, DETECTOR_PATH_OPTION, path)?;
+            }
+            if let Some(path) = &recognizer_path {
+                save_option(tx, RECOGNIZER_PATH_OPTION, path)?;
+            }
+            Ok(())
+        })
+        .map_err(|e| CustomResult::error(Some(format!("保存模型路径失败：{}", e)), None))?;
+        info!("模型文件：检测 {}，识别 {}", detector_file.display(), recognizer_file.display());
+    }
+
+    // 连接池就绪后读取黑帧检测配置、检测和匹配阈值、解锁管道名称、预览画面设置
+    reload_backend_options();
+
+    Ok(CustomResult::success(None, model_change))
 }
 
 // 获取windows所有摄像头
@@ -198,12 +485,13 @@ pub fn get_camera() -> Result<CustomResult, CustomResult> {
 
     // 判断摄像头可用性
     let mut valid_cameras = Vec::new();
-    for (camera_name, index) in video_devices {
+    for (camera_name, index, device_id) in video_devices {
         match is_camera_index_valid(index) {
             Ok(is_valid) => {
                 valid_cameras.push(ValidCameraInfo {
                     camera_name,
                     capture_index: index.to_string(),
+                    device_id,
                     is_valid: is_valid,
                 });
             }
@@ -214,15 +502,82 @@ pub fn get_camera() -> Result<CustomResult, CustomResult> {
     Ok(CustomResult::success(None, Some(json!(valid_cameras))))
 }
 
-// 打开摄像头
+// 探测摄像头时尝试的最大序号（不含）
+const MAX_PROBE_CAMERAS: i32 = 10;
+
+#[derive(Debug, Clone, Serialize)]
+struct ProbedCamera {
+    index: i32,
+    width: f64,
+    height: f64,
+    // 系统设备列表中的名称和设备路径，虚拟摄像头可能没有
+    camera_name: Option<String>,
+    device_id: Option<String>,
+    // 软件正在使用该摄像头，没有重新探测
+    in_use: bool,
+}
+
+// 依次尝试打开序号 0-9 的摄像头，返回能打开的序号和分辨率，供设置页面选择
+// 每个摄像头查询后立即释放；软件正在使用的摄像头不重新打开
+#[tauri::command]
+pub fn list_cameras() -> Result<CustomResult, CustomResult> {
+    let com_init_result = unsafe { CoInitializeEx(None, COINIT_APARTMENTTHREADED) };
+    let devices = get_windows_video_devices().unwrap_or_default();
+    if com_init_result.is_ok() {
+        unsafe { CoUninitialize() };
+    }
+    let open_index = APP_STATE
+        .lock()
+        .map_err(|e| CustomResult::error(Some(format!("获取app状态失败 {}", e)), None))?
+        .camera
+        .is_some()
+        .then(|| CAMERA_INDEX.load(std::sync::atomic::Ordering::SeqCst));
+
+    let mut cameras = Vec::new();
+    for index in 0..MAX_PROBE_CAMERAS {
+        let device = devices.iter().find(|(_, i, _)| *i as i32 == index);
+        let mut camera = ProbedCamera {
+            index,
+            width: 0.0,
+            height: 0.0,
+            camera_name: device.map(|(name, _, _)| name.clone()),
+            device_id: device.and_then(|(_, _, id)| id.clone()),
+            in_use: open_index == Some(index),
+        };
+        if camera.in_use {
+            cameras.push(camera);
+            continue;
+        }
+        let Ok(mut capture) = VideoCapture::new(index, videoio::CAP_DSHOW) else {
+            continue;
+        };
+        if !capture.is_opened().unwrap_or(false) {
+            continue;
+        }
+        camera.width = capture.get(videoio::CAP_PROP_FRAME_WIDTH).unwrap_or_default();
+        camera.height = capture.get(videoio::CAP_PROP_FRAME_HEIGHT).unwrap_or_default();
+        // 立即释放，避免占用摄像头
+        let _ = capture.release();
+        cameras.push(camera);
+    }
+
+    Ok(CustomResult::success(None, Some(json!(cameras))))
+}
+
+// 打开摄像头，未指定序号时使用上次打开的摄像头（默认 0）
 #[tauri::command]
 pub fn open_camera(
     backend: Option<CameraBackend>,
-    camear_index: i32,
+    camera_index: Option<i32>,
 ) -> Result<CustomResult, CustomResult> {
+    let camera_index = camera_index.unwrap_or_else(|| CAMERA_INDEX.load(std::sync::atomic::Ordering::SeqCst));
+    // 读取的画面按该摄像头的校准旋转、镜像、裁剪
+    activate_calibration(camera_index);
+
     let mut app_state = APP_STATE
         .lock()
         .map_err(|e| CustomResult::error(Some(format!("获取app状态失败 {}", e)), None))?;
+    app_state.ensure_ready()?;
 
     // 如果摄像头已打开，直接返回成功
     if app_state.camera.is_some() {
@@ -244,10 +599,11 @@ pub fn open_camera(
 
     // 循环尝试不同后端
     for (idx, backend_inner) in backends_to_try.iter().enumerate() {
-        match try_open_camera_with_backend(*backend_inner, camear_index) {
+        match try_open_camera_with_backend(*backend_inner, camera_index) {
             Ok(cam) => {
-                // 成功打开
+                // 成功打开，记住序号，之后重新打开时使用同一个摄像头
                 app_state.camera = Some(OpenCVResource { inner: cam });
+                CAMERA_INDEX.store(camera_index, std::sync::atomic::Ordering::SeqCst);
                 let msg = if backend.is_some() {
                     format!("使用指定后端 {:?} 成功打开摄像头", backend)
                 } else {
@@ -260,10 +616,13 @@ pub fn open_camera(
                 // 处理失败情况
                 if backend.is_some() {
                     // 指定了后端但失败：直接返回错误
-                    return Err(CustomResult::error(
-                        Some(format!("使用指定后端 {:?} 打开摄像头失败: {}", backend, e)),
-                        None,
-                    ));
+                    let msg = format!("使用指定后端 {:?} 打开摄像头失败: {}", backend, e);
+                    let msg = match backend_inner {
+                        CameraBackend::MSMF => with_capability_hint(CAP_VIDEOIO_MSMF, msg),
+                        CameraBackend::DShow => with_capability_hint(CAP_VIDEOIO_DSHOW, msg),
+                        _ => msg,
+                    };
+                    return Err(CustomResult::error(Some(msg), None));
                 } else {
                     // 未指定后端：打印尝试失败日志，继续尝试下一个
                     warn!("尝试后端 {:?} 失败: {}", backend, e);
@@ -274,10 +633,14 @@ pub fn open_camera(
     }
 
     // 所有后端都尝试失败
-    Err(CustomResult::error(
-        Some("所有摄像头后端均尝试失败，请检查设备是否连接/被占用/有权限".to_string()),
-        None,
-    ))
+    let msg = with_capability_hint(
+        CAP_VIDEOIO_MSMF,
+        with_capability_hint(
+            CAP_VIDEOIO_DSHOW,
+            "所有摄像头后端均尝试失败，请检查设备是否连接/被占用/有权限".to_string(),
+        ),
+    );
+    Err(CustomResult::error(Some(msg), None))
 }
 
 // 关闭摄像头
@@ -290,6 +653,25 @@ pub fn stop_camera() -> Result<CustomResult, CustomResult> {
     Ok(CustomResult::success(None, None))
 }
 
+// 卸载人脸检测和识别模型，释放内存；长时间最小化到托盘或替换模型文件后使用
+// 之后需要模型时（包括锁屏界面解锁）由 ensure_models_loaded 自动重新加载，也可以调用 init_model 提前加载
+// 正在识别时拒绝卸载，避免识别中途失败
+#[tauri::command]
+pub fn unload_models() -> Result<CustomResult, CustomResult> {
+    if IS_RUN.load(std::sync::atomic::Ordering::SeqCst) {
+        return Err(CustomResult::error(Some(String::from("正在识别，请稍后再卸载模型")), None));
+    }
+    let mut app_state = APP_STATE
+        .lock()
+        .map_err(|e| CustomResult::error(Some(format!("获取app状态失败 {}", e)), None))?;
+    let unloaded = app_state.detector.take().is_some() | app_state.recognizer.take().is_some();
+    drop(app_state);
+    if unloaded {
+        info!("已卸载人脸检测和识别模型");
+    }
+    Ok(CustomResult::success(None, Some(json!({"unloaded": unloaded}))))
+}
+
 // 打开指定目录用资源管理器
 #[tauri::command]
 pub fn open_directory(path: String) -> Result<CustomResult, CustomResult> {
@@ -448,14 +830,44 @@ pub fn check_global_autostart() -> Result<CustomResult, CustomResult> {
     ))
 }
 
-// 关闭软件
+// 向前端发送事件，AppHandle 未就绪时忽略
+pub fn emit_event<S: Serialize + Clone>(event: &str, payload: S) {
+    let Ok(guard) = APP_HANDLE.lock() else {
+        return;
+    };
+    if let Some(app_handle) = guard.as_ref() {
+        if let Err(e) = app_handle.emit(event, payload) {
+            warn!("发送事件 {} 失败: {}", event, e);
+        }
+    }
+}
+
+// 获取面容识别当前的线程优先级模式
 #[tauri::command]
-pub fn close_app(app_handle: AppHandle) -> Result<CustomResult, CustomResult> {
-    let window = app_handle.get_webview_window("main").unwrap();
-    let hwnd = window.hwnd().unwrap();
-    unsafe {
-        // 注销 WTS 通知
-        let _ = WTSUnRegisterSessionNotification(HWND(hwnd.0));
+pub fn get_pipeline_priority() -> Result<CustomResult, CustomResult> {
+    Ok(CustomResult::success(
+        None,
+        Some(json!({"mode": current_mode()})),
+    ))
+}
+
+// 退出软件，退出码告诉守护进程是否需要重启（见 supervisor 模块）
+fn shutdown(app_handle: &AppHandle, code: i32) -> Result<(), CustomResult> {
+    // 先切换阶段，退出过程中的命令调用直接返回未就绪
+    set_app_phase(AppPhase::ShuttingDown);
+    if code != EXIT_RESTART {
+        mark_quit_requested();
+    }
+
+    // setup 失败时窗口可能不存在，此时没有注册过 WTS 通知
+    if let Some(hwnd) = app_handle
+        .get_webview_window("main")
+        .and_then(|window| window.hwnd().ok())
+    {
+        unsafe {
+            // 注销 WTS 通知
+            let _ = WTSUnRegisterSessionNotification(HWND(hwnd.0));
+        }
     }
     
     // 关闭系统托盘
@@ -464,11 +876,31 @@ pub fn close_app(app_handle: AppHandle) -> Result<CustomResult, CustomResult> {
         tray_any.set_visible(false)
             .map_err(|e| CustomResult::error(Some(format!("隐藏托盘图标失败: {}", e)), None))?;
     }
+    drop(guard);
 
-    app_handle.exit(0);
+    app_handle.exit(code);
+    Ok(())
+}
 
+// 关闭软件，守护模式下守护进程也随之退出
+#[tauri::command]
+pub fn close_app(app_handle: AppHandle) -> Result<CustomResult, CustomResult> {
+    shutdown(&app_handle, EXIT_CLEAN)?;
     Ok(CustomResult::success(None, None))
 }
+
+// 重启软件，例如修改了需要重启才能生效的设置
+#[tauri::command]
+pub fn restart_app(app_handle: AppHandle) -> Result<CustomResult, CustomResult> {
+    if is_supervised() {
+        // 由守护进程重新启动
+        shutdown(&app_handle, EXIT_RESTART)?;
+        return Ok(CustomResult::success(None, None));
+    }
+    set_app_phase(AppPhase::ShuttingDown);
+    app_handle.restart()
+}
+
 // 使用指定后端尝试打开摄像头并验证读取帧
 fn try_open_camera_with_backend(
     backend: CameraBackend,
@@ -498,7 +930,35 @@ fn try_open_camera_with_backend(
     Ok(cam)
 }
 // 获取windows所有摄像头
-fn get_windows_video_devices() -> windows::core::Result<Vec<(String, u32)>> {
+// 摄像头序号对应的设备路径，没有设备路径的虚拟摄像头返回 None
+pub fn camera_device_id(index: i32) -> Option<String> {
+    // 调用方线程可能已经以其他模式初始化过 COM，此时不需要（也不能）再卸载
+    let com_init_result = unsafe { CoInitializeEx(None, COINIT_APARTMENTTHREADED) };
+    let devices = get_windows_video_devices();
+    if com_init_result.is_ok() {
+        unsafe { CoUninitialize() };
+    }
+    devices
+        .ok()?
+        .into_iter()
+        .find(|(_, i, _)| *i as i32 == index)
+        .and_then(|(_, _, device_id)| device_id)
+}
+
+// 读取属性袋中的字符串属性
+unsafe fn read_bag_string(prop_bag: &IPropertyBag, name: &str) -> Option<String> {
+    let name_bstr = BSTR::from(name);
+    let mut variant = VARIANT::from(BSTR::default());
+    let value = prop_bag
+        .Read(&name_bstr, &mut variant, None)
+        .ok()
+        .map(|_| variant.Anonymous.Anonymous.Anonymous.bstrVal.to_string())
+        .filter(|v| !v.is_empty());
+    VariantClear(&mut variant).ok();
+    value
+}
+
+fn get_windows_video_devices() -> windows::core::Result<Vec<(String, u32, Option<String>)>> {
     // 存放所有摄像头设备信息
     let mut devices = Vec::new();
 
@@ -569,7 +1029,7 @@ fn get_windows_video_devices() -> windows::core::Result<Vec<(String, u32)>> {
             // 清理VARIANT，释放内部资源
             VariantClear(&mut variant).ok();
 
-            devices.push((camera_name, i));
+            devices.push((camera_name, i, read_bag_string(&prop_bag, "DevicePath")));
             i += 1;
         }
     };
@@ -590,16 +1050,317 @@ fn is_camera_index_valid(index: u32) -> opencv::Result<bool> {
     Ok(is_valid)
 }
 
-// 解锁屏幕
-pub fn unlock(user_name: String, password: String) -> windows::core::Result<()> {
-    let client = Client::new(HSTRING::from(r"\\.\pipe\MansonWindowsUnlockRustServer"));
+// DLL 对解锁凭据的回复
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UnlockReply {
+    // 已接收凭据并通知锁屏界面登录，登录是否成功由系统判断
+    Accepted,
+    // DLL 无法使用收到的凭据
+    Rejected,
+    // 管道在回复前关闭，旧版本的 DLL 不回复
+    Closed,
+    // 等待回复超时
+    Timeout,
+}
+
+impl UnlockReply {
+    pub fn succeeded(&self) -> bool {
+        *self == UnlockReply::Accepted
+    }
+}
+
+// 等待 DLL 回复的最长时间
+const UNLOCK_REPLY_TIMEOUT: Duration = Duration::from_secs(2);
+
+// 默认的解锁管道，与 DLL 监听的名称一致
+pub const DEFAULT_PIPE_NAME: &str = r"\\.\pipe\MansonWindowsUnlockRustServer";
+const PIPE_NAME_OPTION: &str = "unlockPipeName";
+// 为 "true" 时按旧的 NUL 结尾格式发送凭据，见 protocol::WIRE_FORMAT
+const LEGACY_PIPE_FORMAT_OPTION: &str = "legacyPipeFormat";
+const PIPE_PREFIX: &str = r"\\.\pipe\";
+// Windows 限制管道全名最长 256 个字符
+const PIPE_NAME_MAX_LEN: usize = 256;
+
+// 检查管道名称：必须是本机管道，名称部分不能为空，也不能包含反斜杠
+fn validate_pipe_name(name: &str) -> Result<String, String> {
+    let name = name.trim();
+    let Some(rest) = name
+        .get(..PIPE_PREFIX.len())
+        .filter(|prefix| prefix.eq_ignore_ascii_case(PIPE_PREFIX))
+        .map(|_| &name[PIPE_PREFIX.len()..])
+    else {
+        return Err(format!("管道名称必须以 {} 开头", PIPE_PREFIX));
+    };
+    if rest.is_empty() || rest.contains('\\') {
+        return Err(format!("管道名称无效：{}", name));
+    }
+    if name.chars().count() > PIPE_NAME_MAX_LEN {
+        return Err(format!("管道名称不能超过 {} 个字符", PIPE_NAME_MAX_LEN));
+    }
+    Ok(name.to_string())
+}
+
+// 当前使用的解锁管道名称
+pub fn pipe_name() -> String {
+    APP_STATE
+        .lock()
+        .map(|state| state.pipe_name.clone())
+        .unwrap_or_else(|_| String::from(DEFAULT_PIPE_NAME))
+}
+
+fn apply_pipe_name(name: String) {
+    if let Ok(mut state) = APP_STATE.lock() {
+        state.pipe_name = name;
+    }
+}
+
+// 读取保存的管道名称，无效时使用默认名称
+pub fn load_pipe_name() {
+    let name = match read_option(PIPE_NAME_OPTION).map(|v| validate_pipe_name(&v)) {
+        Some(Ok(name)) => name,
+        Some(Err(e)) => {
+            warn!("解锁管道名称无效，使用默认名称：{}", e);
+            String::from(DEFAULT_PIPE_NAME)
+        }
+        None => String::from(DEFAULT_PIPE_NAME),
+    };
+    apply_pipe_name(name);
+}
+
+// 修改解锁管道名称，需要与 DLL 监听的名称一致；为空时恢复默认名称
+#[tauri::command]
+pub fn set_pipe_name(pipe_name: Option<String>) -> Result<CustomResult, CustomResult> {
+    ensure_ready()?;
+    let name = match pipe_name.filter(|name| !name.trim().is_empty()) {
+        Some(name) => validate_pipe_name(&name).map_err(|e| CustomResult::error(Some(e), None))?,
+        None => String::from(DEFAULT_PIPE_NAME),
+    };
+    let value = name.clone();
+    db_writer::write(move |tx| save_option(tx, PIPE_NAME_OPTION, &value))
+        .map_err(|e| CustomResult::error(Some(e), None))?;
+    info!("解锁管道名称已修改为 {}", name);
+    apply_pipe_name(name.clone());
+    Ok(CustomResult::success(None, Some(json!({"pipe_name": name}))))
+}
+
+// 是否使用旧的管道格式发送凭据，只在 DLL 尚未升级时打开
+fn use_legacy_pipe_format() -> bool {
+    read_option(LEGACY_PIPE_FORMAT_OPTION).as_deref() == Some("true")
+}
+
+// 解锁屏幕，发送凭据后等待 DLL 的回复
+// password 为空时从凭据管理器读取该账户保存的密码；发送后清除内存中的密码
+pub fn unlock(user_name: String, password: Option<String>) -> windows::core::Result<UnlockReply> {
+    let password = match password {
+        Some(password) => password,
+        None => read_vault_password(&user_name)?.ok_or_else(|| {
+            windows::core::Error::new(E_UNEXPECTED, format!("凭据管理器中没有账户 {} 的密码", user_name))
+        })?,
+    };
+    let mut message = Message::Credentials { user_name, password };
+    let result = send_credentials(&message);
+    if let Message::Credentials { password, .. } = &mut message {
+        password.zeroize();
+    }
+    result
+}
+
+fn send_credentials(message: &Message) -> windows::core::Result<UnlockReply> {
+    let name = pipe_name();
+    info!("连接解锁管道 {}", name);
+    let client = Client::new_duplex(HSTRING::from(name.as_str()));
     if client.is_err() {
         return Err(windows::core::Error::new(E_UNEXPECTED, "管道不存在"));
     }
     let client = client.unwrap();
-    if let Err(e) = crate::utils::pipe::write(client.handle, format!("{}::FaceWinUnlock::{}", user_name, password)) {
-        println!("向客户端写入数据失败: {:?}", e);
+    metrics::mark(STAGE_PIPE_CONNECTED);
+    let encoded = match message {
+        // 过渡期内配合尚未升级的 DLL，使用旧的 NUL 结尾格式；旧 DLL 不回复，结果为 Closed
+        Message::Credentials { user_name, password } if use_legacy_pipe_format() => {
+            encode_legacy(user_name, password)
+        }
+        message => encode(message),
+    };
+    let mut frame = encoded
+        .map_err(|e| windows::core::Error::new(E_UNEXPECTED, format!("编码解锁消息失败: {}", e)))?;
+    // 无论写入是否成功都清除帧中的密码
+    let written = write_frame(client.handle, &frame);
+    frame.zeroize();
+    written?;
+
+    let reply = match read_frame_timeout(client.handle, UNLOCK_REPLY_TIMEOUT) {
+        Ok(Some(frame)) => match decode(&frame) {
+            Ok(Message::Ack { accepted: true }) => {
+                // 只有 DLL 确认接收凭据时才记录，写入成功不代表 DLL 已接收
+                metrics::mark(STAGE_CREDENTIALS_ACKNOWLEDGED);
+                UnlockReply::Accepted
+            }
+            Ok(Message::Ack { accepted: false }) => UnlockReply::Rejected,
+            Ok(message) => {
+                warn!("DLL 回复了无效的消息：{:?}", message);
+                UnlockReply::Rejected
+            }
+            Err(e) => {
+                warn!("无法解析 DLL 的回复：{}", e);
+                UnlockReply::Rejected
+            }
+        },
+        Ok(None) => UnlockReply::Timeout,
+        Err(_) => UnlockReply::Closed,
+    };
+    Ok(reply)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::panic::{catch_unwind, UnwindSafe};
+
+    use tauri::async_runtime::block_on;
+
+    use super::*;
+    use crate::modules::{
+        auto_unlock, backup, calibration, conference, continuous_verify, control, credential, drift, engine, face_search,
+        faces, liveness, metrics, migrations, options, presets, replay, retention, statistics, support,
+    };
+
+    // 测试进程中不会执行 setup，APP_STATE 一直处于 Starting 阶段
+    fn assert_starting() {
+        assert_eq!(APP_STATE.lock().unwrap().phase, AppPhase::Starting);
     }
 
-    Ok(())
-}
\ No newline at end of file
+    fn assert_not_ready(name: &str, result: Result<CustomResult, CustomResult>) {
+        match result {
+            Err(e) => {
+                assert_eq!(e.data["condition"], NOT_READY, "{} 返回了其他错误：{}", name, e.msg);
+                assert_eq!(e.data["retryable"], true, "{}", name);
+            }
+            Ok(_) => panic!("{} 在 Starting 阶段没有返回未就绪", name),
+        }
+    }
+
+    fn assert_no_panic<F>(name: &str, f: F)
+    where
+        F: FnOnce() -> Result<CustomResult, CustomResult> + UnwindSafe,
+    {
+        assert!(catch_unwind(f).is_ok(), "{} 在 Starting 阶段 panic", name);
+    }
+
+    #[test]
+    fn guarded_commands_return_not_ready_while_starting() {
+        assert_starting();
+        let results = vec![
+            ("init_model", init_model(None, None)),
+            ("open_camera", open_camera(None, None)),
+            ("lock_now", lock_now()),
+            ("test_win_logon", block_on(test_win_logon(String::from("user"), None))),
+            ("set_pipe_name", set_pipe_name(None)),
+            ("start_auto_unlock", auto_unlock::start_auto_unlock(None)),
+            ("set_auto_unlock_timeout", auto_unlock::set_auto_unlock_timeout(30)),
+            ("export_face_registrations", backup::export_face_registrations(String::new(), None)),
+            ("import_face_registrations", backup::import_face_registrations(String::new())),
+            ("get_camera_calibration", calibration::get_camera_calibration(None)),
+            ("set_camera_calibration", calibration::set_camera_calibration(None, Default::default())),
+            ("auto_detect_orientation", calibration::auto_detect_orientation(0.9)),
+            ("start_continuous_verify", continuous_verify::start_continuous_verify(0.9, None, None)),
+            ("get_unlock_status", control::get_unlock_status()),
+            ("set_unlock_armed", control::set_unlock_armed(true)),
+            ("validate_stored_credential", credential::validate_stored_credential(None)),
+            ("store_credentials", credential::store_credentials(String::from("user"), String::from("password"))),
+            ("clear_credentials", credential::clear_credentials(String::from("user"))),
+            ("get_reenrollment_status", drift::get_reenrollment_status()),
+            ("snooze_reenrollment_reminder", drift::snooze_reenrollment_reminder(String::from("face"), 7)),
+            ("search_registrations", face_search::search_registrations(Default::default())),
+            ("get_preview_quality", faces::get_preview_quality()),
+            ("set_preview_quality", faces::set_preview_quality(80)),
+            ("get_preview_max_dim", faces::get_preview_max_dim()),
+            ("set_preview_max_dim", faces::set_preview_max_dim(640)),
+            ("check_face_from_img", faces::check_face_from_img(None, 0.9, None, None, None)),
+            ("check_face_from_camera", faces::check_face_from_camera(0.9, None, None)),
+            ("start_preview", faces::start_preview(0.9, None, None, None)),
+            ("get_match_config", faces::get_match_config()),
+            ("set_match_config", faces::set_match_config(MatchConfig::default())),
+            ("get_detector_params", faces::get_detector_params()),
+            ("set_detector_params", faces::set_detector_params(0.9, 0.3, 5000)),
+            ("get_match_threshold", faces::get_match_threshold()),
+            ("set_match_threshold", faces::set_match_threshold(0.363)),
+            (
+                "verify_face",
+                block_on(faces::verify_face(
+                    String::new(), 0.9, None, None, None, None, None, None, None, None, None, None,
+                )),
+            ),
+            ("check_face_quality", faces::check_face_quality(String::new(), 0.9, None)),
+            ("get_duplicate_threshold", faces::get_duplicate_threshold()),
+            ("set_duplicate_threshold", faces::set_duplicate_threshold(None)),
+            (
+                "save_face_registration",
+                faces::save_face_registration(String::from("face"), String::new(), 0.9, None, None, None, None),
+            ),
+            (
+                "save_face_registration_averaged",
+                faces::save_face_registration_averaged(String::from("face"), None, None, 0.9),
+            ),
+            (
+                "add_registration_sample",
+                faces::add_registration_sample(String::from("face"), String::new(), 0.9, None),
+            ),
+            (
+                "update_face_registration",
+                faces::update_face_registration(String::from("face"), String::new(), 0.9, None, None),
+            ),
+            ("prune_registration_samples", faces::prune_registration_samples(String::from("face"), vec![0])),
+            ("list_registered_faces", faces::list_registered_faces()),
+            ("delete_face_registration", faces::delete_face_registration(String::from("face"))),
+            ("reload_face_cache", faces::reload_face_cache()),
+            ("migrate_face_files", faces::migrate_face_files()),
+            ("rename_face_registration", faces::rename_face_registration(String::from("face"), String::from("name"))),
+            ("set_face_username", faces::set_face_username(String::from("face"), String::from("user"))),
+            ("identify_face", faces::identify_face(0.9, None, None, None)),
+            ("verify_face_against_registered", faces::verify_face_against_registered(0.9)),
+            ("validate_face_store", faces::validate_face_store()),
+            ("materialize_face_files", faces::materialize_face_files()),
+            ("verify_liveness", block_on(liveness::verify_liveness(None))),
+            ("start_flash_challenge", liveness::start_flash_challenge()),
+            ("finish_flash_challenge", block_on(liveness::finish_flash_challenge(String::new()))),
+            ("get_unlock_latency_breakdown", metrics::get_unlock_latency_breakdown(None)),
+            ("mark_configuration_known_good", options::mark_configuration_known_good(None)),
+            ("list_config_snapshots", options::list_config_snapshots()),
+            ("revert_to_known_good", options::revert_to_known_good(1)),
+            ("apply_policy_preset", presets::apply_policy_preset(String::from("balanced"))),
+            ("get_settings", presets::get_settings()),
+            ("sync_policy_preset", presets::sync_policy_preset()),
+            ("replay_unlock_attempt", replay::replay_unlock_attempt(String::new(), None)),
+            ("set_debug_capture", replay::set_debug_capture(false, None)),
+            ("get_debug_capture", replay::get_debug_capture()),
+            ("run_retention_maintenance", retention::run_retention_maintenance()),
+            ("reactivate_registration", retention::reactivate_registration(String::from("face"))),
+            ("set_score_precision", statistics::set_score_precision(String::from("exact"), None)),
+            ("get_unlock_statistics", statistics::get_unlock_statistics(None)),
+            ("get_stored_data_summary", statistics::get_stored_data_summary()),
+            ("create_support_bundle", support::create_support_bundle(String::new(), false)),
+        ];
+        for (name, result) in results {
+            assert_not_ready(name, result);
+        }
+    }
+
+    #[test]
+    fn phase_independent_commands_do_not_panic_while_starting() {
+        assert_starting();
+        assert_no_panic("get_app_phase", get_app_phase);
+        assert_no_panic("get_pipeline_priority", get_pipeline_priority);
+        assert_no_panic("stop_camera", stop_camera);
+        assert_no_panic("unload_models", unload_models);
+        assert_no_panic("stop_preview", faces::stop_preview);
+        assert_no_panic("get_faces_dir", faces::get_faces_dir);
+        assert_no_panic("stop_auto_unlock", auto_unlock::stop_auto_unlock);
+        assert_no_panic("stop_continuous_verify", continuous_verify::stop_continuous_verify);
+        assert_no_panic("get_pause_status", conference::get_pause_status);
+        assert_no_panic("get_unlock_engine_trace", engine::get_unlock_engine_trace);
+        assert_no_panic("get_migration_status", migrations::get_migration_status);
+
+        let phase = get_app_phase().unwrap();
+        assert_eq!(phase.data["phase"], "Starting");
+    }
+}
@@ -0,0 +1,259 @@
+// 数据库唯一的写入线程
+// 解锁日志、统计、维护记录等后台写入都通过通道提交到这个线程，每个请求在一个事务中执行
+// 读取仍然使用连接池，WAL 模式下读取不会被写入阻塞
+use std::{
+    path::Path,
+    sync::{mpsc, Mutex},
+    thread,
+};
+
+use lazy_static::lazy_static;
+use r2d2_sqlite::rusqlite::{self, Connection, OpenFlags, Transaction, TransactionBehavior};
+use tauri_plugin_log::log::{info, warn};
+
+// 每个连接打开时执行：WAL 日志、等待锁的超时时间
+// WAL 下 synchronous=NORMAL 在断电时最多丢失最后一次提交，不会损坏数据库
+pub const CONNECTION_PRAGMAS: &str =
+    "PRAGMA journal_mode = WAL; PRAGMA busy_timeout = 5000; PRAGMA synchronous = NORMAL;";
+
+type Job = Box<dyn FnOnce(&mut Connection) + Send>;
+
+lazy_static! {
+    static ref WRITER: Mutex<Option<mpsc::Sender<Job>>> = Mutex::new(None);
+}
+
+// 启动写入线程，已经启动时直接返回
+pub fn start(db_path: &Path) -> Result<(), String> {
+    let mut writer = WRITER
+        .lock()
+        .map_err(|e| format!("获取数据库写入线程锁失败 {}", e))?;
+    if writer.is_some() {
+        return Ok(());
+    }
+
+    let mut conn = Connection::open_with_flags(
+        db_path,
+        OpenFlags::SQLITE_OPEN_READ_WRITE | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+    )
+    .map_err(|e| format!("打开数据库失败：{:?}", e))?;
+    conn.execute_batch(CONNECTION_PRAGMAS)
+        .map_err(|e| format!("设置数据库连接失败：{:?}", e))?;
+
+    *writer = Some(spawn_writer(conn)?);
+    Ok(())
+}
+
+// 在新线程中依次执行写入请求
+// 发布版本 panic = "abort"，不能依赖捕获 panic 保住写入线程：请求中的错误都以 Result 返回，
+// 由 run_in_transaction 回滚并交给调用方处理
+fn spawn_writer(mut conn: Connection) -> Result<mpsc::Sender<Job>, String> {
+    let (sender, receiver) = mpsc::channel::<Job>();
+    thread::Builder::new()
+        .name(String::from("db-writer"))
+        .spawn(move || {
+            for job in receiver {
+                job(&mut conn);
+            }
+            info!("数据库写入线程已退出");
+        })
+        .map_err(|e| format!("创建数据库写入线程失败：{}", e))?;
+    Ok(sender)
+}
+
+fn submit(job: Job) -> Result<(), String> {
+    let sender = WRITER
+        .lock()
+        .map_err(|e| format!("获取数据库写入线程锁失败 {}", e))?
+        .clone()
+        .ok_or_else(|| String::from("数据库写入线程未启动"))?;
+    send_job(&sender, job)
+}
+
+fn send_job(sender: &mpsc::Sender<Job>, job: Job) -> Result<(), String> {
+    sender
+        .send(job)
+        .map_err(|_| String::from("数据库写入线程已退出"))
+}
+
+// 在事务中执行，返回错误时回滚
+fn run_in_transaction<T, F>(conn: &mut Connection, f: F) -> Result<T, String>
+where
+    F: FnOnce(&Transaction) -> Result<T, String>,
+{
+    // 立即获取写锁，避免事务中途升级为写事务时才发现数据库被占用
+    let tx = conn
+        .transaction_with_behavior(TransactionBehavior::Immediate)
+        .map_err(|e| format!("开启事务失败：{:?}", e))?;
+    let value = f(&tx)?;
+    tx.commit().map_err(|e| format!("提交事务失败：{:?}", e))?;
+    Ok(value)
+}
+
+// 提交一组写入并等待结果，f 中的所有语句在同一个事务中执行
+pub fn write<T, F>(f: F) -> Result<T, String>
+where
+    F: FnOnce(&Transaction) -> Result<T, String> + Send + 'static,
+    T: Send + 'static,
+{
+    let (job, result) = reply_job(f);
+    submit(job)?;
+    result
+        .recv()
+        .map_err(|_| String::from("数据库写入请求没有返回结果"))?
+}
+
+// 把写入包装为请求，结果从返回的通道中读取
+fn reply_job<T, F>(f: F) -> (Job, mpsc::Receiver<Result<T, String>>)
+where
+    F: FnOnce(&Transaction) -> Result<T, String> + Send + 'static,
+    T: Send + 'static,
+{
+    let (reply, result) = mpsc::channel();
+    let job: Job = Box::new(move |conn| {
+        let _ = reply.send(run_in_transaction(conn, f));
+    });
+    (job, result)
+}
+
+// 提交一组写入，不等待结果，失败时以 label 记录日志
+pub fn write_detached<F>(label: &'static str, f: F)
+where
+    F: FnOnce(&Transaction) -> Result<(), String> + Send + 'static,
+{
+    let result = submit(Box::new(move |conn| {
+        if let Err(e) = run_in_transaction(conn, f) {
+            warn!("{}：{}", label, e);
+        }
+    }));
+    if let Err(e) = result {
+        warn!("{}：{}", label, e);
+    }
+}
+
+// 完整性检查，返回 ok 或发现的问题
+pub fn quick_check(conn: &Connection) -> Result<Vec<String>, String> {
+    let mut stmt = conn
+        .prepare("PRAGMA quick_check;")
+        .map_err(|e| format!("准备完整性检查失败：{:?}", e))?;
+    let rows = stmt
+        .query_map([], |row| row.get::<usize, String>(0))
+        .map_err(|e| format!("执行完整性检查失败：{:?}", e))?;
+    rows.collect::<rusqlite::Result<Vec<_>>>()
+        .map_err(|e| format!("读取完整性检查结果失败：{:?}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use super::*;
+
+    const WRITERS: usize = 8;
+    const WRITES_PER_WRITER: usize = 50;
+    const READERS: usize = 4;
+
+    fn open(path: &Path) -> Connection {
+        let conn = Connection::open(path).unwrap();
+        conn.execute_batch(CONNECTION_PRAGMAS).unwrap();
+        conn
+    }
+
+    fn write_via<T, F>(sender: &mpsc::Sender<Job>, f: F) -> Result<T, String>
+    where
+        F: FnOnce(&Transaction) -> Result<T, String> + Send + 'static,
+        T: Send + 'static,
+    {
+        let (job, result) = reply_job(f);
+        send_job(sender, job)?;
+        result.recv().map_err(|_| String::from("没有返回结果"))?
+    }
+
+    #[test]
+    fn concurrent_writers_and_readers_under_wal() {
+        let dir = std::env::temp_dir().join(format!("facewinunlock-db-writer-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("test.db");
+        open(&path)
+            .execute_batch("CREATE TABLE unlock_log (id INTEGER PRIMARY KEY, writer INTEGER, seq INTEGER);")
+            .unwrap();
+        let sender = spawn_writer(open(&path)).unwrap();
+
+        // 读取线程在写入期间一直读取，WAL 下不会被写入阻塞，也不会读到一半的事务
+        let done = Arc::new(AtomicBool::new(false));
+        let readers: Vec<_> = (0..READERS)
+            .map(|_| {
+                let path = path.clone();
+                let done = Arc::clone(&done);
+                thread::spawn(move || {
+                    let conn = open(&path);
+                    let mut last = 0;
+                    let mut reads = 0;
+                    while !done.load(Ordering::SeqCst) {
+                        let count: i64 = conn
+                            .query_row("SELECT COUNT(*) FROM unlock_log;", [], |row| row.get(0))
+                            .unwrap();
+                        // 每个事务写入两行，只能看到完整的事务
+                        assert_eq!(count % 2, 0);
+                        assert!(count >= last);
+                        last = count;
+                        reads += 1;
+                        thread::sleep(Duration::from_millis(1));
+                    }
+                    reads
+                })
+            })
+            .collect();
+
+        let writers: Vec<_> = (0..WRITERS)
+            .map(|writer| {
+                let sender = sender.clone();
+                thread::spawn(move || {
+                    for seq in 0..WRITES_PER_WRITER {
+                        write_via(&sender, move |tx| {
+                            for _ in 0..2 {
+                                tx.execute(
+                                    "INSERT INTO unlock_log (writer, seq) VALUES (?1, ?2);",
+                                    rusqlite::params![writer as i64, seq as i64],
+                                )
+                                .map_err(|e| format!("{:?}", e))?;
+                            }
+                            Ok(())
+                        })
+                        .unwrap();
+                    }
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+
+        // 出错的请求回滚，之后的请求照常执行
+        let failed = write_via(&sender, |tx| {
+            tx.execute("INSERT INTO unlock_log (writer, seq) VALUES (-1, -1);", [])
+                .map_err(|e| format!("{:?}", e))?;
+            Err::<(), _>(String::from("rollback"))
+        });
+        assert_eq!(failed, Err(String::from("rollback")));
+        let total: i64 = write_via(&sender, |tx| {
+            tx.query_row("SELECT COUNT(*) FROM unlock_log;", [], |row| row.get(0))
+                .map_err(|e| format!("{:?}", e))
+        })
+        .unwrap();
+
+        done.store(true, Ordering::SeqCst);
+        for reader in readers {
+            assert!(reader.join().unwrap() > 0);
+        }
+        assert_eq!(total as usize, WRITERS * WRITES_PER_WRITER * 2);
+
+        drop(sender);
+        std::fs::remove_dir_all(dir).ok();
+    }
+}
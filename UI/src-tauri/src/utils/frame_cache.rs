@@ -0,0 +1,159 @@
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc, Mutex,
+};
+
+use opencv::{
+    core::{Mat, Vector},
+    imgcodecs,
+};
+
+use crate::modules::faces::resize_mat;
+
+// 同一帧最多缓存多少种尺寸/质量的编码结果
+const MAX_ENTRIES: usize = 4;
+
+// 编码缓存命中/未命中次数
+pub static ENCODE_HITS: AtomicU64 = AtomicU64::new(0);
+pub static ENCODE_MISSES: AtomicU64 = AtomicU64::new(0);
+// 摄像头帧序号，每读取一帧加 1
+static FRAME_SEQ: AtomicU64 = AtomicU64::new(0);
+
+// 当前帧的 JPEG 编码缓存
+struct EncodeCache {
+    frame_id: u64,
+    // (最大边长, 质量) -> 编码后的数据
+    entries: Vec<((i32, i32), Arc<Vec<u8>>)>,
+}
+
+impl EncodeCache {
+    fn new() -> Self {
+        Self { frame_id: 0, entries: Vec::new() }
+    }
+
+    // 返回缓存的编码结果，没有时调用 encode 并缓存；第二个值表示是否命中
+    fn get_or_encode(
+        &mut self,
+        frame_id: u64,
+        key: (i32, i32),
+        encode: impl FnOnce() -> Result<Vec<u8>, String>,
+    ) -> Result<(Arc<Vec<u8>>, bool), String> {
+        if self.frame_id != frame_id {
+            // 新的一帧，旧的缓存全部作废
+            self.frame_id = frame_id;
+            self.entries.clear();
+        } else if let Some((_, bytes)) = self.entries.iter().find(|(k, _)| *k == key) {
+            return Ok((bytes.clone(), true));
+        }
+
+        let bytes = Arc::new(encode()?);
+        if self.entries.len() >= MAX_ENTRIES {
+            self.entries.remove(0);
+        }
+        self.entries.push((key, bytes.clone()));
+        Ok((bytes, false))
+    }
+}
+
+lazy_static::lazy_static! {
+    static ref ENCODE_CACHE: Mutex<EncodeCache> = Mutex::new(EncodeCache::new());
+}
+
+// 从摄像头读取到新帧时调用一次，返回该帧的序号
+// 序号随帧一起传给各个使用方，同一帧的编码请求共享缓存
+pub fn next_frame_id() -> u64 {
+    FRAME_SEQ.fetch_add(1, Ordering::SeqCst) + 1
+}
+
+// 按帧缓存 JPEG 编码结果
+// 同一帧、同样尺寸和质量只编码一次，后续请求直接返回缓存
+// 持有锁期间编码，多个调用方同时请求时只有第一个会真正编码
+pub fn encode_jpeg_cached(
+    frame_id: u64,
+    mat: &Mat,
+    max_dim: f32,
+    quality: i32,
+) -> Result<Arc<Vec<u8>>, String> {
+    let mut cache = ENCODE_CACHE
+        .lock()
+        .map_err(|e| format!("获取编码缓存失败 {}", e))?;
+    let (bytes, hit) = cache.get_or_encode(frame_id, (max_dim as i32, quality), || encode_jpeg(mat, max_dim, quality))?;
+    if hit {
+        ENCODE_HITS.fetch_add(1, Ordering::Relaxed);
+    } else {
+        ENCODE_MISSES.fetch_add(1, Ordering::Relaxed);
+    }
+    Ok(bytes)
+}
+
+// 缩放并编码为 JPEG
+fn encode_jpeg(mat: &Mat, max_dim: f32, quality: i32) -> Result<Vec<u8>, String> {
+    let resized = resize_mat(mat, max_dim)?;
+    let mut buf = Vector::<u8>::new();
+    let params = Vector::<i32>::from_slice(&[imgcodecs::IMWRITE_JPEG_QUALITY, quality]);
+    match imgcodecs::imencode(".jpg", &resized, &mut buf, &params) {
+        Ok(true) => Ok(buf.to_vec()),
+        Ok(false) => Err(String::from("图片编码失败")),
+        Err(e) => Err(format!("图片编码失败: {}", e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+
+    // 三个使用方读取同一个帧序列：预览和上次识别画面使用相同的尺寸和质量，验证显示使用另一种
+    #[test]
+    fn encodes_each_size_once_per_frame() {
+        let consumers = [(640, 80), (640, 80), (320, 70)];
+        let mut cache = EncodeCache::new();
+        let encodes = Cell::new(0);
+        let mut hits = 0;
+        for frame_id in 1..=5u64 {
+            for key in consumers {
+                let (bytes, hit) = cache
+                    .get_or_encode(frame_id, key, || {
+                        encodes.set(encodes.get() + 1);
+                        Ok(vec![frame_id as u8, key.0 as u8, key.1 as u8])
+                    })
+                    .unwrap();
+                assert_eq!(*bytes, vec![frame_id as u8, key.0 as u8, key.1 as u8]);
+                hits += hit as usize;
+            }
+        }
+        // 每帧两种尺寸/质量，编码次数与使用方数量无关
+        assert_eq!(encodes.get(), 5 * 2);
+        assert_eq!(hits, 5);
+    }
+
+    #[test]
+    fn new_frame_invalidates_cache() {
+        let mut cache = EncodeCache::new();
+        cache.get_or_encode(1, (640, 80), || Ok(vec![1])).unwrap();
+        let (bytes, hit) = cache.get_or_encode(2, (640, 80), || Ok(vec![2])).unwrap();
+        assert!(!hit);
+        assert_eq!(*bytes, vec![2]);
+    }
+
+    #[test]
+    fn failed_encode_is_not_cached() {
+        let mut cache = EncodeCache::new();
+        assert!(cache.get_or_encode(1, (640, 80), || Err(String::from("fail"))).is_err());
+        let (_, hit) = cache.get_or_encode(1, (640, 80), || Ok(vec![1])).unwrap();
+        assert!(!hit);
+    }
+
+    #[test]
+    fn keeps_at_most_max_entries() {
+        let mut cache = EncodeCache::new();
+        for size in 0..(MAX_ENTRIES as i32 + 1) {
+            cache.get_or_encode(1, (size, 80), || Ok(vec![size as u8])).unwrap();
+        }
+        assert_eq!(cache.entries.len(), MAX_ENTRIES);
+        // 最早的尺寸被移除，需要重新编码
+        let (_, hit) = cache.get_or_encode(1, (0, 80), || Ok(vec![0])).unwrap();
+        assert!(!hit);
+    }
+}
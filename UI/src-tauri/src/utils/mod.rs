@@ -1,3 +1,8 @@
 pub mod api;
 pub mod custom_result;
-pub mod pipe;
\ No newline at end of file
+pub mod db_writer;
+pub mod frame_cache;
+pub mod pipe;
+pub mod priority;
+pub mod protocol;
+pub mod storage;
\ No newline at end of file
@@ -1,43 +1,118 @@
-use std::{ffi::OsStr, os::windows::ffi::OsStrExt};
+use std::{thread::sleep, time::{Duration, Instant}};
+
 use tauri_plugin_log::log::info;
 use windows::Win32::{
-    Foundation::{CloseHandle, GetLastError, E_UNEXPECTED, GENERIC_WRITE, HANDLE}, 
-    Storage::FileSystem::{CreateFileW, ReadFile, WriteFile, FILE_FLAGS_AND_ATTRIBUTES, FILE_SHARE_MODE, OPEN_EXISTING, PIPE_ACCESS_DUPLEX}, 
-    System::
-        Pipes::{ConnectNamedPipe, CreateNamedPipeW, DisconnectNamedPipe, WaitNamedPipeW, PIPE_READMODE_MESSAGE, PIPE_TYPE_MESSAGE, PIPE_UNLIMITED_INSTANCES, PIPE_WAIT}
+    Foundation::{CloseHandle, GetLastError, LocalFree, E_UNEXPECTED, ERROR_PIPE_CONNECTED, GENERIC_READ, GENERIC_WRITE, HANDLE, HLOCAL, INVALID_HANDLE_VALUE},
+    Security::{
+        Authorization::{ConvertSidToStringSidW, ConvertStringSecurityDescriptorToSecurityDescriptorW, SDDL_REVISION_1},
+        GetTokenInformation, TokenUser, PSECURITY_DESCRIPTOR, SECURITY_ATTRIBUTES, TOKEN_QUERY, TOKEN_USER,
+    },
+    Storage::FileSystem::{CreateFileW, ReadFile, WriteFile, FILE_FLAGS_AND_ATTRIBUTES, FILE_FLAG_FIRST_PIPE_INSTANCE, FILE_SHARE_MODE, OPEN_EXISTING, PIPE_ACCESS_DUPLEX},
+    System::{
+        Pipes::{ConnectNamedPipe, CreateNamedPipeW, DisconnectNamedPipe, GetNamedPipeClientProcessId, PeekNamedPipe, SetNamedPipeHandleState, WaitNamedPipeW, PIPE_READMODE_MESSAGE, PIPE_REJECT_REMOTE_CLIENTS, PIPE_TYPE_MESSAGE, PIPE_UNLIMITED_INSTANCES, PIPE_WAIT},
+        Threading::{GetCurrentProcess, OpenProcess, OpenProcessToken, QueryFullProcessImageNameW, PROCESS_NAME_WIN32, PROCESS_QUERY_LIMITED_INFORMATION},
+    },
 };
-use windows::core::{Error, Result, HSTRING};
+use windows::core::{Error, Result, HSTRING, PWSTR};
+
+use super::protocol::MAX_FRAME_LEN;
+
+// 读取一帧原始数据，解析交给 protocol 模块
+// 缓冲区比最大帧多 1 字节，超长的帧会读取失败或被 decode 拒绝，不会被截断后当作合法帧
+pub fn read_frame(handle: HANDLE) -> Result<Vec<u8>> {
+    read_message(handle, MAX_FRAME_LEN + 1)
+}
 
-pub fn read(handle: HANDLE) -> Result<String> {
-    let mut buf = [0u16; 256];
+// 读取一条消息，超过 max_len 的消息会读取失败（ERROR_MORE_DATA）
+pub fn read_message(handle: HANDLE, max_len: usize) -> Result<Vec<u8>> {
+    let mut buf = vec![0u8; max_len];
     let mut read = 0;
 
-    let byte_slice = unsafe { std::slice::from_raw_parts_mut(buf.as_mut_ptr() as *mut u8, buf.len() * 2) };
-    
-    unsafe { ReadFile(handle, Some(byte_slice), Some(&mut read), None) }?;
+    unsafe { ReadFile(handle, Some(&mut buf), Some(&mut read), None) }?;
+
+    buf.truncate(read as usize);
+    Ok(buf)
+}
+
+// 获取管道另一端的进程 ID 和程序路径
+pub fn client_process(handle: HANDLE) -> (u32, Option<String>) {
+    let mut pid = 0u32;
+    if unsafe { GetNamedPipeClientProcessId(handle, &mut pid) }.is_err() {
+        return (0, None);
+    }
+
+    let image = unsafe {
+        OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, pid)
+            .ok()
+            .and_then(|process| {
+                let mut buffer = [0u16; 1024];
+                let mut size = buffer.len() as u32;
+                let result = QueryFullProcessImageNameW(process, PROCESS_NAME_WIN32, PWSTR(buffer.as_mut_ptr()), &mut size);
+                let _ = CloseHandle(process);
+                result.ok().map(|_| String::from_utf16_lossy(&buffer[..size as usize]))
+            })
+    };
+    (pid, image)
+}
+
+// 获取当前用户的 SID 字符串
+pub(crate) fn current_user_sid() -> Result<String> {
+    unsafe {
+        let mut token = HANDLE::default();
+        OpenProcessToken(GetCurrentProcess(), TOKEN_QUERY, &mut token)?;
+
+        // 先获取需要的缓冲区大小
+        let mut size = 0u32;
+        let _ = GetTokenInformation(token, TokenUser, None, 0, &mut size);
+        let mut buffer = vec![0u8; size as usize];
+        let result = GetTokenInformation(token, TokenUser, Some(buffer.as_mut_ptr() as *mut _), size, &mut size);
+        let _ = CloseHandle(token);
+        result?;
+
+        let token_user = &*(buffer.as_ptr() as *const TOKEN_USER);
+        let mut sid_string = PWSTR::null();
+        ConvertSidToStringSidW(token_user.User.Sid, &mut sid_string)?;
+        let sid = sid_string.to_string().map_err(|e| Error::new(E_UNEXPECTED, format!("SID 转换失败: {:?}", e)));
+        let _ = LocalFree(Some(HLOCAL(sid_string.0 as *mut _)));
+        sid
+    }
+}
+
+// 等待并读取一帧，超时返回 None；对方在回复前关闭管道时返回错误（ERROR_BROKEN_PIPE）
+pub fn read_frame_timeout(handle: HANDLE, timeout: Duration) -> Result<Option<Vec<u8>>> {
+    read_message_timeout(handle, MAX_FRAME_LEN + 1, timeout)
+}
 
-    let content = String::from_utf16_lossy(&buf[.. (read as usize / 2)]);
-    
-    Ok(content.trim_matches('\0').to_string())
+// 等待并读取一条消息，超时返回 None；对方关闭管道时返回错误（ERROR_BROKEN_PIPE）
+// 同步管道的 ReadFile 无法取消，先用 PeekNamedPipe 轮询是否有数据
+pub fn read_message_timeout(handle: HANDLE, max_len: usize, timeout: Duration) -> Result<Option<Vec<u8>>> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        let mut available = 0u32;
+        unsafe { PeekNamedPipe(handle, None, 0, None, Some(&mut available), None) }?;
+        if available > 0 {
+            return read_message(handle, max_len).map(Some);
+        }
+        if Instant::now() >= deadline {
+            return Ok(None);
+        }
+        sleep(Duration::from_millis(10));
+    }
 }
 
-pub fn write(handle: HANDLE, content: String) -> Result<()> { 
-    // 转 UTF-16 含 \0
-    let wide_chars: Vec<u16> = OsStr::new(&content).encode_wide().chain(std::iter::once(0)).collect();
-    // 转 &[u8] 切片
-    let write_buf = unsafe { std::slice::from_raw_parts(
-        wide_chars.as_ptr() as *const u8,
-        wide_chars.len() * 2,
-    ) };
-    // 准备字节数（可变引用，匹配 Option<&mut u32>）
-    let mut total_bytes = write_buf.len() as u32;
-
-    unsafe { WriteFile(
-        handle,
-        Some(write_buf),
-        Some(&mut total_bytes),
-        None
-    ) }
+// 写入一帧由 protocol 模块编码的数据，写入不完整时继续写入剩余部分
+// 消息模式的管道一次写入整条消息，只有字节模式的管道可能出现部分写入
+pub fn write_frame(handle: HANDLE, frame: &[u8]) -> Result<()> {
+    let mut sent = 0;
+    while sent < frame.len() {
+        let mut written = 0u32;
+        unsafe { WriteFile(handle, Some(&frame[sent..]), Some(&mut written), None) }?;
+        if written == 0 {
+            return Err(Error::new(E_UNEXPECTED, "管道写入失败：没有写入任何数据"));
+        }
+        sent += written as usize;
+    }
+    Ok(())
 }
 
 pub struct Server {
@@ -62,9 +137,41 @@ impl Server {
         Self { handle: h_pipe, pipe_name, is_connected: false }
     }
 
+    // 创建只允许当前用户访问的命名管道，拒绝远程客户端，并且不允许其他进程抢先创建同名管道
+    pub fn new_for_current_user(pipe_name: HSTRING) -> Result<Self> {
+        // 只授予当前用户完全访问权限，不继承其他权限
+        let sddl = HSTRING::from(format!("D:P(A;;GA;;;{})", current_user_sid()?));
+        let mut descriptor = PSECURITY_DESCRIPTOR::default();
+        unsafe { ConvertStringSecurityDescriptorToSecurityDescriptorW(&sddl, SDDL_REVISION_1, &mut descriptor, None) }?;
+
+        let attributes = SECURITY_ATTRIBUTES {
+            nLength: std::mem::size_of::<SECURITY_ATTRIBUTES>() as u32,
+            lpSecurityDescriptor: descriptor.0,
+            bInheritHandle: false.into(),
+        };
+        let h_pipe = unsafe { CreateNamedPipeW(
+            &pipe_name,
+            PIPE_ACCESS_DUPLEX | FILE_FLAG_FIRST_PIPE_INSTANCE,
+            PIPE_TYPE_MESSAGE | PIPE_READMODE_MESSAGE | PIPE_WAIT | PIPE_REJECT_REMOTE_CLIENTS,
+            1,
+            4096, 4096, 0,
+            Some(&attributes)
+        ) };
+        let _ = unsafe { LocalFree(Some(HLOCAL(descriptor.0))) };
+
+        if h_pipe == INVALID_HANDLE_VALUE {
+            return Err(Error::from_win32());
+        }
+        Ok(Self { handle: h_pipe, pipe_name, is_connected: false })
+    }
+
     pub fn connect(&mut self) -> Result<()> {
-        // 等待管道连接
-        unsafe { ConnectNamedPipe(self.handle, None) }?;
+        // 等待管道连接，客户端在调用前已经连上时会返回 ERROR_PIPE_CONNECTED
+        if let Err(e) = unsafe { ConnectNamedPipe(self.handle, None) } {
+            if e.code() != ERROR_PIPE_CONNECTED.to_hresult() {
+                return Err(e);
+            }
+        }
         self.is_connected = true;
 
         Ok(())
@@ -94,6 +201,17 @@ pub struct Client {
 
 impl Client {
     pub fn new(pipe_name: HSTRING) -> Result<Self> {
+        Self::open(pipe_name, GENERIC_WRITE.0)
+    }
+
+    // 可读写的连接，发送消息后需要读取回复时使用，读取按消息模式，每次读取一帧
+    pub fn new_duplex(pipe_name: HSTRING) -> Result<Self> {
+        let client = Self::open(pipe_name, GENERIC_READ.0 | GENERIC_WRITE.0)?;
+        unsafe { SetNamedPipeHandleState(client.handle, Some(&PIPE_READMODE_MESSAGE), None, None) }?;
+        Ok(client)
+    }
+
+    fn open(pipe_name: HSTRING, access: u32) -> Result<Self> {
         let result = unsafe { WaitNamedPipeW(&pipe_name, 5000) };
         if !result.as_bool() {
             return Err(Error::new(E_UNEXPECTED, "管道不存在"));
@@ -102,7 +220,7 @@ impl Client {
         // 打开管道
         let handle = unsafe { CreateFileW(
             &pipe_name, // 管道名称
-            GENERIC_WRITE.0, // 对文件的操作模式，只写
+            access, // 对文件的操作模式
             FILE_SHARE_MODE(0), // 阻止对管道的后续打开操作，在我主动关闭之前
             None,
             OPEN_EXISTING, // 只在文件存在时才打开，否则返回错误
@@ -0,0 +1,77 @@
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use serde::Serialize;
+use tauri_plugin_log::log::warn;
+use windows::Win32::System::Threading::{
+    GetCurrentThread, GetThreadPriority, SetThreadPriority, THREAD_PRIORITY,
+    THREAD_PRIORITY_ABOVE_NORMAL, THREAD_PRIORITY_BELOW_NORMAL,
+};
+
+// 面容识别工作的类型，决定线程优先级
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum WorkMode {
+    /// 空闲，没有进行中的识别
+    Idle = 0,
+    /// 后台工作（预览、预热等），降低优先级，不影响用户前台程序
+    Background = 1,
+    /// 锁屏解锁，对延迟敏感，提高优先级
+    LockScreen = 2,
+}
+
+impl From<u8> for WorkMode {
+    fn from(value: u8) -> Self {
+        match value {
+            1 => WorkMode::Background,
+            2 => WorkMode::LockScreen,
+            _ => WorkMode::Idle,
+        }
+    }
+}
+
+// 当前的工作类型，用于诊断信息
+static CURRENT_MODE: AtomicU8 = AtomicU8::new(WorkMode::Idle as u8);
+
+// 获取当前的工作类型
+pub fn current_mode() -> WorkMode {
+    CURRENT_MODE.load(Ordering::SeqCst).into()
+}
+
+// 在作用域内调整当前线程优先级，离开作用域时恢复
+pub struct PriorityGuard {
+    previous_priority: i32,
+    previous_mode: u8,
+}
+
+impl PriorityGuard {
+    pub fn new(mode: WorkMode) -> Self {
+        let priority = match mode {
+            WorkMode::Background => THREAD_PRIORITY_BELOW_NORMAL,
+            WorkMode::LockScreen => THREAD_PRIORITY_ABOVE_NORMAL,
+            WorkMode::Idle => THREAD_PRIORITY(0),
+        };
+
+        let previous_priority = unsafe {
+            let thread = GetCurrentThread();
+            let previous = GetThreadPriority(thread);
+            if let Err(e) = SetThreadPriority(thread, priority) {
+                warn!("设置线程优先级失败 {:?}: {:?}", mode, e);
+            }
+            previous
+        };
+        let previous_mode = CURRENT_MODE.swap(mode as u8, Ordering::SeqCst);
+
+        Self {
+            previous_priority,
+            previous_mode,
+        }
+    }
+}
+
+impl Drop for PriorityGuard {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = SetThreadPriority(GetCurrentThread(), THREAD_PRIORITY(self.previous_priority));
+        }
+        CURRENT_MODE.store(self.previous_mode, Ordering::SeqCst);
+    }
+}
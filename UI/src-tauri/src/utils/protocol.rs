@@ -0,0 +1,561 @@
+// 软件与 DLL 之间的管道协议
+// 只依赖标准库，不涉及 Win32，DLL 通过 #[path] 直接引用本文件，保证两端编解码一致
+//
+// 帧格式（小端）：
+//   magic   4 字节  b"FWUP"
+//   version 1 字节
+//   kind    1 字节  消息类型
+//   length  4 字节  负载长度
+//   payload length 字节
+//
+// 负载按长度划分，不依赖结尾的 NUL；完整格式见 WIRE_FORMAT
+// 版本不一致的帧直接拒绝。过渡期内 DLL 同时接受旧的 NUL 结尾格式（见 decode_legacy），
+// 软件打开 legacyPipeFormat 选项后改用旧格式发送凭据，因此新软件可以配合旧 DLL 使用，两端不需要同时升级
+use std::fmt;
+
+// 线上格式说明，DLL 与软件都以此为准
+// 字符串使用 UTF-8 而不是 UTF-16：两端都是 Rust，String 本身就是 UTF-8，不需要来回转换；
+// 用户名和密码按长度划分，其中的 NUL 不会截断数据；DLL 填写登录凭据时再转换为 UTF-16
+pub const WIRE_FORMAT: &str = "magic b\"FWUP\" | version u8 = 1 | kind u8 | length u32 LE | payload[length]; \
+credentials(kind 1) = user_len u16 LE | user UTF-8 | password UTF-8; run(kind 2) = 空; ack(kind 3) = u8 0/1; \
+legacy = UTF-16LE \"user::FaceWinUnlock::password\" + NUL";
+
+// 帧头标识
+pub const MAGIC: [u8; 4] = *b"FWUP";
+// 当前协议版本，只接受相同版本的帧
+pub const VERSION: u8 = 1;
+// 帧头长度
+pub const HEADER_LEN: usize = 10;
+// 单帧最大长度，与管道缓冲区大小一致，超过的帧一次读不完
+pub const MAX_FRAME_LEN: usize = 512;
+// 负载最大长度
+pub const MAX_PAYLOAD_LEN: usize = MAX_FRAME_LEN - HEADER_LEN;
+
+// 消息类型
+const KIND_CREDENTIALS: u8 = 1;
+const KIND_RUN: u8 = 2;
+const KIND_ACK: u8 = 3;
+
+// 管道消息
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    /// 软件 -> DLL：解锁使用的用户名和密码
+    Credentials { user_name: String, password: String },
+    /// DLL -> 软件：锁屏界面有用户操作，可以开始面容识别
+    Run,
+    /// DLL -> 软件：对 Credentials 的回复，accepted 为 true 表示已接收凭据并通知锁屏界面登录
+    /// 旧版本的 DLL 不回复，收到凭据后直接断开
+    Ack { accepted: bool },
+}
+
+// 编解码错误
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FrameError {
+    /// 数据不足，needed 为至少需要的字节数
+    Truncated { needed: usize, actual: usize },
+    /// 帧超过最大长度
+    TooLarge { len: usize, max: usize },
+    /// 帧头标识错误
+    BadMagic,
+    /// 不支持的协议版本
+    UnsupportedVersion(u8),
+    /// 未知的消息类型
+    UnknownKind(u8),
+    /// 帧头声明的长度与实际数据长度不一致
+    LengthMismatch { declared: usize, actual: usize },
+    /// 负载格式错误
+    InvalidPayload(&'static str),
+}
+
+impl fmt::Display for FrameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FrameError::Truncated { needed, actual } => {
+                write!(f, "帧数据不完整，至少需要 {} 字节，实际 {} 字节", needed, actual)
+            }
+            FrameError::TooLarge { len, max } => {
+                write!(f, "帧长度 {} 超过上限 {}", len, max)
+            }
+            FrameError::BadMagic => write!(f, "帧头标识错误"),
+            FrameError::UnsupportedVersion(v) => write!(f, "不支持的协议版本 {}", v),
+            FrameError::UnknownKind(k) => write!(f, "未知的消息类型 {}", k),
+            FrameError::LengthMismatch { declared, actual } => {
+                write!(f, "帧声明长度 {} 与实际长度 {} 不一致", declared, actual)
+            }
+            FrameError::InvalidPayload(reason) => write!(f, "负载格式错误：{}", reason),
+        }
+    }
+}
+
+impl std::error::Error for FrameError {}
+
+// 编码消息为一帧
+// 负载直接写入帧，不经过中间缓冲区，密码在内存中只有帧这一份副本，调用方发送后负责清除
+pub fn encode(message: &Message) -> Result<Vec<u8>, FrameError> {
+    let (kind, payload_len) = match message {
+        Message::Credentials {
+            user_name,
+            password,
+        } => {
+            if user_name.len() > u16::MAX as usize {
+                return Err(FrameError::InvalidPayload("用户名过长"));
+            }
+            (KIND_CREDENTIALS, 2 + user_name.len() + password.len())
+        }
+        Message::Run => (KIND_RUN, 0),
+        Message::Ack { .. } => (KIND_ACK, 1),
+    };
+
+    if payload_len > MAX_PAYLOAD_LEN {
+        return Err(FrameError::TooLarge {
+            len: HEADER_LEN + payload_len,
+            max: MAX_FRAME_LEN,
+        });
+    }
+
+    // 预先分配全部容量，写入过程中不会重新分配而留下旧的副本
+    let mut frame = Vec::with_capacity(HEADER_LEN + payload_len);
+    frame.extend_from_slice(&MAGIC);
+    frame.push(VERSION);
+    frame.push(kind);
+    frame.extend_from_slice(&(payload_len as u32).to_le_bytes());
+    match message {
+        Message::Credentials {
+            user_name,
+            password,
+        } => {
+            // 用户名长度（u16） + 用户名 + 密码
+            frame.extend_from_slice(&(user_name.len() as u16).to_le_bytes());
+            frame.extend_from_slice(user_name.as_bytes());
+            frame.extend_from_slice(password.as_bytes());
+        }
+        Message::Run => {}
+        Message::Ack { accepted } => frame.push(*accepted as u8),
+    }
+    Ok(frame)
+}
+
+// 解码一帧，数据必须恰好是一个完整的帧
+pub fn decode(frame: &[u8]) -> Result<Message, FrameError> {
+    if frame.len() > MAX_FRAME_LEN {
+        return Err(FrameError::TooLarge {
+            len: frame.len(),
+            max: MAX_FRAME_LEN,
+        });
+    }
+    if frame.len() < HEADER_LEN {
+        return Err(FrameError::Truncated {
+            needed: HEADER_LEN,
+            actual: frame.len(),
+        });
+    }
+    if frame[0..4] != MAGIC {
+        return Err(FrameError::BadMagic);
+    }
+    if frame[4] != VERSION {
+        return Err(FrameError::UnsupportedVersion(frame[4]));
+    }
+    let kind = frame[5];
+    let declared = u32::from_le_bytes([frame[6], frame[7], frame[8], frame[9]]) as usize;
+    // 先检查声明长度，避免按非法长度分配或切片
+    if declared > MAX_PAYLOAD_LEN {
+        return Err(FrameError::TooLarge {
+            len: HEADER_LEN.saturating_add(declared),
+            max: MAX_FRAME_LEN,
+        });
+    }
+    let payload = &frame[HEADER_LEN..];
+    if payload.len() < declared {
+        return Err(FrameError::Truncated {
+            needed: HEADER_LEN + declared,
+            actual: frame.len(),
+        });
+    }
+    if payload.len() != declared {
+        return Err(FrameError::LengthMismatch {
+            declared,
+            actual: payload.len(),
+        });
+    }
+
+    match kind {
+        KIND_CREDENTIALS => decode_credentials(payload),
+        KIND_RUN => {
+            if payload.is_empty() {
+                Ok(Message::Run)
+            } else {
+                Err(FrameError::InvalidPayload("run 消息不应包含负载"))
+            }
+        }
+        KIND_ACK => match payload {
+            [0] => Ok(Message::Ack { accepted: false }),
+            [1] => Ok(Message::Ack { accepted: true }),
+            _ => Err(FrameError::InvalidPayload("ack 消息的负载应为 1 字节的 0 或 1")),
+        },
+        other => Err(FrameError::UnknownKind(other)),
+    }
+}
+
+// 旧格式中用户名与密码之间的分隔符
+pub const LEGACY_SEPARATOR: &str = "::FaceWinUnlock::";
+
+// 按旧格式编码凭据：UTF-16LE 的 "用户名::FaceWinUnlock::密码"，以 NUL 结尾，没有帧头
+// 只用于配合尚未升级的 DLL，密码中不能包含 NUL 或分隔符
+pub fn encode_legacy(user_name: &str, password: &str) -> Result<Vec<u8>, FrameError> {
+    if user_name.is_empty() {
+        return Err(FrameError::InvalidPayload("用户名为空"));
+    }
+    if user_name.contains(['\0']) || password.contains(['\0']) {
+        return Err(FrameError::InvalidPayload("旧格式不支持包含 NUL 的用户名或密码"));
+    }
+    if user_name.contains(LEGACY_SEPARATOR) || password.contains(LEGACY_SEPARATOR) {
+        return Err(FrameError::InvalidPayload("旧格式不支持包含分隔符的用户名或密码"));
+    }
+    let units = user_name.encode_utf16().count()
+        + LEGACY_SEPARATOR.len()
+        + password.encode_utf16().count()
+        + 1;
+    let len = units * 2;
+    if len > MAX_FRAME_LEN {
+        return Err(FrameError::TooLarge {
+            len,
+            max: MAX_FRAME_LEN,
+        });
+    }
+
+    // 与 encode 相同，预先分配全部容量，避免留下密码的副本
+    let mut frame = Vec::with_capacity(len);
+    let text = user_name
+        .encode_utf16()
+        .chain(LEGACY_SEPARATOR.encode_utf16())
+        .chain(password.encode_utf16())
+        .chain(std::iter::once(0));
+    for unit in text {
+        frame.extend_from_slice(&unit.to_le_bytes());
+    }
+    Ok(frame)
+}
+
+// 解码旧格式的凭据
+pub fn decode_legacy(frame: &[u8]) -> Result<Message, FrameError> {
+    if frame.len() > MAX_FRAME_LEN {
+        return Err(FrameError::TooLarge {
+            len: frame.len(),
+            max: MAX_FRAME_LEN,
+        });
+    }
+    if frame.len() % 2 != 0 {
+        return Err(FrameError::InvalidPayload("旧格式的长度应为偶数"));
+    }
+    let units: Vec<u16> = frame
+        .chunks_exact(2)
+        .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+        .collect();
+    // 数据在第一个 NUL 处结束
+    let end = units.iter().position(|unit| *unit == 0).unwrap_or(units.len());
+    let text = String::from_utf16(&units[..end])
+        .map_err(|_| FrameError::InvalidPayload("旧格式不是有效的 UTF-16"))?;
+    let mut parts = text.split(LEGACY_SEPARATOR);
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(user_name), Some(password), None) if !user_name.is_empty() => {
+            Ok(Message::Credentials {
+                user_name: user_name.to_string(),
+                password: password.to_string(),
+            })
+        }
+        _ => Err(FrameError::InvalidPayload("旧格式应为 用户名::FaceWinUnlock::密码")),
+    }
+}
+
+// 解码一帧，帧头标识不符时按旧格式解码，过渡期内 DLL 使用
+pub fn decode_with_legacy(frame: &[u8]) -> Result<Message, FrameError> {
+    match decode(frame) {
+        Err(FrameError::BadMagic) => decode_legacy(frame),
+        result => result,
+    }
+}
+
+fn decode_credentials(payload: &[u8]) -> Result<Message, FrameError> {
+    if payload.len() < 2 {
+        return Err(FrameError::InvalidPayload("缺少用户名长度"));
+    }
+    let user_len = u16::from_le_bytes([payload[0], payload[1]]) as usize;
+    let rest = &payload[2..];
+    if user_len > rest.len() {
+        return Err(FrameError::InvalidPayload("用户名长度超出负载"));
+    }
+    if user_len == 0 {
+        return Err(FrameError::InvalidPayload("用户名为空"));
+    }
+    let user_name = std::str::from_utf8(&rest[..user_len])
+        .map_err(|_| FrameError::InvalidPayload("用户名不是有效的 UTF-8"))?;
+    let password = std::str::from_utf8(&rest[user_len..])
+        .map_err(|_| FrameError::InvalidPayload("密码不是有效的 UTF-8"))?;
+    Ok(Message::Credentials {
+        user_name: user_name.to_string(),
+        password: password.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn credentials(user_name: &str, password: &str) -> Message {
+        Message::Credentials {
+            user_name: user_name.to_string(),
+            password: password.to_string(),
+        }
+    }
+
+    // 手工拼一帧，用于构造 encode 不会生成的非法帧
+    fn raw_frame(version: u8, kind: u8, payload: &[u8]) -> Vec<u8> {
+        let mut frame = Vec::new();
+        frame.extend_from_slice(&MAGIC);
+        frame.push(version);
+        frame.push(kind);
+        frame.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        frame.extend_from_slice(payload);
+        frame
+    }
+
+    #[test]
+    fn round_trip_every_kind() {
+        let messages = [
+            credentials("DESKTOP\\用户", "p@ss::FaceWinUnlock::word"),
+            credentials("user", ""),
+            Message::Run,
+            Message::Ack { accepted: true },
+            Message::Ack { accepted: false },
+        ];
+        for message in messages {
+            let frame = encode(&message).unwrap();
+            assert_eq!(decode(&frame), Ok(message));
+        }
+    }
+
+    #[test]
+    fn largest_credentials_fit_in_one_frame() {
+        let password = "x".repeat(MAX_PAYLOAD_LEN - 2 - 4);
+        let frame = encode(&credentials("user", &password)).unwrap();
+        assert_eq!(frame.len(), MAX_FRAME_LEN);
+        assert_eq!(decode(&frame), Ok(credentials("user", &password)));
+
+        let password = "x".repeat(MAX_PAYLOAD_LEN - 2 - 4 + 1);
+        assert_eq!(
+            encode(&credentials("user", &password)),
+            Err(FrameError::TooLarge {
+                len: MAX_FRAME_LEN + 1,
+                max: MAX_FRAME_LEN
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_bad_magic() {
+        let mut frame = encode(&Message::Run).unwrap();
+        frame[0] = b'X';
+        assert_eq!(decode(&frame), Err(FrameError::BadMagic));
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        for version in [0, VERSION + 1, u8::MAX] {
+            let frame = raw_frame(version, KIND_RUN, &[]);
+            assert_eq!(decode(&frame), Err(FrameError::UnsupportedVersion(version)));
+        }
+    }
+
+    #[test]
+    fn rejects_truncated_header() {
+        let frame = encode(&Message::Run).unwrap();
+        for len in 0..HEADER_LEN {
+            assert_eq!(
+                decode(&frame[..len]),
+                Err(FrameError::Truncated {
+                    needed: HEADER_LEN,
+                    actual: len
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_truncated_payload() {
+        let frame = encode(&credentials("user", "password")).unwrap();
+        let cut = &frame[..frame.len() - 1];
+        assert_eq!(
+            decode(cut),
+            Err(FrameError::Truncated {
+                needed: frame.len(),
+                actual: cut.len()
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_frame_over_max_len() {
+        let mut frame = raw_frame(VERSION, KIND_CREDENTIALS, &[0; MAX_PAYLOAD_LEN]);
+        frame.push(0);
+        assert_eq!(frame.len(), MAX_FRAME_LEN + 1);
+        assert_eq!(
+            decode(&frame),
+            Err(FrameError::TooLarge {
+                len: MAX_FRAME_LEN + 1,
+                max: MAX_FRAME_LEN
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_declared_length_over_max() {
+        let mut frame = raw_frame(VERSION, KIND_CREDENTIALS, &[]);
+        frame[6..10].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(matches!(decode(&frame), Err(FrameError::TooLarge { .. })));
+    }
+
+    #[test]
+    fn rejects_trailing_bytes() {
+        let mut frame = encode(&Message::Ack { accepted: true }).unwrap();
+        frame.push(0);
+        assert_eq!(
+            decode(&frame),
+            Err(FrameError::LengthMismatch {
+                declared: 1,
+                actual: 2
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_kind() {
+        for kind in [0, 99] {
+            assert_eq!(
+                decode(&raw_frame(VERSION, kind, &[])),
+                Err(FrameError::UnknownKind(kind))
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_bad_utf8() {
+        // 用户名不是 UTF-8
+        let mut payload = 2u16.to_le_bytes().to_vec();
+        payload.extend_from_slice(&[0xC3, 0x28]);
+        payload.extend_from_slice(b"password");
+        assert_eq!(
+            decode(&raw_frame(VERSION, KIND_CREDENTIALS, &payload)),
+            Err(FrameError::InvalidPayload("用户名不是有效的 UTF-8"))
+        );
+
+        // 密码不是 UTF-8
+        let mut payload = 4u16.to_le_bytes().to_vec();
+        payload.extend_from_slice(b"user");
+        payload.extend_from_slice(&[0xFF, 0xFE]);
+        assert_eq!(
+            decode(&raw_frame(VERSION, KIND_CREDENTIALS, &payload)),
+            Err(FrameError::InvalidPayload("密码不是有效的 UTF-8"))
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_credentials() {
+        let cases: [&[u8]; 3] = [
+            // 缺少用户名长度
+            &[4],
+            // 用户名长度超出负载
+            &[9, 0, b'u', b's', b'e', b'r'],
+            // 用户名为空
+            &[0, 0, b'p', b'w'],
+        ];
+        for payload in cases {
+            assert!(matches!(
+                decode(&raw_frame(VERSION, KIND_CREDENTIALS, payload)),
+                Err(FrameError::InvalidPayload(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn legacy_round_trip() {
+        let frame = encode_legacy("user", "密码 password").unwrap();
+        assert_eq!(frame.len() % 2, 0);
+        assert_eq!(&frame[frame.len() - 2..], &[0, 0]);
+        assert_eq!(decode_legacy(&frame), Ok(credentials("user", "密码 password")));
+        assert_eq!(
+            decode_with_legacy(&frame),
+            Ok(credentials("user", "密码 password"))
+        );
+    }
+
+    #[test]
+    fn legacy_reader_ignores_padding_after_nul() {
+        let mut frame = encode_legacy("user", "password").unwrap();
+        frame.extend_from_slice(&[0; 8]);
+        assert_eq!(decode_legacy(&frame), Ok(credentials("user", "password")));
+    }
+
+    #[test]
+    fn legacy_rejects_unrepresentable_credentials() {
+        for (user_name, password) in [
+            ("", "password"),
+            ("user", "pass\0word"),
+            ("user", "a::FaceWinUnlock::b"),
+        ] {
+            assert!(matches!(
+                encode_legacy(user_name, password),
+                Err(FrameError::InvalidPayload(_))
+            ));
+        }
+        let password = "x".repeat(MAX_FRAME_LEN);
+        assert!(matches!(
+            encode_legacy("user", &password),
+            Err(FrameError::TooLarge { .. })
+        ));
+    }
+
+    #[test]
+    fn legacy_rejects_malformed_text() {
+        let text: Vec<u8> = "no separator\0"
+            .encode_utf16()
+            .flat_map(|unit| unit.to_le_bytes())
+            .collect();
+        assert!(matches!(
+            decode_legacy(&text),
+            Err(FrameError::InvalidPayload(_))
+        ));
+        // 未配对的代理项
+        assert!(matches!(
+            decode_legacy(&[0x00, 0xD8, 0, 0]),
+            Err(FrameError::InvalidPayload(_))
+        ));
+        assert!(matches!(
+            decode_legacy(&[0x41]),
+            Err(FrameError::InvalidPayload(_))
+        ));
+    }
+
+    #[test]
+    fn new_frames_never_fall_back_to_legacy() {
+        let mut frame = encode(&Message::Run).unwrap();
+        frame[4] = VERSION + 1;
+        assert_eq!(
+            decode_with_legacy(&frame),
+            Err(FrameError::UnsupportedVersion(VERSION + 1))
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_run_and_ack() {
+        assert!(matches!(
+            decode(&raw_frame(VERSION, KIND_RUN, &[0])),
+            Err(FrameError::InvalidPayload(_))
+        ));
+        for payload in [&[][..], &[2][..], &[1, 1][..]] {
+            assert!(matches!(
+                decode(&raw_frame(VERSION, KIND_ACK, payload)),
+                Err(FrameError::InvalidPayload(_))
+            ));
+        }
+    }
+}
@@ -0,0 +1,379 @@
+use std::{
+    env, fs, io,
+    os::windows::fs::MetadataExt,
+    path::{Path, PathBuf},
+    thread::sleep,
+    time::Duration,
+};
+
+use tauri_plugin_log::log::{info, warn};
+use windows::{
+    core::HSTRING,
+    Win32::{
+        Foundation::{LocalFree, ERROR_ACCESS_DENIED, ERROR_LOCK_VIOLATION, ERROR_SHARING_VIOLATION, HLOCAL},
+        Security::{
+            Authorization::{ConvertStringSecurityDescriptorToSecurityDescriptorW, SDDL_REVISION_1},
+            PSECURITY_DESCRIPTOR, SECURITY_ATTRIBUTES,
+        },
+        Storage::FileSystem::{
+            CreateDirectoryW, FILE_ATTRIBUTE_OFFLINE, FILE_ATTRIBUTE_RECALL_ON_DATA_ACCESS,
+            FILE_ATTRIBUTE_RECALL_ON_OPEN, FILE_ATTRIBUTE_REPARSE_POINT,
+        },
+    },
+};
+use winreg::{enums::HKEY_LOCAL_MACHINE, RegKey};
+
+use crate::{modules::face_store::FACE_STORE_FILE, utils::pipe::current_user_sid, ROOT_DIR};
+
+// 与 tauri.conf.json 中的 identifier 一致
+const APP_IDENTIFIER: &str = "com.administrator.facewinunlock-tauri";
+
+// 文件被占用时的重试间隔（毫秒），每次翻倍
+const RETRY_BACKOFF_MS: [u64; 4] = [50, 100, 200, 400];
+
+lazy_static::lazy_static! {
+    // 面容数据目录，程序运行期间只解析一次
+    static ref FACES_DIR: PathBuf = resolve_faces_dir();
+}
+
+// 获取面容数据目录
+pub fn faces_dir() -> &'static Path {
+    FACES_DIR.as_path()
+}
+
+// 选择面容数据目录
+// 默认在软件目录下，如果软件目录被 OneDrive 同步，则改用 LocalAppData（OneDrive 不会同步该目录）
+fn resolve_faces_dir() -> PathBuf {
+    let default_dir = legacy_faces_dir();
+    if !is_cloud_synced(&ROOT_DIR) && !is_cloud_synced(&default_dir) {
+        return default_dir;
+    }
+
+    let Some(local_dir) = app_data_dir().map(|dir| dir.join("faces")) else {
+        warn!("软件目录处于云同步目录中，但获取 LocalAppData 失败，继续使用默认目录");
+        return default_dir;
+    };
+
+    if let Err(e) = fs::create_dir_all(&local_dir) {
+        warn!("创建 LocalAppData 面容目录失败，继续使用默认目录: {}", e);
+        return default_dir;
+    }
+
+    // 旧目录中的数据由启动迁移复制过去
+    info!("软件目录处于云同步目录中，面容数据改为存放在 {:?}", local_dir);
+    local_dir
+}
+
+// 软件在 LocalAppData 中的数据目录，获取环境变量失败时返回 None
+pub fn app_data_dir() -> Option<PathBuf> {
+    env::var_os("LOCALAPPDATA").map(|dir| PathBuf::from(dir).join("facewinunlock-tauri"))
+}
+
+// tauri_plugin_sql 存放数据库的目录，与 tauri 的 app_config_dir 相同（AppData\Roaming\<identifier>）
+// 迁移在 tauri 启动前执行，拿不到 AppHandle，这里按同样的规则拼出路径
+pub fn app_config_dir() -> Option<PathBuf> {
+    env::var_os("APPDATA").map(|dir| PathBuf::from(dir).join(APP_IDENTIFIER))
+}
+
+// 面容特征数据库，与 tauri_plugin_sql 注册的 FACE_STORE_URL 是同一个文件，软件安装在 Program Files 时也可以写入
+// 获取 AppData 失败时放在面容目录旁边
+pub fn face_store_path() -> PathBuf {
+    app_config_dir()
+        .unwrap_or_else(|| faces_dir().parent().unwrap_or(&ROOT_DIR).to_path_buf())
+        .join(FACE_STORE_FILE)
+}
+
+// 软件目录下的默认面容目录
+pub fn legacy_faces_dir() -> PathBuf {
+    ROOT_DIR.join("faces")
+}
+
+// 调试数据目录，与面容数据放在一起，不放在可能被云同步的软件目录下
+pub fn debug_dir() -> PathBuf {
+    faces_dir().parent().unwrap_or(&ROOT_DIR).join("debug")
+}
+
+// 创建只有当前用户和 SYSTEM 可以访问的目录，不继承上级目录的权限
+// 目录已存在时不修改权限
+pub fn create_private_dir(path: &Path) -> io::Result<()> {
+    if path.exists() {
+        return Ok(());
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let sid = current_user_sid().map_err(io::Error::other)?;
+    let sddl = HSTRING::from(format!("D:P(A;OICI;FA;;;{})(A;OICI;FA;;;SY)", sid));
+    let mut descriptor = PSECURITY_DESCRIPTOR::default();
+    unsafe { ConvertStringSecurityDescriptorToSecurityDescriptorW(&sddl, SDDL_REVISION_1, &mut descriptor, None) }
+        .map_err(io::Error::other)?;
+    let attributes = SECURITY_ATTRIBUTES {
+        nLength: std::mem::size_of::<SECURITY_ATTRIBUTES>() as u32,
+        lpSecurityDescriptor: descriptor.0,
+        bInheritHandle: false.into(),
+    };
+    let result = unsafe { CreateDirectoryW(&HSTRING::from(path.as_os_str()), Some(&attributes)) };
+    let _ = unsafe { LocalFree(Some(HLOCAL(descriptor.0))) };
+    result.map_err(io::Error::other)
+}
+
+// 把旧目录中的面容文件复制到当前目录，已存在的文件跳过
+// 旧文件保留，避免复制中断导致数据丢失，返回复制的文件数
+pub fn copy_missing_faces(from: &Path, to: &Path) -> io::Result<usize> {
+    let mut copied = 0;
+    for entry in fs::read_dir(from)?.flatten() {
+        let target = to.join(entry.file_name());
+        if target.exists() {
+            continue;
+        }
+        with_retry(|| fs::copy(entry.path(), &target))?;
+        copied += 1;
+    }
+    Ok(copied)
+}
+
+// 判断路径是否处于 OneDrive 等云同步目录中
+pub fn is_cloud_synced(path: &Path) -> bool {
+    // OneDrive 会设置这几个环境变量，指向同步根目录
+    for var in ["OneDrive", "OneDriveConsumer", "OneDriveCommercial"] {
+        if let Some(root) = env::var_os(var) {
+            if !root.is_empty() && path.starts_with(PathBuf::from(root)) {
+                return true;
+            }
+        }
+    }
+
+    // 同步目录本身带有重解析点属性
+    match fs::metadata(path) {
+        Ok(meta) => meta.file_attributes() & FILE_ATTRIBUTE_REPARSE_POINT.0 != 0,
+        Err(_) => false,
+    }
+}
+
+// 判断文件是否为云端占位文件（已被 OneDrive 释放本地空间）
+pub fn is_cloud_placeholder(path: &Path) -> bool {
+    let mask = FILE_ATTRIBUTE_OFFLINE.0
+        | FILE_ATTRIBUTE_RECALL_ON_OPEN.0
+        | FILE_ATTRIBUTE_RECALL_ON_DATA_ACCESS.0;
+    match fs::metadata(path) {
+        Ok(meta) => meta.file_attributes() & mask != 0,
+        Err(_) => false,
+    }
+}
+
+// 判断是否开启了 Windows 受控文件夹访问（Defender 勒索软件防护）
+pub fn is_controlled_folder_access_enabled() -> bool {
+    let hklm = RegKey::predef(HKEY_LOCAL_MACHINE);
+    let Ok(key) = hklm.open_subkey(
+        "SOFTWARE\\Microsoft\\Windows Defender\\Windows Defender Exploit Guard\\Controlled Folder Access",
+    ) else {
+        return false;
+    };
+
+    // 1 开启 2 仅审核
+    matches!(key.get_value::<u32, _>("EnableControlledFolderAccess"), Ok(1))
+}
+
+// 文件被占用/被拦截时按退避时间重试
+pub fn with_retry<T, F>(mut f: F) -> io::Result<T>
+where
+    F: FnMut() -> io::Result<T>,
+{
+    for delay in RETRY_BACKOFF_MS {
+        match f() {
+            Ok(v) => return Ok(v),
+            Err(e) if is_transient(&e) => {
+                warn!("文件被占用，{}ms 后重试: {}", delay, e);
+                sleep(Duration::from_millis(delay));
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    // 最后再试一次，失败直接返回错误
+    f()
+}
+
+// 是否为可以重试的临时错误
+fn is_transient(e: &io::Error) -> bool {
+    match e.raw_os_error() {
+        Some(code) => {
+            let code = code as u32;
+            code == ERROR_SHARING_VIOLATION.0
+                || code == ERROR_LOCK_VIOLATION.0
+                || code == ERROR_ACCESS_DENIED.0
+        }
+        None => false,
+    }
+}
+
+// Windows 保留的设备名，不能作为文件名
+const RESERVED_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+// 文件名主体的最大长度（不含哈希后缀）
+const MAX_STEM_LEN: usize = 48;
+
+// 把用户输入的名称转换为安全的文件名（不含扩展名）
+// 只保留 ASCII 字母数字和 -_，中文等字符会被去掉，并追加原名称的哈希，避免不同名称冲突
+pub fn safe_file_stem(name: &str) -> String {
+    let name = name.trim();
+    let mut stem = String::new();
+    let mut is_lossy = false;
+    for c in name.chars() {
+        if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+            stem.push(c);
+        } else if c == ' ' || c == '.' {
+            stem.push('_');
+        } else {
+            is_lossy = true;
+        }
+    }
+    stem.truncate(MAX_STEM_LEN);
+    let stem = stem.trim_matches('_').to_string();
+
+    let mut result = if stem.is_empty() {
+        format!("face-{:016x}", fnv1a_hash(name))
+    } else if is_lossy || stem.len() == MAX_STEM_LEN {
+        format!("{}-{:08x}", stem, fnv1a_hash(name) as u32)
+    } else {
+        stem
+    };
+
+    // CON、NUL 之类的名称不能使用
+    if RESERVED_NAMES.contains(&result.to_ascii_uppercase().as_str()) {
+        result.insert(0, '_');
+    }
+    result
+}
+
+// 用于比较/搜索的名称，按 Unicode 大小写折叠忽略大小写（ß 与 SS、ς 与 Σ 视为相同）
+pub fn name_key(name: &str) -> String {
+    let mut key = String::new();
+    for c in name.trim().chars() {
+        fold_case(c, &mut key);
+    }
+    key
+}
+
+// 完整大小写折叠（CaseFolding.txt 中的 C 和 F）中与 char::to_lowercase 结果不同的字符单独处理
+fn fold_case(c: char, out: &mut String) {
+    match c {
+        'ß' | 'ẞ' => out.push_str("ss"),
+        'ŉ' => out.push_str("ʼn"),
+        'ſ' => out.push('s'),
+        'µ' => out.push('μ'),
+        'ς' => out.push('σ'),
+        'ϐ' => out.push('β'),
+        'ϑ' => out.push('θ'),
+        'ϕ' => out.push('φ'),
+        'ϖ' => out.push('π'),
+        'ϰ' => out.push('κ'),
+        'ϱ' => out.push('ρ'),
+        'ϵ' => out.push('ε'),
+        '\u{0345}' | 'ι' => out.push('ι'),
+        'ẛ' => out.push('ṡ'),
+        'ﬀ' => out.push_str("ff"),
+        'ﬁ' => out.push_str("fi"),
+        'ﬂ' => out.push_str("fl"),
+        'ﬃ' => out.push_str("ffi"),
+        'ﬄ' => out.push_str("ffl"),
+        'ﬅ' | 'ﬆ' => out.push_str("st"),
+        // 切罗基文小写字母折叠为大写，大写字母不变
+        '\u{13A0}'..='\u{13F5}' => out.push(c),
+        '\u{13F8}'..='\u{13FD}' => out.push(char::from_u32(c as u32 - 8).unwrap_or(c)),
+        '\u{AB70}'..='\u{ABBF}' => out.push(char::from_u32(c as u32 - 0xAB70 + 0x13A0).unwrap_or(c)),
+        _ => out.extend(c.to_lowercase()),
+    }
+}
+
+// 把面容名称等敏感名称替换为哈希，用于诊断信息中区分不同条目
+pub fn redact_name(name: &str) -> String {
+    format!("name-{:016x}", fnv1a_hash(name))
+}
+
+// FNV-1a 64 位哈希，结果与平台和 Rust 版本无关，适合用在文件名中
+fn fnv1a_hash(s: &str) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for b in s.as_bytes() {
+        hash ^= *b as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    fn is_safe(stem: &str) -> bool {
+        !stem.is_empty()
+            && stem.len() <= MAX_STEM_LEN + 9
+            && stem.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+            && !RESERVED_NAMES.contains(&stem.to_ascii_uppercase().as_str())
+    }
+
+    #[test]
+    fn ascii_names_are_kept() {
+        assert_eq!(safe_file_stem("alice"), "alice");
+        assert_eq!(safe_file_stem("  Bob Smith "), "Bob_Smith");
+        assert_eq!(safe_file_stem("v1.2"), "v1_2");
+    }
+
+    #[test]
+    fn non_ascii_names_get_distinct_safe_stems() {
+        let names = ["张三", "李四", "山田太郎", "😀", "😀😀", "👨‍👩‍👧", "Zoë", "Zoe", "Иван Petrov", "김민수 Kim", "张三 (2)"];
+        let stems: Vec<_> = names.iter().map(|n| safe_file_stem(n)).collect();
+        for (name, stem) in names.iter().zip(&stems) {
+            assert!(is_safe(stem), "{} -> {}", name, stem);
+        }
+        // 文件名不区分大小写，比较时忽略大小写
+        let keys: HashSet<_> = stems.iter().map(|s| s.to_ascii_lowercase()).collect();
+        assert_eq!(keys.len(), names.len());
+
+        // 纯中文和表情只剩哈希，带 ASCII 的保留可读部分
+        assert!(stems[0].starts_with("face-"));
+        assert!(stems[3].starts_with("face-"));
+        assert!(stems[6].starts_with("Zo-"));
+        assert_eq!(stems[7], "Zoe");
+        assert!(stems[8].starts_with("Petrov-"));
+        assert!(stems[9].starts_with("Kim-"));
+        // 同一个名称总是得到同一个文件名
+        assert_eq!(safe_file_stem("张三"), stems[0]);
+    }
+
+    #[test]
+    fn reserved_device_names_are_escaped() {
+        for name in ["CON", "con", "Nul", "COM1", "lpt9", "AUX"] {
+            let stem = safe_file_stem(name);
+            assert!(is_safe(&stem), "{} -> {}", name, stem);
+            assert_eq!(stem, format!("_{}", name));
+        }
+        // 只是包含保留名的名称不需要处理
+        assert_eq!(safe_file_stem("CONSOLE"), "CONSOLE");
+        assert_eq!(safe_file_stem("con.txt"), "con_txt");
+    }
+
+    #[test]
+    fn long_names_are_truncated_with_hash() {
+        let long = "a".repeat(100);
+        let stem = safe_file_stem(&long);
+        assert!(is_safe(&stem));
+        assert_ne!(stem, safe_file_stem(&"a".repeat(99)));
+    }
+
+    #[test]
+    fn name_key_uses_case_folding() {
+        assert_eq!(name_key("Straße"), name_key("STRASSE"));
+        assert_eq!(name_key("ὈΔΥΣΣΕΎΣ"), name_key("ὀδυσσεύς"));
+        assert_eq!(name_key(" ǅemal "), name_key("ǆemal"));
+        assert_eq!(name_key("ﬁle"), "file");
+        assert_eq!(name_key("ᏸᎠ"), name_key("ᏰᎠ"));
+        assert_eq!(name_key("张三"), "张三");
+        assert_eq!(name_key("😀 Bob"), "😀 bob");
+        assert_ne!(name_key("张三"), name_key("张四"));
+    }
+}
@@ -0,0 +1,471 @@
+// 主窗口在多显示器、混合 DPI 环境下的位置保存与恢复
+// 窗口隐藏到托盘时记录所在显示器和相对位置，从托盘显示时按显示器恢复
+// 位置计算部分不依赖 Win32，只操作矩形
+use std::collections::BTreeMap;
+
+use r2d2_sqlite::rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use tauri::{Runtime, WebviewWindow, Window};
+use tauri_plugin_log::log::{info, warn};
+use windows::{
+    core::{BOOL, PCWSTR},
+    Win32::{
+        Foundation::{HWND, LPARAM, POINT, RECT},
+        Graphics::Gdi::{
+            EnumDisplayDevicesW, EnumDisplayMonitors, GetMonitorInfoW, DISPLAY_DEVICEW, HDC,
+            HMONITOR, MONITORINFO, MONITORINFOEXW,
+        },
+        UI::{
+            HiDpi::{GetDpiForMonitor, MDT_EFFECTIVE_DPI},
+            WindowsAndMessaging::{
+                GetCursorPos, GetWindowRect, IsIconic, IsWindowVisible, SetWindowPos,
+                EDD_GET_DEVICE_INTERFACE_NAME, SWP_NOACTIVATE, SWP_NOZORDER,
+            },
+        },
+    },
+};
+
+use crate::{
+    modules::options::{get_conn, query_option, save_option},
+    utils::db_writer,
+};
+
+// 保存位置的设置项，值为 JSON：{ "last": 显示器 id, "monitors": { 显示器 id: 位置 } }
+const PLACEMENT_OPTION: &str = "windowPlacement";
+// 96 DPI 对应 100% 缩放
+const BASE_DPI: i32 = 96;
+// 窗口至少要有这么多像素（逻辑像素）留在工作区内，否则视为跑出屏幕
+const MIN_VISIBLE: i32 = 48;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Rect {
+    pub left: i32,
+    pub top: i32,
+    pub right: i32,
+    pub bottom: i32,
+}
+
+impl Rect {
+    pub fn width(&self) -> i32 {
+        self.right - self.left
+    }
+
+    pub fn height(&self) -> i32 {
+        self.bottom - self.top
+    }
+
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        x >= self.left && x < self.right && y >= self.top && y < self.bottom
+    }
+
+    fn intersection_area(&self, other: &Rect) -> i64 {
+        let w = (self.right.min(other.right) - self.left.max(other.left)).max(0) as i64;
+        let h = (self.bottom.min(other.bottom) - self.top.max(other.top)).max(0) as i64;
+        w * h
+    }
+}
+
+impl From<RECT> for Rect {
+    fn from(r: RECT) -> Self {
+        Self { left: r.left, top: r.top, right: r.right, bottom: r.bottom }
+    }
+}
+
+// 一个显示器，坐标均为物理像素
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Monitor {
+    // 显示器设备接口名，重新插拔后保持不变
+    pub id: String,
+    // 工作区（不含任务栏）
+    pub work: Rect,
+    pub dpi: u32,
+}
+
+impl Monitor {
+    fn to_physical(&self, logical: i32) -> i32 {
+        logical * self.dpi as i32 / BASE_DPI
+    }
+
+    fn to_logical(&self, physical: i32) -> i32 {
+        physical * BASE_DPI / self.dpi.max(1) as i32
+    }
+}
+
+// 保存的位置：相对显示器工作区左上角的偏移和窗口大小，按 96 DPI 的逻辑像素保存
+// 显示器缩放比例变化后仍能按比例恢复
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SavedPlacement {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct PlacementStore {
+    // 最后一次隐藏时所在的显示器
+    last: Option<String>,
+    monitors: BTreeMap<String, SavedPlacement>,
+}
+
+// 把窗口矩形换算为相对于所在显示器的保存位置
+pub fn to_saved(monitor: &Monitor, window: Rect) -> SavedPlacement {
+    SavedPlacement {
+        x: monitor.to_logical(window.left - monitor.work.left),
+        y: monitor.to_logical(window.top - monitor.work.top),
+        width: monitor.to_logical(window.width()),
+        height: monitor.to_logical(window.height()),
+    }
+}
+
+// 窗口所在的显示器：与窗口重叠面积最大的那个，都不重叠时返回 None
+pub fn monitor_for_rect<'a>(monitors: &'a [Monitor], rect: Rect) -> Option<&'a Monitor> {
+    monitors
+        .iter()
+        .map(|m| (m, m.work.intersection_area(&rect)))
+        .filter(|(_, area)| *area > 0)
+        .max_by_key(|(_, area)| *area)
+        .map(|(m, _)| m)
+}
+
+// 包含指定点的显示器，点不在任何显示器上时取第一个（主显示器）
+pub fn monitor_at(monitors: &[Monitor], x: i32, y: i32) -> Option<&Monitor> {
+    monitors
+        .iter()
+        .find(|m| m.work.contains(x, y))
+        .or_else(|| monitors.first())
+}
+
+// 把窗口限制在工作区内：比工作区大时缩小，超出边界时移回
+pub fn clamp_to_work_area(rect: Rect, work: Rect) -> Rect {
+    let width = rect.width().min(work.width()).max(0);
+    let height = rect.height().min(work.height()).max(0);
+    let left = rect.left.clamp(work.left, work.right - width);
+    let top = rect.top.clamp(work.top, work.bottom - height);
+    Rect { left, top, right: left + width, bottom: top + height }
+}
+
+// 窗口是否有足够的部分留在某个显示器上，标题栏不可见时用户无法拖回
+pub fn is_reachable(monitors: &[Monitor], rect: Rect) -> bool {
+    monitors.iter().any(|m| {
+        let visible_w = rect.right.min(m.work.right) - rect.left.max(m.work.left);
+        let title_visible = rect.top >= m.work.top && rect.top < m.work.bottom;
+        visible_w >= m.to_physical(MIN_VISIBLE) && title_visible
+    })
+}
+
+// 计算恢复时的窗口矩形
+// 保存的显示器仍然存在时按原相对位置恢复，否则在光标所在显示器上居中
+// current 为窗口当前矩形，没有任何保存位置时用它的大小
+pub fn resolve_placement(
+    saved: Option<(&str, &SavedPlacement)>,
+    monitors: &[Monitor],
+    cursor: (i32, i32),
+    current: Rect,
+) -> Option<Rect> {
+    if let Some((id, placement)) = saved {
+        if let Some(monitor) = monitors.iter().find(|m| m.id == id) {
+            let left = monitor.work.left + monitor.to_physical(placement.x);
+            let top = monitor.work.top + monitor.to_physical(placement.y);
+            let rect = Rect {
+                left,
+                top,
+                right: left + monitor.to_physical(placement.width),
+                bottom: top + monitor.to_physical(placement.height),
+            };
+            return Some(clamp_to_work_area(rect, monitor.work));
+        }
+    }
+
+    let monitor = monitor_at(monitors, cursor.0, cursor.1)?;
+    // 保存的大小按目标显示器的缩放换算，没有保存时沿用当前大小
+    let (width, height) = match saved {
+        Some((_, p)) => (monitor.to_physical(p.width), monitor.to_physical(p.height)),
+        None => (current.width(), current.height()),
+    };
+    let left = monitor.work.left + (monitor.work.width() - width) / 2;
+    let top = monitor.work.top + (monitor.work.height() - height) / 2;
+    Some(clamp_to_work_area(
+        Rect { left, top, right: left + width, bottom: top + height },
+        monitor.work,
+    ))
+}
+
+// 可能跑出屏幕的窗口重新放回最近的显示器，不需要移动时返回 None
+pub fn reclamp(monitors: &[Monitor], rect: Rect) -> Option<Rect> {
+    if is_reachable(monitors, rect) {
+        return None;
+    }
+    let center = ((rect.left + rect.right) / 2, (rect.top + rect.bottom) / 2);
+    let monitor = monitor_for_rect(monitors, rect).or_else(|| monitor_at(monitors, center.0, center.1))?;
+    let clamped = clamp_to_work_area(rect, monitor.work);
+    (clamped != rect).then_some(clamped)
+}
+
+unsafe extern "system" fn collect_monitor(
+    hmonitor: HMONITOR,
+    _hdc: HDC,
+    _rect: *mut RECT,
+    data: LPARAM,
+) -> BOOL {
+    let monitors = &mut *(data.0 as *mut Vec<Monitor>);
+    let mut info = MONITORINFOEXW::default();
+    info.monitorInfo.cbSize = std::mem::size_of::<MONITORINFOEXW>() as u32;
+    if !GetMonitorInfoW(hmonitor, &mut info as *mut MONITORINFOEXW as *mut MONITORINFO).as_bool() {
+        return BOOL(1);
+    }
+
+    // szDevice（\\.\DISPLAY1）在重新插拔后可能变化，优先使用设备接口名
+    let mut device = DISPLAY_DEVICEW {
+        cb: std::mem::size_of::<DISPLAY_DEVICEW>() as u32,
+        ..Default::default()
+    };
+    let id = if EnumDisplayDevicesW(
+        PCWSTR(info.szDevice.as_ptr()),
+        0,
+        &mut device,
+        EDD_GET_DEVICE_INTERFACE_NAME,
+    )
+    .as_bool()
+        && device.DeviceID[0] != 0
+    {
+        wide_to_string(&device.DeviceID)
+    } else {
+        wide_to_string(&info.szDevice)
+    };
+
+    let mut dpi_x = BASE_DPI as u32;
+    let mut dpi_y = BASE_DPI as u32;
+    if GetDpiForMonitor(hmonitor, MDT_EFFECTIVE_DPI, &mut dpi_x, &mut dpi_y).is_err() {
+        dpi_x = BASE_DPI as u32;
+    }
+
+    monitors.push(Monitor { id, work: info.monitorInfo.rcWork.into(), dpi: dpi_x });
+    BOOL(1)
+}
+
+fn wide_to_string(buf: &[u16]) -> String {
+    let len = buf.iter().position(|c| *c == 0).unwrap_or(buf.len());
+    String::from_utf16_lossy(&buf[..len])
+}
+
+// 枚举当前所有显示器
+pub fn enumerate_monitors() -> Vec<Monitor> {
+    let mut monitors: Vec<Monitor> = Vec::new();
+    unsafe {
+        let _ = EnumDisplayMonitors(
+            None,
+            None,
+            Some(collect_monitor),
+            LPARAM(&mut monitors as *mut Vec<Monitor> as isize),
+        );
+    }
+    monitors
+}
+
+fn window_rect(hwnd: HWND) -> Option<Rect> {
+    let mut rect = RECT::default();
+    unsafe { GetWindowRect(hwnd, &mut rect) }.ok()?;
+    Some(rect.into())
+}
+
+fn move_window(hwnd: HWND, rect: Rect) {
+    if let Err(e) = unsafe {
+        SetWindowPos(
+            hwnd,
+            None,
+            rect.left,
+            rect.top,
+            rect.width(),
+            rect.height(),
+            SWP_NOZORDER | SWP_NOACTIVATE,
+        )
+    } {
+        warn!("调整窗口位置失败: {}", e);
+    }
+}
+
+fn load_store(conn: &Connection) -> PlacementStore {
+    query_option(conn, PLACEMENT_OPTION)
+        .and_then(|v| serde_json::from_str(&v).ok())
+        .unwrap_or_default()
+}
+
+// 窗口隐藏前记录位置，最小化状态下的矩形没有意义，跳过
+pub fn save_placement(hwnd: HWND) {
+    if unsafe { IsIconic(hwnd) }.as_bool() {
+        return;
+    }
+    let Some(rect) = window_rect(hwnd) else {
+        return;
+    };
+    let monitors = enumerate_monitors();
+    let Some(monitor) = monitor_for_rect(&monitors, rect) else {
+        return;
+    };
+    // 数据库由前端初始化，写入线程在 init_model 中启动，之前无法保存
+    // 读取和写入在同一个事务中，不会覆盖其他写入
+    let id = monitor.id.clone();
+    let saved = to_saved(monitor, rect);
+    let result = db_writer::write(move |tx| {
+        let mut store = load_store(tx);
+        store.monitors.insert(id.clone(), saved);
+        store.last = Some(id);
+        let value = serde_json::to_string(&store).map_err(|e| format!("序列化窗口位置失败: {}", e))?;
+        save_option(tx, PLACEMENT_OPTION, &value)
+    });
+    if let Err(e) = result {
+        warn!("保存窗口位置失败: {}", e);
+    }
+}
+
+// 按保存的位置摆放窗口，在显示窗口之前调用
+pub fn restore_placement(hwnd: HWND) {
+    let Some(current) = window_rect(hwnd) else {
+        return;
+    };
+    let monitors = enumerate_monitors();
+    let store = get_conn().map(|conn| load_store(&conn)).unwrap_or_default();
+    let saved = store
+        .last
+        .as_deref()
+        .and_then(|id| store.monitors.get(id).map(|p| (id, p)));
+    let mut cursor = POINT::default();
+    let _ = unsafe { GetCursorPos(&mut cursor) };
+
+    if saved.is_some_and(|(id, _)| !monitors.iter().any(|m| m.id == id)) {
+        info!("上次所在的显示器已断开，在光标所在显示器上显示窗口");
+    }
+    if let Some(rect) = resolve_placement(saved, &monitors, (cursor.x, cursor.y), current) {
+        if rect != current {
+            move_window(hwnd, rect);
+        }
+    }
+}
+
+// 分辨率或显示器布局变化、DPI 变化后，可见的窗口跑出屏幕时移回
+pub fn ensure_on_screen(hwnd: HWND) {
+    if !unsafe { IsWindowVisible(hwnd) }.as_bool() || unsafe { IsIconic(hwnd) }.as_bool() {
+        return;
+    }
+    let Some(rect) = window_rect(hwnd) else {
+        return;
+    };
+    if let Some(target) = reclamp(&enumerate_monitors(), rect) {
+        info!("窗口位于屏幕外，移回显示器工作区");
+        move_window(hwnd, target);
+    }
+}
+
+// 恢复位置后显示主窗口并获取焦点，托盘和重复启动都通过这里显示
+pub fn show_main_window<R: Runtime>(window: &WebviewWindow<R>) {
+    if !window.is_visible().unwrap_or(false) {
+        if let Ok(hwnd) = window.hwnd() {
+            restore_placement(HWND(hwnd.0));
+        }
+        let _ = window.show();
+    }
+    let _ = window.set_focus();
+}
+
+// 主窗口隐藏到托盘
+pub fn hide_main_window<R: Runtime>(window: &Window<R>) {
+    if let Ok(hwnd) = window.hwnd() {
+        save_placement(HWND(hwnd.0));
+    }
+    let _ = window.hide();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect(left: i32, top: i32, right: i32, bottom: i32) -> Rect {
+        Rect { left, top, right, bottom }
+    }
+
+    // 左侧 100% 缩放的主显示器，右侧 150% 缩放的副显示器
+    fn monitors() -> Vec<Monitor> {
+        vec![
+            Monitor { id: String::from("primary"), work: rect(0, 0, 1920, 1040), dpi: 96 },
+            Monitor { id: String::from("secondary"), work: rect(1920, 0, 4480, 1400), dpi: 144 },
+        ]
+    }
+
+    #[test]
+    fn saved_placement_round_trips_on_scaled_monitor() {
+        let monitors = monitors();
+        let window = rect(2070, 90, 3270, 990);
+        let saved = to_saved(&monitors[1], window);
+        assert_eq!(saved, SavedPlacement { x: 100, y: 60, width: 800, height: 600 });
+        let restored = resolve_placement(Some(("secondary", &saved)), &monitors, (0, 0), rect(0, 0, 10, 10));
+        assert_eq!(restored, Some(window));
+    }
+
+    #[test]
+    fn missing_monitor_centers_on_cursor_monitor() {
+        let monitors = monitors();
+        let saved = SavedPlacement { x: 100, y: 60, width: 800, height: 600 };
+        let current = rect(0, 0, 10, 10);
+        assert_eq!(
+            resolve_placement(Some(("unplugged", &saved)), &monitors, (100, 100), current),
+            Some(rect(560, 220, 1360, 820))
+        );
+        // 保存的大小按目标显示器的缩放换算
+        assert_eq!(
+            resolve_placement(Some(("unplugged", &saved)), &monitors, (3000, 500), current),
+            Some(rect(2600, 250, 3800, 1150))
+        );
+    }
+
+    #[test]
+    fn without_saved_placement_keeps_current_size() {
+        let monitors = monitors();
+        // 光标不在任何显示器上时使用主显示器
+        assert_eq!(
+            resolve_placement(None, &monitors, (-500, -500), rect(0, 0, 1000, 800)),
+            Some(rect(460, 120, 1460, 920))
+        );
+        assert_eq!(resolve_placement(None, &[], (0, 0), rect(0, 0, 1000, 800)), None);
+    }
+
+    #[test]
+    fn monitor_selection_uses_largest_overlap() {
+        let monitors = monitors();
+        assert_eq!(monitor_for_rect(&monitors, rect(1800, 100, 2600, 700)).unwrap().id, "secondary");
+        assert_eq!(monitor_for_rect(&monitors, rect(1000, 100, 2000, 700)).unwrap().id, "primary");
+        assert_eq!(monitor_for_rect(&monitors, rect(-900, 100, -100, 700)), None);
+        assert_eq!(monitor_at(&monitors, 2000, 10).unwrap().id, "secondary");
+        assert_eq!(monitor_at(&monitors, 5000, 10).unwrap().id, "primary");
+    }
+
+    #[test]
+    fn clamp_moves_and_shrinks_into_work_area() {
+        let work = monitors()[0].work;
+        assert_eq!(clamp_to_work_area(rect(1800, 900, 2200, 1200), work), rect(1520, 740, 1920, 1040));
+        assert_eq!(clamp_to_work_area(rect(-100, -50, 3000, 2000), work), work);
+        assert_eq!(clamp_to_work_area(rect(100, 100, 500, 400), work), rect(100, 100, 500, 400));
+    }
+
+    #[test]
+    fn off_screen_window_is_moved_back() {
+        let monitors = monitors();
+        assert_eq!(reclamp(&monitors, rect(100, 100, 900, 700)), None);
+        // 标题栏在屏幕上方
+        assert_eq!(reclamp(&monitors, rect(100, -500, 900, 100)), Some(rect(100, 0, 900, 600)));
+        // 完全在屏幕外时放回主显示器
+        assert_eq!(reclamp(&monitors, rect(-5000, 100, -4200, 700)), Some(rect(0, 100, 800, 700)));
+    }
+
+    #[test]
+    fn sliver_on_screen_is_not_reachable() {
+        let monitors = monitors();
+        let window = rect(1900, 100, 2700, 700);
+        // 大部分在副显示器上
+        assert!(is_reachable(&monitors, window));
+        // 拔掉副显示器后只剩 20 像素可见
+        let primary = &monitors[..1];
+        assert!(!is_reachable(primary, window));
+        assert_eq!(reclamp(primary, window), Some(rect(1120, 100, 1920, 700)));
+    }
+}
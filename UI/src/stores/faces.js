@@ -1,4 +1,5 @@
 import { defineStore } from 'pinia';
+import { invoke } from '@tauri-apps/api/core';
 import { select, insert, update, deleteData } from '../utils/sqlite';
 import { formatObjectString, getCurrentDateTime, removeFace } from '../utils/function'
 import { info, error as errorLog, warn } from '@tauri-apps/plugin-log';
@@ -8,6 +9,7 @@ export const useFacesStore = defineStore('faces', {
         init(){
             return new Promise((resolve, reject) => {
                 select('faces', ['*']).then((result)=>{
+                    this.faceList.length = 0;
                     for(let i = 0; i < result.rows.length; i++){
                         const item = result.rows[i];
                         this.addFaceToList(item);
@@ -26,10 +28,14 @@ export const useFacesStore = defineStore('faces', {
          */
         addFace(data){
             return new Promise((resolve, reject) => {
-                insert("faces", 
-                    ["user_name", "user_pwd", "account_type", "face_token", "json_data"],
-                    [data.user_name, data.user_pwd, data.account_type, data.face_token, data.json_data]
-                ).then((result)=>{
+                // 先把用户名写入面容特征数据库，再保存账户
+                invoke("set_face_username", {fileName: data.face_token, userName: data.user_name}).then(()=>{
+                    // 密码保存在Windows凭据管理器中，数据库中只保留空字符串
+                    return insert("faces", 
+                        ["user_name", "user_pwd", "account_type", "face_token", "json_data"],
+                        [data.user_name, "", data.account_type, data.face_token, data.json_data]
+                    );
+                }).then((result)=>{
                     this.addFaceToList({
                         id: result.lastId,
                         createTime: getCurrentDateTime(),
@@ -59,20 +65,21 @@ export const useFacesStore = defineStore('faces', {
                     return;
                 }
 
-                update("faces", 
-                    {
-                        user_name: data.user_name, user_pwd: data.user_pwd, 
-                        account_type: data.account_type, face_token: data.face_token, 
-                        json_data: data.json_data
-                    },
-                    "id = ?",
-                    [id]
-                ).then(()=>{
+                invoke("set_face_username", {fileName: data.face_token, userName: data.user_name}).then(()=>{
+                    return update("faces", 
+                        {
+                            user_name: data.user_name, 
+                            account_type: data.account_type, face_token: data.face_token, 
+                            json_data: data.json_data
+                        },
+                        "id = ?",
+                        [id]
+                    );
+                }).then(()=>{
                     // 如果解析失败，直接返回失败，后续操作会throw error
                     this.faceList[faceIndex].json_data = JSON.parse(data.json_data);
 
                     this.faceList[faceIndex].user_name = data.user_name;
-                    this.faceList[faceIndex].user_pwd = data.user_pwd;
                     this.faceList[faceIndex].account_type = data.account_type;
                     this.faceList[faceIndex].face_token = data.face_token;
                     resolve();
@@ -144,7 +151,6 @@ export const useFacesStore = defineStore('faces', {
             this.faceList.push({
                 id: data.id,
                 user_name: data.user_name,
-                user_pwd: data.user_pwd,
                 account_type: data.account_type,
                 face_token: data.face_token,
                 json_data: JSON.parse(data.json_data),
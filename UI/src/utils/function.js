@@ -1,4 +1,6 @@
-import { useFile } from "../hook/useFile";
+import { invoke } from '@tauri-apps/api/core';
+import { warn } from '@tauri-apps/plugin-log';
+import { ElMessage } from 'element-plus';
 
 /**
  * 格式化信息为字符串
@@ -99,16 +101,9 @@ function handleLocalAccount(authForm, add){
  * @param {string} tips 提示信息
  */
 function removeFace(face_name, tips = "删除面容"){
-    const { reomve } = useFile();
-
-    reomve("faces\\" + face_name + ".faceimg").catch((error)=>{
-        const info = formatObjectString(tips + "图片失败：", error);
-        warn(info);
-        ElMessage.warning(info);
-    });
-
-    reomve("faces\\" + face_name + ".face").catch((error)=>{
-        const info = formatObjectString(tips + "特征失败：", error);
+    // 特征保存在面容数据库中，由后端同时删除特征和图片
+    invoke("delete_face_registration", {fileName: face_name}).catch((error)=>{
+        const info = formatObjectString(tips + "失败：", error);
         warn(info);
         ElMessage.warning(info);
     });
@@ -27,7 +27,7 @@ const databseTable = [
             { name: 'id', type: 'INTEGER', primaryKey: true, autoIncrement: true, notNull: true },
             // 对应的Windows用户名
             { name: 'user_name', type: 'TEXT', notNull: true },
-            // 加密后的Windows密码（由DLL用来调起登录）
+            // 已废弃，密码保存在Windows凭据管理器中，这里保存空字符串
             { name: 'user_pwd', type: 'TEXT', notNull: true },
             // windows账户类型
             { name: 'account_type', type: 'TEXT', notNull: true },
@@ -39,6 +39,8 @@ const databseTable = [
             // threshold 置信度
             // view 是否在列表页显示图片缩略图
             // faceDetectionThreshold 人脸的置信度
+            // expired 长时间未使用，被数据保留策略停用
+            // reactivatedTime 停用后重新启用的时间
             { name: 'json_data', type: 'TEXT', notNull: true },
             // 创建时间
             { name: 'createTime', type: 'TEXT', defaultValue: "datetime('now', 'localtime')" }
@@ -52,9 +54,95 @@ const databseTable = [
             { name: 'face_id', type: 'INTEGER' },
             // 是否成功解锁
             { name: 'is_unlock', type: 'INTEGER', notNull: true },
+            // 锁屏到解锁各阶段的耗时（JSON，毫秒）
+            { name: 'latency', type: 'TEXT' },
+            // 匹配分数，解锁失败时为本次最高分，可能是分箱后的值
+            { name: 'score', type: 'REAL' },
+            // 是否已经降低精度（分数分箱、时间精确到小时）
+            { name: 'bucketed', type: 'INTEGER', notNull: true, defaultValue: '0' },
+            // 附加说明，例如匹配分数无效（invalid_score）
+            { name: 'note', type: 'TEXT' },
+            // 多人脸策略和使用的人脸序号
+            { name: 'face_policy', type: 'TEXT' },
+            { name: 'face_index', type: 'INTEGER' },
+            // 分数最高的参考样本序号，以及说明模式下各样本的分数（JSON）
+            { name: 'sample_index', type: 'INTEGER' },
+            { name: 'match_detail', type: 'TEXT' },
             // 上次更新时间
             { name: 'lastTime', type: 'TEXT', defaultValue: "datetime('now', 'localtime')" }
         ]
+    },{
+        // 面容匹配分数统计，用于提示重新录入
+        name: 'face_score_stats',
+        columns: [
+            { name: 'id', type: 'INTEGER', primaryKey: true, autoIncrement: true, notNull: true },
+            // 面容标识符，对应 faces 表的 face_token
+            { name: 'face_token', type: 'TEXT', notNull: true, unique: true },
+            // 近期成功匹配分数的滑动平均
+            { name: 'recent_avg', type: 'REAL', notNull: true },
+            // 长期成功匹配分数的滑动平均
+            { name: 'long_avg', type: 'REAL', notNull: true },
+            // 成功匹配次数
+            { name: 'sample_count', type: 'INTEGER', notNull: true },
+            // 提醒暂停到该时间
+            { name: 'snooze_until', type: 'TEXT' },
+            // 上次更新时间
+            { name: 'lastTime', type: 'TEXT', defaultValue: "datetime('now', 'localtime')" }
+        ]
+    },{
+        // 可用配置快照
+        name: 'config_snapshot',
+        columns: [
+            { name: 'id', type: 'INTEGER', primaryKey: true, autoIncrement: true, notNull: true },
+            // 快照名称
+            { name: 'name', type: 'TEXT', notNull: true },
+            // 设置项、模型、摄像头等信息（JSON）
+            { name: 'json_data', type: 'TEXT', notNull: true },
+            // 创建时间
+            { name: 'createTime', type: 'TEXT', defaultValue: "datetime('now', 'localtime')" }
+        ]
+    },{
+        // 数据保留策略的维护记录
+        name: 'maintenance_log',
+        columns: [
+            { name: 'id', type: 'INTEGER', primaryKey: true, autoIncrement: true, notNull: true },
+            // 本次维护的策略和清理结果（JSON）
+            { name: 'json_data', type: 'TEXT', notNull: true },
+            // 创建时间
+            { name: 'createTime', type: 'TEXT', defaultValue: "datetime('now', 'localtime')" }
+        ]
+    },{
+        // 控制管道的调用记录
+        name: 'control_log',
+        columns: [
+            { name: 'id', type: 'INTEGER', primaryKey: true, autoIncrement: true, notNull: true },
+            // 命令
+            { name: 'verb', type: 'TEXT', notNull: true },
+            // 调用方进程 ID 和程序路径
+            { name: 'pid', type: 'INTEGER', notNull: true },
+            { name: 'image', type: 'TEXT' },
+            // 执行结果
+            { name: 'code', type: 'INTEGER', notNull: true },
+            { name: 'msg', type: 'TEXT' },
+            // 创建时间
+            { name: 'createTime', type: 'TEXT', defaultValue: "datetime('now', 'localtime')" }
+        ]
+    },{
+        // 守护进程的重启记录
+        name: 'supervisor_log',
+        columns: [
+            { name: 'id', type: 'INTEGER', primaryKey: true, autoIncrement: true, notNull: true },
+            // 事件：restart、restart_requested、gave_up、fatal_init、spawn_failed
+            { name: 'event', type: 'TEXT', notNull: true },
+            // 子进程的退出码
+            { name: 'exit_code', type: 'INTEGER' },
+            // 连续重启的次数
+            { name: 'attempt', type: 'INTEGER', notNull: true },
+            // 子进程运行的时间（秒）
+            { name: 'uptime', type: 'INTEGER', notNull: true },
+            // 创建时间
+            { name: 'createTime', type: 'TEXT', defaultValue: "datetime('now', 'localtime')" }
+        ]
     }
 ];
 